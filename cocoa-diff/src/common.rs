@@ -4,7 +4,7 @@ pub use log::{info, warn};
 
 pub use data_beans::sparse_data_visitors::*;
 pub use data_beans::sparse_io::*;
-pub use data_beans::sparse_io_vector::SparseIoVec;
+pub use data_beans::sparse_io_vector::{ColumnSelectArgs, RowAlignment, SparseIoVec};
 
 pub type Mat = nalgebra::DMatrix<f32>;
 pub type DVec = nalgebra::DVector<f32>;
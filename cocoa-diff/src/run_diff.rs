@@ -6,7 +6,7 @@ pub use clap::Parser;
 
 use matrix_param::io::*;
 use matrix_util::common_io::basename;
-pub use matrix_util::common_io::{extension, read_lines, read_lines_of_words};
+pub use matrix_util::common_io::{read_lines, read_lines_of_words};
 use matrix_util::dmatrix_util::concatenate_vertical;
 use matrix_util::traits::IoOps;
 pub use std::sync::Arc;
@@ -16,8 +16,8 @@ use std::collections::HashSet;
 
 #[derive(Parser, Debug, Clone)]
 pub struct DiffArgs {
-    /// data files of either `.zarr` or `.h5` format. All the formats
-    /// in the given list should be identical. We can convert `.mtx`
+    /// data files of either `.zarr` or `.h5` format. The formats can
+    /// be mixed in the given list. We can convert `.mtx`
     /// to `.zarr` or `.h5` using `asap-data build` command.
     #[arg(required = true)]
     data_files: Vec<Box<str>>,
@@ -32,6 +32,14 @@ pub struct DiffArgs {
     #[arg(long, short, value_delimiter(','))]
     topic_assignment_files: Option<Vec<Box<str>>>,
 
+    /// how to line up rows (genes) across data files: `strict` (same
+    /// rows), `intersection` (shared rows), or `union` (zero-filled)
+    #[arg(long, value_enum, default_value = "strict")]
+    row_alignment: RowAlignment,
+
+    #[command(flatten)]
+    columns: ColumnSelectArgs,
+
     /// each line corresponds to (1) individual name and (2) exposure name
     #[arg(long, short)]
     exposure_assignment_file: Box<str>,
@@ -64,9 +72,8 @@ pub struct DiffArgs {
     #[arg(long, short, required = true)]
     out: Box<str>,
 
-    /// preload all the columns data
-    #[arg(long, default_value_t = false)]
-    preload_data: bool,
+    #[command(flatten)]
+    preload: PreloadArgs,
 
     /// verbosity
     #[arg(long, short)]
@@ -178,13 +185,6 @@ struct ArgInputData {
 
 fn parse_arg_input_data(args: DiffArgs) -> anyhow::Result<ArgInputData> {
     // push data files and collect batch membership
-    let file = args.data_files[0].as_ref();
-    let backend = match extension(file)?.to_string().as_str() {
-        "h5" => SparseIoBackend::HDF5,
-        "zarr" => SparseIoBackend::Zarr,
-        _ => SparseIoBackend::Zarr,
-    };
-
     if args.indv_files.len() != args.data_files.len() {
         return Err(anyhow::anyhow!("# sample files != # of data files"));
     }
@@ -216,6 +216,7 @@ fn parse_arg_input_data(args: DiffArgs) -> anyhow::Result<ArgInputData> {
     info!("{} exposure groups", n_exposure);
 
     let mut sparse_data = SparseIoVec::new();
+    sparse_data.set_row_alignment(args.row_alignment)?;
     let mut cell_to_indv = vec![];
     let mut topic_vec = vec![];
 
@@ -231,21 +232,8 @@ fn parse_arg_input_data(args: DiffArgs) -> anyhow::Result<ArgInputData> {
     {
         info!("Importing: {}, {}", this_data_file, indv_file);
 
-        match extension(&this_data_file)?.as_ref() {
-            "zarr" => {
-                assert_eq!(backend, SparseIoBackend::Zarr);
-            }
-            "h5" => {
-                assert_eq!(backend, SparseIoBackend::HDF5);
-            }
-            _ => return Err(anyhow::anyhow!("Unknown file format: {}", this_data_file)),
-        };
-
-        let mut this_data = open_sparse_matrix(&this_data_file, &backend)?;
-
-        if args.preload_data {
-            this_data.preload_columns()?;
-        }
+        let mut this_data = open_sparse_matrix_by_extension(&this_data_file)?;
+        args.preload.preload(this_data.as_mut())?;
 
         let ndata = this_data.num_columns().unwrap_or(0);
 
@@ -272,6 +260,15 @@ fn parse_arg_input_data(args: DiffArgs) -> anyhow::Result<ArgInputData> {
 
     let cell_topic = concatenate_vertical(topic_vec.as_slice())?;
 
+    let (sparse_data, cells) = args.columns.select_columns(sparse_data)?;
+    let (cell_to_indv, cell_topic) = match cells {
+        Some(cells) => (
+            cells.iter().map(|&j| cell_to_indv[j].clone()).collect(),
+            cell_topic.select_rows(cells.iter()),
+        ),
+        None => (cell_to_indv, cell_topic),
+    };
+
     Ok(ArgInputData {
         sparse_data,
         cell_to_indv,
@@ -343,10 +343,7 @@ pub fn run_sim_diff_data(args: SimArgs) -> anyhow::Result<()> {
     mkdir(&output)?;
 
     let backend = args.backend.clone();
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => output.to_string() + ".h5",
-        SparseIoBackend::Zarr => output.to_string() + ".zarr",
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     let mtx_file = output.to_string() + ".mtx.gz";
     let row_file = output.to_string() + ".rows.gz";
@@ -411,6 +408,7 @@ pub fn run_sim_diff_data(args: SimArgs) -> anyhow::Result<()> {
 
     data.register_row_names_vec(&rows);
     data.register_column_names_vec(&cols);
+    data.flush_backend()?;
 
     info!("done");
     Ok(())
@@ -1,3 +1,4 @@
 pub mod collapse_data;
 pub mod normalization;
 pub mod random_projection;
+pub mod transformed_view;
@@ -1,5 +1,6 @@
 #![allow(dead_code)]
 
+use crate::transformed_view::SparseIoVecView;
 use data_beans::sparse_data_visitors::*;
 use data_beans::sparse_io_vector::SparseIoVec;
 use std::sync::{Arc, Mutex};
@@ -73,15 +74,17 @@ pub trait RandProjOps {
 }
 
 /// column-wise visitor for random projection
-fn project_columns_visitor(
+fn project_columns_visitor<D>(
     job: (usize, usize),
-    data_vec: &SparseIoVec,
+    data: &D,
     basis_dk: &nalgebra::DMatrix<f32>,
     arc_proj_kn: Arc<Mutex<&mut nalgebra::DMatrix<f32>>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
     let (lb, ub) = job;
-    let mut xx_dm = data_vec.read_columns_csc(lb..ub)?;
-    xx_dm.normalize_columns_inplace();
+    let xx_dm = data.read_columns_csc(lb..ub)?;
     let chunk = (xx_dm.transpose() * basis_dk).transpose();
 
     let mut proj_kn = arc_proj_kn.lock().expect("proj_kn lock");
@@ -120,12 +123,14 @@ impl RandProjOps for SparseIoVec {
 
         let basis_dk = nalgebra::DMatrix::<f32>::rnorm(nrows, target_dim);
 
-        self.visit_columns_by_block(
-            &project_columns_visitor,
-            &basis_dk,
-            &mut proj_kn,
-            block_size,
-        )?;
+        SparseIoVecView::new(self)?
+            .normalize()
+            .visit_columns_by_block(
+                &project_columns_visitor,
+                &basis_dk,
+                &mut proj_kn,
+                block_size,
+            )?;
 
         if let Some(col_to_batch) = batch_membership {
             info!("adjusting batch biases ...");
@@ -0,0 +1,204 @@
+use crate::normalization::AdjustByDivisionOp;
+
+use data_beans::sparse_data_visitors::ReadColumnsOps;
+use data_beans::sparse_io_vector::SparseIoVec;
+use matrix_util::traits::MatOps;
+use nalgebra::DMatrix;
+use nalgebra_sparse::{CooMatrix, CscMatrix};
+
+/// A transformation applied to each block of columns as it is read
+pub enum ColumnTransform<'a> {
+    /// divide each column by its L2 norm (at least 1), as in
+    /// `normalize_columns_inplace`
+    Normalize,
+    /// rescale each column to sum up to the target (size factor)
+    SizeFactor(f32),
+    /// `log(1 + x)` of the non-zero elements
+    Log1p,
+    /// standardize each column, as in `scale_columns_inplace`
+    Standardize,
+    /// divide each column by the `d x b` batch effects of its batch,
+    /// as in `adjust_by_division`
+    AdjustByBatch(&'a DMatrix<f32>),
+    /// take or aggregate rows by left-multiplying a sparse `m x d`
+    /// matrix
+    MapRows(CscMatrix<f32>),
+}
+
+/// A lazy view of `SparseIoVec` that applies a pipeline of per-column
+/// transformations whenever columns are read
+///
+/// ```text
+/// SparseIoVec --read_columns_csc--> [normalize] -> [batch] -> [log1p] -> ...
+/// ```
+///
+/// It implements `ReadColumnsOps`, so the same column visitors can
+/// walk over the raw data or the view.
+///
+/// ```text
+/// let view = SparseIoVecView::new(&data_vec)?
+///     .normalize()
+///     .adjust_by_batch(&delta_db)?
+///     .log1p();
+/// view.visit_columns_by_block(&visitor, &shared_in, &mut shared_out, None)?;
+/// ```
+pub struct SparseIoVecView<'a> {
+    data_vec: &'a SparseIoVec,
+    transforms: Vec<ColumnTransform<'a>>,
+    nrows: usize,
+}
+
+impl<'a> SparseIoVecView<'a> {
+    /// A view without any transformation
+    pub fn new(data_vec: &'a SparseIoVec) -> anyhow::Result<Self> {
+        Ok(Self {
+            data_vec,
+            transforms: vec![],
+            nrows: data_vec.num_rows()?,
+        })
+    }
+
+    /// The underlying data vector
+    pub fn data_vec(&self) -> &SparseIoVec {
+        self.data_vec
+    }
+
+    /// Transformations in the order of application
+    pub fn transforms(&self) -> &[ColumnTransform<'a>] {
+        &self.transforms
+    }
+
+    /// Append a transformation to the pipeline, checking the number
+    /// of rows it expects
+    pub fn push(mut self, transform: ColumnTransform<'a>) -> anyhow::Result<Self> {
+        match &transform {
+            ColumnTransform::AdjustByBatch(delta_db) if delta_db.nrows() != self.nrows => {
+                return Err(anyhow::anyhow!(
+                    "batch effects have {} rows, but the view has {}",
+                    delta_db.nrows(),
+                    self.nrows
+                ));
+            }
+            ColumnTransform::MapRows(map_md) => {
+                if map_md.ncols() != self.nrows {
+                    return Err(anyhow::anyhow!(
+                        "row map takes {} rows, but the view has {}",
+                        map_md.ncols(),
+                        self.nrows
+                    ));
+                }
+                self.nrows = map_md.nrows();
+            }
+            _ => {}
+        }
+        self.transforms.push(transform);
+        Ok(self)
+    }
+
+    /// Divide each column by its L2 norm
+    pub fn normalize(mut self) -> Self {
+        self.transforms.push(ColumnTransform::Normalize);
+        self
+    }
+
+    /// Rescale each column to sum up to `target`
+    pub fn size_factor(mut self, target: f32) -> Self {
+        self.transforms.push(ColumnTransform::SizeFactor(target));
+        self
+    }
+
+    /// `log(1 + x)`
+    pub fn log1p(mut self) -> Self {
+        self.transforms.push(ColumnTransform::Log1p);
+        self
+    }
+
+    /// Standardize each column
+    pub fn standardize(mut self) -> Self {
+        self.transforms.push(ColumnTransform::Standardize);
+        self
+    }
+
+    /// Divide each column by the batch effects of its batch; the
+    /// batch membership should be registered in the data vector
+    /// * `delta_db` - feature x batch effects
+    pub fn adjust_by_batch(self, delta_db: &'a DMatrix<f32>) -> anyhow::Result<Self> {
+        self.push(ColumnTransform::AdjustByBatch(delta_db))
+    }
+
+    /// Keep the rows in this order
+    /// * `rows` - row indices of the current view
+    pub fn subset_rows(self, rows: &[usize]) -> anyhow::Result<Self> {
+        let mut coo = CooMatrix::new(rows.len(), self.nrows);
+        for (r, &i) in rows.iter().enumerate() {
+            if i >= self.nrows {
+                return Err(anyhow::anyhow!("row {} is out of bound {}", i, self.nrows));
+            }
+            coo.push(r, i, 1.);
+        }
+        self.push(ColumnTransform::MapRows(CscMatrix::from(&coo)))
+    }
+
+    /// Sum up the rows of the same module, e.g., aggregate features
+    /// into `m` modules
+    /// * `row_membership` - module of each row of the current view
+    pub fn aggregate_rows(self, row_membership: &[usize]) -> anyhow::Result<Self> {
+        if row_membership.len() != self.nrows {
+            return Err(anyhow::anyhow!(
+                "{} memberships for {} rows",
+                row_membership.len(),
+                self.nrows
+            ));
+        }
+        let mm = row_membership.iter().max().map(|&m| m + 1).unwrap_or(1);
+        let mut coo = CooMatrix::new(mm, self.nrows);
+        for (i, &m) in row_membership.iter().enumerate() {
+            coo.push(m, i, 1.);
+        }
+        self.push(ColumnTransform::MapRows(CscMatrix::from(&coo)))
+    }
+}
+
+impl ReadColumnsOps for SparseIoVecView<'_> {
+    fn num_rows(&self) -> anyhow::Result<usize> {
+        Ok(self.nrows)
+    }
+
+    fn num_columns(&self) -> anyhow::Result<usize> {
+        self.data_vec.num_columns()
+    }
+
+    fn read_columns_csc<I>(&self, cells: I) -> anyhow::Result<CscMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let cells: Vec<usize> = cells.collect();
+        let mut x_dn = self.data_vec.read_columns_csc(cells.iter().copied())?;
+
+        for transform in self.transforms.iter() {
+            match transform {
+                ColumnTransform::Normalize => x_dn.normalize_columns_inplace(),
+                ColumnTransform::SizeFactor(target) => {
+                    x_dn.col_iter_mut().for_each(|mut x_j| {
+                        let xsum: f32 = x_j.values().iter().sum();
+                        if xsum > 0. {
+                            x_j.values_mut()
+                                .iter_mut()
+                                .for_each(|x| *x *= target / xsum);
+                        }
+                    });
+                }
+                ColumnTransform::Log1p => {
+                    x_dn.values_mut().iter_mut().for_each(|x| *x = x.ln_1p());
+                }
+                ColumnTransform::Standardize => x_dn.scale_columns_inplace(),
+                ColumnTransform::AdjustByBatch(delta_db) => {
+                    let batches = self.data_vec.get_batch_membership(cells.iter().copied());
+                    x_dn.adjust_by_division(delta_db, &batches);
+                }
+                ColumnTransform::MapRows(map_md) => x_dn = map_md * &x_dn,
+            }
+        }
+        Ok(x_dn)
+    }
+}
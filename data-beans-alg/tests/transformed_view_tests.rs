@@ -0,0 +1,83 @@
+use data_beans::sparse_data_visitors::*;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::SparseIoVec;
+use data_beans_alg::transformed_view::*;
+use matrix_util::traits::SampleOps;
+use std::sync::{Arc, Mutex};
+
+fn column_sums_visitor<D>(
+    (lb, ub): (usize, usize),
+    data: &D,
+    _: &(),
+    out: Arc<Mutex<&mut Vec<f32>>>,
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
+    let x = data.read_columns_ndarray(lb..ub)?;
+    let mut out = out.lock().expect("lock");
+    for (j, x_j) in x.columns().into_iter().enumerate() {
+        out[lb + j] = x_j.sum();
+    }
+    Ok(())
+}
+
+#[test]
+fn transformed_view_pipeline() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(6, 10);
+
+    let mut data = create_sparse_from_ndarray(&x, None, Some(&SparseIoBackend::Memory))?;
+    let rows: Vec<Box<str>> = (0..6).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..10).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(data), None)?;
+
+    // size factor -> log1p -> aggregate rows {0, 1, 2} and {3, 4, 5}
+    let view = SparseIoVecView::new(&data_vec)?
+        .size_factor(10.)
+        .log1p()
+        .aggregate_rows(&[0, 0, 0, 1, 1, 1])?;
+    assert_eq!(view.num_rows()?, 2);
+
+    let expected = {
+        let mut y = x.clone();
+        for mut y_j in y.columns_mut() {
+            let s = y_j.sum();
+            y_j.mapv_inplace(|v| (v * 10. / s).ln_1p());
+        }
+        ndarray::stack(
+            Axis(0),
+            &[
+                y.slice(s![0..3, ..]).sum_axis(Axis(0)).view(),
+                y.slice(s![3..6, ..]).sum_axis(Axis(0)).view(),
+            ],
+        )?
+    };
+
+    let y = view.read_columns_ndarray(vec![4, 0, 9].into_iter())?;
+    for (a, b) in y.iter().zip(expected.select(Axis(1), &[4, 0, 9]).iter()) {
+        approx::assert_abs_diff_eq!(a, b, epsilon = 1e-4);
+    }
+
+    // the same visitor walks over the raw data and the view
+    let mut raw_sums = vec![0_f32; 10];
+    data_vec.visit_columns_by_block(&column_sums_visitor, &(), &mut raw_sums, Some(3))?;
+    let mut view_sums = vec![0_f32; 10];
+    view.visit_columns_by_block(&column_sums_visitor, &(), &mut view_sums, Some(3))?;
+
+    for j in 0..10 {
+        approx::assert_abs_diff_eq!(raw_sums[j], x.column(j).sum(), epsilon = 1e-4);
+        approx::assert_abs_diff_eq!(view_sums[j], expected.column(j).sum(), epsilon = 1e-4);
+    }
+
+    // rows that don't match the batch effects are rejected
+    let delta_db = DMatrix::<f32>::from_element(6, 2, 1.);
+    assert!(SparseIoVecView::new(&data_vec)?
+        .subset_rows(&[5, 1])?
+        .adjust_by_batch(&delta_db)
+        .is_err());
+    Ok(())
+}
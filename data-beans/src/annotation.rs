@@ -0,0 +1,270 @@
+#![allow(dead_code)]
+
+use crate::sparse_io::build_name2index_map;
+use log::info;
+use matrix_util::common_io::read_lines_of_words_delim;
+use std::collections::HashMap;
+
+/// Category name for entries missing in an annotation table
+pub const MISSING_CATEGORY: &str = "NA";
+
+/// Attribute name to keep the type of an annotation column
+pub const ENCODING_ATTR: &str = "encoding-type";
+
+/// Which side of the matrix an annotation table describes
+///
+/// ```text
+/// (root)
+///     ├── obs (per column/cell)
+///     │   └── {name} (encoding-type)
+///     │       ├── categories, codes (categorical)
+///     │       └── values (numeric or string)
+///     └── var (per row/feature)
+///         └── {name} ...
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AnnotationAxis {
+    /// per column/cell
+    Obs,
+    /// per row/feature
+    Var,
+}
+
+impl AnnotationAxis {
+    pub fn group_name(&self) -> &'static str {
+        match self {
+            AnnotationAxis::Obs => "/obs",
+            AnnotationAxis::Var => "/var",
+        }
+    }
+
+    /// Key of the annotation column `name` in the backend
+    pub fn key(&self, name: &str) -> String {
+        format!("{}/{}", self.group_name(), name)
+    }
+}
+
+/// A typed column of an annotation table
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnnotationColumn {
+    /// `codes[i]` points to one of the `categories`
+    Categorical {
+        categories: Vec<Box<str>>,
+        codes: Vec<u64>,
+    },
+    Numeric(Vec<f32>),
+    String(Vec<Box<str>>),
+}
+
+impl AnnotationColumn {
+    pub const CATEGORICAL: &'static str = "categorical";
+    pub const NUMERIC: &'static str = "numeric";
+    pub const STRING: &'static str = "string";
+
+    /// Build a categorical column with sorted unique categories
+    /// * `values` - a value for each element
+    pub fn categorical(values: &[Box<str>]) -> Self {
+        let mut categories = values.to_vec();
+        categories.sort();
+        categories.dedup();
+        let cat2code = build_name2index_map(&categories);
+        let codes = values.iter().map(|v| cat2code[v] as u64).collect();
+        AnnotationColumn::Categorical { categories, codes }
+    }
+
+    /// Numeric if all the values can be parsed as numbers (empty or
+    /// `NA` as `NaN`); otherwise, categorical
+    /// * `values` - a value for each element
+    pub fn parse(values: &[Box<str>]) -> Self {
+        let numbers: Option<Vec<f32>> = values
+            .iter()
+            .map(|v| match v.as_ref() {
+                "" | MISSING_CATEGORY => Some(f32::NAN),
+                v => v.parse::<f32>().ok(),
+            })
+            .collect();
+        match numbers {
+            Some(numbers) if numbers.iter().any(|x| !x.is_nan()) => {
+                AnnotationColumn::Numeric(numbers)
+            }
+            _ => Self::categorical(values),
+        }
+    }
+
+    pub fn encoding_type(&self) -> &'static str {
+        match self {
+            AnnotationColumn::Categorical { .. } => Self::CATEGORICAL,
+            AnnotationColumn::Numeric(_) => Self::NUMERIC,
+            AnnotationColumn::String(_) => Self::STRING,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            AnnotationColumn::Categorical { codes, .. } => codes.len(),
+            AnnotationColumn::Numeric(values) => values.len(),
+            AnnotationColumn::String(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A string for each element, e.g., batch membership
+    pub fn to_strings(&self) -> Vec<Box<str>> {
+        match self {
+            AnnotationColumn::Categorical { categories, codes } => codes
+                .iter()
+                .map(|&c| categories[c as usize].clone())
+                .collect(),
+            AnnotationColumn::Numeric(values) => {
+                values.iter().map(|x| x.to_string().into()).collect()
+            }
+            AnnotationColumn::String(values) => values.clone(),
+        }
+    }
+
+    /// Take elements in a new order; `None` gives a missing value
+    /// (`NaN`, empty string or the `NA` category)
+    /// * `indices` - old position for each new element
+    pub fn take(&self, indices: &[Option<usize>]) -> Self {
+        match self {
+            AnnotationColumn::Categorical { categories, codes } => {
+                let mut categories = categories.clone();
+                let missing = indices.iter().any(|i| i.is_none());
+                let na_code = match categories
+                    .iter()
+                    .position(|c| c.as_ref() == MISSING_CATEGORY)
+                {
+                    Some(c) => c,
+                    None if missing => {
+                        categories.push(MISSING_CATEGORY.into());
+                        categories.len() - 1
+                    }
+                    None => 0,
+                };
+                let codes = indices
+                    .iter()
+                    .map(|i| i.map_or(na_code as u64, |i| codes[i]))
+                    .collect();
+                AnnotationColumn::Categorical { categories, codes }
+            }
+            AnnotationColumn::Numeric(values) => AnnotationColumn::Numeric(
+                indices
+                    .iter()
+                    .map(|i| i.map_or(f32::NAN, |i| values[i]))
+                    .collect(),
+            ),
+            AnnotationColumn::String(values) => AnnotationColumn::String(
+                indices
+                    .iter()
+                    .map(|i| i.map_or(Box::from(""), |i| values[i].clone()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Stack columns of the same name from multiple data sets.
+    /// Categories are merged; if the types differ, everything
+    /// becomes categorical.
+    pub fn concatenate(columns: &[AnnotationColumn]) -> anyhow::Result<Self> {
+        let first = columns
+            .first()
+            .ok_or(anyhow::anyhow!("nothing to concatenate"))?;
+
+        let same_type = columns
+            .iter()
+            .all(|c| c.encoding_type() == first.encoding_type());
+
+        match first {
+            AnnotationColumn::Numeric(_) if same_type => {
+                let mut ret = vec![];
+                for c in columns {
+                    if let AnnotationColumn::Numeric(values) = c {
+                        ret.extend_from_slice(values);
+                    }
+                }
+                Ok(AnnotationColumn::Numeric(ret))
+            }
+            AnnotationColumn::String(_) if same_type => {
+                let mut ret = vec![];
+                for c in columns {
+                    if let AnnotationColumn::String(values) = c {
+                        ret.extend_from_slice(values);
+                    }
+                }
+                Ok(AnnotationColumn::String(ret))
+            }
+            _ => {
+                let values: Vec<Box<str>> = columns.iter().flat_map(|c| c.to_strings()).collect();
+                Ok(Self::categorical(&values))
+            }
+        }
+    }
+}
+
+/// Read a table of annotations with a header line. The first column
+/// contains names (e.g., cell barcodes) and the rest are parsed as
+/// numeric or categorical columns.
+///
+/// * `table_file` - tab- or comma-separated file (gzipped or not)
+/// * `names` - names of the elements in the backend; the rows of the
+///   table will be matched by these names
+/// * `categorical` - columns to be kept categorical
+///
+pub fn read_annotation_table(
+    table_file: &str,
+    names: &[Box<str>],
+    categorical: &[Box<str>],
+) -> anyhow::Result<Vec<(Box<str>, AnnotationColumn)>> {
+    let (lines, header) = read_lines_of_words_delim(table_file, vec!['\t', ','], 0)?;
+
+    if header.len() < 2 {
+        return Err(anyhow::anyhow!(
+            "{} should have names followed by at least one column",
+            table_file
+        ));
+    }
+
+    let name2line: HashMap<&str, usize> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(l, words)| words.first().map(|w| (w.as_ref(), l)))
+        .collect();
+
+    let positions: Vec<Option<usize>> = names
+        .iter()
+        .map(|x| name2line.get(x.as_ref()).copied())
+        .collect();
+
+    let nmissing = positions.iter().filter(|p| p.is_none()).count();
+    if nmissing == names.len() {
+        return Err(anyhow::anyhow!("no names matched in {}", table_file));
+    }
+    if nmissing > 0 {
+        info!("{} names are missing in {}", nmissing, table_file);
+    }
+
+    let mut ret = vec![];
+    for (k, column_name) in header.iter().enumerate().skip(1) {
+        let values: Vec<Box<str>> = lines
+            .iter()
+            .map(|words| words.get(k).cloned().unwrap_or_default())
+            .collect();
+        let column = if categorical.contains(column_name) {
+            AnnotationColumn::categorical(&values)
+        } else {
+            AnnotationColumn::parse(&values)
+        }
+        .take(&positions);
+        info!(
+            "read {} column `{}` from {}",
+            column.encoding_type(),
+            column_name,
+            table_file
+        );
+        ret.push((column_name.clone(), column));
+    }
+    Ok(ret)
+}
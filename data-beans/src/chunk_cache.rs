@@ -0,0 +1,369 @@
+use crate::compressed_parts::CompressedParts;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Default bound of the decoded blocks kept in memory (bytes), shared
+/// by all the backends of a process
+pub const DEFAULT_CHUNK_CACHE_BYTES: usize = 256 << 20;
+
+/// Default number of elements per block if the storage is not chunked
+pub const DEFAULT_BLOCK_SIZE: usize = 8192;
+
+/// Decoded indices and values of a block of a compressed (CSC or
+/// CSR) dataset
+pub struct CachedBlock {
+    pub indices: Vec<u64>,
+    pub data: Vec<f32>,
+}
+
+impl CachedBlock {
+    fn num_bytes(&self) -> usize {
+        self.indices.len() * std::mem::size_of::<u64>()
+            + self.data.len() * std::mem::size_of::<f32>()
+    }
+}
+
+/// (cache scope, dataset key, block index)
+type BlockKey = (u64, Box<str>, usize);
+
+#[derive(Default)]
+struct LruBlocks {
+    blocks: HashMap<BlockKey, (u64, Arc<CachedBlock>)>,
+    recency: BTreeMap<u64, BlockKey>,
+    tick: u64,
+    num_bytes: usize,
+}
+
+/// Blocks of one or more caches within one bound
+struct BlockStore {
+    max_bytes: AtomicUsize,
+    inner: Mutex<LruBlocks>,
+}
+
+impl BlockStore {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes: AtomicUsize::new(max_bytes),
+            inner: Mutex::new(LruBlocks::default()),
+        }
+    }
+}
+
+/// The store behind `ChunkCache::default()`, bounded for the whole
+/// process rather than per backend
+fn shared_store() -> &'static Arc<BlockStore> {
+    static SHARED: OnceLock<Arc<BlockStore>> = OnceLock::new();
+    SHARED.get_or_init(|| Arc::new(BlockStore::new(DEFAULT_CHUNK_CACHE_BYTES)))
+}
+
+/// Change the bound of the blocks shared by all the backends in this
+/// process (`DEFAULT_CHUNK_CACHE_BYTES` unless set); older blocks are
+/// dropped on the next insertion if over the new bound
+pub fn set_shared_chunk_cache_bytes(max_bytes: usize) {
+    shared_store().max_bytes.store(max_bytes, Ordering::Relaxed);
+}
+
+/// A bounded LRU cache of decoded blocks, aligned with the storage
+/// chunks, shared by the clones of a backend (and rayon workers)
+///
+/// ```text
+/// by_column/{data,indices}
+///     [ block 0 ][ block 1 ][ block 2 ] ...
+///         ^ cached    ^ cached
+/// ```
+///
+/// Blocks are keyed by the dataset key (e.g., `/by_column` of a
+/// layer) and the block index, so layers don't collide. Writers
+/// should `clear` the cache.
+///
+/// `ChunkCache::default()` caches of all the backends keep their
+/// blocks in one process-wide store (see
+/// `set_shared_chunk_cache_bytes`), so opening many files doesn't
+/// multiply the memory; `ChunkCache::new` has its own bound.
+pub struct ChunkCache {
+    scope: u64,
+    store: Arc<BlockStore>,
+}
+
+impl fmt::Debug for ChunkCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkCache")
+            .field("max_bytes", &self.max_bytes())
+            .field("num_bytes", &self.num_bytes())
+            .finish()
+    }
+}
+
+impl Default for ChunkCache {
+    fn default() -> Self {
+        Self::with_store(shared_store().clone())
+    }
+}
+
+impl ChunkCache {
+    /// * `max_bytes` - keep at most this many bytes of decoded blocks
+    pub fn new(max_bytes: usize) -> Self {
+        Self::with_store(Arc::new(BlockStore::new(max_bytes)))
+    }
+
+    fn with_store(store: Arc<BlockStore>) -> Self {
+        static NEXT_SCOPE: AtomicU64 = AtomicU64::new(0);
+        Self {
+            scope: NEXT_SCOPE.fetch_add(1, Ordering::Relaxed),
+            store,
+        }
+    }
+
+    /// Bound of the decoded blocks, shared with the other default
+    /// caches if this is one of them
+    pub fn max_bytes(&self) -> usize {
+        self.store.max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Bytes of the decoded blocks currently kept by this cache
+    pub fn num_bytes(&self) -> usize {
+        self.lock()
+            .blocks
+            .iter()
+            .filter(|(k, _)| k.0 == self.scope)
+            .map(|(_, (_, x))| x.num_bytes())
+            .sum()
+    }
+
+    /// Forget all the blocks of this cache, e.g., after the datasets
+    /// have changed
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        let lru = &mut *lru;
+        let scope = self.scope;
+        let mut freed = 0;
+        lru.blocks.retain(|k, (_, x)| {
+            let keep = k.0 != scope;
+            if !keep {
+                freed += x.num_bytes();
+            }
+            keep
+        });
+        lru.recency.retain(|_, k| k.0 != scope);
+        lru.num_bytes -= freed;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LruBlocks> {
+        self.store.inner.lock().expect("failed to lock chunk cache")
+    }
+
+    fn get(&self, key: &str, block: usize) -> Option<Arc<CachedBlock>> {
+        let mut lru = self.lock();
+        let block_key: BlockKey = (self.scope, key.into(), block);
+        lru.tick += 1;
+        let tick = lru.tick;
+        let (old_tick, ret) = match lru.blocks.get_mut(&block_key) {
+            Some((stamp, ret)) => (std::mem::replace(stamp, tick), ret.clone()),
+            None => return None,
+        };
+        lru.recency.remove(&old_tick);
+        lru.recency.insert(tick, block_key);
+        Some(ret)
+    }
+
+    fn insert(&self, key: &str, block: usize, value: CachedBlock) -> Arc<CachedBlock> {
+        let value = Arc::new(value);
+        let nbytes = value.num_bytes();
+        let max_bytes = self.max_bytes();
+        if nbytes > max_bytes {
+            return value;
+        }
+
+        let mut lru = self.lock();
+        lru.tick += 1;
+        let tick = lru.tick;
+        let block_key: BlockKey = (self.scope, key.into(), block);
+
+        if let Some((old_tick, old)) = lru.blocks.insert(block_key.clone(), (tick, value.clone())) {
+            lru.recency.remove(&old_tick);
+            lru.num_bytes -= old.num_bytes();
+        }
+        lru.recency.insert(tick, block_key);
+        lru.num_bytes += nbytes;
+
+        while lru.num_bytes > max_bytes {
+            let Some((_, oldest)) = lru.recency.pop_first() else {
+                break;
+            };
+            if let Some((_, old)) = lru.blocks.remove(&oldest) {
+                lru.num_bytes -= old.num_bytes();
+            }
+        }
+        value
+    }
+}
+
+/// Read the triplets of the requested columns (rows) of a
+/// compressed dataset through the cache
+///
+/// Blocks are visited in the sorted order and consecutive missing
+/// blocks are fetched in one `load` call, while the triplets come
+/// out in the requested order.
+///
+/// * `cache` - shared block cache
+/// * `key` - dataset key, e.g., `/by_column` (of a layer)
+/// * `indptr` - pointers of the compressed dataset
+/// * `outer` - columns (rows) to read; out-of-range ones are skipped
+/// * `block_size` - elements per block, ideally the storage chunk size
+/// * `load` - read indices and values within `[lb, ub)`
+///
+/// Returns (inner index, position in `outer`, value) triplets
+pub fn read_compressed_through_cache<F>(
+    cache: &ChunkCache,
+    key: &str,
+    indptr: &[u64],
+    outer: &[usize],
+    block_size: usize,
+    load: F,
+) -> anyhow::Result<Vec<(u64, u64, f32)>>
+where
+    F: Fn(Range<usize>) -> anyhow::Result<(Vec<u64>, Vec<f32>)>,
+{
+    let mut ret = Vec::with_capacity(count_elements(indptr, outer));
+    visit_compressed_through_cache(
+        cache,
+        key,
+        indptr,
+        outer,
+        block_size,
+        load,
+        |jj, indices, data| {
+            let jj = jj as u64;
+            for (&ii, &x) in indices.iter().zip(data.iter()) {
+                ret.push((ii, jj, x));
+            }
+        },
+    )?;
+    Ok(ret)
+}
+
+/// Like `read_compressed_through_cache`, but assemble the offsets,
+/// indices and values of the requested columns (rows) directly
+pub fn read_compressed_parts_through_cache<F>(
+    cache: &ChunkCache,
+    key: &str,
+    indptr: &[u64],
+    outer: &[usize],
+    block_size: usize,
+    load: F,
+) -> anyhow::Result<CompressedParts>
+where
+    F: Fn(Range<usize>) -> anyhow::Result<(Vec<u64>, Vec<f32>)>,
+{
+    let mut ret = CompressedParts::with_capacity(outer.len(), count_elements(indptr, outer));
+    visit_compressed_through_cache(
+        cache,
+        key,
+        indptr,
+        outer,
+        block_size,
+        load,
+        |jj, indices, data| ret.append(jj, indices, data),
+    )?;
+    Ok(ret)
+}
+
+fn count_elements(indptr: &[u64], outer: &[usize]) -> usize {
+    let nouter = indptr.len().saturating_sub(1);
+    outer
+        .iter()
+        .filter(|&&j| j < nouter)
+        .map(|&j| (indptr[j + 1] - indptr[j]) as usize)
+        .sum()
+}
+
+/// Visit the requested columns (rows) in order, calling `visit` with
+/// the position in `outer` and consecutive pieces of the column
+/// (row), one piece per block it spans
+fn visit_compressed_through_cache<F, V>(
+    cache: &ChunkCache,
+    key: &str,
+    indptr: &[u64],
+    outer: &[usize],
+    block_size: usize,
+    load: F,
+    mut visit: V,
+) -> anyhow::Result<()>
+where
+    F: Fn(Range<usize>) -> anyhow::Result<(Vec<u64>, Vec<f32>)>,
+    V: FnMut(usize, &[u64], &[f32]),
+{
+    let nouter = indptr.len().saturating_sub(1);
+    let nelem = indptr.last().copied().unwrap_or(0) as usize;
+    let block_size = block_size.max(1);
+
+    let range_of = |j: usize| indptr[j] as usize..indptr[j + 1] as usize;
+
+    // blocks needed by the requested columns in the sorted order
+    let needed: BTreeSet<usize> = outer
+        .iter()
+        .filter(|&&j| j < nouter)
+        .map(|&j| range_of(j))
+        .filter(|r| r.start < r.end)
+        .flat_map(|r| (r.start / block_size)..=((r.end - 1) / block_size))
+        .collect();
+
+    let mut blocks: HashMap<usize, Arc<CachedBlock>> = HashMap::with_capacity(needed.len());
+    let mut missing: Vec<usize> = vec![];
+    for &b in needed.iter() {
+        match cache.get(key, b) {
+            Some(block) => {
+                blocks.insert(b, block);
+            }
+            None => missing.push(b),
+        }
+    }
+
+    // coalesce consecutive missing blocks into one read
+    for run in missing.chunk_by(|a, b| a + 1 == *b) {
+        let (first, last) = (run[0], run[run.len() - 1]);
+        let lb = first * block_size;
+        let ub = ((last + 1) * block_size).min(nelem);
+        let (indices, data) = load(lb..ub)?;
+
+        if indices.len() != ub - lb || data.len() != ub - lb {
+            return Err(anyhow::anyhow!(
+                "expected {} elements of {}, but got {} indices and {} values",
+                ub - lb,
+                key,
+                indices.len(),
+                data.len()
+            ));
+        }
+
+        for (b, (indices, data)) in
+            (first..=last).zip(indices.chunks(block_size).zip(data.chunks(block_size)))
+        {
+            let block = CachedBlock {
+                indices: indices.to_vec(),
+                data: data.to_vec(),
+            };
+            blocks.insert(b, cache.insert(key, b, block));
+        }
+    }
+
+    for (jj, &j) in outer.iter().enumerate() {
+        if j >= nouter {
+            continue;
+        }
+        let mut pos = indptr[j] as usize;
+        let end = indptr[j + 1] as usize;
+        while pos < end {
+            let block = &blocks[&(pos / block_size)];
+            let lb = pos % block_size;
+            let ub = (end - pos + lb).min(block.data.len());
+            visit(jj, &block.indices[lb..ub], &block.data[lb..ub]);
+            pos += ub - lb;
+        }
+    }
+    Ok(())
+}
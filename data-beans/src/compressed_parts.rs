@@ -0,0 +1,228 @@
+use crate::sparse_io::*;
+
+use candle_util::candle_core::Device;
+use nalgebra_sparse::CooMatrix;
+use std::ops::Range;
+
+/// Offsets, inner indices and values of a compressed sparse matrix
+/// assembled outer slice by outer slice (columns of CSC, rows of
+/// CSR), without going through `(row, column, value)` triplets
+///
+/// ```text
+/// stored indptr:  [ 0, 3, 3, 5, 9 ]     requested: [3, 0]
+/// offsets:        [ 0, 4, 7 ]           (indptr[4] - indptr[3], ...)
+/// indices/values: [ data[5..9] ][ data[0..3] ]
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompressedParts {
+    offsets: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f32>,
+}
+
+impl Default for CompressedParts {
+    fn default() -> Self {
+        Self::with_capacity(0, 0)
+    }
+}
+
+impl CompressedParts {
+    /// * `nouter` - expected number of outer slices
+    /// * `nnz` - expected number of non-zero elements
+    pub fn with_capacity(nouter: usize, nnz: usize) -> Self {
+        let mut offsets = Vec::with_capacity(nouter + 1);
+        offsets.push(0);
+        Self {
+            offsets,
+            indices: Vec::with_capacity(nnz),
+            values: Vec::with_capacity(nnz),
+        }
+    }
+
+    /// Take the outer slices of compressed arrays kept at hand
+    ///
+    /// * `indptr` - pointers of the compressed arrays
+    /// * `indices` - inner indices
+    /// * `values` - values
+    /// * `outer` - outer slices to take; out-of-range ones are empty
+    pub fn from_slices<I>(indptr: &[u64], indices: &[u64], values: &[f32], outer: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let nouter = indptr.len().saturating_sub(1);
+        let outer = outer.into_iter();
+        let mut ret = Self::with_capacity(outer.size_hint().0, 0);
+        for (pos, j) in outer.enumerate() {
+            if j < nouter {
+                let range = indptr[j] as usize..indptr[j + 1] as usize;
+                ret.append(pos, &indices[range.clone()], &values[range]);
+            }
+        }
+        ret
+    }
+
+    /// Take a contiguous range of outer slices with one `load` call
+    ///
+    /// * `indptr` - pointers of the compressed arrays
+    /// * `outer` - range of outer slices, clipped to `indptr`
+    /// * `load` - read indices and values within `[lb, ub)`
+    pub fn from_contiguous<F>(indptr: &[u64], outer: Range<usize>, load: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce(Range<usize>) -> anyhow::Result<(Vec<u64>, Vec<f32>)>,
+    {
+        let nouter = indptr.len().saturating_sub(1);
+        let (lb, ub) = (outer.start.min(nouter), outer.end.min(nouter));
+        if lb >= ub {
+            return Ok(Self::default());
+        }
+
+        let start = indptr[lb];
+        let end = indptr[ub];
+        let (indices, values) = load(start as usize..end as usize)?;
+
+        if indices.len() as u64 != end - start || values.len() as u64 != end - start {
+            return Err(anyhow::anyhow!(
+                "expected {} elements, but got {} indices and {} values",
+                end - start,
+                indices.len(),
+                values.len()
+            ));
+        }
+
+        Ok(Self {
+            offsets: indptr[lb..=ub]
+                .iter()
+                .map(|&p| (p - start) as usize)
+                .collect(),
+            indices: indices.into_iter().map(|i| i as usize).collect(),
+            values,
+        })
+    }
+
+    /// Append elements to the outer slice `outer`, closing the ones
+    /// before it. Outer slices should come in the increasing order.
+    pub fn append(&mut self, outer: usize, indices: &[u64], values: &[f32]) {
+        debug_assert_eq!(indices.len(), values.len());
+        self.close_until(outer);
+        self.indices.extend(indices.iter().map(|&i| i as usize));
+        self.values.extend_from_slice(values);
+    }
+
+    /// Append (inner index, value) pairs to the outer slice `outer`
+    pub fn append_entries<I>(&mut self, outer: usize, entries: I)
+    where
+        I: IntoIterator<Item = (usize, f32)>,
+    {
+        self.close_until(outer);
+        for (i, x) in entries {
+            self.indices.push(i);
+            self.values.push(x);
+        }
+    }
+
+    fn close_until(&mut self, outer: usize) {
+        debug_assert!(outer + 1 >= self.offsets.len());
+        while self.offsets.len() <= outer {
+            self.offsets.push(self.indices.len());
+        }
+    }
+
+    /// Columns of a `nrow x ncol` CSC matrix
+    pub fn into_csc(mut self, nrow: usize, ncol: usize) -> anyhow::Result<CscMatrix<f32>> {
+        self.close_until(ncol);
+        if self.is_sorted_within(nrow)? {
+            Ok(
+                CscMatrix::try_from_csc_data(nrow, ncol, self.offsets, self.indices, self.values)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?,
+            )
+        } else {
+            let mut coo = CooMatrix::new(nrow, ncol);
+            for (j, i, x) in self.iter_entries() {
+                coo.push(i, j, x);
+            }
+            Ok(CscMatrix::from(&coo))
+        }
+    }
+
+    /// Rows of a `nrow x ncol` CSR matrix
+    pub fn into_csr(mut self, nrow: usize, ncol: usize) -> anyhow::Result<CsrMatrix<f32>> {
+        self.close_until(nrow);
+        if self.is_sorted_within(ncol)? {
+            Ok(
+                CsrMatrix::try_from_csr_data(nrow, ncol, self.offsets, self.indices, self.values)
+                    .map_err(|e| anyhow::anyhow!("{}", e))?,
+            )
+        } else {
+            let mut coo = CooMatrix::new(nrow, ncol);
+            for (i, j, x) in self.iter_entries() {
+                coo.push(i, j, x);
+            }
+            Ok(CsrMatrix::from(&coo))
+        }
+    }
+
+    /// Check the inner indices are within `[0, ninner)` and strictly
+    /// increasing in each outer slice; if not, duplicates are summed
+    /// up through a COO matrix like `from_nonzero_triplets` does
+    fn is_sorted_within(&self, ninner: usize) -> anyhow::Result<bool> {
+        if let Some(&i) = self.indices.iter().find(|&&i| i >= ninner) {
+            return Err(anyhow::anyhow!(
+                "inner index {} is out of bound {}",
+                i,
+                ninner
+            ));
+        }
+        Ok(self
+            .offsets
+            .windows(2)
+            .all(|w| self.indices[w[0]..w[1]].windows(2).all(|x| x[0] < x[1])))
+    }
+
+    /// (outer, inner, value) entries
+    fn iter_entries(&self) -> impl Iterator<Item = (usize, usize, f32)> + '_ {
+        self.offsets
+            .windows(2)
+            .enumerate()
+            .flat_map(move |(j, w)| (w[0]..w[1]).map(move |k| (j, self.indices[k], self.values[k])))
+    }
+}
+
+/// Fill in a dense row-major buffer from (row, column, value) entries
+fn row_major_buffer<I>(nrow: usize, ncol: usize, entries: I) -> Vec<f32>
+where
+    I: Iterator<Item = (usize, usize, f32)>,
+{
+    let mut ret = vec![0_f32; nrow * ncol];
+    for (i, j, x) in entries {
+        ret[i * ncol + j] += x;
+    }
+    ret
+}
+
+/// Dense `ndarray::Array2` of a CSC matrix
+pub fn csc_to_ndarray(csc: &CscMatrix<f32>) -> anyhow::Result<Array2<f32>> {
+    let (nrow, ncol) = (csc.nrows(), csc.ncols());
+    let buffer = row_major_buffer(nrow, ncol, csc.triplet_iter().map(|(i, j, &x)| (i, j, x)));
+    Ok(Array2::from_shape_vec((nrow, ncol), buffer)?)
+}
+
+/// Dense `candle_core::Tensor` of a CSC matrix
+pub fn csc_to_tensor(csc: &CscMatrix<f32>) -> anyhow::Result<Tensor> {
+    let (nrow, ncol) = (csc.nrows(), csc.ncols());
+    let buffer = row_major_buffer(nrow, ncol, csc.triplet_iter().map(|(i, j, &x)| (i, j, x)));
+    Ok(Tensor::from_vec(buffer, (nrow, ncol), &Device::Cpu)?)
+}
+
+/// Dense `ndarray::Array2` of a CSR matrix
+pub fn csr_to_ndarray(csr: &CsrMatrix<f32>) -> anyhow::Result<Array2<f32>> {
+    let (nrow, ncol) = (csr.nrows(), csr.ncols());
+    let buffer = row_major_buffer(nrow, ncol, csr.triplet_iter().map(|(i, j, &x)| (i, j, x)));
+    Ok(Array2::from_shape_vec((nrow, ncol), buffer)?)
+}
+
+/// Dense `candle_core::Tensor` of a CSR matrix
+pub fn csr_to_tensor(csr: &CsrMatrix<f32>) -> anyhow::Result<Tensor> {
+    let (nrow, ncol) = (csr.nrows(), csr.ncols());
+    let buffer = row_major_buffer(nrow, ncol, csr.triplet_iter().map(|(i, j, &x)| (i, j, x)));
+    Ok(Tensor::from_vec(buffer, (nrow, ncol), &Device::Cpu)?)
+}
@@ -0,0 +1,300 @@
+use crate::sparse_data_visitors::*;
+use crate::sparse_io::ROW_SEP;
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::info;
+use matrix_util::common_io::write_lines;
+use matrix_util::parquet::write_long_format_parquet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+/// Feature type written in the third column of `features.tsv.gz`
+/// for rows without a registered feature type
+const TENX_FEATURE_TYPE: &str = "Gene Expression";
+
+/// Feature types whose words may trail a row name imported from a
+/// three-column `features.tsv.gz` without registering them
+const TENX_FEATURE_TYPES: [&str; 4] = [
+    "Gene Expression",
+    "Antibody Capture",
+    "CRISPR Guide Capture",
+    "Multiplexing Capture",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+#[clap(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// long-format (row, column, value) parquet files, one for each
+    /// block of columns
+    Parquet,
+    /// 10x directory with `matrix.mtx.gz`, `features.tsv.gz`, and
+    /// `barcodes.tsv.gz`
+    #[value(name = "10x")]
+    TenX,
+}
+
+type NamesDir<'a> = (&'a [Box<str>], &'a [Box<str>], &'a str);
+
+/// (first column, part file)
+type PartFiles = Vec<(usize, Box<str>)>;
+
+/// (first column, number of non-zeros, gzip member file)
+type MtxParts = Vec<(usize, usize, Box<str>)>;
+
+/// Write the non-zero elements in the long format, streaming one
+/// parquet file per block of columns
+///
+/// ```text
+/// {output_dir}/part-0000000000.parquet    columns [0, block_size)
+/// {output_dir}/part-0000001000.parquet    columns [block_size, ...)
+///
+/// row     column     value
+/// ACTB    AAACCT-1   3.0
+/// ```
+///
+/// Returns the part files in the order of columns.
+///
+/// * `data` - data to visit by blocks of columns
+/// * `row_names` - row (feature) names
+/// * `column_names` - column (cell) names
+/// * `output_dir` - output directory; previous part files are removed
+/// * `block_size` - number of columns per part file
+pub fn export_parquet_long<D>(
+    data: &D,
+    row_names: &[Box<str>],
+    column_names: &[Box<str>],
+    output_dir: &str,
+    block_size: Option<usize>,
+) -> anyhow::Result<Vec<Box<str>>>
+where
+    D: ReadColumnsOps,
+{
+    check_names(data, row_names, column_names)?;
+    prepare_output_dir(output_dir, |name| {
+        name.starts_with("part-") && name.ends_with(".parquet")
+    })?;
+
+    let mut part_files: PartFiles = vec![];
+    data.visit_columns_by_block(
+        &parquet_block_visitor,
+        &(row_names, column_names, output_dir),
+        &mut part_files,
+        block_size,
+    )?;
+
+    part_files.sort();
+    info!("wrote {} parquet files in {}", part_files.len(), output_dir);
+    Ok(part_files.into_iter().map(|(_, file)| file).collect())
+}
+
+fn parquet_block_visitor<D>(
+    (lb, ub): (usize, usize),
+    data: &D,
+    names: &NamesDir,
+    part_files: Arc<Mutex<&mut PartFiles>>,
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
+    let (row_names, column_names, output_dir) = *names;
+    let x_nm = data.read_columns_csc(lb..ub)?;
+
+    let part_file = format!("{}/part-{:010}.parquet", output_dir, lb);
+    write_long_format_parquet(
+        &part_file,
+        x_nm.triplet_iter()
+            .map(|(i, j, &x)| (row_names[i].as_ref(), column_names[lb + j].as_ref(), x)),
+    )?;
+
+    part_files
+        .lock()
+        .expect("failed to lock part files")
+        .push((lb, part_file.into_boxed_str()));
+    Ok(())
+}
+
+/// Write a complete 10x directory, streaming the matrix by blocks
+/// of columns
+///
+/// ```text
+/// {output_dir}
+///     ├── matrix.mtx.gz     (1-based, column by column)
+///     ├── features.tsv.gz   (id, name, feature type)
+///     └── barcodes.tsv.gz
+/// ```
+///
+/// Each block is compressed as a gzip member of its own and the
+/// members are concatenated after the header, which `zcat` and other
+/// gzip readers read through as one stream. A row name `{id}_{name}`
+/// is split at the first `_` to give the first two columns of the
+/// features, dropping the words of the feature type that may trail
+/// the name (`ENSG00000075624_ACTB_Gene_Expression`); a name without
+/// `_` is used for both.
+///
+/// * `data` - data to visit by blocks of columns
+/// * `row_names` - row (feature) names
+/// * `column_names` - column (cell) names, i.e., barcodes
+/// * `feature_types` - feature type of each row (`Gene Expression`
+///   for all if `None`)
+/// * `output_dir` - output directory
+/// * `block_size` - number of columns per block
+pub fn export_10x<D>(
+    data: &D,
+    row_names: &[Box<str>],
+    column_names: &[Box<str>],
+    feature_types: Option<&[Box<str>]>,
+    output_dir: &str,
+    block_size: Option<usize>,
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
+    check_names(data, row_names, column_names)?;
+    if let Some(feature_types) = feature_types {
+        if feature_types.len() != row_names.len() {
+            return Err(anyhow::anyhow!(
+                "{} feature types for {} rows",
+                feature_types.len(),
+                row_names.len()
+            ));
+        }
+    }
+    prepare_output_dir(output_dir, |name| {
+        name.starts_with(".matrix-") && name.ends_with(".mtx.gz")
+    })?;
+
+    let mut parts: MtxParts = vec![];
+    data.visit_columns_by_block(&mtx_block_visitor, &output_dir, &mut parts, block_size)?;
+    parts.sort();
+
+    let nnz: usize = parts.iter().map(|&(_, nnz, _)| nnz).sum();
+    let (nrow, ncol) = (data.num_rows()?, data.num_columns()?);
+
+    let matrix_file = format!("{}/matrix.mtx.gz", output_dir);
+    let mut matrix = BufWriter::new(File::create(&matrix_file)?);
+
+    let mut header = GzEncoder::new(vec![], Compression::default());
+    writeln!(header, "%%MatrixMarket matrix coordinate real general")?;
+    writeln!(header, "{}\t{}\t{}", nrow, ncol, nnz)?;
+    matrix.write_all(&header.finish()?)?;
+
+    for (_, _, part_file) in parts {
+        std::io::copy(&mut File::open(part_file.as_ref())?, &mut matrix)?;
+        std::fs::remove_file(part_file.as_ref())?;
+    }
+    matrix.flush()?;
+    info!("wrote {} x {} with {} non-zeros", nrow, ncol, nnz);
+
+    let features: Vec<Box<str>> = row_names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let (id, symbol) = match feature_types {
+                Some(feature_types) => tenx_feature(name, &[feature_types[i].as_ref()]),
+                None => tenx_feature(name, &TENX_FEATURE_TYPES),
+            };
+            let feature_type = feature_types.map_or(TENX_FEATURE_TYPE, |x| x[i].as_ref());
+            format!("{}\t{}\t{}", id, symbol, feature_type).into_boxed_str()
+        })
+        .collect();
+    write_lines(&features, &format!("{}/features.tsv.gz", output_dir))?;
+    write_lines(
+        &column_names.to_vec(),
+        &format!("{}/barcodes.tsv.gz", output_dir),
+    )?;
+
+    info!("wrote a 10x directory {}", output_dir);
+    Ok(())
+}
+
+/// (id, symbol) of a row name `{id}_{symbol}`, where the symbol may
+/// be followed by some words of its feature type
+/// * `name` - row name
+/// * `feature_types` - feature types to strip off the symbol
+fn tenx_feature<'a>(name: &'a str, feature_types: &[&str]) -> (&'a str, &'a str) {
+    let Some((id, rest)) = name.split_once(ROW_SEP) else {
+        return (name, name);
+    };
+
+    // the longest run of leading words, e.g., `_Gene_Expression`
+    // or `_Gene` when the name was cut short on import
+    let symbol = feature_types
+        .iter()
+        .find_map(|feature_type| {
+            let words: Vec<&str> = feature_type.split_whitespace().collect();
+            (1..=words.len()).rev().find_map(|k| {
+                rest.strip_suffix(&format!("{}{}", ROW_SEP, words[..k].join(ROW_SEP)))
+            })
+        })
+        .filter(|symbol| !symbol.is_empty())
+        .unwrap_or(rest);
+    (id, symbol)
+}
+
+fn mtx_block_visitor<D>(
+    (lb, ub): (usize, usize),
+    data: &D,
+    output_dir: &&str,
+    parts: Arc<Mutex<&mut MtxParts>>,
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
+    let x_nm = data.read_columns_csc(lb..ub)?;
+
+    let part_file = format!("{}/.matrix-{:010}.mtx.gz", output_dir, lb);
+    let mut buf = GzEncoder::new(
+        BufWriter::new(File::create(&part_file)?),
+        Compression::default(),
+    );
+
+    // write them with 1-based indices
+    for (j, x_j) in x_nm.col_iter().enumerate() {
+        for (&i, &x) in x_j.row_indices().iter().zip(x_j.values()) {
+            writeln!(buf, "{}\t{}\t{}", i + 1, lb + j + 1, x)?;
+        }
+    }
+    buf.finish()?.flush()?;
+
+    parts
+        .lock()
+        .expect("failed to lock parts")
+        .push((lb, x_nm.nnz(), part_file.into_boxed_str()));
+    Ok(())
+}
+
+fn check_names<D>(data: &D, row_names: &[Box<str>], column_names: &[Box<str>]) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
+    let (nrow, ncol) = (data.num_rows()?, data.num_columns()?);
+    if row_names.len() != nrow || column_names.len() != ncol {
+        return Err(anyhow::anyhow!(
+            "{} x {} names for {} x {} data",
+            row_names.len(),
+            column_names.len(),
+            nrow,
+            ncol
+        ));
+    }
+    Ok(())
+}
+
+/// Create the output directory and remove the files left by a
+/// previous export
+fn prepare_output_dir<F>(output_dir: &str, is_stale: F) -> anyhow::Result<()>
+where
+    F: Fn(&str) -> bool,
+{
+    std::fs::create_dir_all(output_dir)?;
+    for entry in std::fs::read_dir(output_dir)? {
+        let entry = entry?;
+        if entry.file_name().to_str().is_some_and(&is_stale) {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
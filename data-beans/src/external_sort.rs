@@ -0,0 +1,377 @@
+use crate::sparse_io::ValueType;
+use log::info;
+use matrix_util::common_io::open_buf_reader;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// Number of elements to be recorded in the backend at once
+pub const STREAM_CHUNK_SIZE: usize = 1 << 20;
+
+/// Never spill runs smaller than this many triplets
+const MIN_RUN_SIZE: usize = 1 << 16;
+
+/// (row, column, value): 8 + 8 + 4 bytes on disk
+const TRIPLET_BYTES: usize = 20;
+
+/// Merge at most this many runs at once, not to run out of file
+/// descriptors; more runs are merged in several passes
+const MAX_FAN_IN: usize = 64;
+
+/// (row, column, value)
+pub type Triplet = (u64, u64, f32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    /// sort by (column, row) for CSC
+    ByColumn,
+    /// sort by (row, column) for CSR
+    ByRow,
+}
+
+impl SortOrder {
+    fn key(&self, x: &Triplet) -> (u64, u64) {
+        match self {
+            SortOrder::ByColumn => (x.1, x.0),
+            SortOrder::ByRow => (x.0, x.1),
+        }
+    }
+}
+
+/// Sort triplets within a memory budget: sorted runs are spilled to
+/// temporary files and merged back lazily.
+pub struct ExternalSorter {
+    order: SortOrder,
+    max_run_size: usize,
+    buffer: Vec<Triplet>,
+    runs: Vec<PathBuf>,
+    nrun_files: usize,
+    max_fan_in: usize,
+    temp_dir: Arc<TempDir>,
+    len: usize,
+}
+
+impl ExternalSorter {
+    /// * `order` - sort by column or row
+    /// * `memory_budget` - bytes to hold in memory before spilling
+    /// * `temp_dir` - where sorted runs are written
+    pub fn new(order: SortOrder, memory_budget: usize, temp_dir: Arc<TempDir>) -> Self {
+        let max_run_size = (memory_budget / std::mem::size_of::<Triplet>()).max(MIN_RUN_SIZE);
+        Self {
+            order,
+            max_run_size,
+            buffer: vec![],
+            runs: vec![],
+            nrun_files: 0,
+            max_fan_in: MAX_FAN_IN,
+            temp_dir,
+            len: 0,
+        }
+    }
+
+    /// A sorter spilling runs to its own temporary directory
+    /// * `order` - sort by column or row
+    /// * `memory_budget` - bytes to hold in memory before spilling
+    pub fn with_temp_dir(order: SortOrder, memory_budget: usize) -> anyhow::Result<Self> {
+        let temp_dir = Arc::new(tempfile::tempdir()?);
+        Ok(Self::new(order, memory_budget, temp_dir))
+    }
+
+    /// Merge at most `max_fan_in` (at least 2) runs at once
+    #[allow(dead_code)]
+    pub fn set_max_fan_in(&mut self, max_fan_in: usize) {
+        self.max_fan_in = max_fan_in.max(2);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push(&mut self, triplet: Triplet) -> anyhow::Result<()> {
+        self.buffer.push(triplet);
+        self.len += 1;
+        if self.buffer.len() >= self.max_run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn sort_buffer(&mut self) {
+        let order = self.order;
+        self.buffer.par_sort_by_key(|x| order.key(x));
+    }
+
+    fn spill(&mut self) -> anyhow::Result<()> {
+        self.sort_buffer();
+        let buffer = std::mem::take(&mut self.buffer);
+        let run_file = self.write_run(buffer.iter().map(|&x| Ok(x)))?;
+
+        info!(
+            "spilled {} sorted triplets to {}",
+            buffer.len(),
+            run_file.display()
+        );
+
+        self.buffer = buffer;
+        self.buffer.clear();
+        self.runs.push(run_file);
+        Ok(())
+    }
+
+    /// Write sorted triplets to a new run file
+    fn write_run<I>(&mut self, triplets: I) -> anyhow::Result<PathBuf>
+    where
+        I: Iterator<Item = anyhow::Result<Triplet>>,
+    {
+        let run_file = self
+            .temp_dir
+            .path()
+            .join(format!("{:?}_{}.run", self.order, self.nrun_files));
+        self.nrun_files += 1;
+
+        let mut buf = BufWriter::new(File::create(&run_file)?);
+        for x in triplets {
+            let (i, j, x) = x?;
+            buf.write_all(&i.to_le_bytes())?;
+            buf.write_all(&j.to_le_bytes())?;
+            buf.write_all(&x.to_le_bytes())?;
+        }
+        buf.flush()?;
+        Ok(run_file)
+    }
+
+    /// Merge all the sorted runs (files and what is left in memory),
+    /// first merging the files into fewer, longer runs as long as
+    /// there are more than `max_fan_in` of them
+    pub fn into_sorted(mut self) -> anyhow::Result<MergedTriplets> {
+        self.sort_buffer();
+
+        while self.runs.len() >= self.max_fan_in {
+            let runs = std::mem::take(&mut self.runs);
+            info!("merging {} runs {} at a time", runs.len(), self.max_fan_in);
+            for group in runs.chunks(self.max_fan_in) {
+                if let [run] = group {
+                    self.runs.push(run.clone());
+                    continue;
+                }
+                let merged = MergedTriplets::new(self.order, open_runs(group)?, None)?;
+                let run_file = self.write_run(merged)?;
+                for run in group {
+                    std::fs::remove_file(run)?;
+                }
+                self.runs.push(run_file);
+            }
+        }
+
+        let mut sources = open_runs(&self.runs)?;
+        sources.push(RunSource::Memory(
+            std::mem::take(&mut self.buffer).into_iter(),
+        ));
+        MergedTriplets::new(self.order, sources, Some(self.temp_dir))
+    }
+}
+
+fn open_runs(runs: &[PathBuf]) -> anyhow::Result<Vec<RunSource>> {
+    runs.iter()
+        .map(|run| Ok(RunSource::File(BufReader::new(File::open(run)?))))
+        .collect()
+}
+
+enum RunSource {
+    File(BufReader<File>),
+    Memory(std::vec::IntoIter<Triplet>),
+}
+
+impl RunSource {
+    fn next_triplet(&mut self) -> anyhow::Result<Option<Triplet>> {
+        match self {
+            RunSource::File(reader) => {
+                let mut bytes = [0_u8; TRIPLET_BYTES];
+                match reader.read_exact(&mut bytes) {
+                    Ok(()) => {
+                        let i = u64::from_le_bytes(bytes[0..8].try_into()?);
+                        let j = u64::from_le_bytes(bytes[8..16].try_into()?);
+                        let x = f32::from_le_bytes(bytes[16..20].try_into()?);
+                        Ok(Some((i, j, x)))
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            RunSource::Memory(iter) => Ok(iter.next()),
+        }
+    }
+}
+
+/// k-way merge over sorted runs
+pub struct MergedTriplets {
+    order: SortOrder,
+    sources: Vec<RunSource>,
+    heads: Vec<Option<Triplet>>,
+    heap: BinaryHeap<Reverse<((u64, u64), usize)>>,
+    _temp_dir: Option<Arc<TempDir>>,
+}
+
+impl MergedTriplets {
+    /// * `temp_dir` - kept until the merge is done, if given
+    fn new(
+        order: SortOrder,
+        mut sources: Vec<RunSource>,
+        temp_dir: Option<Arc<TempDir>>,
+    ) -> anyhow::Result<Self> {
+        let mut heads = vec![None; sources.len()];
+        let mut heap = BinaryHeap::new();
+        for (r, source) in sources.iter_mut().enumerate() {
+            if let Some(x) = source.next_triplet()? {
+                heap.push(Reverse((order.key(&x), r)));
+                heads[r] = Some(x);
+            }
+        }
+
+        Ok(Self {
+            order,
+            sources,
+            heads,
+            heap,
+            _temp_dir: temp_dir,
+        })
+    }
+}
+
+impl Iterator for MergedTriplets {
+    type Item = anyhow::Result<Triplet>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, r)) = self.heap.pop()?;
+        let ret = self.heads[r].take()?;
+        match self.sources[r].next_triplet() {
+            Ok(Some(x)) => {
+                self.heap.push(Reverse((self.order.key(&x), r)));
+                self.heads[r] = Some(x);
+            }
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(ret))
+    }
+}
+
+/// Triplets sorted both by column (CSC) and by row (CSR) within a
+/// memory budget shared by the two sorters
+pub struct ExternalTriplets {
+    by_column: ExternalSorter,
+    by_row: ExternalSorter,
+    value_type: ValueType,
+}
+
+impl ExternalTriplets {
+    /// * `memory_budget` - total bytes to hold triplets in memory
+    pub fn new(memory_budget: usize) -> anyhow::Result<Self> {
+        let temp_dir = Arc::new(tempfile::tempdir()?);
+        let budget = memory_budget / 2;
+        Ok(Self {
+            by_column: ExternalSorter::new(SortOrder::ByColumn, budget, temp_dir.clone()),
+            by_row: ExternalSorter::new(SortOrder::ByRow, budget, temp_dir),
+            value_type: ValueType::U16,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_column.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_column.is_empty()
+    }
+
+    /// The narrowest type to store the values pushed so far
+    pub fn value_type(&self) -> ValueType {
+        self.value_type
+    }
+
+    pub fn push(&mut self, triplet: Triplet) -> anyhow::Result<()> {
+        self.value_type = self.value_type.widen(triplet.2);
+        self.by_column.push(triplet)?;
+        self.by_row.push(triplet)
+    }
+
+    /// Returns sorted iterators (by column, by row)
+    pub fn into_sorted(self) -> anyhow::Result<(MergedTriplets, MergedTriplets)> {
+        Ok((self.by_column.into_sorted()?, self.by_row.into_sorted()?))
+    }
+}
+
+/// Stream a matrix market file without holding all the triplets
+/// in memory and return the shape in the header
+///
+/// * `mtx_file` - matrix market file (gzipped or not)
+/// * `visitor` - called for each 0-based (row, column, value)
+pub fn visit_mtx_triplets(
+    mtx_file: &str,
+    visitor: &mut dyn FnMut(Triplet) -> anyhow::Result<()>,
+) -> anyhow::Result<(usize, usize, usize)> {
+    let buf = open_buf_reader(mtx_file)?;
+    let mut mtx_shape = None;
+
+    for (lineno, line) in buf.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('%') || line.trim().is_empty() {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+
+        let (nrow, ncol, _) = match mtx_shape {
+            Some(shape) => shape,
+            None => {
+                let mut next = || -> anyhow::Result<usize> {
+                    Ok(words
+                        .next()
+                        .ok_or(anyhow::anyhow!("Failed to parse mtx header"))?
+                        .parse::<usize>()?)
+                };
+                mtx_shape = Some((next()?, next()?, next()?));
+                continue;
+            }
+        };
+
+        let (Some(i), Some(j), Some(x)) = (words.next(), words.next(), words.next()) else {
+            return Err(anyhow::anyhow!(
+                "{} line {}: expected row, column, and value",
+                mtx_file,
+                lineno + 1
+            ));
+        };
+
+        // 1-based indices within the shape in the header
+        let index = |k: &str, n: usize| -> anyhow::Result<u64> {
+            match k.parse::<u64>() {
+                Ok(k) if k >= 1 && k <= n as u64 => Ok(k - 1),
+                _ => Err(anyhow::anyhow!(
+                    "{} line {}: index `{}` out of 1..={}",
+                    mtx_file,
+                    lineno + 1,
+                    k,
+                    n
+                )),
+            }
+        };
+        let i = index(i, nrow)?;
+        let j = index(j, ncol)?;
+        let x = x
+            .parse::<f32>()
+            .map_err(|e| anyhow::anyhow!("{} line {}: {}", mtx_file, lineno + 1, e))?;
+        visitor((i, j, x))?;
+    }
+
+    mtx_shape.ok_or(anyhow::anyhow!("Failed to parse mtx header"))
+}
@@ -0,0 +1,237 @@
+use crate::annotation::{AnnotationAxis, AnnotationColumn};
+use crate::external_sort::Triplet;
+use crate::sparse_io::*;
+
+use log::info;
+use matrix_util::common_io::{read_lines, remove_file};
+
+/// Row annotation holding the 10x feature type of each row, e.g.,
+/// `Gene Expression`, `Antibody Capture`, or `Peaks`
+pub const FEATURE_TYPE_ANNOTATION: &str = "feature_type";
+
+/// Column of the feature type in a 10x `features.tsv.gz`
+///
+/// ```text
+/// ENSG00000243485  MIR1302-2HG  Gene Expression
+/// CD3_TotalSeqB    CD3          Antibody Capture
+/// ```
+const FEATURE_TYPE_FIELD: usize = 2;
+
+/// Number of columns read at a time to split the rows
+const BLOCK_SIZE: usize = 100;
+
+/// Read the third (tab-separated) column of a 10x features file.
+/// Returns `None` for a features file without feature types, e.g.,
+/// the older two-column `genes.tsv`.
+/// * `features_file` - `features.tsv.gz` or `features.tsv`
+pub fn read_feature_types_tsv(features_file: &str) -> anyhow::Result<Option<Vec<Box<str>>>> {
+    let lines = read_lines(features_file)?;
+
+    let feature_types: Option<Vec<Box<str>>> = lines
+        .iter()
+        .map(|line| line.split('\t').nth(FEATURE_TYPE_FIELD).map(Box::from))
+        .collect();
+
+    Ok(feature_types.filter(|x| !x.is_empty()))
+}
+
+/// A short, file name-friendly tag of a feature type, e.g.,
+/// `Gene Expression` to `gene_expression`
+pub fn feature_type_tag(feature_type: &str) -> Box<str> {
+    feature_type
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .into_boxed_str()
+}
+
+/// Group row indices by feature types in the order of their first
+/// appearance
+/// * `feature_types` - feature type of each row
+pub fn rows_by_feature_type(feature_types: &[Box<str>]) -> Vec<(Box<str>, Vec<usize>)> {
+    let mut ret: Vec<(Box<str>, Vec<usize>)> = vec![];
+    for (i, x) in feature_types.iter().enumerate() {
+        match ret.iter_mut().find(|(k, _)| k == x) {
+            Some((_, rows)) => rows.push(i),
+            None => ret.push((x.clone(), vec![i])),
+        }
+    }
+    ret
+}
+
+/// Tag the rows of the data with their feature types
+/// * `data` - sparse matrix backend
+/// * `feature_types` - feature type of each row
+pub fn register_feature_types<T>(data: &mut T, feature_types: &[Box<str>]) -> anyhow::Result<()>
+where
+    T: SparseIo + ?Sized,
+{
+    let groups = rows_by_feature_type(feature_types);
+    for (feature_type, rows) in groups.iter() {
+        info!("{}: {} rows", feature_type, rows.len());
+    }
+    data.register_row_annotation(
+        FEATURE_TYPE_ANNOTATION,
+        &AnnotationColumn::categorical(feature_types),
+    )
+}
+
+/// Feature types tagged by `register_feature_types`, or `None` if the
+/// rows were never tagged
+/// * `data` - sparse matrix backend
+pub fn registered_feature_types<T>(data: &T) -> anyhow::Result<Option<Vec<Box<str>>>>
+where
+    T: SparseIo + ?Sized,
+{
+    let names = data.annotation_names(AnnotationAxis::Var)?;
+    if names.iter().any(|x| x.as_ref() == FEATURE_TYPE_ANNOTATION) {
+        Ok(Some(
+            data.row_annotation(FEATURE_TYPE_ANNOTATION)?.to_strings(),
+        ))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Write one backend per feature type, `{output}.{tag}.{ext}`, taking
+/// the rows tagged by `register_feature_types`. The columns of the
+/// data and of each layer are read once for all the feature types,
+/// and the data file itself is left as it is.
+///
+/// ```text
+/// pbmc.zarr -> pbmc.gene_expression.zarr
+///              pbmc.antibody_capture.zarr
+/// ```
+///
+/// Returns (feature type, backend file) pairs.
+///
+/// * `backend_file` - data file with the `feature_type` row annotation
+/// * `backend` - backend type
+/// * `output` - output file header
+pub fn split_by_feature_type(
+    backend_file: &str,
+    backend: &SparseIoBackend,
+    output: &str,
+) -> anyhow::Result<Vec<(Box<str>, Box<str>)>> {
+    let ext = backend_extension(backend_file)?;
+
+    let mut data = open_sparse_matrix(backend_file, backend)?;
+    let feature_types = data.row_annotation(FEATURE_TYPE_ANNOTATION)?.to_strings();
+    let groups = rows_by_feature_type(&feature_types);
+
+    let split_files: Vec<String> = groups
+        .iter()
+        .map(|(feature_type, _)| format!("{}.{}.{}", output, feature_type_tag(feature_type), ext))
+        .collect();
+
+    if split_files.iter().any(|x| x == backend_file) {
+        return Err(anyhow::anyhow!("output would overwrite {}", backend_file));
+    }
+
+    // each row goes to one feature type at a new position
+    let mut row_to_group = vec![(0, 0); feature_types.len()];
+    for (g, (_, rows)) in groups.iter().enumerate() {
+        for (i_new, &i) in rows.iter().enumerate() {
+            row_to_group[i] = (g, i_new as u64);
+        }
+    }
+
+    // read the columns once for all the feature types
+    data.preload_columns()?;
+    let mut group_triplets = split_triplets_by_rows(data.as_ref(), &row_to_group, groups.len())?;
+    data.clean_preloaded_columns();
+
+    let mut layers = vec![];
+    for layer in data.layer_names()? {
+        let layer_data = data.open_layer(Some(&layer))?;
+        let triplets = split_triplets_by_rows(layer_data.as_ref(), &row_to_group, groups.len())?;
+        layers.push((layer, triplets));
+    }
+
+    let ncol = data
+        .num_columns()
+        .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+    let row_names = data.row_names()?;
+    let column_names = data.column_names()?;
+    let obs_annotations = data.annotations(AnnotationAxis::Obs)?;
+    let var_annotations = data.annotations(AnnotationAxis::Var)?;
+    let options = data.create_options();
+
+    let mut ret = vec![];
+    for (g, ((feature_type, rows), split_file)) in groups.into_iter().zip(split_files).enumerate() {
+        info!("{}: {} rows -> {}", feature_type, rows.len(), &split_file);
+        remove_file(&split_file)?;
+
+        let triplets = std::mem::take(&mut group_triplets[g]);
+        let nnz = triplets.len();
+        let mut split = create_sparse_from_triplets_with_options(
+            triplets,
+            (rows.len(), ncol, nnz),
+            Some(&split_file),
+            Some(backend),
+            &options,
+        )?;
+
+        let names: Vec<Box<str>> = rows.iter().map(|&i| row_names[i].clone()).collect();
+        split.register_row_names_vec(&names);
+        split.register_column_names_vec(&column_names);
+
+        for (name, column) in obs_annotations.iter() {
+            split.register_annotation(AnnotationAxis::Obs, name, column)?;
+        }
+
+        let new2old_rows: Vec<Option<usize>> = rows.iter().map(|&i| Some(i)).collect();
+        for (name, column) in var_annotations.iter() {
+            split.register_annotation(AnnotationAxis::Var, name, &column.take(&new2old_rows))?;
+        }
+
+        for (layer, triplets) in layers.iter_mut() {
+            let mut triplets = std::mem::take(&mut triplets[g]);
+            if triplets.is_empty() {
+                info!("dropping the empty layer `{}`", layer);
+                continue;
+            }
+            split.register_layer_triplets(layer, &mut triplets)?;
+        }
+        split.flush_backend()?;
+
+        ret.push((feature_type, split_file.into_boxed_str()));
+    }
+    Ok(ret)
+}
+
+/// Read all the columns block by block and send each triplet to
+/// the group of its row
+/// * `data` - sparse matrix backend (or its layer)
+/// * `row_to_group` - (group, new row) of each row
+/// * `ngroup` - the number of groups
+fn split_triplets_by_rows<T>(
+    data: &T,
+    row_to_group: &[(usize, u64)],
+    ngroup: usize,
+) -> anyhow::Result<Vec<Vec<Triplet>>>
+where
+    T: SparseIo + ?Sized,
+{
+    let ncol = data
+        .num_columns()
+        .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+
+    let mut ret = vec![vec![]; ngroup];
+    for lb in (0..ncol).step_by(BLOCK_SIZE) {
+        let ub = (lb + BLOCK_SIZE).min(ncol);
+        let (_, _, triplets) = data.read_triplets_by_column_range(lb..ub)?;
+        for (i, j, x) in triplets {
+            let (g, i_new) = row_to_group[i as usize];
+            ret[g].push((i_new, j + lb as u64, x));
+        }
+    }
+    Ok(ret)
+}
@@ -0,0 +1,364 @@
+use crate::external_sort::ExternalTriplets;
+use clap::ValueEnum;
+use log::{info, warn};
+use matrix_util::common_io::{open_buf_reader, read_lines};
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+#[clap(rename_all = "lowercase")]
+pub enum FragmentCount {
+    /// Tn5 insertion sites, the two ends of each fragment
+    Insertions,
+    /// fragments, once per region that either end falls in
+    Fragments,
+}
+
+/// A half-open genomic interval `[start, end)` as in BED
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenomicRegion {
+    pub chrom: Box<str>,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl GenomicRegion {
+    /// `{chrom}:{start}-{end}`, e.g., `chr1:10000-15000`
+    pub fn name(&self) -> Box<str> {
+        format!("{}:{}-{}", self.chrom, self.start, self.end).into_boxed_str()
+    }
+}
+
+/// Where to count fragments
+pub enum FragmentRegions {
+    /// fixed-width tiles over each chromosome (only tiles with counts
+    /// will be kept)
+    Tiles(u64),
+    /// peaks, one row each in the given order
+    Peaks(Vec<GenomicRegion>),
+}
+
+/// Peak (row) by barcode (column) counts
+pub struct FragmentCounts {
+    pub row_names: Vec<Box<str>>,
+    pub column_names: Vec<Box<str>>,
+    pub triplets: ExternalTriplets,
+}
+
+/// Read a barcode whitelist, taking the first word of each line and
+/// dropping repeated barcodes
+/// * `barcode_file` - one barcode per line, e.g., 10x `barcodes.tsv.gz`
+pub fn read_barcode_whitelist(barcode_file: &str) -> anyhow::Result<Vec<Box<str>>> {
+    let mut seen: HashSet<Box<str>> = HashSet::new();
+    let mut barcodes: Vec<Box<str>> = vec![];
+    let mut ndup = 0;
+
+    for line in read_lines(barcode_file)? {
+        match line.split(['\t', ',', ' ']).next() {
+            Some(x) if !x.is_empty() => {
+                if seen.insert(x.into()) {
+                    barcodes.push(x.into());
+                } else {
+                    ndup += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if ndup > 0 {
+        warn!("dropped {} repeated barcodes in {}", ndup, barcode_file);
+    }
+
+    if barcodes.is_empty() {
+        return Err(anyhow::anyhow!("no barcodes in {}", barcode_file));
+    }
+    Ok(barcodes)
+}
+
+/// Read the first three columns of a BED file
+/// * `bed_file` - `.bed` or `.bed.gz`
+pub fn read_bed_regions(bed_file: &str) -> anyhow::Result<Vec<GenomicRegion>> {
+    let mut ret = vec![];
+    for (lineno, line) in read_lines(bed_file)?.iter().enumerate() {
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let parse = |x: &str| -> anyhow::Result<u64> {
+            x.parse()
+                .map_err(|e| anyhow::anyhow!("{} line {}: `{}`: {}", bed_file, lineno + 1, x, e))
+        };
+        match words[..] {
+            [chrom, start, end, ..] => ret.push(GenomicRegion {
+                chrom: chrom.into(),
+                start: parse(start)?,
+                end: parse(end)?,
+            }),
+            _ => return Err(anyhow::anyhow!("invalid line `{}` in {}", line, bed_file)),
+        }
+    }
+    Ok(ret)
+}
+
+/// (start, end, peak index) sorted by starts and the running maximum
+/// of their ends
+type SortedPeaks = (Vec<(u64, u64, usize)>, Vec<u64>);
+
+/// Peaks of each chromosome sorted by their starts with the running
+/// maximum of their ends, to find every peak covering a position
+/// even if peaks overlap
+struct PeakIndex {
+    by_chrom: HashMap<Box<str>, SortedPeaks>,
+}
+
+impl PeakIndex {
+    fn new(peaks: &[GenomicRegion]) -> Self {
+        let mut by_chrom: HashMap<Box<str>, SortedPeaks> = HashMap::new();
+        for (i, peak) in peaks.iter().enumerate() {
+            by_chrom
+                .entry(peak.chrom.clone())
+                .or_default()
+                .0
+                .push((peak.start, peak.end, i));
+        }
+        for (intervals, max_end) in by_chrom.values_mut() {
+            intervals.sort();
+            let mut running = 0;
+            *max_end = intervals
+                .iter()
+                .map(|&(_, end, _)| {
+                    running = running.max(end);
+                    running
+                })
+                .collect();
+        }
+        Self { by_chrom }
+    }
+
+    /// Peaks covering the position `pos` of the chromosome `chrom`
+    fn visit_hits(&self, chrom: &str, pos: u64, visit: &mut impl FnMut(usize)) {
+        if let Some((intervals, max_end)) = self.by_chrom.get(chrom) {
+            let mut k = intervals.partition_point(|&(start, _, _)| start <= pos);
+            while k > 0 && max_end[k - 1] > pos {
+                k -= 1;
+                let (_, end, i) = intervals[k];
+                if end > pos {
+                    visit(i);
+                }
+            }
+        }
+    }
+}
+
+enum RegionLookup {
+    Tiles(u64),
+    Peaks(PeakIndex),
+}
+
+/// Counts of one chromosome, flushed to the triplets before moving on
+/// to the next one
+struct ChromCounts {
+    chrom: Box<str>,
+    /// (tile or peak, barcode) -> count
+    counts: HashMap<(u64, usize), f32>,
+}
+
+impl ChromCounts {
+    /// Push the counts to `triplets`, adding rows for the tiles with
+    /// counts in order
+    fn flush(
+        self,
+        regions: &FragmentRegions,
+        row_names: &mut Vec<Box<str>>,
+        triplets: &mut ExternalTriplets,
+    ) -> anyhow::Result<()> {
+        match regions {
+            FragmentRegions::Peaks(_) => {
+                for ((i, j), x) in self.counts {
+                    triplets.push((i, j as u64, x))?;
+                }
+            }
+            FragmentRegions::Tiles(size) => {
+                let mut tiles: Vec<u64> = self.counts.keys().map(|&(t, _)| t).collect();
+                tiles.sort_unstable();
+                tiles.dedup();
+
+                let offset = row_names.len() as u64;
+                row_names.extend(tiles.iter().map(|&t| {
+                    GenomicRegion {
+                        chrom: self.chrom.clone(),
+                        start: t * size,
+                        end: (t + 1) * size,
+                    }
+                    .name()
+                }));
+
+                for ((t, j), x) in self.counts {
+                    let i = tiles.binary_search(&t).expect("tile with counts") as u64;
+                    triplets.push((offset + i, j as u64, x))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Count fragments of the barcodes in the whitelist into genomic
+/// regions, streaming a 10x `fragments.tsv.gz`
+///
+/// ```text
+/// chrom   start   end     barcode              duplicates
+/// chr1    10066   10279   AAACGAAAGACCATAA-1   2
+/// ```
+///
+/// The fragments should be sorted by chromosome, as in 10x outputs,
+/// so that only one chromosome's counts are kept in memory.
+///
+/// * `fragment_file` - fragment file (`.tsv.gz` or `.tsv`)
+/// * `barcodes` - barcode whitelist, one column each in this order
+/// * `regions` - fixed-width tiles or peaks
+/// * `count` - count insertion sites or fragments
+/// * `memory_budget` - bytes to hold the triplets in memory
+pub fn count_fragments(
+    fragment_file: &str,
+    barcodes: &[Box<str>],
+    regions: &FragmentRegions,
+    count: FragmentCount,
+    memory_budget: usize,
+) -> anyhow::Result<FragmentCounts> {
+    let barcode_index: HashMap<&str, usize> = barcodes
+        .iter()
+        .enumerate()
+        .map(|(j, x)| (x.as_ref(), j))
+        .collect();
+
+    if barcode_index.len() < barcodes.len() {
+        return Err(anyhow::anyhow!("repeated barcodes in the whitelist"));
+    }
+
+    let lookup = match regions {
+        FragmentRegions::Peaks(peaks) => RegionLookup::Peaks(PeakIndex::new(peaks)),
+        FragmentRegions::Tiles(0) => return Err(anyhow::anyhow!("need a positive tile size")),
+        FragmentRegions::Tiles(size) => RegionLookup::Tiles(*size),
+    };
+
+    // tiles will be added in the order of chromosomes in the file
+    let mut row_names: Vec<Box<str>> = match regions {
+        FragmentRegions::Peaks(peaks) => peaks.iter().map(|x| x.name()).collect(),
+        FragmentRegions::Tiles(_) => vec![],
+    };
+    let mut triplets = ExternalTriplets::new(memory_budget)?;
+
+    let mut done_chroms: HashSet<Box<str>> = HashSet::new();
+    let mut current: Option<ChromCounts> = None;
+
+    let (mut ntot, mut nkept) = (0_usize, 0_usize);
+    let mut hits: Vec<u64> = Vec::with_capacity(4);
+
+    for (lineno, line) in open_buf_reader(fragment_file)?.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        ntot += 1;
+
+        let words: Vec<&str> = line.split('\t').collect();
+        let parse = |x: &str| -> anyhow::Result<u64> {
+            x.parse().map_err(|e| {
+                anyhow::anyhow!("{} line {}: `{}`: {}", fragment_file, lineno + 1, x, e)
+            })
+        };
+        let (chrom, start, end, barcode) = match words[..] {
+            [chrom, start, end, barcode, ..] => (chrom, parse(start)?, parse(end)?, barcode),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "invalid line `{}` in {}",
+                    line,
+                    fragment_file
+                ))
+            }
+        };
+
+        if current.as_ref().map(|c| c.chrom.as_ref()) != Some(chrom) {
+            if done_chroms.contains(chrom) {
+                return Err(anyhow::anyhow!(
+                    "{} line {}: `{}` again after other chromosomes; sort the fragments by chromosome",
+                    fragment_file,
+                    lineno + 1,
+                    chrom
+                ));
+            }
+            if let Some(done) = current.take() {
+                done_chroms.insert(done.chrom.clone());
+                done.flush(regions, &mut row_names, &mut triplets)?;
+            }
+            current = Some(ChromCounts {
+                chrom: chrom.into(),
+                counts: HashMap::new(),
+            });
+        }
+        let counts = &mut current.as_mut().expect("current chromosome").counts;
+
+        let j = match barcode_index.get(barcode) {
+            Some(&j) => j,
+            None => continue,
+        };
+        nkept += 1;
+
+        // Tn5 insertion sites of the half-open fragment [start, end)
+        hits.clear();
+        for pos in [start, end.saturating_sub(1)] {
+            match &lookup {
+                RegionLookup::Tiles(size) => hits.push(pos / size),
+                RegionLookup::Peaks(peaks) => {
+                    peaks.visit_hits(chrom, pos, &mut |i| hits.push(i as u64))
+                }
+            }
+        }
+
+        if count == FragmentCount::Fragments {
+            hits.sort_unstable();
+            hits.dedup();
+        }
+
+        for &i in hits.iter() {
+            *counts.entry((i, j)).or_insert(0.) += 1.;
+        }
+    }
+
+    if let Some(done) = current {
+        done.flush(regions, &mut row_names, &mut triplets)?;
+    }
+
+    info!(
+        "{} fragments, {} of which from the {} barcodes",
+        ntot,
+        nkept,
+        barcodes.len()
+    );
+
+    if triplets.is_empty() {
+        return Err(anyhow::anyhow!(
+            "no fragments of the barcodes counted in {}",
+            fragment_file
+        ));
+    }
+
+    info!(
+        "{} regions x {} barcodes with {} non-zeros",
+        row_names.len(),
+        barcodes.len(),
+        triplets.len()
+    );
+
+    Ok(FragmentCounts {
+        row_names,
+        column_names: barcodes.to_vec(),
+        triplets,
+    })
+}
@@ -1,7 +1,22 @@
+pub mod annotation; // per-column and per-row annotation tables
+pub mod chunk_cache; // LRU cache of decoded chunks for column reads
+pub mod compressed_parts; // CSC/CSR assembled straight from indptr slices
+pub mod export; // streaming export to parquet and 10x directories
+pub mod external_sort; // out-of-core sorting of triplets
+pub mod feature_types; // 10x feature types (modalities) of rows
+pub mod fragments; // scATAC fragments counted in tiles or peaks
+pub mod misc; // hdf5 helper functions
+pub mod mmap_columns; // memory-mapped preload of columns
 pub mod simulate; // helper function for simulation
 pub mod sparse_data_visitors; // visitor
 pub mod sparse_io; // traits for sparse matrix
 pub mod sparse_io_vector;
+pub mod sparse_matrix_h5ad; // read-only sparse matrix over AnnData h5ad
 pub mod sparse_matrix_hdf5; // sparse matrix with hdf5 backend
+pub mod sparse_matrix_memory; // sparse matrix kept in memory
 pub mod sparse_matrix_zarr; //  sparse matrix with zarr backend
 pub mod statistics; // statistics related functions // traits and struct for a vector of sparse matrices
+pub mod transcripts; // cell- or bin-level counts from spatial molecule tables
+pub mod usa_import; // spliced/unspliced/ambiguous layers from alevin-fry and kb
+pub mod validate; // integrity checks of backends
+pub mod zarr_zip_store; // zarr store in a single zip archive
@@ -1,17 +1,38 @@
+mod annotation;
+mod chunk_cache;
+mod compressed_parts;
+mod export;
+mod external_sort;
+mod feature_types;
+mod fragments;
 mod misc;
+mod mmap_columns;
 mod simulate;
 mod sparse_data_visitors;
 mod sparse_io;
 mod sparse_io_vector;
+mod sparse_matrix_h5ad;
 mod sparse_matrix_hdf5;
+mod sparse_matrix_memory;
 mod sparse_matrix_zarr;
 mod statistics;
-
+mod transcripts;
+mod usa_import;
+mod validate;
+mod zarr_zip_store;
+
+use crate::annotation::read_annotation_table;
+use crate::export::*;
+use crate::external_sort::{visit_mtx_triplets, ExternalTriplets};
+use crate::feature_types::*;
+use crate::fragments::*;
 use crate::misc::*;
 use crate::sparse_data_visitors::*;
 use crate::sparse_io::*;
 use crate::sparse_io_vector::*;
 use crate::statistics::RunningStatistics;
+use crate::transcripts::*;
+use crate::usa_import::*;
 
 use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use indicatif::ParallelProgressIterator;
@@ -21,13 +42,14 @@ use matrix_util::common_io::basename;
 use matrix_util::traits::IoOps;
 use matrix_util::*;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     let cli = Cli::parse();
+    chunk_cache::set_shared_chunk_cache_bytes(cli.chunk_cache_mb << 20);
 
     match &cli.commands {
         Commands::FromMtx(args) => {
@@ -42,6 +64,12 @@ fn main() -> anyhow::Result<()> {
         Commands::FromH5(args) => {
             run_build_from_h5_triplets(args)?;
         }
+        Commands::ToH5ad(args) => {
+            run_export_to_h5ad(args)?;
+        }
+        Commands::Export(args) => {
+            run_export(args)?;
+        }
         Commands::Simulate(args) => {
             run_simulate(args)?;
         }
@@ -57,6 +85,15 @@ fn main() -> anyhow::Result<()> {
         Commands::Squeeze(args) => {
             run_squeeze(args)?;
         }
+        Commands::Validate(args) => {
+            run_validate(args)?;
+        }
+        Commands::Pack(args) => {
+            run_pack(args)?;
+        }
+        Commands::Unpack(args) => {
+            run_unpack(args)?;
+        }
         Commands::Columns(args) => {
             take_columns(args)?;
         }
@@ -69,12 +106,39 @@ fn main() -> anyhow::Result<()> {
         Commands::SubsetColumns(args) => {
             subset_columns(args)?;
         }
+        Commands::SubsetRows(args) => {
+            subset_rows(args)?;
+        }
         Commands::SortRows(args) => {
             reorder_rows(args)?;
         }
         Commands::MergeMtx(args) => {
             run_merge_mtx(args)?;
         }
+        Commands::FromUsa(args) => {
+            run_build_from_usa(args)?;
+        }
+        Commands::FromTranscripts(args) => {
+            run_build_from_transcripts(args)?;
+        }
+        Commands::FromFragments(args) => {
+            run_build_from_fragments(args)?;
+        }
+        Commands::Annotate(args) => {
+            run_annotate(args)?;
+        }
+        Commands::Append(args) => {
+            run_append(args)?;
+        }
+        Commands::AddLayer(args) => {
+            run_add_layer(args)?;
+        }
+        Commands::ListLayers(args) => {
+            list_layers(args)?;
+        }
+        Commands::DropLayer(args) => {
+            run_drop_layer(args)?;
+        }
     }
 
     Ok(())
@@ -94,10 +158,14 @@ fn main() -> anyhow::Result<()> {
 ///     │   ├── data
 ///     │   ├── indices (row indices)
 ///     │   └── indptr (column pointers)
-///     └── by_row
-///         ├── data
-///         ├── indices (column indices)
-///         └── indptr (row pointers)
+///     ├── by_row
+///     │   ├── data
+///     │   ├── indices (column indices)
+///     │   └── indptr (row pointers)
+///     └── layers (optional)
+///         └── {layer}
+///             ├── by_column
+///             └── by_row
 /// ```
 ///
 /// - build: build from .mtx fileset to another faster format
@@ -110,6 +178,11 @@ fn main() -> anyhow::Result<()> {
 struct Cli {
     #[command(subcommand)]
     commands: Commands,
+
+    /// keep at most this much of decoded blocks (MB) in memory, shared
+    /// by all the opened backend files
+    #[arg(long, global = true, default_value_t = 256)]
+    chunk_cache_mb: usize,
 }
 
 #[derive(Subcommand, Debug)]
@@ -126,6 +199,14 @@ enum Commands {
     /// List what are included in `h5` file
     ListH5(ListH5Args),
 
+    /// Export a backend to AnnData `h5ad` (cells as rows) with
+    /// optional `obs` batch column and `obsm` latent matrices
+    ToH5ad(ToH5adArgs),
+
+    /// Export the matrix with its row and column names to long-format
+    /// `parquet` files or a 10x directory, block by block
+    Export(ExportArgs),
+
     /// Sort rows according to the order of row names specified in a
     /// row name file
     SortRows(SortRowsArgs),
@@ -143,13 +224,64 @@ enum Commands {
     /// Take columns from the sparse matrix and create a new sparse matrix backend.
     SubsetColumns(SubsetColumnsArgs),
 
+    /// Take rows (features) by names or a regular expression, e.g.,
+    /// dropping `^MT-` genes, and rebuild the data file or create a
+    /// new backend
+    SubsetRows(SubsetRowsArgs),
+
     /// Merge multiple 10x `.mtx` files into one fileset
     MergeMtx(MergeMtxArgs),
 
+    /// Build a backend from alevin-fry (USA mode) or kb
+    /// spliced/unspliced outputs with one layer per splicing status
+    /// and the summed counts as the main matrix
+    FromUsa(FromUsaArgs),
+
+    /// Build a backend and a coordinate file for `pinto --coord`
+    /// from a spatial molecule table, e.g., Xenium `transcripts.parquet`,
+    /// counting molecules by cells or square/hexagonal bins
+    FromTranscripts(FromTranscriptsArgs),
+
+    /// Build a backend of scATAC-seq counts from a 10x
+    /// `fragments.tsv.gz` in fixed genomic tiles or peaks
+    FromFragments(FromFragmentsArgs),
+
+    /// Store per-column (`obs`) and per-row (`var`) annotation
+    /// tables inside the backend, e.g., donor or batch of each cell
+    Annotate(AnnotateArgs),
+
+    /// Append columns (e.g., cells of a new batch) from another
+    /// backend or `mtx` with the same rows to an existing backend
+    Append(AppendArgs),
+
+    /// Add (or replace) a named layer, e.g., `spliced`, `unspliced`,
+    /// or `raw`, sharing the same rows and columns
+    AddLayer(AddLayerArgs),
+
+    /// List named layers stored in addition to the main matrix
+    ListLayers(ListLayersArgs),
+
+    /// Remove a named layer from the backend
+    DropLayer(DropLayerArgs),
+
     /// Squeeze out rows and columns with too few non-zeros. It will
-    /// overwrite the original (be careful) and save the indices kept.
+    /// rebuild the original, or write a new backend with `--output`,
+    /// and save the indices kept.
     Squeeze(RunSqueezeArgs),
 
+    /// Check the integrity of a backend (attributes, `indptr`,
+    /// indices, names, and CSR/CSC consistency) and optionally
+    /// rebuild a broken CSR side from the CSC side
+    Validate(ValidateArgs),
+
+    /// Pack a zarr directory (`.zarr`) into a single zip archive
+    /// (`.zarr.zip`), which can be used as a backend file directly
+    Pack(PackArgs),
+
+    /// Unpack a zip archive (`.zarr.zip`) into a zarr directory
+    /// (`.zarr`)
+    Unpack(UnpackArgs),
+
     /// Show basic information of a sparse matrix. If output header is
     /// provided, row and column names will be saved.
     Info(InfoArgs),
@@ -167,6 +299,152 @@ enum Commands {
     Simulate(RunSimulateArgs),
 }
 
+#[derive(Args, Debug)]
+pub struct AnnotateArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// per-column (cell) table with a header line (`.tsv`, `.csv`,
+    /// or gzipped); the first column should contain column names
+    #[arg(long)]
+    obs_file: Option<Box<str>>,
+
+    /// per-row (feature) table with a header line (`.tsv`, `.csv`,
+    /// or gzipped); the first column should contain row names
+    #[arg(long)]
+    var_file: Option<Box<str>>,
+
+    /// keep these columns categorical even if they look numeric,
+    /// e.g., `donor` coded as 1, 2, 3
+    #[arg(long, value_delimiter = ',')]
+    categorical_columns: Option<Vec<Box<str>>>,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct AppendArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// another backend (`.zarr`, `.h5`, `.h5ad`) or `.mtx` (or
+    /// `.mtx.gz`) file with the same rows
+    #[arg(short, long, required = true)]
+    source: Box<str>,
+
+    /// row/feature name file of the `mtx` source (name per each line;
+    /// `.tsv.gz` or `.tsv`); the rows of `data_file` if not given
+    #[arg(short, long)]
+    row: Option<Box<str>>,
+
+    /// column/cell name file of the `mtx` source (name per each line;
+    /// `.tsv.gz` or `.tsv`)
+    #[arg(short, long)]
+    col: Option<Box<str>>,
+
+    /// sort triplets out of core within this memory budget (MB)
+    #[arg(long, default_value_t = 1024)]
+    memory_budget_mb: usize,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// rebuild the CSR side (`by_row`) from the CSC side
+    /// (`by_column`) where only the CSR side is broken
+    #[arg(long, default_value_t = false)]
+    repair_csr: bool,
+
+    /// sort triplets out of core within this memory budget (MB)
+    #[arg(long, default_value_t = 1024)]
+    memory_budget_mb: usize,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct PackArgs {
+    /// zarr directory, e.g., `data.zarr`
+    zarr_dir: Box<str>,
+
+    /// output zip file; `{zarr_dir}.zip` if not given
+    #[arg(short, long)]
+    output: Option<Box<str>>,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct UnpackArgs {
+    /// zip archive, e.g., `data.zarr.zip`
+    zip_file: Box<str>,
+
+    /// output zarr directory; `zip_file` without `.zip` if not given
+    #[arg(short, long)]
+    output: Option<Box<str>>,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct AddLayerArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// name of the layer, e.g., `spliced`
+    #[arg(short, long, required = true)]
+    name: Box<str>,
+
+    /// `.mtx` (or `.mtx.gz`) file of the same shape, or another
+    /// backend (`.zarr`, `.h5`, `.h5ad`) with the same row and column
+    /// names
+    #[arg(short, long, required = true)]
+    source: Box<str>,
+
+    /// take this layer of the `source` backend instead of its main
+    /// matrix
+    #[arg(long)]
+    source_layer: Option<Box<str>>,
+
+    /// sort triplets out of core within this memory budget (MB)
+    #[arg(long, default_value_t = 1024)]
+    memory_budget_mb: usize,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct ListLayersArgs {
+    /// data file -- `.zarr`, `.h5`, or `.h5ad` (read-only)
+    data_file: Box<str>,
+}
+
+#[derive(Args, Debug)]
+pub struct DropLayerArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// name of the layer to be removed
+    #[arg(short, long, required = true)]
+    name: Box<str>,
+}
+
 #[derive(Args, Debug)]
 pub struct SortRowsArgs {
     /// Data file -- either `.zarr` or `.h5`
@@ -179,7 +457,7 @@ pub struct SortRowsArgs {
 
 #[derive(Args, Debug)]
 pub struct TakeColumnsArgs {
-    /// data file -- either `.zarr` or `.h5`
+    /// data file -- `.zarr`, `.h5`, or `.h5ad` (read-only)
     data_file: Box<str>,
 
     /// column indices to take: e.g., `0,1,2,3`
@@ -197,7 +475,7 @@ pub struct TakeColumnsArgs {
 
 #[derive(Args, Debug)]
 pub struct TakeColumnNamesArgs {
-    /// data file -- either `.zarr` or `.h5`
+    /// data file -- `.zarr`, `.h5`, or `.h5ad` (read-only)
     data_file: Box<str>,
 
     /// output file
@@ -207,7 +485,7 @@ pub struct TakeColumnNamesArgs {
 
 #[derive(Args, Debug)]
 pub struct TakeRowNamesArgs {
-    /// data file -- either `.zarr` or `.h5`
+    /// data file -- `.zarr`, `.h5`, or `.h5ad` (read-only)
     data_file: Box<str>,
 
     /// output file
@@ -217,7 +495,7 @@ pub struct TakeRowNamesArgs {
 
 #[derive(Args, Debug)]
 pub struct SubsetColumnsArgs {
-    /// data file -- either `.zarr` or `.h5`
+    /// data file -- `.zarr`, `.h5`, or `.h5ad` (read-only)
     data_file: Box<str>,
 
     /// column indices to take: e.g., `0,1,2,3`
@@ -235,6 +513,57 @@ pub struct SubsetColumnsArgs {
     /// output file
     #[arg(short, long, required = true)]
     output: Box<str>,
+
+    #[command(flatten)]
+    zarr: ZarrWriteOptions,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    /// data file -- `.zarr`, `.h5`, or `.h5ad`
+    data_file: Box<str>,
+
+    /// `parquet`: long-format (row, column, value) files, one per
+    /// block of columns; `10x`: `matrix.mtx.gz`, `features.tsv.gz`,
+    /// and `barcodes.tsv.gz`
+    #[arg(long, value_enum, default_value = "parquet")]
+    format: ExportFormat,
+
+    /// output directory
+    #[arg(short, long, required = true)]
+    output: Box<str>,
+
+    /// number of columns per block (per `parquet` file)
+    #[arg(long, default_value = "1000")]
+    block_size: usize,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct SubsetRowsArgs {
+    /// data file -- either `.zarr` or `.h5`
+    data_file: Box<str>,
+
+    /// row name file where each line is a row name
+    #[arg(short = 'f', long)]
+    name_file: Option<Box<str>>,
+
+    /// regular expression of row names, e.g., `^MT-`, matched against
+    /// the whole name and each `_`-separated part of it
+    #[arg(short = 'r', long)]
+    regex: Option<Box<str>>,
+
+    /// drop the matched rows instead of keeping them
+    #[arg(long, default_value_t = false)]
+    exclude: bool,
+
+    /// output header for a new backend file (with the same extension
+    /// as the data file) instead of rebuilding the data file
+    #[arg(short, long)]
+    output: Option<Box<str>>,
 }
 
 #[derive(Args, Debug)]
@@ -262,6 +591,14 @@ pub struct FromMtxArgs {
     #[arg(long, default_value_t = false)]
     do_squeeze: bool,
 
+    /// sort triplets out of core within this memory budget (MB)
+    /// instead of loading all of them in memory
+    #[arg(long)]
+    memory_budget_mb: Option<usize>,
+
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
     /// verbose mode
     #[arg(short, long, action = ArgAction::Count)]
     verbose: u8,
@@ -310,10 +647,205 @@ pub struct FromH5Args {
     #[arg(short = 'c', long, default_value = "matrix/barcodes")]
     column_name_field: Box<str>,
 
+    /// dataset name for feature types (modalities), e.g., `Gene
+    /// Expression` or `Antibody Capture`, kept as the `feature_type`
+    /// row annotation
+    #[arg(long, default_value = "matrix/features/feature_type")]
+    feature_type_field: Box<str>,
+
+    /// write one backend per feature type,
+    /// {output}.{feature_type}.{backend}, instead of one for all
+    #[arg(long, default_value_t = false)]
+    split_feature_types: bool,
+
+    /// squeeze
+    #[arg(long, default_value_t = false)]
+    do_squeeze: bool,
+
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Args, Debug)]
+pub struct ToH5adArgs {
+    /// data file -- `.zarr`, `.h5`, or `.h5ad`
+    data_file: Box<str>,
+
+    /// output file header: {output}.h5ad
+    #[arg(short, long)]
+    output: Option<Box<str>>,
+
+    /// latent parquet files (comma-separated), e.g.,
+    /// `{out}.latent.parquet` by `senna`, to be attached to `obsm`
+    /// as `X_latent`. Rows are matched by column/cell names.
+    #[arg(short, long, value_delimiter = ',')]
+    latent_files: Option<Vec<Box<str>>>,
+
+    /// batch membership file (one batch name per each column/cell),
+    /// to be attached to `obs` as a categorical column
+    #[arg(short, long)]
+    batch_file: Option<Box<str>>,
+
+    /// `obs` column name for the batch membership
+    #[arg(long, default_value = "batch")]
+    batch_column_name: Box<str>,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+/// Import splice-aware quantification into one layered backend.
+#[derive(Args, Debug)]
+pub struct FromUsaArgs {
+    /// alevin-fry output directory (with `alevin/quants_mat.mtx`) or
+    /// kb output directory (with `spliced.mtx` and `unspliced.mtx`)
+    data_dir: Box<str>,
+
+    /// backend for the output file
+    #[arg(long, value_enum, default_value = "zarr")]
+    backend: SparseIoBackend,
+
+    /// output file header: {output}.{backend}
+    #[arg(short, long, required = true)]
+    output: Box<str>,
+
+    /// layers summed up for the main matrix, e.g.,
+    /// `spliced,ambiguous` for single-cell data (default: all the
+    /// layers found in the input)
+    #[arg(long, value_delimiter = ',')]
+    sum_layers: Option<Vec<Box<str>>>,
+
+    /// also keep the summed counts as a `counts` layer
+    #[arg(long, default_value_t = false)]
+    counts_layer: bool,
+
+    /// squeeze
+    #[arg(long, default_value_t = false)]
+    do_squeeze: bool,
+
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+/// Aggregate molecules of imaging-based spatial data into a backend.
+#[derive(Args, Debug)]
+pub struct FromTranscriptsArgs {
+    /// molecule table (`.parquet`, `.csv.gz`, or `.tsv.gz`) with one
+    /// molecule per row
+    transcript_file: Box<str>,
+
+    /// backend for the output file
+    #[arg(long, value_enum, default_value = "zarr")]
+    backend: SparseIoBackend,
+
+    /// output file header: {output}.{backend} and
+    /// {output}.coord.parquet (`x` and `y` of each column)
+    #[arg(short, long, required = true)]
+    output: Box<str>,
+
+    /// count molecules by the cell IDs or in square/hexagonal bins
+    #[arg(short, long, value_enum, default_value = "cell")]
+    aggregate: TranscriptAggregation,
+
+    /// bin size in the unit of the coordinates (side of a square or
+    /// distance between neighbouring hexagons)
+    #[arg(long)]
+    bin_size: Option<f64>,
+
+    /// gene column (MERSCOPE: `gene`, CosMx: `target`)
+    #[arg(long, default_value = "feature_name")]
+    gene_column: Box<str>,
+
+    /// x coordinate column (MERSCOPE: `global_x`, CosMx: `x_global_px`)
+    #[arg(long, default_value = "x_location")]
+    x_column: Box<str>,
+
+    /// y coordinate column (MERSCOPE: `global_y`, CosMx: `y_global_px`)
+    #[arg(long, default_value = "y_location")]
+    y_column: Box<str>,
+
+    /// cell ID column (CosMx: `cell`)
+    #[arg(long, default_value = "cell_id")]
+    cell_column: Box<str>,
+
+    /// cell IDs of molecules outside of any cell (CosMx: `0`)
+    #[arg(long, value_delimiter = ',', default_value = "UNASSIGNED,-1")]
+    unassigned_cell_ids: Vec<Box<str>>,
+
+    /// quality value column
+    #[arg(long, default_value = "qv")]
+    qv_column: Box<str>,
+
+    /// drop molecules below this quality value, e.g., 20 for Xenium
+    #[arg(long)]
+    min_qv: Option<f64>,
+
+    /// drop genes matching this regular expression, e.g.,
+    /// `^(NegControl|BLANK|Unassigned|Deprecated)`
+    #[arg(long)]
+    exclude_genes: Option<Box<str>>,
+
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
+    /// verbose mode
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+/// Count fragments into genomic regions (rows) by barcodes (columns).
+#[derive(Args, Debug)]
+pub struct FromFragmentsArgs {
+    /// fragment file (`fragments.tsv.gz`) with chrom, start, end,
+    /// and barcode of each fragment
+    fragment_file: Box<str>,
+
+    /// barcode whitelist with one barcode per line, e.g., 10x
+    /// `barcodes.tsv.gz` of called cells
+    #[arg(short, long, required = true)]
+    barcodes: Box<str>,
+
+    /// peak BED file (chrom, start, end); fixed-width tiles are used
+    /// if not given
+    #[arg(short, long)]
+    peaks: Option<Box<str>>,
+
+    /// tile width in base pairs
+    #[arg(long, default_value_t = 5000)]
+    tile_size: u64,
+
+    /// count Tn5 insertion sites or fragments
+    #[arg(long, value_enum, default_value = "insertions")]
+    count: FragmentCount,
+
+    /// sort triplets out of core within this memory budget (MB)
+    #[arg(long, default_value_t = 1024)]
+    memory_budget_mb: usize,
+
+    /// backend for the output file
+    #[arg(long, value_enum, default_value = "zarr")]
+    backend: SparseIoBackend,
+
+    /// output file header: {output}.{backend}
+    #[arg(short, long, required = true)]
+    output: Box<str>,
+
     /// squeeze
     #[arg(long, default_value_t = false)]
     do_squeeze: bool,
 
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
     /// verbose mode
     #[arg(short, long, action = ArgAction::Count)]
     verbose: u8,
@@ -360,18 +892,33 @@ pub struct MergeMtxArgs {
     #[arg(long, default_value_t = 5)]
     num_barcode_name_words: usize,
 
+    /// write one backend per feature type (the third column of the
+    /// feature file), {output}.{feature_type}.{backend}, instead of
+    /// one for all
+    #[arg(long, default_value_t = false)]
+    split_feature_types: bool,
+
     /// squeeze
     #[arg(long, default_value_t = false)]
     do_squeeze: bool,
 
+    /// sort triplets out of core within this memory budget (MB)
+    /// instead of loading all of them in memory
+    #[arg(long)]
+    memory_budget_mb: Option<usize>,
+
+    #[command(flatten)]
+    create: SparseCreateOptions,
+
     /// verbose mode
     #[arg(short, long, action = ArgAction::Count)]
     verbose: u8,
 }
 
 /// Squeeze out rows and columns that have fewer non-zeros. This will
-/// overwrite the original file (be careful) and save the row/column
-/// indices that are kept in the output file.
+/// rebuild the original file, or write a new backend file with
+/// `--output`, and save the row/column indices that are kept. The
+/// original is replaced only after the new backend is complete.
 #[derive(Args, Debug)]
 #[command(about)]
 pub struct RunSqueezeArgs {
@@ -389,6 +936,11 @@ pub struct RunSqueezeArgs {
     /// block_size for parallel processing
     #[arg(long, default_value = "100")]
     block_size: usize,
+
+    /// output header for a new backend file (with the same extension
+    /// as the data file) instead of rebuilding the data file
+    #[arg(short, long)]
+    output: Option<Box<str>>,
 }
 
 #[derive(ValueEnum, Clone, Debug, PartialEq)]
@@ -401,8 +953,8 @@ enum StatDim {
 /// Generate row-wise and column-wise basic statistics.
 #[derive(Args, Debug)]
 pub struct RunStatArgs {
-    /// Data files of either `.zarr` or `.h5` format. All the formats
-    /// in the given list should be identical. We can convert `.mtx`
+    /// Data files of either `.zarr` or `.h5` format. The formats can
+    /// be mixed in the given list. We can convert `.mtx`
     /// to `.zarr` or `.h5` using `asap-data build` command.
     #[arg(required = true)]
     data_files: Vec<Box<str>>,
@@ -423,7 +975,7 @@ pub struct RunStatArgs {
 /// A quick information of the underlying matrix of a backend file.
 #[derive(Args, Debug)]
 pub struct InfoArgs {
-    /// data file -- .zarr or .h5 file
+    /// data file -- .zarr, .h5, or .h5ad file
     data_file: Box<str>,
 
     /// file header for {output}.{rows.gz,columns.gz}
@@ -526,14 +1078,11 @@ fn reorder_rows(args: &SortRowsArgs) -> anyhow::Result<()> {
     let data_file = args.data_file.clone();
     let row_names_order: Vec<Box<str>> = read_row_names(args.row_file.clone(), MAX_ROW_NAME_IDX)?;
 
-    let backend = match common_io::extension(&data_file)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-    };
+    let backend = writable_backend(&data_file)?;
 
     let mut data = open_sparse_matrix(&data_file, &backend.clone())?;
     data.reorder_rows(&row_names_order)?;
+    data.flush_backend()?;
 
     Ok(())
 }
@@ -544,16 +1093,18 @@ fn subset_columns(args: &SubsetColumnsArgs) -> anyhow::Result<()> {
     let columns_indices = args.column_indices.clone();
     let column_name_file = args.name_file.clone();
 
-    let backend = match common_io::extension(&data_file)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-    };
+    let backend = SparseIoBackend::from_file_name(&data_file)?;
 
     let output = args.output.clone();
 
     let data = open_sparse_matrix(&data_file, &backend.clone())?;
 
+    // h5ad is read-only, so the subset goes to a zarr backend
+    let backend = match backend {
+        SparseIoBackend::H5ad => SparseIoBackend::Zarr,
+        _ => backend,
+    };
+
     let row_names = data.row_names()?;
     let cols = data.column_names()?;
 
@@ -598,10 +1149,7 @@ fn subset_columns(args: &SubsetColumnsArgs) -> anyhow::Result<()> {
         ));
     };
 
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => format!("{}.h5", &output),
-        SparseIoBackend::Zarr => format!("{}.zarr", &output),
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     let mtx_shape = (nrow, ncol, triplets.len());
 
@@ -613,11 +1161,20 @@ fn subset_columns(args: &SubsetColumnsArgs) -> anyhow::Result<()> {
         common_io::remove_file(&backend_file)?;
     }
 
-    let mut data =
-        create_sparse_from_triplets(triplets, mtx_shape, Some(&backend_file), Some(&backend))?;
+    let mut data = create_sparse_from_triplets_with_options(
+        triplets,
+        mtx_shape,
+        Some(&backend_file),
+        Some(&backend),
+        &SparseCreateOptions {
+            value_type: data.value_type(),
+            zarr: args.zarr.clone(),
+        },
+    )?;
 
     data.register_row_names_vec(&row_names);
     data.register_column_names_vec(&col_names);
+    data.flush_backend()?;
 
     info!(
         "Successfully created a sparse backend file: {}",
@@ -631,6 +1188,7 @@ fn subset_columns(args: &SubsetColumnsArgs) -> anyhow::Result<()> {
             row_nnz_cutoff: 0,
             column_nnz_cutoff: 0,
             block_size: 100,
+            output: None,
         };
 
         run_squeeze(&squeeze_args)?;
@@ -639,17 +1197,136 @@ fn subset_columns(args: &SubsetColumnsArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn run_export(args: &ExportArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data = open_sparse_matrix_by_extension(&args.data_file)?;
+    let row_names = data.row_names()?;
+    let column_names = data.column_names()?;
+    let feature_types = registered_feature_types(data.as_ref())?;
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(data), None)?;
+
+    match args.format {
+        ExportFormat::Parquet => {
+            export_parquet_long(
+                &data_vec,
+                &row_names,
+                &column_names,
+                &args.output,
+                Some(args.block_size),
+            )?;
+        }
+        ExportFormat::TenX => {
+            export_10x(
+                &data_vec,
+                &row_names,
+                &column_names,
+                feature_types.as_deref(),
+                &args.output,
+                Some(args.block_size),
+            )?;
+        }
+    }
+
+    info!("Exported {} to {}", args.data_file, args.output);
+    Ok(())
+}
+
+fn subset_rows(args: &SubsetRowsArgs) -> anyhow::Result<()> {
+    let data_file = args.data_file.clone();
+
+    let backend = writable_backend(&data_file)?;
+
+    let name_set: Option<HashSet<Box<str>>> = match args.name_file.as_ref() {
+        Some(name_file) => Some(
+            read_row_names(name_file.clone(), MAX_ROW_NAME_IDX)?
+                .into_iter()
+                .collect(),
+        ),
+        None => None,
+    };
+    let regex = match args.regex.as_ref() {
+        Some(pattern) => Some(regex::Regex::new(pattern)?),
+        None => None,
+    };
+
+    if name_set.is_none() && regex.is_none() {
+        return Err(anyhow::anyhow!(
+            "either `name-file` or `regex` must be provided"
+        ));
+    }
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    let row_names = data.row_names()?;
+
+    // a row matches by its whole name or any part of it
+    let is_matched = |name: &str| -> bool {
+        std::iter::once(name).chain(name.split(ROW_SEP)).any(|x| {
+            name_set.as_ref().is_some_and(|set| set.contains(x))
+                || regex.as_ref().is_some_and(|re| re.is_match(x))
+        })
+    };
+
+    let rows: Vec<usize> = row_names
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| is_matched(name) != args.exclude)
+        .map(|(i, _)| i)
+        .collect();
+
+    if rows.is_empty() {
+        return Err(anyhow::anyhow!("no rows left"));
+    }
+    info!("taking {} of {} rows", rows.len(), row_names.len());
+
+    if let Some(output) = args.output.as_ref() {
+        data.set_backend_file_name(&output_backend_file(&data_file, output)?)?;
+    }
+
+    data.preload_columns()?;
+    data.subset_columns_rows(None, Some(&rows))?;
+    data.flush_backend()?;
+
+    info!(
+        "Successfully created a sparse backend file: {}",
+        data.get_backend_file_name()
+    );
+    Ok(())
+}
+
+/// Backend of an existing file to be modified in place; `.h5ad`
+/// files are read-only
+fn writable_backend(data_file: &str) -> anyhow::Result<SparseIoBackend> {
+    match SparseIoBackend::from_file_name(data_file)? {
+        SparseIoBackend::H5ad => Err(anyhow::anyhow!(
+            "{} is a read-only h5ad file; convert it to zarr or hdf5 first",
+            data_file
+        )),
+        backend => Ok(backend),
+    }
+}
+
+/// A new backend file name `{output}.{ext}` with the same extension
+/// as `data_file`, e.g., `.zarr`, `.zarr.zip`, or `.h5`
+fn output_backend_file(data_file: &str, output: &str) -> anyhow::Result<String> {
+    if zarr_zip_store::is_zarr_zip(data_file) {
+        Ok(format!("{}{}", output, zarr_zip_store::ZARR_ZIP_SUFFIX))
+    } else {
+        Ok(format!("{}.{}", output, backend_extension(data_file)?))
+    }
+}
+
 fn take_columns(args: &TakeColumnsArgs) -> anyhow::Result<()> {
     let data_file = args.data_file.clone();
 
     let columns = args.column_indices.clone();
     let column_name_file = args.name_file.clone();
 
-    let backend = match common_io::extension(&data_file)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-    };
+    let backend = SparseIoBackend::from_file_name(&data_file)?;
 
     let output = args.output.clone();
 
@@ -820,6 +1497,7 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
     info!("Finding common rows/features ...");
 
     let mut row_hash: HashMap<Box<str>, usize> = HashMap::new();
+    let mut row_feature_type: HashMap<Box<str>, Box<str>> = HashMap::new();
 
     for row_file in row_files.iter() {
         let row_names = read_row_names(row_file.clone(), args.num_feature_name_words)?;
@@ -827,6 +1505,12 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
             let n = row_hash.entry(name.clone()).or_insert(0);
             *n += 1;
         }
+
+        if let Some(feature_types) = read_feature_types_tsv(row_file)? {
+            for (name, x) in row_names.into_iter().zip(feature_types) {
+                row_feature_type.entry(name).or_insert(x);
+            }
+        }
     }
 
     let mut common_rows: Vec<Box<str>> = row_hash
@@ -854,6 +1538,15 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
         num_batches
     );
 
+    let feature_types: Option<Vec<Box<str>>> = common_rows
+        .iter()
+        .map(|x| row_feature_type.get(x).cloned())
+        .collect();
+
+    if args.split_feature_types && feature_types.is_none() {
+        return Err(anyhow::anyhow!("no feature types found to split rows"));
+    }
+
     info!("Elongating column/barcode names ...");
 
     let mut column_names = vec![];
@@ -877,8 +1570,11 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
     info!("Renaming triplets...");
 
     let mut renamed_triplets = vec![];
+    let mut external_triplets = args
+        .memory_budget_mb
+        .map(|mb| ExternalTriplets::new(mb << 20))
+        .transpose()?;
     let mut offset = 0;
-    let mut nnz_tot = 0;
 
     let pb = ProgressBar::new(num_batches as u64);
 
@@ -889,7 +1585,27 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
     for b in 0..num_batches {
         let row_names = read_row_names(row_files[b].clone(), args.num_feature_name_words)?;
 
-        let (triplets, shape) = mtx_io::read_mtx_triplets(&mtx_files[b].clone())?;
+        let rename = |(batch_i, batch_j, x_ij): (u64, u64, f32)| {
+            row_pos
+                .get(&row_names[batch_i as usize])
+                .map(|&i| (i as u64, batch_j + offset, x_ij))
+        };
+
+        let shape = if let Some(external) = external_triplets.as_mut() {
+            Some(visit_mtx_triplets(
+                &mtx_files[b],
+                &mut |x| match rename(x) {
+                    Some(x) => external.push(x),
+                    None => Ok(()),
+                },
+            )?)
+        } else {
+            let (triplets, shape) = mtx_io::read_mtx_triplets(&mtx_files[b].clone())?;
+            if shape.is_some() {
+                renamed_triplets.extend(triplets.into_iter().filter_map(rename));
+            }
+            shape
+        };
 
         if let Some((nrow, ncol, nnz)) = shape {
             info!(
@@ -897,22 +1613,6 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
                 &mtx_files[b], nrow, ncol, nnz
             );
 
-            let triplets = triplets
-                .into_iter()
-                .filter_map(|(batch_i, batch_j, x_ij)| {
-                    if let Some(i) = row_pos.get(&row_names[batch_i as usize]) {
-                        let i = *i as u64;
-                        let j = batch_j + offset;
-                        Some((i, j, x_ij))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<_>>();
-
-            nnz_tot += triplets.len();
-            renamed_triplets.extend(triplets);
-
             let batch_col_names =
                 read_col_names(col_files[b].clone(), args.num_barcode_name_words)?;
             let batch_name = batch_names[b].clone();
@@ -938,10 +1638,7 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
     let output = args.output.clone();
     let batch_memb_file = (output.to_string() + ".batch.gz").into_boxed_str();
 
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => format!("{}.h5", &output),
-        SparseIoBackend::Zarr => format!("{}.zarr", &output),
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     if std::path::Path::new(&backend_file).exists() {
         info!(
@@ -954,15 +1651,37 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
     common_io::write_lines(&column_batch_names, &batch_memb_file)?;
     info!("Wrote batch membership file: {}", &batch_memb_file);
 
-    let mut data = create_sparse_from_triplets(
-        renamed_triplets,
-        (row_pos.len(), offset as usize, nnz_tot),
-        Some(&backend_file),
-        Some(&backend),
-    )?;
+    let mut data = match external_triplets {
+        Some(triplets) => {
+            let nnz_tot = triplets.len();
+            create_sparse_from_external_triplets(
+                triplets,
+                (row_pos.len(), offset as usize, nnz_tot),
+                Some(&backend_file),
+                Some(&backend),
+                &args.create,
+            )?
+        }
+        None => {
+            let nnz_tot = renamed_triplets.len();
+            create_sparse_from_triplets_with_options(
+                renamed_triplets,
+                (row_pos.len(), offset as usize, nnz_tot),
+                Some(&backend_file),
+                Some(&backend),
+                &args.create,
+            )?
+        }
+    };
 
     data.register_row_names_vec(&common_rows);
     data.register_column_names_vec(&column_names);
+    data.register_column_annotation("batch", &AnnotationColumn::categorical(&column_batch_names))?;
+
+    if let Some(feature_types) = feature_types.as_ref() {
+        register_feature_types(data.as_mut(), feature_types)?;
+    }
+    data.flush_backend()?;
 
     info!(
         "Successfully created a sparse backend file: {}",
@@ -971,11 +1690,234 @@ fn run_merge_mtx(args: &MergeMtxArgs) -> anyhow::Result<()> {
 
     if args.do_squeeze {
         info!("Squeeze the backend data {}", &backend_file);
+        let squeeze_args = RunSqueezeArgs {
+            data_file: backend_file.clone().into_boxed_str(),
+            row_nnz_cutoff: 0,
+            column_nnz_cutoff: 0,
+            block_size: 100,
+            output: None,
+        };
+
+        run_squeeze(&squeeze_args)?;
+    }
+
+    if args.split_feature_types {
+        drop(data);
+        split_backend_by_feature_type(&backend_file, &backend, &output)?;
+    }
+
+    Ok(())
+}
+
+/// Replace the backend with one backend per feature type
+fn split_backend_by_feature_type(
+    backend_file: &str,
+    backend: &SparseIoBackend,
+    output: &str,
+) -> anyhow::Result<()> {
+    for (feature_type, split_file) in split_by_feature_type(backend_file, backend, output)? {
+        info!("{}: {}", feature_type, split_file);
+    }
+    common_io::remove_file(backend_file)?;
+    info!(
+        "Removed the backend with all feature types: {}",
+        backend_file
+    );
+    Ok(())
+}
+
+fn run_build_from_usa(args: &FromUsaArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let backend = args.backend.clone();
+    let output = args.output.clone();
+
+    let backend_file = backend_output_file(&output, &backend)?;
+
+    let usa = read_usa_counts(&args.data_dir)?;
+    info!(
+        "{} genes x {} barcodes with layers {:?}",
+        usa.num_rows(),
+        usa.num_columns(),
+        usa.layer_names()
+    );
+
+    if std::path::Path::new(&backend_file).exists() {
+        info!(
+            "This existing backend file '{}' will be deleted",
+            &backend_file
+        );
+        common_io::remove_file(&backend_file)?;
+    }
+
+    create_sparse_from_usa(
+        usa,
+        args.sum_layers.as_deref(),
+        args.counts_layer,
+        Some(&backend_file),
+        Some(&backend),
+        &args.create,
+    )?
+    .flush_backend()?;
+
+    info!(
+        "Successfully created a sparse backend file: {}",
+        &backend_file
+    );
+
+    if args.do_squeeze {
+        let squeeze_args = RunSqueezeArgs {
+            data_file: backend_file.into_boxed_str(),
+            row_nnz_cutoff: 0,
+            column_nnz_cutoff: 0,
+            block_size: 100,
+            output: None,
+        };
+
+        run_squeeze(&squeeze_args)?;
+    }
+
+    Ok(())
+}
+
+fn run_build_from_transcripts(args: &FromTranscriptsArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let backend = args.backend.clone();
+    let output = args.output.clone();
+
+    let backend_file = backend_output_file(&output, &backend)?;
+    let coord_file = format!("{}.coord.parquet", &output);
+
+    // the cell and quality columns are needed only if used
+    let columns = TranscriptColumns {
+        gene: args.gene_column.clone(),
+        x: args.x_column.clone(),
+        y: args.y_column.clone(),
+        cell: (args.aggregate == TranscriptAggregation::Cell).then(|| args.cell_column.clone()),
+        qv: args.min_qv.map(|_| args.qv_column.clone()),
+    };
+
+    let filter = TranscriptFilter {
+        min_qv: args.min_qv,
+        exclude_genes: args
+            .exclude_genes
+            .as_ref()
+            .map(|x| regex::Regex::new(x))
+            .transpose()?,
+        unassigned_cells: args.unassigned_cell_ids.clone(),
+    };
+
+    let mut aggregated = aggregate_transcripts(
+        &args.transcript_file,
+        &columns,
+        &filter,
+        args.aggregate,
+        args.bin_size,
+    )?;
+
+    if std::path::Path::new(&backend_file).exists() {
+        info!(
+            "This existing backend file '{}' will be deleted",
+            &backend_file
+        );
+        common_io::remove_file(&backend_file)?;
+    }
+
+    let triplets = std::mem::take(&mut aggregated.triplets);
+    let nnz = triplets.len();
+    let mut data = create_sparse_from_triplets_with_options(
+        triplets,
+        (
+            aggregated.row_names.len(),
+            aggregated.column_names.len(),
+            nnz,
+        ),
+        Some(&backend_file),
+        Some(&backend),
+        &args.create,
+    )?;
+    data.register_row_names_vec(&aggregated.row_names);
+    data.register_column_names_vec(&aggregated.column_names);
+    data.flush_backend()?;
+
+    info!(
+        "Successfully created a sparse backend file: {}",
+        &backend_file
+    );
+
+    aggregated.write_coordinates(&coord_file)?;
+    info!("Wrote coordinates: {}", &coord_file);
+
+    Ok(())
+}
+
+fn run_build_from_fragments(args: &FromFragmentsArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let backend = args.backend.clone();
+    let output = args.output.clone();
+
+    let backend_file = backend_output_file(&output, &backend)?;
+
+    let barcodes = read_barcode_whitelist(&args.barcodes)?;
+    info!("Read {} barcodes", barcodes.len());
+
+    let regions = match args.peaks.as_ref() {
+        Some(bed_file) => {
+            let peaks = read_bed_regions(bed_file)?;
+            info!("Read {} peaks", peaks.len());
+            FragmentRegions::Peaks(peaks)
+        }
+        None => FragmentRegions::Tiles(args.tile_size),
+    };
+
+    let counted = count_fragments(
+        &args.fragment_file,
+        &barcodes,
+        &regions,
+        args.count,
+        args.memory_budget_mb << 20,
+    )?;
+
+    if std::path::Path::new(&backend_file).exists() {
+        info!(
+            "This existing backend file '{}' will be deleted",
+            &backend_file
+        );
+        common_io::remove_file(&backend_file)?;
+    }
+
+    let nnz = counted.triplets.len();
+    let mut data = create_sparse_from_external_triplets(
+        counted.triplets,
+        (counted.row_names.len(), counted.column_names.len(), nnz),
+        Some(&backend_file),
+        Some(&backend),
+        &args.create,
+    )?;
+    data.register_row_names_vec(&counted.row_names);
+    data.register_column_names_vec(&counted.column_names);
+    data.flush_backend()?;
+
+    info!(
+        "Successfully created a sparse backend file: {}",
+        &backend_file
+    );
+
+    if args.do_squeeze {
         let squeeze_args = RunSqueezeArgs {
             data_file: backend_file.into_boxed_str(),
             row_nnz_cutoff: 0,
             column_nnz_cutoff: 0,
             block_size: 100,
+            output: None,
         };
 
         run_squeeze(&squeeze_args)?;
@@ -1016,10 +1958,7 @@ fn run_build_from_mtx(args: &FromMtxArgs) -> anyhow::Result<()> {
         }
     };
 
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => format!("{}.h5", &output),
-        SparseIoBackend::Zarr => format!("{}.zarr", &output),
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     if std::path::Path::new(&backend_file).exists() {
         info!(
@@ -1029,7 +1968,21 @@ fn run_build_from_mtx(args: &FromMtxArgs) -> anyhow::Result<()> {
         common_io::remove_file(&backend_file)?;
     }
 
-    let mut data = create_sparse_from_mtx_file(mtx_file, Some(&backend_file), Some(&backend))?;
+    let mut data = match args.memory_budget_mb {
+        Some(mb) => create_sparse_from_mtx_file_external(
+            mtx_file,
+            Some(&backend_file),
+            Some(&backend),
+            mb << 20,
+            &args.create,
+        )?,
+        None => create_sparse_from_mtx_file_with_options(
+            mtx_file,
+            Some(&backend_file),
+            Some(&backend),
+            &args.create,
+        )?,
+    };
 
     if let Some(row_file) = row_file {
         data.register_row_names_file(row_file);
@@ -1044,6 +1997,7 @@ fn run_build_from_mtx(args: &FromMtxArgs) -> anyhow::Result<()> {
         let col_names: Vec<Box<str>> = (1..(ncol + 1)).map(|i| format!("{}", i).into()).collect();
         data.register_column_names_vec(&col_names);
     }
+    data.flush_backend()?;
 
     if args.do_squeeze {
         let squeeze_args = RunSqueezeArgs {
@@ -1051,6 +2005,7 @@ fn run_build_from_mtx(args: &FromMtxArgs) -> anyhow::Result<()> {
             row_nnz_cutoff: 0,
             column_nnz_cutoff: 0,
             block_size: 100,
+            output: None,
         };
 
         run_squeeze(&squeeze_args)?;
@@ -1160,10 +2115,7 @@ fn run_build_from_h5_triplets(cmd_args: &FromH5Args) -> anyhow::Result<()> {
         }
     };
 
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => format!("{}.h5", &output),
-        SparseIoBackend::Zarr => format!("{}.zarr", &output),
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     if std::path::Path::new(&backend_file).exists() {
         info!(
@@ -1182,6 +2134,7 @@ fn run_build_from_h5_triplets(cmd_args: &FromH5Args) -> anyhow::Result<()> {
     let indptr_name = &cmd_args.indptr_field.to_string();
     let row_name = &cmd_args.row_name_field.to_string();
     let column_name = &cmd_args.column_name_field.to_string();
+    let feature_type_name = &cmd_args.feature_type_field.to_string();
 
     if let Ok(data) = file.group(group_name) {
         let (triplets, mtx_shape) =
@@ -1210,15 +2163,33 @@ fn run_build_from_h5_triplets(cmd_args: &FromH5Args) -> anyhow::Result<()> {
         info!("Read {} column names", column_names.len());
         assert_eq!(ncols, column_names.len());
 
-        let mut out = create_sparse_from_triplets(
+        let feature_types: Option<Vec<Box<str>>> = match file.dataset(feature_type_name) {
+            Ok(types) => Some(read_hdf5_strings(types)?).filter(|x| x.len() == nrows),
+            _ => None,
+        };
+
+        if cmd_args.split_feature_types && feature_types.is_none() {
+            return Err(anyhow::anyhow!(
+                "no feature types in `{}` to split rows",
+                feature_type_name
+            ));
+        }
+
+        let mut out = create_sparse_from_triplets_with_options(
             triplets,
             (nrows, ncols, nnz),
             Some(&backend_file),
             Some(&backend),
+            &cmd_args.create,
         )?;
         info!("created sparse matrix: {}", backend_file);
         out.register_row_names_vec(&row_names);
         out.register_column_names_vec(&column_names);
+
+        if let Some(feature_types) = feature_types.as_ref() {
+            register_feature_types(out.as_mut(), feature_types)?;
+        }
+        out.flush_backend()?;
         info!("done");
     } else {
         return Err(anyhow::anyhow!("data group `{}` is missing", group_name));
@@ -1227,15 +2198,116 @@ fn run_build_from_h5_triplets(cmd_args: &FromH5Args) -> anyhow::Result<()> {
     if cmd_args.do_squeeze {
         info!("Squeeze the backend data {}", &backend_file);
         let squeeze_args = RunSqueezeArgs {
-            data_file: backend_file.into_boxed_str(),
+            data_file: backend_file.clone().into_boxed_str(),
             row_nnz_cutoff: 0,
             column_nnz_cutoff: 0,
             block_size: 100,
+            output: None,
         };
 
         run_squeeze(&squeeze_args)?;
     }
 
+    if cmd_args.split_feature_types {
+        split_backend_by_feature_type(&backend_file, &backend, &output)?;
+    }
+
+    Ok(())
+}
+
+fn run_export_to_h5ad(cmd_args: &ToH5adArgs) -> anyhow::Result<()> {
+    if cmd_args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data_file = cmd_args.data_file.clone();
+
+    let backend = SparseIoBackend::from_file_name(&data_file)?;
+
+    let output = match cmd_args.output.clone() {
+        Some(output) => output,
+        None => {
+            let (dir, base, _ext) = common_io::dir_base_ext(&data_file)?;
+
+            match (dir.len(), base.len()) {
+                (0, 0) => "./".to_string().into_boxed_str(),
+                (0, _) => format!("./{}", base).into_boxed_str(),
+                _ => format!("{}/{}", dir, base).into_boxed_str(),
+            }
+        }
+    };
+
+    let h5ad_file = format!("{}.h5ad", &output);
+
+    if h5ad_file == data_file.as_ref() {
+        return Err(anyhow::anyhow!("output would overwrite {}", data_file));
+    }
+
+    common_io::mkdir(&h5ad_file)?;
+
+    let data = open_sparse_matrix(&data_file, &backend)?;
+    let column_names = data.column_names()?;
+    let ncol = column_names.len();
+
+    let mut annotations = sparse_matrix_h5ad::H5adAnnotations::default();
+
+    if let Some(batch_file) = cmd_args.batch_file.as_ref() {
+        let batches = common_io::read_lines(batch_file)?;
+        if batches.len() != ncol {
+            return Err(anyhow::anyhow!(
+                "{} has {} lines, but found {} columns",
+                batch_file,
+                batches.len(),
+                ncol
+            ));
+        }
+        annotations.obs_columns.push((
+            cmd_args.batch_column_name.clone(),
+            AnnotationColumn::categorical(&batches),
+        ));
+    }
+
+    // annotation columns stored in the backend
+    for (name, column) in data.annotations(AnnotationAxis::Obs)? {
+        let taken = annotations.obs_columns.iter().any(|(k, _)| *k == name);
+        if !taken {
+            annotations.obs_columns.push((name, column));
+        }
+    }
+
+    for latent_file in cmd_args.latent_files.iter().flatten() {
+        let (cells, _, z_nk) = Array2::<f32>::from_parquet(latent_file)?;
+
+        // `{out}.latent.parquet` -> `X_latent`
+        let key = basename(latent_file)?
+            .rsplit('.')
+            .next()
+            .map(|x| format!("X_{}", x))
+            .unwrap_or("X_latent".to_string())
+            .into_boxed_str();
+
+        if annotations.obsm.iter().any(|(k, _)| *k == key) {
+            return Err(anyhow::anyhow!(
+                "{} would be stored as `obsm/{}` as another latent file; rename either file",
+                latent_file,
+                key
+            ));
+        }
+
+        let (z_full, nmatched) = sparse_matrix_h5ad::align_obsm_rows(&cells, &z_nk, &column_names);
+
+        if nmatched == 0 {
+            return Err(anyhow::anyhow!(
+                "no row of {} matches with column names",
+                latent_file
+            ));
+        }
+        info!("{}: matched {} of {} columns", &key, nmatched, ncol);
+        annotations.obsm.push((key, z_full));
+    }
+
+    data.to_h5ad_file(&h5ad_file, &annotations)?;
+    info!("Successfully exported to {}", &h5ad_file);
     Ok(())
 }
 
@@ -1245,11 +2317,7 @@ fn take_column_names(cmd_args: &TakeColumnNamesArgs) -> anyhow::Result<()> {
     let output = cmd_args.output.clone();
     let input = cmd_args.data_file.clone();
 
-    let backend = match common_io::extension(&input)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", input)),
-    };
+    let backend = SparseIoBackend::from_file_name(&input)?;
 
     let data = open_sparse_matrix(&input, &backend.clone())?;
     let col_names = data.column_names()?;
@@ -1264,11 +2332,7 @@ fn take_row_names(cmd_args: &TakeRowNamesArgs) -> anyhow::Result<()> {
     let output = cmd_args.output.clone();
     let input = cmd_args.data_file.clone();
 
-    let backend = match common_io::extension(&input)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", input)),
-    };
+    let backend = SparseIoBackend::from_file_name(&input)?;
 
     let data = open_sparse_matrix(&input, &backend.clone())?;
 
@@ -1282,11 +2346,7 @@ fn show_info(cmd_args: &InfoArgs) -> anyhow::Result<()> {
     let output = cmd_args.output.clone();
     let input = cmd_args.data_file.clone();
 
-    let backend = match common_io::extension(&input)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", input)),
-    };
+    let backend = SparseIoBackend::from_file_name(&input)?;
 
     let data = open_sparse_matrix(&input, &backend.clone())?;
 
@@ -1298,6 +2358,10 @@ fn show_info(cmd_args: &InfoArgs) -> anyhow::Result<()> {
         println!("number_of_nonzeros:\t{}", nnz);
     }
 
+    if let Some(value_type) = data.value_type() {
+        println!("value_type:\t{}", value_type.name());
+    }
+
     if output.len() > 0 {
         use common_io::{mkdir, write_lines};
         mkdir(&output)?;
@@ -1307,6 +2371,222 @@ fn show_info(cmd_args: &InfoArgs) -> anyhow::Result<()> {
         write_lines(&col_names, &(output.to_string() + ".columns.gz"))?;
     }
 
+    for (tag, axis) in [("obs", AnnotationAxis::Obs), ("var", AnnotationAxis::Var)] {
+        for (name, column) in data.annotations(axis)? {
+            println!("{}_annotation:\t{}\t{}", tag, name, column.encoding_type());
+        }
+    }
+
+    for layer in data.layer_names()? {
+        println!("layer:\t{}", layer);
+    }
+
+    Ok(())
+}
+
+fn run_annotate(args: &AnnotateArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data_file = args.data_file.clone();
+    let backend = writable_backend(&data_file)?;
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    let categorical = args.categorical_columns.clone().unwrap_or_default();
+
+    if let Some(obs_file) = args.obs_file.as_ref() {
+        let names = data.column_names()?;
+        for (name, column) in read_annotation_table(obs_file, &names, &categorical)? {
+            data.register_column_annotation(&name, &column)?;
+            info!("registered obs/{} ({})", name, column.encoding_type());
+        }
+    }
+
+    if let Some(var_file) = args.var_file.as_ref() {
+        let names = data.row_names()?;
+        for (name, column) in read_annotation_table(var_file, &names, &categorical)? {
+            data.register_row_annotation(&name, &column)?;
+            info!("registered var/{} ({})", name, column.encoding_type());
+        }
+    }
+
+    data.flush_backend()?;
+    Ok(())
+}
+
+fn run_append(args: &AppendArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data_file = args.data_file.clone();
+    let backend = writable_backend(&data_file)?;
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    let memory_budget = args.memory_budget_mb << 20;
+    let source = args.source.clone();
+
+    if source.ends_with(".mtx") || source.ends_with(".mtx.gz") {
+        // build a temporary backend to take names and read columns
+        let temp_file = common_io::create_temp_dir_file(".zarr")?;
+        let temp_file = temp_file.to_str().expect("invalid temp file");
+        let mut source_data = create_sparse_from_mtx_file_external(
+            &source,
+            Some(temp_file),
+            Some(&SparseIoBackend::Zarr),
+            memory_budget,
+            &SparseCreateOptions::default(),
+        )?;
+
+        if let Some(row_file) = args.row.as_ref() {
+            source_data.register_row_names_file(row_file);
+        } else if source_data.num_rows() == data.num_rows() {
+            source_data.register_row_names_vec(&data.row_names()?);
+        }
+
+        if let Some(col_file) = args.col.as_ref() {
+            source_data.register_column_names_file(col_file);
+        } else if let (Some(ncol_old), Some(ncol)) = (data.num_columns(), source_data.num_columns())
+        {
+            let col_names: Vec<Box<str>> = ((ncol_old + 1)..(ncol_old + ncol + 1))
+                .map(|i| format!("{}", i).into())
+                .collect();
+            source_data.register_column_names_vec(&col_names);
+        }
+
+        let ret = data.append_columns(source_data.as_ref(), memory_budget);
+        source_data.remove_backend_file()?;
+        ret?;
+    } else {
+        let source_backend = SparseIoBackend::from_file_name(&source)?;
+        let source_data = open_sparse_matrix(&source, &source_backend)?;
+        data.append_columns(source_data.as_ref(), memory_budget)?;
+    }
+
+    data.flush_backend()?;
+    info!("appended {} to {}", source, data_file);
+    Ok(())
+}
+
+fn run_validate(args: &ValidateArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data_file = args.data_file.clone();
+    let backend = writable_backend(&data_file)?;
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    let mut report = data.validate()?;
+    println!("{}", report);
+
+    if args.repair_csr && !report.is_valid() {
+        let memory_budget = args.memory_budget_mb << 20;
+        for layer in report.repairable_layers() {
+            info!("repairing the CSR side of {:?}", layer);
+            data.repair_csr_from_csc(layer.as_deref(), memory_budget)?;
+        }
+        data.flush_backend()?;
+        report = data.validate()?;
+        println!("{}", report);
+    }
+
+    if !report.is_valid() {
+        return Err(anyhow::anyhow!("validation failed: {}", data_file));
+    }
+    Ok(())
+}
+
+fn run_pack(args: &PackArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let zarr_dir = args.zarr_dir.trim_end_matches('/');
+    let output = match args.output.as_ref() {
+        Some(output) => output.to_string(),
+        None => format!("{}.zip", zarr_dir),
+    };
+
+    if !zarr_zip_store::is_zarr_zip(&output) {
+        return Err(anyhow::anyhow!(
+            "output should end with `{}`: {}",
+            zarr_zip_store::ZARR_ZIP_SUFFIX,
+            output
+        ));
+    }
+
+    zarr_zip_store::pack_zarr_directory(zarr_dir, &output)?;
+    info!("packed {} into {}", zarr_dir, output);
+    Ok(())
+}
+
+fn run_unpack(args: &UnpackArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let zip_file = args.zip_file.clone();
+    let output = match args.output.as_ref() {
+        Some(output) => output.to_string(),
+        None => zip_file
+            .strip_suffix(".zip")
+            .ok_or(anyhow::anyhow!("not a zip file: {}", zip_file))?
+            .to_string(),
+    };
+
+    zarr_zip_store::unpack_zarr_zip(&zip_file, &output)?;
+    info!("unpacked {} into {}", zip_file, output);
+    Ok(())
+}
+
+fn run_add_layer(args: &AddLayerArgs) -> anyhow::Result<()> {
+    if args.verbose > 0 {
+        std::env::set_var("RUST_LOG", "info");
+    }
+
+    let data_file = args.data_file.clone();
+    let backend = writable_backend(&data_file)?;
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    let memory_budget = args.memory_budget_mb << 20;
+    let source = args.source.clone();
+
+    if source.ends_with(".mtx") || source.ends_with(".mtx.gz") {
+        data.import_mtx_file_as_layer(&args.name, &source, memory_budget)?;
+    } else {
+        let source_backend = SparseIoBackend::from_file_name(&source)?;
+        let source_data =
+            open_sparse_matrix_layer(&source, &source_backend, args.source_layer.as_deref())?;
+        data.register_layer_from_sparse(&args.name, source_data.as_ref(), memory_budget)?;
+    }
+
+    data.flush_backend()?;
+    info!("added layer `{}` to {}", args.name, data_file);
+    Ok(())
+}
+
+fn list_layers(args: &ListLayersArgs) -> anyhow::Result<()> {
+    let data_file = args.data_file.clone();
+    let backend = SparseIoBackend::from_file_name(&data_file)?;
+
+    let data = open_sparse_matrix(&data_file, &backend)?;
+    for layer in data.layer_names()? {
+        let nnz = data.open_layer(Some(&layer))?.num_non_zeros().unwrap_or(0);
+        println!("{}\t{}", layer, nnz);
+    }
+    Ok(())
+}
+
+fn run_drop_layer(args: &DropLayerArgs) -> anyhow::Result<()> {
+    let data_file = args.data_file.clone();
+    let backend = writable_backend(&data_file)?;
+
+    let mut data = open_sparse_matrix(&data_file, &backend)?;
+    data.remove_layer(&args.name)?;
+    data.flush_backend()?;
+    info!("removed layer `{}` from {}", args.name, data_file);
     Ok(())
 }
 
@@ -1314,26 +2594,9 @@ fn run_stat(cmd_args: &RunStatArgs) -> anyhow::Result<()> {
     let output = cmd_args.output.clone();
     common_io::mkdir(&output)?;
 
-    let file = cmd_args.data_files[0].as_ref();
-    let backend = match common_io::extension(file)?.to_string().as_str() {
-        "h5" => SparseIoBackend::HDF5,
-        "zarr" => SparseIoBackend::Zarr,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", file)),
-    };
-
     let mut data = SparseIoVec::new();
     for data_file in cmd_args.data_files.iter() {
-        match common_io::extension(data_file)?.as_ref() {
-            "zarr" => {
-                assert_eq!(backend, SparseIoBackend::Zarr);
-            }
-            "h5" => {
-                assert_eq!(backend, SparseIoBackend::HDF5);
-            }
-            _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-        };
-
-        let this_data = open_sparse_matrix(data_file, &backend)?;
+        let this_data = open_sparse_matrix_by_extension(data_file)?;
         let data_name = basename(data_file)?;
         data.push(Arc::from(this_data), Some(data_name))?;
     }
@@ -1400,21 +2663,7 @@ fn run_squeeze(cmd_args: &RunSqueezeArgs) -> anyhow::Result<()> {
 
     use common_io::extension as file_ext;
 
-    let backend = match file_ext(&data_file)?.as_ref() {
-        "zarr" => SparseIoBackend::Zarr,
-        "h5" => SparseIoBackend::HDF5,
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-    };
-
-    match file_ext(&data_file)?.as_ref() {
-        "zarr" => {
-            assert_eq!(backend, SparseIoBackend::Zarr);
-        }
-        "h5" => {
-            assert_eq!(backend, SparseIoBackend::HDF5);
-        }
-        _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-    }
+    let backend = writable_backend(&data_file)?;
 
     let data = open_sparse_matrix(&data_file, &backend)?;
 
@@ -1446,15 +2695,25 @@ fn run_squeeze(cmd_args: &RunSqueezeArgs) -> anyhow::Result<()> {
     let col_nnz_vec = col_stat.count_positives().to_vec();
     let col_idx = nnz_index(&col_nnz_vec.to_vec(), col_nnz_cutoff);
 
+    // write a new backend instead of rebuilding the data file
+    let hdr = match cmd_args.output.as_ref() {
+        Some(output) => {
+            data.set_backend_file_name(&output_backend_file(&data_file, output)?)?;
+            format!("{}.", output)
+        }
+        None => data_file.replace(file_ext(&data_file)?.as_ref(), ""),
+    };
+
     data.subset_columns_rows(Some(&col_idx), Some(&row_idx))?;
+    data.flush_backend()?;
 
     info!(
-        "after squeeze -- data: {} rows x {} columns",
+        "after squeeze -- data: {} rows x {} columns in {}",
         data.num_rows().unwrap(),
-        data.num_columns().unwrap()
+        data.num_columns().unwrap(),
+        data.get_backend_file_name()
     );
 
-    let hdr = data_file.replace(file_ext(&data_file)?.as_ref(), "");
     let row_idx_file = format!("{}row.idx.gz", hdr);
     let col_idx_file = format!("{}col.idx.gz", hdr);
 
@@ -1478,10 +2737,7 @@ fn run_simulate(cmd_args: &RunSimulateArgs) -> anyhow::Result<()> {
 
     let backend = cmd_args.backend.clone();
 
-    let backend_file = match backend {
-        SparseIoBackend::HDF5 => output.to_string() + ".h5",
-        SparseIoBackend::Zarr => output.to_string() + ".zarr",
-    };
+    let backend_file = backend_output_file(&output, &backend)?;
 
     let mtx_file = output.to_string() + ".mtx.gz";
     let row_file = output.to_string() + ".rows.gz";
@@ -1571,6 +2827,7 @@ fn run_simulate(cmd_args: &RunSimulateArgs) -> anyhow::Result<()> {
 
     data.register_row_names_vec(&rows);
     data.register_column_names_vec(&cols);
+    data.flush_backend()?;
 
     info!("done");
     Ok(())
@@ -0,0 +1,202 @@
+use crate::external_sort::STREAM_CHUNK_SIZE;
+use crate::sparse_io::*;
+
+use log::info;
+use memmap2::Mmap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Suffix of the uncompressed on-disk cache next to a backend file
+pub const MMAP_CACHE_SUFFIX: &str = ".mmap";
+
+const INDICES_FILE: &str = "indices.u64";
+const DATA_FILE: &str = "data.f32";
+const FINGERPRINT_FILE: &str = "fingerprint";
+
+/// Values and row indices of all the columns kept at hand
+#[derive(Debug)]
+pub enum PreloadedColumns {
+    /// copied into the heap
+    Heap { indices: Vec<u64>, data: Vec<f32> },
+    /// memory-mapped from the uncompressed on-disk cache, so the OS
+    /// can page them in and out
+    Mapped { indices: Mmap, data: Mmap },
+}
+
+impl PreloadedColumns {
+    pub fn indices(&self) -> &[u64] {
+        match self {
+            Self::Heap { indices, .. } => indices,
+            Self::Mapped { indices, .. } => bytemuck::cast_slice(indices),
+        }
+    }
+
+    pub fn data(&self) -> &[f32] {
+        match self {
+            Self::Heap { data, .. } => data,
+            Self::Mapped { data, .. } => bytemuck::cast_slice(data),
+        }
+    }
+}
+
+/// Directory of the uncompressed on-disk cache of a backend file
+///
+/// ```text
+/// data.zarr.mmap
+///     ├── by_column
+///     │   ├── indices.u64
+///     │   ├── data.f32
+///     │   └── fingerprint
+///     └── layers/{layer}/by_column/...
+/// ```
+pub fn mmap_cache_dir(backend_file: &str) -> PathBuf {
+    PathBuf::from(format!(
+        "{}{}",
+        backend_file.trim_end_matches('/'),
+        MMAP_CACHE_SUFFIX
+    ))
+}
+
+/// Remove the on-disk cache of a backend file, e.g., after the
+/// backend has been modified
+pub fn remove_mmap_cache(backend_file: &str) -> anyhow::Result<()> {
+    let dir = mmap_cache_dir(backend_file);
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+/// Memory-map the values and indices of a compressed (CSC) group,
+/// writing the uncompressed on-disk cache first if it is missing or
+/// doesn't match `indptr`
+///
+/// * `data` - backend
+/// * `key` - e.g., `/by_column` (of a layer)
+/// * `indptr` - column pointers of the group
+pub fn mmap_compressed_columns<T>(
+    data: &T,
+    key: &str,
+    indptr: &[u64],
+) -> anyhow::Result<PreloadedColumns>
+where
+    T: SparseIo + ?Sized,
+{
+    let nnz = indptr.last().copied().unwrap_or(0) as usize;
+
+    if nnz == 0 {
+        return Ok(PreloadedColumns::Heap {
+            indices: vec![],
+            data: vec![],
+        });
+    }
+
+    let dir = mmap_cache_dir(data.get_backend_file_name()).join(key.trim_matches('/'));
+    let fingerprint = format!(
+        "{}\t{}",
+        indptr_fingerprint(indptr),
+        data_stamp(data.get_backend_file_name(), key)?
+    );
+
+    let is_fresh = std::fs::read_to_string(dir.join(FINGERPRINT_FILE))
+        .is_ok_and(|x| x.trim() == fingerprint)
+        && file_len(&dir.join(INDICES_FILE)) == Some(nnz * 8)
+        && file_len(&dir.join(DATA_FILE)) == Some(nnz * 4);
+
+    if !is_fresh {
+        info!("writing uncompressed {} to {}", key, dir.display());
+        write_mmap_cache(data, key, nnz, &dir, &fingerprint)?;
+    }
+
+    // SAFETY: the cache files are only replaced by renaming new
+    // files, never modified in place
+    let indices = unsafe { Mmap::map(&File::open(dir.join(INDICES_FILE))?)? };
+    let values = unsafe { Mmap::map(&File::open(dir.join(DATA_FILE))?)? };
+
+    Ok(PreloadedColumns::Mapped {
+        indices,
+        data: values,
+    })
+}
+
+fn write_mmap_cache<T>(
+    data: &T,
+    key: &str,
+    nnz: usize,
+    dir: &Path,
+    fingerprint: &str,
+) -> anyhow::Result<()>
+where
+    T: SparseIo + ?Sized,
+{
+    std::fs::create_dir_all(dir)?;
+    let _ = std::fs::remove_file(dir.join(FINGERPRINT_FILE));
+
+    let indices_partial = dir.join(format!("{}.partial", INDICES_FILE));
+    let data_partial = dir.join(format!("{}.partial", DATA_FILE));
+    let mut indices_out = BufWriter::new(File::create(&indices_partial)?);
+    let mut data_out = BufWriter::new(File::create(&data_partial)?);
+
+    for lb in (0..nnz).step_by(STREAM_CHUNK_SIZE) {
+        let ub = (lb + STREAM_CHUNK_SIZE).min(nnz);
+        let (indices, values) = data.read_compressed_chunk_backend(key, lb..ub)?;
+        indices_out.write_all(bytemuck::cast_slice(&indices))?;
+        data_out.write_all(bytemuck::cast_slice(&values))?;
+    }
+
+    indices_out.flush()?;
+    data_out.flush()?;
+    drop(indices_out);
+    drop(data_out);
+
+    std::fs::rename(&indices_partial, dir.join(INDICES_FILE))?;
+    std::fs::rename(&data_partial, dir.join(DATA_FILE))?;
+    std::fs::write(dir.join(FINGERPRINT_FILE), fingerprint)?;
+    Ok(())
+}
+
+fn indptr_fingerprint(indptr: &[u64]) -> String {
+    let mut hasher = DefaultHasher::new();
+    indptr.hash(&mut hasher);
+    format!("{}\t{:x}", indptr.len(), hasher.finish())
+}
+
+/// Size and last modification time of the stored values of `key`:
+/// the `data` array in a zarr directory, otherwise the whole backend
+/// file (e.g., `.h5` or `.zarr.zip`), so that the cache goes stale
+/// whoever modifies the backend
+fn data_stamp(backend_file: &str, key: &str) -> anyhow::Result<String> {
+    let data_dir = Path::new(backend_file)
+        .join(key.trim_matches('/'))
+        .join("data");
+
+    let mut size = 0;
+    let mut modified = std::time::UNIX_EPOCH;
+    let mut paths = vec![if data_dir.is_dir() {
+        data_dir
+    } else {
+        PathBuf::from(backend_file)
+    }];
+
+    while let Some(path) = paths.pop() {
+        let meta = std::fs::metadata(&path)?;
+        if meta.is_dir() {
+            for entry in std::fs::read_dir(&path)? {
+                paths.push(entry?.path());
+            }
+        } else {
+            size += meta.len();
+            modified = modified.max(meta.modified()?);
+        }
+    }
+
+    let modified = modified.duration_since(std::time::UNIX_EPOCH)?;
+    Ok(format!("{}\t{}", size, modified.as_nanos()))
+}
+
+fn file_len(path: &Path) -> Option<usize> {
+    std::fs::metadata(path).ok().map(|x| x.len() as usize)
+}
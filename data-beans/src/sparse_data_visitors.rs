@@ -1,4 +1,6 @@
 #![allow(dead_code)]
+use crate::compressed_parts::{csc_to_ndarray, csc_to_tensor};
+use crate::sparse_io::{CscMatrix, CsrMatrix, DMatrix, Tensor};
 use crate::sparse_io_vector::SparseIoVec;
 use indicatif::ParallelProgressIterator;
 
@@ -7,6 +9,64 @@ use std::sync::{Arc, Mutex};
 
 const DEFAULT_BLOCK_SIZE: usize = 100;
 
+/// Read columns (cells) of a feature x cell matrix, either raw as in
+/// `SparseIoVec` or transformed on the fly by a view, so that column
+/// visitors can take any of them
+pub trait ReadColumnsOps: Sync + Send {
+    fn num_rows(&self) -> anyhow::Result<usize>;
+
+    fn num_columns(&self) -> anyhow::Result<usize>;
+
+    fn read_columns_csc<I>(&self, cells: I) -> anyhow::Result<CscMatrix<f32>>
+    where
+        I: Iterator<Item = usize>;
+
+    fn read_columns_csr<I>(&self, cells: I) -> anyhow::Result<CsrMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        Ok(CsrMatrix::from(&self.read_columns_csc(cells)?))
+    }
+
+    fn read_columns_dmatrix<I>(&self, cells: I) -> anyhow::Result<DMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        Ok(DMatrix::from(&self.read_columns_csc(cells)?))
+    }
+
+    fn read_columns_ndarray<I>(&self, cells: I) -> anyhow::Result<ndarray::Array2<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        csc_to_ndarray(&self.read_columns_csc(cells)?)
+    }
+
+    fn read_columns_tensor<I>(&self, cells: I) -> anyhow::Result<Tensor>
+    where
+        I: Iterator<Item = usize>,
+    {
+        csc_to_tensor(&self.read_columns_csc(cells)?)
+    }
+}
+
+impl ReadColumnsOps for SparseIoVec {
+    fn num_rows(&self) -> anyhow::Result<usize> {
+        SparseIoVec::num_rows(self)
+    }
+
+    fn num_columns(&self) -> anyhow::Result<usize> {
+        SparseIoVec::num_columns(self)
+    }
+
+    fn read_columns_csc<I>(&self, cells: I) -> anyhow::Result<CscMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        SparseIoVec::read_columns_csc(self, cells)
+    }
+}
+
 pub trait VisitColumnsOps {
     fn visit_columns_by_block<Visitor, SharedIn, SharedOut>(
         &self,
@@ -43,7 +103,10 @@ pub trait VisitColumnsOps {
         SharedOut: Sync + Send;
 }
 
-impl VisitColumnsOps for SparseIoVec {
+impl<T> VisitColumnsOps for T
+where
+    T: ReadColumnsOps,
+{
     fn visit_columns_by_block<Visitor, SharedIn, SharedOut>(
         &self,
         visitor: &Visitor,
@@ -103,6 +166,54 @@ impl VisitColumnsOps for SparseIoVec {
     }
 }
 
+/// Visit blocks of rows (features), e.g., for per-gene computation,
+/// reading each data set's `by_row` (CSR) storage
+pub trait VisitRowsOps {
+    fn visit_rows_by_block<Visitor, SharedIn, SharedOut>(
+        &self,
+        visitor: &Visitor,
+        shared_in: &SharedIn,
+        shared_out: &mut SharedOut,
+        block_size: Option<usize>,
+    ) -> anyhow::Result<()>
+    where
+        Visitor: Fn((usize, usize), &Self, &SharedIn, Arc<Mutex<&mut SharedOut>>) -> anyhow::Result<()>
+            + Sync
+            + Send,
+        SharedIn: Sync + Send,
+        SharedOut: Sync + Send;
+}
+
+impl VisitRowsOps for SparseIoVec {
+    /// The visitor takes the `[lb, ub)` range of the aligned rows and
+    /// reads them with `read_rows_*`, which concatenates the columns
+    /// of all the data sets
+    fn visit_rows_by_block<Visitor, SharedIn, SharedOut>(
+        &self,
+        visitor: &Visitor,
+        shared_in: &SharedIn,
+        shared_out: &mut SharedOut,
+        block_size: Option<usize>,
+    ) -> anyhow::Result<()>
+    where
+        Visitor: Fn((usize, usize), &Self, &SharedIn, Arc<Mutex<&mut SharedOut>>) -> anyhow::Result<()>
+            + Sync
+            + Send,
+        SharedIn: Sync + Send,
+        SharedOut: Sync + Send,
+    {
+        let ntot = self.num_rows()?;
+        let jobs = create_jobs(ntot, block_size);
+
+        let arc_shared_out = Arc::new(Mutex::new(shared_out));
+
+        jobs.par_iter()
+            .progress_count(jobs.len() as u64)
+            .map(|&(lb, ub)| visitor((lb, ub), self, shared_in, arc_shared_out.clone()))
+            .collect()
+    }
+}
+
 pub fn create_jobs(ntot: usize, block_size: Option<usize>) -> Vec<(usize, usize)> {
     let block_size = block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
     let nblock = ntot.div_ceil(block_size);
@@ -1,8 +1,15 @@
 #![allow(dead_code)]
 
+use crate::compressed_parts::*;
+use crate::external_sort::*;
+use crate::sparse_matrix_h5ad;
 use crate::sparse_matrix_hdf5;
+use crate::sparse_matrix_memory;
 use crate::sparse_matrix_zarr;
+use crate::validate::ValidationReport;
 
+pub use crate::annotation::{AnnotationAxis, AnnotationColumn};
+pub use crate::sparse_matrix_zarr::ZarrWriteOptions;
 pub use candle_util::candle_core::Tensor;
 pub use nalgebra::DMatrix;
 pub use nalgebra_sparse::{csc::CscMatrix, csr::CsrMatrix};
@@ -12,8 +19,11 @@ pub const MAX_ROW_NAME_IDX: usize = 3;
 pub const MAX_COLUMN_NAME_IDX: usize = 10;
 pub const COLUMN_SEP: &str = "@";
 pub const ROW_SEP: &str = "_";
+pub const LAYERS_GROUP: &str = "/layers";
+pub const MEMORY_BACKEND_NAME: &str = "(memory)";
+pub const VALUE_TYPE_ATTR: &str = "value_type";
 
-use clap::ValueEnum;
+use clap::{Args, ValueEnum};
 use indicatif::ParallelProgressIterator;
 use log::info;
 use matrix_util::mtx_io::*;
@@ -28,11 +38,195 @@ use std::sync::{Arc, Mutex};
 pub enum SparseIoBackend {
     Zarr,
     HDF5,
+    /// read-only AnnData `.h5ad`
+    H5ad,
+    /// in-memory data without a backing file
+    Memory,
+}
+
+impl SparseIoBackend {
+    /// Figure out the backend from the file extension
+    /// * `backend_file`: `.zarr`, `.h5`, or `.h5ad` file
+    pub fn from_file_name(backend_file: &str) -> anyhow::Result<Self> {
+        if crate::zarr_zip_store::is_zarr_zip(backend_file) {
+            return Ok(SparseIoBackend::Zarr);
+        }
+
+        let ext = std::path::Path::new(backend_file)
+            .extension()
+            .and_then(|x| x.to_str());
+        match ext {
+            Some("zarr") => Ok(SparseIoBackend::Zarr),
+            Some("h5") => Ok(SparseIoBackend::HDF5),
+            Some("h5ad") => Ok(SparseIoBackend::H5ad),
+            _ => Err(anyhow::anyhow!(
+                "Unknown file format: {} (expected `.zarr`, `.zarr.zip`, `.h5`, or `.h5ad`)",
+                backend_file
+            )),
+        }
+    }
+}
+
+/// A new backend file name for the output header, `{output}.zarr`
+/// or `{output}.h5`
+/// * `output`: output file header
+/// * `backend`: zarr or hdf5; h5ad and memory can't be created as files
+pub fn backend_output_file(output: &str, backend: &SparseIoBackend) -> anyhow::Result<String> {
+    match backend {
+        SparseIoBackend::HDF5 => Ok(format!("{}.h5", output)),
+        SparseIoBackend::Zarr => Ok(format!("{}.zarr", output)),
+        SparseIoBackend::H5ad | SparseIoBackend::Memory => {
+            Err(anyhow::anyhow!("can't create a {:?} backend file", backend))
+        }
+    }
+}
+
+/// Type of the values (`data`) stored in a backend, from the
+/// narrowest to the widest. Values are parsed and read back as `f32`
+/// regardless, so integers above 16777216 are already rounded before
+/// they are stored, and `f64` keeps no more precision than `f32`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[clap(rename_all = "lowercase")]
+pub enum ValueType {
+    /// non-negative integers up to 65535, e.g., UMI counts
+    U16,
+    /// non-negative integers up to 4294967295
+    U32,
+    F32,
+    F64,
+}
+
+/// How to write a new backend: the layout of zarr arrays and the
+/// type of the stored values
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct SparseCreateOptions {
+    /// type of the stored values (default: `u16` or `u32` for
+    /// integer counts, otherwise `f32`)
+    #[arg(long, value_enum)]
+    pub value_type: Option<ValueType>,
+
+    #[command(flatten)]
+    pub zarr: ZarrWriteOptions,
+}
+
+/// How to preload the columns of a data file before reading them
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct PreloadArgs {
+    /// preload all the columns data
+    #[arg(long, default_value_t = false)]
+    pub preload_data: bool,
+
+    /// preload the columns by memory-mapping an uncompressed copy
+    /// kept next to each data file (`{data_file}.mmap`, written once)
+    /// instead of copying them into memory; implies `--preload-data`
+    #[arg(long, default_value_t = false)]
+    pub preload_mmap: bool,
+}
+
+impl PreloadArgs {
+    /// Preload the columns of `data` as asked, if at all
+    pub fn preload(&self, data: &mut dyn SparseIo<IndexIter = Vec<usize>>) -> anyhow::Result<()> {
+        if self.preload_mmap {
+            data.preload_columns_mmap()
+        } else if self.preload_data {
+            data.preload_columns()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl ValueType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValueType::U16 => "u16",
+            ValueType::U32 => "u32",
+            ValueType::F32 => "f32",
+            ValueType::F64 => "f64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        <Self as ValueEnum>::from_str(name, true)
+            .map_err(|_| anyhow::anyhow!("unknown value type: {}", name))
+    }
+
+    /// Whether `x` can be stored as this type without any further loss
+    pub fn holds(&self, x: f32) -> bool {
+        let is_count = |max: f64| x >= 0. && x.fract() == 0. && (x as f64) <= max;
+        match self {
+            ValueType::U16 => is_count(u16::MAX as f64),
+            ValueType::U32 => is_count(u32::MAX as f64),
+            ValueType::F32 | ValueType::F64 => true,
+        }
+    }
+
+    /// This type or the narrowest wider one that holds `x`
+    pub fn widen(self, x: f32) -> Self {
+        [ValueType::U16, ValueType::U32, ValueType::F32]
+            .into_iter()
+            .find(|value_type| value_type.holds(x))
+            .map_or(self, |value_type| value_type.max(self))
+    }
+
+    /// The narrowest type that holds all the `values`: `u16` or `u32`
+    /// for counts, otherwise `f32`
+    pub fn detect<'a, I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = &'a f32>,
+    {
+        values
+            .into_iter()
+            .fold(ValueType::U16, |value_type, &x| value_type.widen(x))
+    }
+
+    /// Convert `values` to be stored as this type
+    /// * `cast` - e.g., `|x| x as u16`
+    pub fn checked_values<V, F>(&self, values: &[f32], cast: F) -> anyhow::Result<Vec<V>>
+    where
+        F: Fn(f32) -> V,
+    {
+        self.check(values)?;
+        Ok(values.iter().map(|&x| cast(x)).collect())
+    }
+
+    /// Fail on the first of the `values` this type can't hold
+    pub fn check(&self, values: &[f32]) -> anyhow::Result<()> {
+        match values.iter().find(|&&x| !self.holds(x)) {
+            Some(x) => Err(anyhow::anyhow!(
+                "{} can't be stored as {}; try `--value-type f32`",
+                x,
+                self.name()
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Extension of a backend file, where a zarr store packed in a zip
+/// archive (`.zarr.zip`) counts as `zarr`
+/// * `backend_file`: file path to the sparse matrix
+pub fn backend_extension(backend_file: &str) -> anyhow::Result<Box<str>> {
+    if crate::zarr_zip_store::is_zarr_zip(backend_file) {
+        return Ok("zarr".into());
+    }
+    matrix_util::common_io::extension(backend_file)
+}
+
+/// Open a sparse matrix io (backend) after figuring out the backend
+/// of each file separately, so that `.zarr`, `.h5`, and `.h5ad`
+/// files can be mixed in one `SparseIoVec`
+/// * `backend_file`: file path to the sparse matrix
+pub fn open_sparse_matrix_by_extension(
+    backend_file: &str,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let backend = SparseIoBackend::from_file_name(backend_file)?;
+    open_sparse_matrix(backend_file, &backend)
 }
 
 /// Open a sparse matrix io (backend)
 /// * `backend_file`: file path to the sparse matrix
-/// * `backend`: backend type (HDF5 or Zarr)
+/// * `backend`: backend type (HDF5, Zarr, or H5ad)
 pub fn open_sparse_matrix(
     backend_file: &str,
     backend: &SparseIoBackend,
@@ -44,6 +238,29 @@ pub fn open_sparse_matrix(
         SparseIoBackend::HDF5 => Ok(Box::new(sparse_matrix_hdf5::SparseMtxData::open(
             backend_file,
         )?)),
+        SparseIoBackend::H5ad => Ok(Box::new(sparse_matrix_h5ad::SparseMtxData::open(
+            backend_file,
+        )?)),
+        SparseIoBackend::Memory => Err(anyhow::anyhow!(
+            "memory backend has no file to open: {}",
+            backend_file
+        )),
+    }
+}
+
+/// Open a layer of a sparse matrix io (backend)
+/// * `backend_file`: file path to the sparse matrix
+/// * `backend`: backend type (HDF5, Zarr, or H5ad)
+/// * `layer`: name of the layer (the main matrix if `None`)
+pub fn open_sparse_matrix_layer(
+    backend_file: &str,
+    backend: &SparseIoBackend,
+    layer: Option<&str>,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let data = open_sparse_matrix(backend_file, backend)?;
+    match layer {
+        Some(_) => data.open_layer(layer),
+        None => Ok(data),
     }
 }
 
@@ -55,51 +272,147 @@ pub fn create_sparse_from_triplets(
     mtx_shape: (usize, usize, usize),
     backend_file: Option<&str>,
     backend: Option<&SparseIoBackend>,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    create_sparse_from_triplets_with_options(
+        triplets,
+        mtx_shape,
+        backend_file,
+        backend,
+        &SparseCreateOptions::default(),
+    )
+}
+
+/// Create a sparse matrix io (backend) with triplets
+/// * `backend_file`: file path to the sparse matrix
+/// * `backend`: backend type (HDF5 or Zarr)
+/// * `options`: zarr layout and the type of the stored values
+pub fn create_sparse_from_triplets_with_options(
+    triplets: Vec<(u64, u64, f32)>,
+    mtx_shape: (usize, usize, usize),
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+    options: &SparseCreateOptions,
 ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
     let mut triplets: Vec<(u64, u64, f32)> = triplets.clone();
 
+    let mut ret = new_sparse_backend(backend_file, backend, options)?;
+    ret.record_mtx_shape(Some(mtx_shape))?;
+    ret.record_triplets_by_col(&mut triplets)?;
+    ret.record_triplets_by_row(&mut triplets)?;
+    ret.read_column_indptr()?;
+    ret.read_row_indptr()?;
+    Ok(ret)
+}
+
+/// Create a sparse matrix io (backend) with 10x mtx
+/// * `mtx_file`: file path to the 10x mtx
+/// * `backend_file`: file path to the sparse matrix
+/// * `backend`: backend type (HDF5 or Zarr)
+pub fn create_sparse_from_mtx_file(
+    mtx_file: &str,
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    create_sparse_from_mtx_file_with_options(
+        mtx_file,
+        backend_file,
+        backend,
+        &SparseCreateOptions::default(),
+    )
+}
+
+/// Create a sparse matrix io (backend) with 10x mtx
+/// * `mtx_file`: file path to the 10x mtx
+/// * `backend_file`: file path to the sparse matrix
+/// * `backend`: backend type (HDF5 or Zarr)
+/// * `options`: zarr layout and the type of the stored values
+pub fn create_sparse_from_mtx_file_with_options(
+    mtx_file: &str,
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+    options: &SparseCreateOptions,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let value_type = options.value_type;
     match backend {
-        Some(SparseIoBackend::HDF5) => {
-            let mut ret = Box::new(sparse_matrix_hdf5::SparseMtxData::new(backend_file)?);
+        Some(SparseIoBackend::H5ad) => Err(read_only_h5ad_error()),
+
+        Some(SparseIoBackend::Memory) => Ok(Box::new(
+            sparse_matrix_memory::SparseMtxData::from_mtx_file(mtx_file, Some(true), value_type)?,
+        )),
 
-            ret.record_mtx_shape(Some(mtx_shape))?;
-            ret.record_triplets_by_col(&mut triplets)?;
-            ret.record_triplets_by_row(&mut triplets)?;
-            ret.read_column_indptr()?;
-            ret.read_row_indptr()?;
-            Ok(ret)
+        Some(SparseIoBackend::HDF5) => {
+            Ok(Box::new(sparse_matrix_hdf5::SparseMtxData::from_mtx_file(
+                mtx_file,
+                backend_file,
+                Some(true),
+                value_type,
+            )?))
         }
 
         Some(SparseIoBackend::Zarr) | None => {
-            let mut ret = Box::new(sparse_matrix_zarr::SparseMtxData::new(backend_file)?);
-            ret.record_mtx_shape(Some(mtx_shape))?;
-            ret.record_triplets_by_col(&mut triplets)?;
-            ret.record_triplets_by_row(&mut triplets)?;
-            ret.read_column_indptr()?;
-            ret.read_row_indptr()?;
-            Ok(ret)
+            Ok(Box::new(sparse_matrix_zarr::SparseMtxData::from_mtx_file(
+                mtx_file,
+                backend_file,
+                Some(true),
+                &options.zarr,
+                value_type,
+            )?))
         }
     }
 }
 
-/// Create a sparse matrix io (backend) with 10x mtx
+/// Create a sparse matrix io (backend) with triplets sorted out of
+/// core within a memory budget
+/// * `triplets`: triplets spilled to disk
+/// * `mtx_shape`: (nrow, ncol, nnz)
+/// * `backend_file`: file path to the sparse matrix
+/// * `backend`: backend type (HDF5 or Zarr)
+/// * `options`: zarr layout and the type of the stored values
+///   (detected while the triplets were pushed if not given)
+pub fn create_sparse_from_external_triplets(
+    triplets: ExternalTriplets,
+    mtx_shape: (usize, usize, usize),
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+    options: &SparseCreateOptions,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut ret = new_sparse_backend(backend_file, backend, options)?;
+    ret.set_value_type(options.value_type.unwrap_or(triplets.value_type()))?;
+    ret.record_mtx_shape(Some(mtx_shape))?;
+    ret.record_external_triplets(triplets)?;
+    ret.read_column_indptr()?;
+    ret.read_row_indptr()?;
+    Ok(ret)
+}
+
+/// Create a sparse matrix io (backend) with 10x mtx without loading
+/// all the triplets in memory
 /// * `mtx_file`: file path to the 10x mtx
 /// * `backend_file`: file path to the sparse matrix
 /// * `backend`: backend type (HDF5 or Zarr)
-pub fn create_sparse_from_mtx_file(
+/// * `memory_budget`: bytes to hold triplets in memory
+/// * `options`: zarr layout and the type of the stored values
+pub fn create_sparse_from_mtx_file_external(
     mtx_file: &str,
     backend_file: Option<&str>,
     backend: Option<&SparseIoBackend>,
+    memory_budget: usize,
+    options: &SparseCreateOptions,
 ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
-    match backend {
-        Some(SparseIoBackend::HDF5) => Ok(Box::new(
-            sparse_matrix_hdf5::SparseMtxData::from_mtx_file(mtx_file, backend_file, Some(true))?,
-        )),
-
-        Some(SparseIoBackend::Zarr) | None => Ok(Box::new(
-            sparse_matrix_zarr::SparseMtxData::from_mtx_file(mtx_file, backend_file, Some(true))?,
-        )),
+    let mut triplets = ExternalTriplets::new(memory_budget)?;
+    let (nrow, ncol, _) = visit_mtx_triplets(mtx_file, &mut |x| triplets.push(x))?;
+    info!("read mtx file: {}", mtx_file);
+    if triplets.is_empty() {
+        return Err(anyhow::anyhow!("No data in mtx file"));
     }
+    let nnz = triplets.len();
+    create_sparse_from_external_triplets(
+        triplets,
+        (nrow, ncol, nnz),
+        backend_file,
+        backend,
+        options,
+    )
 }
 
 /// Create a sparse matrix io (backend) with dense `Array2`
@@ -112,13 +425,24 @@ pub fn create_sparse_from_ndarray(
     backend: Option<&SparseIoBackend>,
 ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
     match backend {
+        Some(SparseIoBackend::H5ad) => Err(read_only_h5ad_error()),
+
+        Some(SparseIoBackend::Memory) => Ok(Box::new(
+            sparse_matrix_memory::SparseMtxData::from_ndarray(data, Some(true))?,
+        )),
+
         Some(SparseIoBackend::HDF5) => Ok(Box::new(
             sparse_matrix_hdf5::SparseMtxData::from_ndarray(data, backend_file, Some(true))?,
         )),
 
-        Some(SparseIoBackend::Zarr) | None => Ok(Box::new(
-            sparse_matrix_zarr::SparseMtxData::from_ndarray(data, backend_file, Some(true))?,
-        )),
+        Some(SparseIoBackend::Zarr) | None => {
+            Ok(Box::new(sparse_matrix_zarr::SparseMtxData::from_ndarray(
+                data,
+                backend_file,
+                Some(true),
+                &ZarrWriteOptions::default(),
+            )?))
+        }
     }
 }
 
@@ -132,14 +456,53 @@ pub fn create_sparse_from_dmatrix(
     backend: Option<&SparseIoBackend>,
 ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
     match backend {
+        Some(SparseIoBackend::H5ad) => Err(read_only_h5ad_error()),
+
+        Some(SparseIoBackend::Memory) => Ok(Box::new(
+            sparse_matrix_memory::SparseMtxData::from_dmatrix(data, Some(true))?,
+        )),
+
         Some(SparseIoBackend::HDF5) => Ok(Box::new(
             sparse_matrix_hdf5::SparseMtxData::from_dmatrix(data, backend_file, Some(true))?,
         )),
 
-        Some(SparseIoBackend::Zarr) | None => Ok(Box::new(
-            sparse_matrix_zarr::SparseMtxData::from_dmatrix(data, backend_file, Some(true))?,
-        )),
+        Some(SparseIoBackend::Zarr) | None => {
+            Ok(Box::new(sparse_matrix_zarr::SparseMtxData::from_dmatrix(
+                data,
+                backend_file,
+                Some(true),
+                &ZarrWriteOptions::default(),
+            )?))
+        }
+    }
+}
+
+/// An empty backend to be filled, with the value type set if given
+fn new_sparse_backend(
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+    options: &SparseCreateOptions,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut ret: Box<dyn SparseIo<IndexIter = Vec<usize>>> = match backend {
+        Some(SparseIoBackend::H5ad) => return Err(read_only_h5ad_error()),
+        Some(SparseIoBackend::Memory) => Box::new(sparse_matrix_memory::SparseMtxData::new()),
+        Some(SparseIoBackend::HDF5) => {
+            Box::new(sparse_matrix_hdf5::SparseMtxData::new(backend_file)?)
+        }
+        Some(SparseIoBackend::Zarr) | None => Box::new(sparse_matrix_zarr::SparseMtxData::new(
+            backend_file,
+            &options.zarr,
+        )?),
+    };
+
+    if let Some(value_type) = options.value_type {
+        ret.set_value_type(value_type)?;
     }
+    Ok(ret)
+}
+
+fn read_only_h5ad_error() -> anyhow::Error {
+    anyhow::anyhow!("h5ad backend is read-only; use zarr or hdf5 to create a new one")
 }
 
 /////////////////////
@@ -163,80 +526,92 @@ pub trait SparseIo: Sync + Send {
     /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_columns_ndarray(&self, columns: Self::IndexIter) -> anyhow::Result<Array2<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_columns(columns)?;
-        Array2::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        csc_to_ndarray(&self.read_columns_csc(columns)?)
     }
 
     /// Read columns within the range and return dense `candle_core::Tensor`
     /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_columns_tensor(&self, columns: Self::IndexIter) -> anyhow::Result<Tensor> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_columns(columns)?;
-        Tensor::from_nonzero_triplets(nrow, ncol, triplets)
+        csc_to_tensor(&self.read_columns_csc(columns)?)
     }
 
     /// Read columns within the range and return dense `nalgebrea::DMatrix`
     /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_columns_dmatrix(&self, columns: Self::IndexIter) -> anyhow::Result<DMatrix<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_columns(columns)?;
-        DMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(DMatrix::from(&self.read_columns_csc(columns)?))
     }
 
     /// Read columns within the range and return sparse `CsrMatrix`
     /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_columns_csr(&self, columns: Self::IndexIter) -> anyhow::Result<CsrMatrix<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_columns(columns)?;
-        CsrMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(CsrMatrix::from(&self.read_columns_csc(columns)?))
     }
 
-    /// Read columns within the range and return sparse `CsrMatrix`
+    /// Read columns within the range and return sparse `CscMatrix`
     /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
+    /// Backends override this to build the matrix straight from the
+    /// stored `indptr`; the other `read_columns_*` go through it.
     fn read_columns_csc(&self, columns: Self::IndexIter) -> anyhow::Result<CscMatrix<f32>> {
         let (nrow, ncol, triplets) = self.read_triplets_by_columns(columns)?;
         CscMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
     }
 
+    /// Read a contiguous range of columns and return sparse
+    /// `CscMatrix` without collecting the column indices first
+    /// * `columns` : range e.g., 0..3
+    ///
+    fn read_column_range_csc(&self, columns: Range<usize>) -> anyhow::Result<CscMatrix<f32>> {
+        self.read_columns_csc(columns.collect())
+    }
+
     /// Read rows within the range and return dense `ndarray::Array2`
     /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_rows_ndarray(&self, rows: Self::IndexIter) -> anyhow::Result<Array2<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_rows(rows)?;
-        Array2::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        csr_to_ndarray(&self.read_rows_csr(rows)?)
     }
 
     /// Read rows within the range and return dense `candle_core::Tensor`
     /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_rows_tensor(&self, rows: Self::IndexIter) -> anyhow::Result<Tensor> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_rows(rows)?;
-        Tensor::from_nonzero_triplets(nrow, ncol, triplets)
+        csr_to_tensor(&self.read_rows_csr(rows)?)
     }
 
     /// Read rows within the range and return dense `nalgebra::DMatrix`
     /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_rows_dmatrix(&self, rows: Self::IndexIter) -> anyhow::Result<DMatrix<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_rows(rows)?;
-        DMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(DMatrix::from(&self.read_rows_csr(rows)?))
     }
 
     /// Read rows within the range and return sparse `CsrMatrix`
     /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
+    /// Backends override this to build the matrix straight from the
+    /// stored `indptr`; the other `read_rows_*` go through it.
     fn read_rows_csr(&self, rows: Self::IndexIter) -> anyhow::Result<CsrMatrix<f32>> {
         let (nrow, ncol, triplets) = self.read_triplets_by_rows(rows)?;
         CsrMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
     }
 
+    /// Read a contiguous range of rows and return sparse `CsrMatrix`
+    /// without collecting the row indices first
+    /// * `rows` : range e.g., 0..3
+    ///
+    fn read_row_range_csr(&self, rows: Range<usize>) -> anyhow::Result<CsrMatrix<f32>> {
+        self.read_rows_csr(rows.collect())
+    }
+
     /// Read rows within the range and return sparse `CscMatrix`
     /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
     ///
     fn read_rows_csc(&self, rows: Self::IndexIter) -> anyhow::Result<CscMatrix<f32>> {
-        let (nrow, ncol, triplets) = self.read_triplets_by_rows(rows)?;
-        CscMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(CscMatrix::from(&self.read_rows_csr(rows)?))
     }
 
     /////////////////////////////
@@ -359,10 +734,38 @@ pub trait SparseIo: Sync + Send {
         col: usize,
     ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)>;
 
+    /// Read a contiguous range of columns and return a vector of
+    /// triplets (row, col, value) without collecting the column
+    /// indices first
+    /// * `columns` : range e.g., 0..3
+    ///
+    fn read_triplets_by_column_range(
+        &self,
+        columns: Range<usize>,
+    ) -> anyhow::Result<(usize, usize, Vec<Triplet>)> {
+        let x = self.read_column_range_csc(columns)?;
+        let triplets = x
+            .triplet_iter()
+            .map(|(i, j, &v)| (i as u64, j as u64, v))
+            .collect();
+        Ok((x.nrows(), x.ncols(), triplets))
+    }
+
     /// Export the data to a mtx file. This will take time.
     /// * `mtx_file`: mtx file to be written
     fn to_mtx_file(&self, mtx_file: &str) -> anyhow::Result<()>;
 
+    /// Export the data to an AnnData `.h5ad` file (cells as rows in CSR)
+    /// * `h5ad_file`: h5ad file to be written
+    /// * `annotations`: `obs` columns and `obsm` matrices to attach
+    fn to_h5ad_file(
+        &self,
+        h5ad_file: &str,
+        annotations: &sparse_matrix_h5ad::H5adAnnotations,
+    ) -> anyhow::Result<()> {
+        sparse_matrix_h5ad::write_h5ad(self, h5ad_file, annotations)
+    }
+
     /// Number of rows in the underlying data matrix
     fn num_rows(&self) -> Option<usize>;
 
@@ -414,6 +817,542 @@ pub trait SparseIo: Sync + Send {
     /// * `key`: key for the registered names
     fn retrieve_registered_names(&self, key: &str) -> anyhow::Result<Vec<Box<str>>>;
 
+    ////////////////////////////////
+    // `obs` and `var` annotation //
+    ////////////////////////////////
+
+    /// Add (or replace) an annotation column
+    /// * `axis`: `Obs` (per column) or `Var` (per row)
+    /// * `name`: name of the annotation, e.g., `donor`
+    /// * `column`: categorical, numeric or string values
+    fn register_annotation(
+        &mut self,
+        axis: AnnotationAxis,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()>;
+
+    /// Get back the annotation column
+    /// * `axis`: `Obs` (per column) or `Var` (per row)
+    /// * `name`: name of the annotation
+    fn retrieve_annotation(
+        &self,
+        axis: AnnotationAxis,
+        name: &str,
+    ) -> anyhow::Result<AnnotationColumn>;
+
+    /// Names of the annotation columns (empty if nothing registered)
+    /// * `axis`: `Obs` (per column) or `Var` (per row)
+    fn annotation_names(&self, axis: AnnotationAxis) -> anyhow::Result<Vec<Box<str>>>;
+
+    /// All the annotation columns along the `axis`
+    fn annotations(
+        &self,
+        axis: AnnotationAxis,
+    ) -> anyhow::Result<Vec<(Box<str>, AnnotationColumn)>> {
+        self.annotation_names(axis)?
+            .into_iter()
+            .map(|name| {
+                let column = self.retrieve_annotation(axis, &name)?;
+                Ok((name, column))
+            })
+            .collect()
+    }
+
+    /// Add a per-column (cell) annotation
+    /// * `name`: name of the annotation, e.g., `donor`
+    /// * `column`: one value for each column
+    fn register_column_annotation(
+        &mut self,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        if Some(column.len()) != self.num_columns() {
+            return Err(anyhow::anyhow!(
+                "annotation `{}` has {} values, but {:?} columns",
+                name,
+                column.len(),
+                self.num_columns()
+            ));
+        }
+        self.register_annotation(AnnotationAxis::Obs, name, column)
+    }
+
+    /// Add a per-row (feature) annotation
+    /// * `name`: name of the annotation
+    /// * `column`: one value for each row
+    fn register_row_annotation(
+        &mut self,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        if Some(column.len()) != self.num_rows() {
+            return Err(anyhow::anyhow!(
+                "annotation `{}` has {} values, but {:?} rows",
+                name,
+                column.len(),
+                self.num_rows()
+            ));
+        }
+        self.register_annotation(AnnotationAxis::Var, name, column)
+    }
+
+    fn column_annotation(&self, name: &str) -> anyhow::Result<AnnotationColumn> {
+        self.retrieve_annotation(AnnotationAxis::Obs, name)
+    }
+
+    fn row_annotation(&self, name: &str) -> anyhow::Result<AnnotationColumn> {
+        self.retrieve_annotation(AnnotationAxis::Var, name)
+    }
+
+    ///////////////////////////////////////////
+    // named layers sharing rows and columns //
+    ///////////////////////////////////////////
+
+    /// Names of the layers kept in addition to the main matrix
+    /// (empty if nothing registered)
+    fn layer_names(&self) -> anyhow::Result<Vec<Box<str>>>;
+
+    /// Open the layer `layer` (or the main matrix if `None`) as
+    /// another sparse matrix sharing row/column names and annotations
+    /// * `layer`: name of the layer, e.g., `spliced`
+    fn open_layer(
+        &self,
+        layer: Option<&str>,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Self::IndexIter>>>;
+
+    /// Remove the layer `layer` from the backend
+    fn remove_layer(&mut self, layer: &str) -> anyhow::Result<()>;
+
+    /// Check if the layer `layer` exists
+    fn has_layer(&self, layer: &str) -> anyhow::Result<bool> {
+        Ok(self.layer_names()?.iter().any(|x| x.as_ref() == layer))
+    }
+
+    /// Add (or replace) a layer with triplets of the same shape as
+    /// the main matrix
+    /// * `layer`: name of the layer
+    /// * `row_col_val_triplets`: (row, column, value) triplets
+    fn register_layer_triplets(
+        &mut self,
+        layer: &str,
+        row_col_val_triplets: &mut Vec<(u64, u64, f32)>,
+    ) -> anyhow::Result<()> {
+        let (nrow, ncol) = self.layer_shape(layer)?;
+        let nnz = row_col_val_triplets.len();
+
+        if nnz == 0 {
+            return Err(anyhow::anyhow!("no data for the layer `{}`", layer));
+        }
+
+        if row_col_val_triplets
+            .iter()
+            .any(|&(i, j, _)| i as usize >= nrow || j as usize >= ncol)
+        {
+            return Err(anyhow::anyhow!(
+                "triplets out of the shape {} x {}",
+                nrow,
+                ncol
+            ));
+        }
+
+        if self.has_layer(layer)? {
+            info!("replacing the existing layer `{}`", layer);
+            self.remove_layer(layer)?;
+        }
+
+        let value_type = self.widen_value_type(ValueType::detect(
+            row_col_val_triplets.iter().map(|(_, _, x)| x),
+        ))?;
+
+        row_col_val_triplets.par_sort_by_key(|&(i, j, _)| (j, i));
+        let mut by_column = row_col_val_triplets.iter().map(|&(i, j, x)| Ok((j, i, x)));
+        let key = layer_key(Some(layer), "by_column");
+        self.record_sorted_triplets_backend(&key, ncol, nnz, value_type, &mut by_column)?;
+
+        row_col_val_triplets.par_sort_by_key(|&(i, j, _)| (i, j));
+        let mut by_row = row_col_val_triplets.iter().map(|&x| Ok(x));
+        let key = layer_key(Some(layer), "by_row");
+        self.record_sorted_triplets_backend(&key, nrow, nnz, value_type, &mut by_row)?;
+
+        info!("registered layer `{}` with {} non-zeros", layer, nnz);
+        Ok(())
+    }
+
+    /// Add (or replace) a layer from an mtx file of the same shape as
+    /// the main matrix, sorting triplets out of core
+    /// * `layer`: name of the layer
+    /// * `mtx_file`: mtx file to be read into the layer
+    /// * `memory_budget`: bytes to hold triplets in memory
+    fn import_mtx_file_as_layer(
+        &mut self,
+        layer: &str,
+        mtx_file: &str,
+        memory_budget: usize,
+    ) -> anyhow::Result<()> {
+        let (nrow, ncol) = self.layer_shape(layer)?;
+
+        let mut triplets = ExternalTriplets::new(memory_budget)?;
+        let (nrow_mtx, ncol_mtx, _) = visit_mtx_triplets(mtx_file, &mut |x| triplets.push(x))?;
+
+        if (nrow_mtx, ncol_mtx) != (nrow, ncol) {
+            return Err(anyhow::anyhow!(
+                "{} x {} in {} != {} x {}",
+                nrow_mtx,
+                ncol_mtx,
+                mtx_file,
+                nrow,
+                ncol
+            ));
+        }
+
+        self.register_layer_external_triplets(layer, triplets)?;
+
+        info!("registered layer `{}` from {}", layer, mtx_file);
+        Ok(())
+    }
+
+    /// Add (or replace) a layer by copying another sparse matrix
+    /// with the same row and column names, e.g., spliced counts
+    /// * `layer`: name of the layer
+    /// * `source`: sparse matrix to be copied
+    /// * `memory_budget`: bytes to hold triplets in memory
+    fn register_layer_from_sparse(
+        &mut self,
+        layer: &str,
+        source: &dyn SparseIo<IndexIter = Self::IndexIter>,
+        memory_budget: usize,
+    ) -> anyhow::Result<()> {
+        if source.row_names()? != self.row_names()? {
+            return Err(anyhow::anyhow!("Row names mismatched"));
+        }
+        if source.column_names()? != self.column_names()? {
+            return Err(anyhow::anyhow!("Column names mismatched"));
+        }
+
+        let ncol = source
+            .num_columns()
+            .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+
+        let mut triplets = ExternalTriplets::new(memory_budget)?;
+        let block_size = 100;
+
+        for lb in (0..ncol).step_by(block_size) {
+            let ub = (lb + block_size).min(ncol);
+            let (_, _, triplets_b) = source.read_triplets_by_column_range(lb..ub)?;
+            for (i, j, x) in triplets_b {
+                triplets.push((i, j + lb as u64, x))?;
+            }
+        }
+
+        self.register_layer_external_triplets(layer, triplets)?;
+
+        info!(
+            "registered layer `{}` from {}",
+            layer,
+            source.get_backend_file_name()
+        );
+        Ok(())
+    }
+
+    /// Add (or replace) a layer with triplets sorted out of core
+    /// * `layer`: name of the layer
+    /// * `triplets`: triplets within the shape of the main matrix
+    fn register_layer_external_triplets(
+        &mut self,
+        layer: &str,
+        triplets: ExternalTriplets,
+    ) -> anyhow::Result<()> {
+        self.layer_shape(layer)?;
+
+        if triplets.is_empty() {
+            return Err(anyhow::anyhow!("no data for the layer `{}`", layer));
+        }
+
+        if self.has_layer(layer)? {
+            info!("replacing the existing layer `{}`", layer);
+            self.remove_layer(layer)?;
+        }
+        self.record_external_triplets_in_layer(Some(layer), triplets)
+    }
+
+    /// Check the name of a new layer and return the shape (nrow,
+    /// ncol) that the layer should have
+    fn layer_shape(&self, layer: &str) -> anyhow::Result<(usize, usize)> {
+        if layer.is_empty() || layer.contains('/') {
+            return Err(anyhow::anyhow!("invalid layer name: `{}`", layer));
+        }
+
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow::anyhow!("should have `nrow`"))?;
+        let ncol = self
+            .num_columns()
+            .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+        Ok((nrow, ncol))
+    }
+
+    /// Collect triplets of all the layers after remapping rows and
+    /// columns; elements not found in the maps will be dropped
+    /// * `old2new_rows`: old to new row indices
+    /// * `old2new_cols`: old to new column indices
+    fn remap_layer_triplets(
+        &self,
+        old2new_rows: &HashMap<u64, u64>,
+        old2new_cols: &HashMap<u64, u64>,
+    ) -> anyhow::Result<Vec<(Box<str>, Vec<Triplet>)>> {
+        let mut ret = vec![];
+        for layer in self.layer_names()? {
+            info!("remapping triplets in the layer `{}` ...", layer);
+            let data = self.open_layer(Some(&layer))?;
+            let triplets = old2new_cols
+                .par_iter()
+                .map(|(&j, &j_new)| {
+                    let (_, _, triplets_j) = data.read_triplets_by_single_column(j as usize)?;
+                    Ok(triplets_j
+                        .into_iter()
+                        .filter_map(|(i, _, x)| {
+                            old2new_rows.get(&i).map(|&i_new| (i_new, j_new, x))
+                        })
+                        .collect::<Vec<_>>())
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect();
+            ret.push((layer, triplets));
+        }
+        Ok(ret)
+    }
+
+    ///////////////////////////////////////////////
+    // appending columns to the existing backend //
+    ///////////////////////////////////////////////
+
+    /// Append the columns of `source` sharing the same rows, e.g.,
+    /// cells of a new batch. Column names, `obs` annotations and all
+    /// the layers are extended along with the main matrix.
+    ///
+    /// * `source`: sparse matrix with the same row names (and the
+    ///   same layers)
+    /// * `memory_budget`: bytes to hold triplets in memory
+    fn append_columns(
+        &mut self,
+        source: &dyn SparseIo<IndexIter = Self::IndexIter>,
+        memory_budget: usize,
+    ) -> anyhow::Result<()> {
+        if source.row_names()? != self.row_names()? {
+            return Err(anyhow::anyhow!("Row names mismatched"));
+        }
+
+        let layers = self.layer_names()?;
+        for layer in layers.iter() {
+            if !source.has_layer(layer)? {
+                return Err(anyhow::anyhow!(
+                    "no layer `{}` in {}",
+                    layer,
+                    source.get_backend_file_name()
+                ));
+            }
+        }
+
+        let (nrow, ncol_old, nnz_old) =
+            match (self.num_rows(), self.num_columns(), self.num_non_zeros()) {
+                (Some(nrow), Some(ncol), Some(nnz)) => (nrow, ncol, nnz),
+                _ => return Err(anyhow::anyhow!("should have the shape")),
+            };
+        let ncol_new = source
+            .num_columns()
+            .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+
+        // annotations missing on either side are filled with NA
+        let old_obs = self.annotation_names(AnnotationAxis::Obs)?;
+        let new_obs = source.annotation_names(AnnotationAxis::Obs)?;
+        let mut obs_names = old_obs.clone();
+        obs_names.extend(new_obs.iter().filter(|x| !old_obs.contains(x)).cloned());
+
+        let mut obs = vec![];
+        for name in obs_names {
+            let old = old_obs
+                .contains(&name)
+                .then(|| self.column_annotation(&name))
+                .transpose()?;
+            let new = new_obs
+                .contains(&name)
+                .then(|| source.column_annotation(&name))
+                .transpose()?;
+            let (old, new) = match (old, new) {
+                (Some(old), Some(new)) => (old, new),
+                (Some(old), None) => {
+                    let new = old.take(&vec![None; ncol_new]);
+                    (old, new)
+                }
+                (None, Some(new)) => (new.take(&vec![None; ncol_old]), new),
+                (None, None) => continue,
+            };
+            obs.push((name, AnnotationColumn::concatenate(&[old, new])?));
+        }
+
+        let mut column_names = self.column_names()?;
+        column_names.extend(source.column_names()?);
+
+        // layers first while the shape still describes the old data
+        for layer in layers.iter() {
+            info!("appending columns to the layer `{}`", layer);
+            let source_layer = source.open_layer(Some(layer))?;
+            self.append_columns_in_layer(Some(layer), source_layer.as_ref(), memory_budget)?;
+        }
+
+        let nnz_new = self.append_columns_in_layer(None, source, memory_budget)?;
+        self.update_mtx_shape((nrow, ncol_old + ncol_new, nnz_old + nnz_new))?;
+
+        self.register_column_names_vec(&column_names);
+        for (name, column) in obs.iter() {
+            self.register_annotation(AnnotationAxis::Obs, name, column)?;
+        }
+
+        self.read_column_indptr()?;
+        self.read_row_indptr()?;
+        self.clean_preloaded_columns();
+
+        info!(
+            "appended {} columns ({} non-zeros) from {}",
+            ncol_new,
+            nnz_new,
+            source.get_backend_file_name()
+        );
+        Ok(())
+    }
+
+    /// Append the columns of `source` to the layer `layer` (or the
+    /// main matrix if `None`) and return the number of non-zero
+    /// elements added. The CSC arrays are extended in place, while
+    /// the CSR arrays are rebuilt by merging the old and new rows
+    /// out of core. The shape and names are left for the caller.
+    ///
+    /// * `layer`: name of the layer
+    /// * `source`: sparse matrix with the same rows
+    /// * `memory_budget`: bytes to hold triplets in memory
+    fn append_columns_in_layer(
+        &mut self,
+        layer: Option<&str>,
+        source: &dyn SparseIo<IndexIter = Self::IndexIter>,
+        memory_budget: usize,
+    ) -> anyhow::Result<usize> {
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow::anyhow!("should have `nrow`"))?;
+        let ncol_new = source
+            .num_columns()
+            .ok_or(anyhow::anyhow!("should have `ncol`"))?;
+        let nnz_new = source
+            .num_non_zeros()
+            .ok_or(anyhow::anyhow!("should have `nnz`"))?;
+
+        let column_key = layer_key(layer, "by_column");
+        let row_key = layer_key(layer, "by_row");
+
+        let mut indptr = self.read_compressed_indptr_backend(&column_key)?;
+        let nnz_old = *indptr
+            .last()
+            .ok_or(anyhow::anyhow!("empty `{}/indptr`", column_key))?
+            as usize;
+        let ncol_old = indptr.len() - 1;
+
+        let block_size = 100;
+        let mut by_row = ExternalSorter::with_temp_dir(SortOrder::ByRow, memory_budget)?;
+
+        // the old triplets to be merged with the new ones by row
+        {
+            let old = self.open_layer(layer)?;
+            for lb in (0..ncol_old).step_by(block_size) {
+                let ub = (lb + block_size).min(ncol_old);
+                let (_, _, triplets) = old.read_triplets_by_column_range(lb..ub)?;
+                for (i, j, x) in triplets {
+                    by_row.push((i, j + lb as u64, x))?;
+                }
+            }
+        }
+
+        // extend the columns in place
+        self.resize_compressed_dataset_backend(&column_key, nnz_old + nnz_new)?;
+
+        let mut indices = vec![];
+        let mut values = vec![];
+        let mut offset = nnz_old;
+
+        for lb in (0..ncol_new).step_by(block_size) {
+            let ub = (lb + block_size).min(ncol_new);
+            let (_, _, mut triplets) = source.read_triplets_by_column_range(lb..ub)?;
+            triplets.par_sort_by_key(|&(i, j, _)| (j, i));
+
+            let mut counts = vec![0_u64; ub - lb];
+            for (i, j, x) in triplets {
+                counts[j as usize] += 1;
+                indices.push(i);
+                values.push(x);
+                by_row.push((i, (ncol_old + lb) as u64 + j, x))?;
+            }
+
+            for nnz_j in counts {
+                let last = *indptr.last().expect("non-empty indptr");
+                indptr.push(last + nnz_j);
+            }
+
+            if indices.len() >= STREAM_CHUNK_SIZE {
+                self.record_compressed_chunk_backend(&column_key, offset, &indices, &values)?;
+                offset += indices.len();
+                indices.clear();
+                values.clear();
+            }
+        }
+
+        if !indices.is_empty() {
+            self.record_compressed_chunk_backend(&column_key, offset, &indices, &values)?;
+            offset += indices.len();
+        }
+
+        if offset != nnz_old + nnz_new {
+            return Err(anyhow::anyhow!(
+                "expected {} triplets, but streamed {}",
+                nnz_old + nnz_new,
+                offset
+            ));
+        }
+
+        self.record_compressed_indptr_backend(&column_key, &indptr)?;
+
+        info!("rebuilding {} triplets by row", offset);
+        let mut by_row = by_row.into_sorted()?;
+        let value_type = self.value_type().unwrap_or(ValueType::F32);
+        self.record_sorted_triplets_backend(&row_key, nrow, offset, value_type, &mut by_row)?;
+
+        Ok(nnz_new)
+    }
+
+    ///////////////////////////////////
+    // integrity checks of the data //
+    ///////////////////////////////////
+
+    /// Check attributes, `indptr`, indices, names, and CSR/CSC
+    /// consistency of the main matrix and all the layers
+    fn validate(&self) -> anyhow::Result<ValidationReport> {
+        crate::validate::validate_backend(self)
+    }
+
+    /// Rebuild the CSR side of the main matrix (`None`) or a layer
+    /// from its CSC side, e.g., after [`SparseIo::validate`] found
+    /// the CSR side broken
+    /// * `layer`: name of the layer
+    /// * `memory_budget`: max number of triplets kept in memory
+    fn repair_csr_from_csc(
+        &mut self,
+        layer: Option<&str>,
+        memory_budget: usize,
+    ) -> anyhow::Result<()> {
+        crate::validate::repair_csr_from_csc(self, layer, memory_budget)
+    }
+
     /////////////////////////////
     // major structural change //
     /////////////////////////////
@@ -489,15 +1428,27 @@ pub trait SparseIo: Sync + Send {
                     }
                 });
 
-            /////////////////////////////////////
-            // 2. Remove previous backend file //
-            /////////////////////////////////////
-            self.remove_backend_file()?;
+            // annotations and layers to be carried over
+            let obs_annotations = self.annotations(AnnotationAxis::Obs)?;
+            let var_annotations = self.annotations(AnnotationAxis::Var)?;
+            let layer_triplets = self.remap_layer_triplets(&old2new_rows, &old2new_cols)?;
+
+            ///////////////////////////////////////////////////
+            // 2. Write a new backend next to the target one //
+            ///////////////////////////////////////////////////
 
-            info!(
-                "removing and recreating the backend {}",
-                self.get_backend_file_name()
-            );
+            // the target file is replaced only after the new backend
+            // is complete, so a crash leaves it untouched
+            let backend_file = self.get_backend_file_name().to_string();
+            let staging = if backend_file != MEMORY_BACKEND_NAME {
+                let (staging_dir, staging_file) = staging_backend_file(&backend_file)?;
+                self.set_backend_file_name(&staging_file)?;
+                Some(staging_dir)
+            } else {
+                None
+            };
+
+            info!("writing a new backend {}", self.get_backend_file_name());
 
             ///////////////////////////////
             // 3. populate a new backend //
@@ -524,6 +1475,33 @@ pub trait SparseIo: Sync + Send {
 
             self.register_row_names_vec(&new_row_names);
             self.register_column_names_vec(&new_col_names);
+
+            let new2old_cols = take_subset_positions(columns, ncol_data);
+            for (name, column) in obs_annotations {
+                self.register_annotation(AnnotationAxis::Obs, &name, &column.take(&new2old_cols))?;
+            }
+
+            let new2old_rows = take_subset_positions(rows, nrow_data);
+            for (name, column) in var_annotations {
+                self.register_annotation(AnnotationAxis::Var, &name, &column.take(&new2old_rows))?;
+            }
+
+            for (layer, mut triplets) in layer_triplets {
+                if triplets.is_empty() {
+                    info!("dropping the empty layer `{}`", layer);
+                    continue;
+                }
+                self.register_layer_triplets(&layer, &mut triplets)?;
+            }
+
+            ////////////////////////////////////////
+            // 4. Replace the target backend file //
+            ////////////////////////////////////////
+            if let Some(staging_dir) = staging {
+                self.move_backend_file(&backend_file)?;
+                std::fs::remove_dir_all(staging_dir)?;
+            }
+
             info!("registered new data to {}", self.get_backend_file_name());
         } else {
             return Err(anyhow::anyhow!("missing shape information"));
@@ -538,6 +1516,11 @@ pub trait SparseIo: Sync + Send {
     fn reorder_rows(&mut self, row_names_order: &[Box<str>]) -> anyhow::Result<()> {
         let new_col_names = self.column_names()?.clone();
         let name2new = build_name2index_map(row_names_order);
+        let name2old = build_name2index_map(&self.row_names()?);
+        let new2old_rows: Vec<Option<usize>> = row_names_order
+            .iter()
+            .map(|name| name2old.get(name).copied())
+            .collect();
 
         let block_size = 100;
 
@@ -573,8 +1556,7 @@ pub trait SparseIo: Sync + Send {
                 })
                 .for_each(|(lb, ub)| {
                     let (lb, ub) = (lb as usize, ub as usize);
-                    let (_, _, _triplets_b) =
-                        self.read_triplets_by_columns((lb..ub).collect()).unwrap();
+                    let (_, _, _triplets_b) = self.read_triplets_by_column_range(lb..ub).unwrap();
                     let _triplets_b = _triplets_b
                         .into_iter()
                         .filter_map(|(i, j, x)| old2new.get(&i).map(|&i_new| (i_new, j, x)));
@@ -584,6 +1566,12 @@ pub trait SparseIo: Sync + Send {
                     }
                 });
 
+            // annotations and layers to be carried over
+            let obs_annotations = self.annotations(AnnotationAxis::Obs)?;
+            let var_annotations = self.annotations(AnnotationAxis::Var)?;
+            let identity_cols = (0..(ncol as u64)).map(|j| (j, j)).collect();
+            let layer_triplets = self.remap_layer_triplets(&old2new, &identity_cols)?;
+
             /////////////////////////////////////
             // 2. Remove previous backend file //
             /////////////////////////////////////
@@ -615,6 +1603,22 @@ pub trait SparseIo: Sync + Send {
 
             self.register_row_names_vec(row_names_order);
             self.register_column_names_vec(&new_col_names);
+
+            for (name, column) in obs_annotations {
+                self.register_annotation(AnnotationAxis::Obs, &name, &column)?;
+            }
+            for (name, column) in var_annotations {
+                self.register_annotation(AnnotationAxis::Var, &name, &column.take(&new2old_rows))?;
+            }
+
+            for (layer, mut triplets) in layer_triplets {
+                if triplets.is_empty() {
+                    info!("dropping the empty layer `{}`", layer);
+                    continue;
+                }
+                self.register_layer_triplets(&layer, &mut triplets)?;
+            }
+
             info!("registered new data to {}", self.get_backend_file_name());
         }
 
@@ -629,8 +1633,68 @@ pub trait SparseIo: Sync + Send {
     /// Initialize backend
     fn initialize_backend(&mut self) -> anyhow::Result<()>;
 
+    /// Direct the next `initialize_backend` to `file_name`; the data
+    /// are still read from the current file until then, and the
+    /// current file is left untouched
+    /// * `file_name`: new backend file name
+    fn set_backend_file_name(&mut self, file_name: &str) -> anyhow::Result<()>;
+
+    /// Move the backend file to `file_name`, replacing what is there,
+    /// and keep working on the moved file
+    /// * `file_name`: destination of the backend file
+    fn move_backend_file(&mut self, file_name: &str) -> anyhow::Result<()>;
+
+    /// Write out what the backend still holds back, e.g., a zarr
+    /// store kept in a zip archive; call this once all the changes
+    /// are made, so that errors are not lost when it is dropped
+    fn flush_backend(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     fn record_mtx_shape(&mut self, mtx_shape: Option<(usize, usize, usize)>) -> anyhow::Result<()>;
 
+    /// Type of the stored values (`None` until any data are recorded)
+    fn value_type(&self) -> Option<ValueType>;
+
+    /// How to write a new backend like this one, e.g., to split it
+    fn create_options(&self) -> SparseCreateOptions {
+        SparseCreateOptions {
+            value_type: self.value_type(),
+            ..Default::default()
+        }
+    }
+
+    /// Store the values recorded from now on as `value_type`
+    /// * `value_type`: e.g., `u16` for UMI counts
+    fn set_value_type(&mut self, value_type: ValueType) -> anyhow::Result<()>;
+
+    /// Type to store `values` of the main matrix in: the type set
+    /// beforehand (e.g., by `--value-type`), which must hold all of
+    /// them, or else the narrowest one that does
+    /// * `values`: values to be recorded
+    fn value_type_for(&mut self, values: &[f32]) -> anyhow::Result<ValueType> {
+        match self.value_type() {
+            Some(value_type) => {
+                value_type.check(values)?;
+                Ok(value_type)
+            }
+            None => self.widen_value_type(ValueType::detect(values)),
+        }
+    }
+
+    /// Type to store the values of a layer in: the recorded type, or
+    /// a wider one if it can't hold them (e.g., normalized values in
+    /// a count matrix), which then becomes the recorded type
+    /// * `detected`: the narrowest type that holds the values
+    fn widen_value_type(&mut self, detected: ValueType) -> anyhow::Result<ValueType> {
+        let value_type = self.value_type().map_or(detected, |x| x.max(detected));
+        if self.value_type() != Some(value_type) {
+            info!("storing values as {}", value_type.name());
+            self.set_value_type(value_type)?;
+        }
+        Ok(value_type)
+    }
+
     /// Helper function to add triplets to zarr backend by row (CSR format)
     fn record_triplets_by_row(
         &mut self,
@@ -759,12 +1823,197 @@ pub trait SparseIo: Sync + Send {
         csc_colptr: &[u64],
     ) -> anyhow::Result<()>;
 
+    /// Allocate `data` and `indices` of `nnz` elements under the
+    /// group `key` (`/by_column` or `/by_row`) to be filled chunk by
+    /// chunk with `record_compressed_chunk_backend`
+    fn allocate_compressed_dataset_backend(
+        &mut self,
+        key: &str,
+        nnz: usize,
+        value_type: ValueType,
+    ) -> anyhow::Result<()>;
+
+    /// Fill in `indices` and `data` under the group `key` starting
+    /// from the `offset` position
+    fn record_compressed_chunk_backend(
+        &mut self,
+        key: &str,
+        offset: usize,
+        indices: &[u64],
+        values: &[f32],
+    ) -> anyhow::Result<()>;
+
+    /// Record `indptr` under the group `key` after all the chunks
+    fn record_compressed_indptr_backend(&mut self, key: &str, indptr: &[u64])
+        -> anyhow::Result<()>;
+
+    /// Read back `indptr` under the group `key`
+    fn read_compressed_indptr_backend(&self, key: &str) -> anyhow::Result<Vec<u64>>;
+
+    /// Lengths of `indices` and `data` under the group `key`
+    fn compressed_dataset_len_backend(&self, key: &str) -> anyhow::Result<(usize, usize)>;
+
+    /// Read back `indices` and `data` under the group `key` within
+    /// the `range` of positions
+    fn read_compressed_chunk_backend(
+        &self,
+        key: &str,
+        range: Range<usize>,
+    ) -> anyhow::Result<(Vec<u64>, Vec<f32>)>;
+
+    /// Grow `data` and `indices` under the group `key` to `nnz`
+    /// elements, keeping the existing ones in front
+    fn resize_compressed_dataset_backend(&mut self, key: &str, nnz: usize) -> anyhow::Result<()>;
+
+    /// Overwrite the shape of the data, e.g., after appending columns
+    fn update_mtx_shape(&mut self, mtx_shape: (usize, usize, usize)) -> anyhow::Result<()>;
+
+    /// Stream sorted (outer, inner, value) triplets into the
+    /// compressed format under the group `key` without holding them
+    /// all in memory
+    ///
+    /// * `key` - `/by_column` (outer = column) or `/by_row` (outer = row)
+    /// * `nouter` - number of columns or rows
+    /// * `nnz` - number of triplets
+    /// * `sorted_triplets` - triplets sorted by (outer, inner)
+    fn record_sorted_triplets_backend(
+        &mut self,
+        key: &str,
+        nouter: usize,
+        nnz: usize,
+        value_type: ValueType,
+        sorted_triplets: &mut dyn Iterator<Item = anyhow::Result<(u64, u64, f32)>>,
+    ) -> anyhow::Result<()> {
+        self.allocate_compressed_dataset_backend(key, nnz, value_type)?;
+
+        let chunk_size = STREAM_CHUNK_SIZE.min(nnz.max(1));
+        let mut indptr = vec![0_u64; nouter + 1];
+        let mut indices = Vec::with_capacity(chunk_size);
+        let mut values = Vec::with_capacity(chunk_size);
+        let mut offset = 0;
+
+        for triplet in sorted_triplets {
+            let (outer, inner, x) = triplet?;
+            if outer as usize >= nouter {
+                return Err(anyhow::anyhow!(
+                    "{} index {} out of 0..{} in {}",
+                    if key.ends_with("by_row") {
+                        "row"
+                    } else {
+                        "column"
+                    },
+                    outer,
+                    nouter,
+                    key
+                ));
+            }
+            indptr[outer as usize + 1] += 1;
+            indices.push(inner);
+            values.push(x);
+
+            if indices.len() >= chunk_size {
+                self.record_compressed_chunk_backend(key, offset, &indices, &values)?;
+                offset += indices.len();
+                indices.clear();
+                values.clear();
+            }
+        }
+
+        if !indices.is_empty() {
+            self.record_compressed_chunk_backend(key, offset, &indices, &values)?;
+            offset += indices.len();
+        }
+
+        if offset != nnz {
+            return Err(anyhow::anyhow!(
+                "expected {} triplets, but streamed {}",
+                nnz,
+                offset
+            ));
+        }
+
+        // counts to pointers
+        for k in 1..indptr.len() {
+            indptr[k] += indptr[k - 1];
+        }
+
+        self.record_compressed_indptr_backend(key, &indptr)
+    }
+
+    /// Record externally sorted triplets in both CSC and CSR formats
+    /// * `triplets` - triplets spilled to disk within a memory budget
+    fn record_external_triplets(&mut self, triplets: ExternalTriplets) -> anyhow::Result<()> {
+        self.record_external_triplets_in_layer(None, triplets)
+    }
+
+    /// Record externally sorted triplets in both CSC and CSR formats
+    /// under the layer `layer` (or the main matrix if `None`)
+    /// * `triplets` - triplets spilled to disk within a memory budget
+    fn record_external_triplets_in_layer(
+        &mut self,
+        layer: Option<&str>,
+        triplets: ExternalTriplets,
+    ) -> anyhow::Result<()> {
+        let nrow = self.num_rows().expect("should have `nrow`");
+        let ncol = self.num_columns().expect("should have `ncol`");
+        let nnz = triplets.len();
+        let value_type = match (layer, self.value_type()) {
+            (None, Some(value_type)) if value_type < triplets.value_type() => {
+                return Err(anyhow::anyhow!(
+                    "values can't be stored as {}; try `--value-type {}`",
+                    value_type.name(),
+                    triplets.value_type().name()
+                ));
+            }
+            (None, Some(value_type)) => value_type,
+            _ => self.widen_value_type(triplets.value_type())?,
+        };
+
+        let (by_column, mut by_row) = triplets.into_sorted()?;
+
+        // (row, column, value) -> (column, row, value)
+        let mut by_column = by_column.map(|x| x.map(|(i, j, v)| (j, i, v)));
+        info!("recording {} triplets by column", nnz);
+        let key = layer_key(layer, "by_column");
+        self.record_sorted_triplets_backend(&key, ncol, nnz, value_type, &mut by_column)?;
+
+        info!("recording {} triplets by row", nnz);
+        let key = layer_key(layer, "by_row");
+        self.record_sorted_triplets_backend(&key, nrow, nnz, value_type, &mut by_row)
+    }
+
+    /// Read mtx file line by line, sort the triplets out of core
+    /// within the memory budget, and populate both CSC and CSR
+    /// * `mtx_file`: mtx file to be read into the backend
+    /// * `memory_budget`: bytes to hold triplets in memory
+    fn import_mtx_file_external(
+        &mut self,
+        mtx_file: &str,
+        memory_budget: usize,
+    ) -> anyhow::Result<()> {
+        let mut triplets = ExternalTriplets::new(memory_budget)?;
+        let (nrow, ncol, _) = visit_mtx_triplets(mtx_file, &mut |x| triplets.push(x))?;
+        info!("read mtx file: {}", mtx_file);
+        if triplets.is_empty() {
+            return Err(anyhow::anyhow!("No data in mtx file"));
+        }
+        self.record_mtx_shape(Some((nrow, ncol, triplets.len())))?;
+        self.record_external_triplets(triplets)
+    }
+
     fn read_row_indptr(&mut self) -> anyhow::Result<()>;
 
     fn read_column_indptr(&mut self) -> anyhow::Result<()>;
 
     fn preload_columns(&mut self) -> anyhow::Result<()>;
 
+    /// Like `preload_columns`, but memory-map an uncompressed copy of
+    /// the columns, written once next to the backend file, instead of
+    /// copying them into the heap
+    fn preload_columns_mmap(&mut self) -> anyhow::Result<()> {
+        self.preload_columns()
+    }
+
     fn clean_preloaded_columns(&mut self);
 
     /// Backend file name
@@ -784,6 +2033,61 @@ pub trait SparseIo: Sync + Send {
 //     // iter.clone().into_iter().count()
 // }
 
+/// A staging directory next to `backend_file` and the file name
+/// within it, where a new backend can be written before it replaces
+/// `backend_file` on the same file system
+///
+/// ```text
+/// data.zarr.partial/data.zarr  --(rename)-->  data.zarr
+/// ```
+///
+/// A stale staging directory left by a crash is removed.
+pub fn staging_backend_file(backend_file: &str) -> anyhow::Result<(String, String)> {
+    let path = std::path::Path::new(backend_file);
+    let base = path
+        .file_name()
+        .and_then(|x| x.to_str())
+        .ok_or(anyhow::anyhow!(
+            "invalid backend file name: {}",
+            backend_file
+        ))?;
+
+    let staging_dir = format!("{}.partial", backend_file.trim_end_matches('/'));
+    if std::path::Path::new(&staging_dir).exists() {
+        info!("removing a stale staging directory {}", staging_dir);
+        std::fs::remove_dir_all(&staging_dir)?;
+    }
+    std::fs::create_dir_all(&staging_dir)?;
+
+    let staging_file = std::path::Path::new(&staging_dir)
+        .join(base)
+        .to_str()
+        .ok_or(anyhow::anyhow!("invalid staging file name"))?
+        .to_string();
+    Ok((staging_dir, staging_file))
+}
+
+/// Move a backend file or directory `from` to `to`, replacing
+/// whatever is there. A directory can't be renamed over another, so
+/// the old one steps aside first and is removed last; at any moment,
+/// a complete backend exists at `to` or next to it.
+pub fn replace_backend_path(from: &str, to: &str) -> anyhow::Result<()> {
+    let target = std::path::Path::new(to);
+    if target.is_dir() {
+        let old = format!("{}.old", to.trim_end_matches('/'));
+        if std::path::Path::new(&old).exists() {
+            std::fs::remove_dir_all(&old)?;
+        }
+        std::fs::rename(to, &old)?;
+        std::fs::rename(from, to)?;
+        std::fs::remove_dir_all(&old)?;
+    } else {
+        // a file is replaced atomically
+        std::fs::rename(from, to)?;
+    }
+    Ok(())
+}
+
 pub fn build_name2index_map(_names: &[Box<str>]) -> HashMap<Box<str>, usize> {
     _names
         .iter()
@@ -817,6 +2121,20 @@ pub fn take_subset_indices_names(
     (old2new, new_names)
 }
 
+/// Old position for each new position after subsetting
+/// * `new_indices`: if nothing, keep everything
+/// * `ntot`: number of elements before subsetting
+pub fn take_subset_positions(new_indices: Option<&Vec<usize>>, ntot: usize) -> Vec<Option<usize>> {
+    match new_indices {
+        Some(new_indices) => new_indices
+            .iter()
+            .filter(|&&idx| idx < ntot)
+            .map(|&idx| Some(idx))
+            .collect(),
+        None => (0..ntot).map(Some).collect(),
+    }
+}
+
 pub fn take_subset_indices_names_if_needed(
     new_indices: Option<&Vec<usize>>,
     ntot: Option<usize>,
@@ -834,6 +2152,24 @@ pub fn take_subset_indices_names_if_needed(
     }
 }
 
+/// Key of the compressed data `name` (e.g., `by_column`) in the main
+/// matrix or a named layer
+///
+/// ```text
+/// (root)
+///     ├── by_column, by_row (main matrix)
+///     └── layers
+///         └── {layer}
+///             ├── by_column
+///             └── by_row
+/// ```
+pub fn layer_key(layer: Option<&str>, name: &str) -> String {
+    match layer {
+        Some(layer) => format!("{}/{}/{}", LAYERS_GROUP, layer, name),
+        None => format!("/{}", name),
+    }
+}
+
 pub fn ndarray_to_triplets(array: &Array2<f32>) -> Vec<(u64, u64, f32)> {
     let eps = 1e-6;
     array
@@ -1,23 +1,73 @@
 #![allow(dead_code)]
 
+use crate::compressed_parts::*;
+use crate::external_sort::Triplet;
 use crate::sparse_io::*;
 
+use clap::{Args, ValueEnum};
 use log::info;
+use matrix_util::common_io::read_lines;
 use matrix_util::knn_match::ColumnDict;
 use matrix_util::traits::*;
 use matrix_util::utils::*;
 use rayon::prelude::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Index;
 use std::sync::Arc;
 
 type SparseData = dyn SparseIo<IndexIter = Vec<usize>>;
 
+/// How to line up rows (features) across multiple data sets
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+#[clap(rename_all = "lowercase")]
+pub enum RowAlignment {
+    /// the same row names in the same order
+    #[default]
+    Strict,
+    /// rows shared by all the data sets (in the order of the first)
+    Intersection,
+    /// rows found in any data set; missing rows are zeros
+    Union,
+}
+
+/// Which columns (cells) of the data files to keep
+#[derive(Args, Clone, Debug, Default, PartialEq)]
+pub struct ColumnSelectArgs {
+    /// file with column (cell) names to keep, one per line; names
+    /// may come with or without the `@data` tag
+    #[arg(long)]
+    pub columns_file: Option<Box<str>>,
+}
+
+impl ColumnSelectArgs {
+    /// Keep the columns listed in the file, if given, without copying
+    /// any data; also return their global indices in `data_vec` to
+    /// subset per-column inputs (e.g., batch membership) the same way
+    pub fn select_columns(
+        &self,
+        data_vec: SparseIoVec,
+    ) -> anyhow::Result<(SparseIoVec, Option<Vec<usize>>)> {
+        match &self.columns_file {
+            Some(columns_file) => {
+                info!("Selecting columns in: {}", columns_file);
+                let cells = data_vec.columns_by_names(&read_lines(columns_file)?)?;
+                Ok((data_vec.subset_columns(&cells)?, Some(cells)))
+            }
+            None => Ok((data_vec, None)),
+        }
+    }
+}
+
 pub struct SparseIoVec {
     data_vec: Vec<Arc<SparseData>>,
+    row_alignment: RowAlignment,
+    data_row_names: Vec<Vec<Box<str>>>,
+    data_row_to_glob: Vec<Option<Vec<Option<u64>>>>,
+    data_glob_to_row: Vec<Option<Vec<Option<usize>>>>,
     col_to_data: Vec<usize>,
     data_to_cols: HashMap<usize, Vec<usize>>,
     col_glob_to_loc: Vec<usize>,
+    col_glob_to_parent: Option<Vec<usize>>,
     offset: usize,
     row_name_position: HashMap<Box<str>, usize>,
     column_names_with_data_tag: Vec<Box<str>>,
@@ -47,9 +97,14 @@ impl SparseIoVec {
     pub fn new() -> Self {
         Self {
             data_vec: vec![],
+            row_alignment: RowAlignment::default(),
+            data_row_names: vec![],
+            data_row_to_glob: vec![],
+            data_glob_to_row: vec![],
             col_to_data: vec![],
             data_to_cols: HashMap::new(),
             col_glob_to_loc: vec![],
+            col_glob_to_parent: None,
             offset: 0,
             row_name_position: HashMap::new(),
             column_names_with_data_tag: vec![],
@@ -67,6 +122,18 @@ impl SparseIoVec {
         self.data_vec.len()
     }
 
+    /// Change how rows of the data sets are lined up; rows of the
+    /// data sets pushed so far will be aligned again
+    /// * `row_alignment` - strict, intersection, or union (with zeros)
+    pub fn set_row_alignment(&mut self, row_alignment: RowAlignment) -> anyhow::Result<()> {
+        self.row_alignment = row_alignment;
+        self.align_rows()
+    }
+
+    pub fn row_alignment(&self) -> RowAlignment {
+        self.row_alignment
+    }
+
     pub fn assign_groups(&mut self, cell_to_group: Vec<usize>, ncells_per_group: Option<usize>) {
         self.group_to_cols = Some(
             partition_by_membership(&cell_to_group, ncells_per_group)
@@ -93,10 +160,24 @@ impl SparseIoVec {
         data: Arc<SparseData>,
         data_name: Option<Box<str>>,
     ) -> anyhow::Result<()> {
+        if self.col_glob_to_parent.is_some() {
+            return Err(anyhow::anyhow!("can't push data into a column subset"));
+        }
         if let Some(ncol_data) = data.num_columns() {
             debug_assert!(self.col_glob_to_loc.len() == self.offset);
             debug_assert!(self.col_to_data.len() == self.offset);
             let didx = self.data_vec.len();
+            let column_names = data.column_names()?;
+
+            // line up the rows first to leave the columns untouched
+            // if they don't fit
+            info!("Checking row names...");
+            self.data_row_names.push(data.row_names()?);
+            if let Err(err) = self.align_rows() {
+                self.data_row_names.pop();
+                return Err(err);
+            }
+
             self.data_to_cols.insert(didx, vec![]);
             let data_to_cells = self
                 .data_to_cols
@@ -118,26 +199,11 @@ impl SparseIoVec {
             };
 
             self.column_names_with_data_tag.extend(
-                data.column_names()?
+                column_names
                     .into_iter()
                     .map(|x| (x.to_string() + &data_tag).into_boxed_str())
                     .collect::<Vec<_>>(),
             );
-            info!("Checking row names...");
-
-            for (data_row_pos, row) in data.row_names()?.iter().enumerate() {
-                let glob_row_pos = self
-                    .row_name_position
-                    .entry(row.clone())
-                    .or_insert(data_row_pos);
-                if *glob_row_pos != data_row_pos {
-                    return Err(anyhow::anyhow!(
-                        "Row names mismatched: {} vs. {}",
-                        *glob_row_pos,
-                        data_row_pos
-                    ));
-                }
-            }
 
             self.data_vec.push(data.clone());
             self.offset += ncol_data;
@@ -148,11 +214,157 @@ impl SparseIoVec {
         Ok(())
     }
 
-    pub fn num_columns_by_data(&self) -> anyhow::Result<Vec<usize>> {
-        Ok(self
+    /// Line up the rows of all the data sets and figure out where
+    /// each row of each data set goes
+    fn align_rows(&mut self) -> anyhow::Result<()> {
+        let mut row_name_position: HashMap<Box<str>, usize> = HashMap::new();
+
+        match self.row_alignment {
+            RowAlignment::Strict => {
+                for rows in self.data_row_names.iter() {
+                    for (data_row_pos, row) in rows.iter().enumerate() {
+                        let glob_row_pos =
+                            row_name_position.entry(row.clone()).or_insert(data_row_pos);
+                        if *glob_row_pos != data_row_pos {
+                            return Err(anyhow::anyhow!(
+                                "Row names mismatched: {} vs. {}",
+                                *glob_row_pos,
+                                data_row_pos
+                            ));
+                        }
+                    }
+                }
+                self.row_name_position = row_name_position;
+                self.data_row_to_glob = vec![None; self.data_row_names.len()];
+                self.data_glob_to_row = vec![None; self.data_row_names.len()];
+                return Ok(());
+            }
+            RowAlignment::Intersection => {
+                if let Some((first, rest)) = self.data_row_names.split_first() {
+                    let rest: Vec<HashSet<&Box<str>>> =
+                        rest.iter().map(|rows| rows.iter().collect()).collect();
+                    for row in first {
+                        if rest.iter().all(|rows| rows.contains(row))
+                            && !row_name_position.contains_key(row)
+                        {
+                            let pos = row_name_position.len();
+                            row_name_position.insert(row.clone(), pos);
+                        }
+                    }
+                }
+            }
+            RowAlignment::Union => {
+                for row in self.data_row_names.iter().flatten() {
+                    if !row_name_position.contains_key(row) {
+                        let pos = row_name_position.len();
+                        row_name_position.insert(row.clone(), pos);
+                    }
+                }
+            }
+        }
+
+        if !self.data_row_names.is_empty() && row_name_position.is_empty() {
+            return Err(anyhow::anyhow!("no rows shared across the data sets"));
+        }
+
+        self.data_row_to_glob = self
+            .data_row_names
+            .iter()
+            .map(|rows| {
+                Some(
+                    rows.iter()
+                        .map(|row| row_name_position.get(row).map(|&i| i as u64))
+                        .collect(),
+                )
+            })
+            .collect();
+
+        // and the other way around, to look up rows by their position
+        self.data_glob_to_row = self
+            .data_row_to_glob
+            .iter()
+            .map(|row_to_glob| {
+                row_to_glob.as_ref().map(|row_to_glob| {
+                    let mut ret = vec![None; row_name_position.len()];
+                    for (i, glob) in row_to_glob.iter().enumerate() {
+                        if let Some(glob) = glob {
+                            ret[*glob as usize] = Some(i);
+                        }
+                    }
+                    ret
+                })
+            })
+            .collect();
+
+        info!(
+            "{} rows aligned ({:?}) across {} data sets",
+            row_name_position.len(),
+            self.row_alignment,
+            self.data_row_names.len()
+        );
+
+        self.row_name_position = row_name_position;
+        Ok(())
+    }
+
+    /// Read the column `glob` with its rows remapped to the aligned
+    /// rows (rows dropped in the intersection are skipped)
+    /// * `glob` - global column index
+    fn read_aligned_single_column(
+        &self,
+        glob: usize,
+    ) -> anyhow::Result<(usize, usize, Vec<Triplet>)> {
+        let didx = self.col_to_data[glob];
+        let loc = self.col_glob_to_loc[glob];
+        let (nrow, ncol, triplets) = self.data_vec[didx].read_triplets_by_single_column(loc)?;
+
+        match self.data_row_to_glob[didx].as_ref() {
+            Some(row_to_glob) => {
+                let triplets = triplets
+                    .into_iter()
+                    .filter_map(|(i, j, x)| row_to_glob[i as usize].map(|i| (i, j, x)))
+                    .collect();
+                Ok((self.row_name_position.len(), ncol, triplets))
+            }
+            None => Ok((nrow, ncol, triplets)),
+        }
+    }
+
+    /// Stack the per-column annotation `name` across all the data
+    /// sets, e.g., `donor` to be used as batch membership
+    /// * `name` - annotation name stored in each backend
+    pub fn column_annotation(&self, name: &str) -> anyhow::Result<AnnotationColumn> {
+        let columns = self
             .data_vec
             .iter()
-            .map(|d| d.num_columns().unwrap_or(0_usize))
+            .map(|data| data.column_annotation(name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let stacked = AnnotationColumn::concatenate(&columns)?;
+
+        if self.col_glob_to_parent.is_none() {
+            return Ok(stacked);
+        }
+
+        // position of each column in the stacked annotation
+        let mut data_offset = Vec::with_capacity(columns.len());
+        let mut acc = 0;
+        for column in columns.iter() {
+            data_offset.push(acc);
+            acc += column.len();
+        }
+        let indices: Vec<Option<usize>> = self
+            .col_to_data
+            .iter()
+            .zip(self.col_glob_to_loc.iter())
+            .map(|(&didx, &loc)| Some(data_offset[didx] + loc))
+            .collect();
+        Ok(stacked.take(&indices))
+    }
+
+    /// number of columns (in this vector) from each data set
+    pub fn num_columns_by_data(&self) -> anyhow::Result<Vec<usize>> {
+        Ok((0..self.data_vec.len())
+            .map(|didx| self.data_to_cols.get(&didx).map_or(0, |cols| cols.len()))
             .collect())
     }
 
@@ -164,6 +376,10 @@ impl SparseIoVec {
     }
 
     pub fn num_rows(&self) -> anyhow::Result<usize> {
+        if self.row_alignment != RowAlignment::Strict {
+            return Ok(self.row_name_position.len());
+        }
+
         let mut ret = 0;
         for dat in self.data_vec.iter() {
             let nr = dat
@@ -174,18 +390,187 @@ impl SparseIoVec {
         Ok(ret)
     }
 
-    /// total number of columns across all data files
+    /// total number of columns across all data files (or the ones
+    /// selected by `subset_columns`)
     pub fn num_columns(&self) -> anyhow::Result<usize> {
-        let mut ret = 0;
-        for data in self.data_vec.iter() {
-            let nc = data
-                .num_columns()
-                .ok_or(anyhow::anyhow!("can't figure out the number of columns"))?;
-            ret += nc;
+        Ok(self.col_to_data.len())
+    }
+
+    ////////////////////
+    // subset columns //
+    ////////////////////
+
+    /// Global indices of the columns named in `names` (in that
+    /// order), matched with the data tag (`cell@data`) or without it
+    /// (`cell`, possibly found in several data sets)
+    /// * `names` - column names; unmatched ones are skipped
+    pub fn columns_by_names(&self, names: &[Box<str>]) -> anyhow::Result<Vec<usize>> {
+        let mut name_to_cols: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (glob, name) in self.column_names_with_data_tag.iter().enumerate() {
+            name_to_cols.entry(name.as_ref()).or_default().push(glob);
+            if let Some((base, _tag)) = name.rsplit_once(COLUMN_SEP) {
+                name_to_cols.entry(base).or_default().push(glob);
+            }
+        }
+
+        let mut taken = HashSet::new();
+        let mut ret = vec![];
+        let mut nmissing = 0;
+        for name in names.iter() {
+            match name_to_cols.get(name.as_ref()) {
+                Some(cols) => ret.extend(cols.iter().filter(|&&glob| taken.insert(glob))),
+                None => nmissing += 1,
+            }
+        }
+
+        if ret.is_empty() {
+            return Err(anyhow::anyhow!("none of the {} names found", names.len()));
+        }
+        if nmissing > 0 {
+            info!(
+                "{} of {} names not found in the columns",
+                nmissing,
+                names.len()
+            );
         }
         Ok(ret)
     }
 
+    /// A subset of the columns `cells` (in this order) sharing the
+    /// same data sets without copying any data; batches, groups and
+    /// column names follow the subset
+    ///
+    /// ```text
+    /// parent: c0 c1 c2 c3 c4 c5     cells: [4, 1]
+    /// subset: c4 c1                 col_glob_to_parent: [4, 1]
+    /// ```
+    ///
+    /// * `cells` - distinct global column indices of this vector
+    pub fn subset_columns(&self, cells: &[usize]) -> anyhow::Result<SparseIoVec> {
+        let ntot = self.num_columns()?;
+        let mut parent_to_glob: HashMap<usize, usize> = HashMap::with_capacity(cells.len());
+        for (jj, &glob) in cells.iter().enumerate() {
+            if glob >= ntot {
+                return Err(anyhow::anyhow!("column {} is out of bound {}", glob, ntot));
+            }
+            if parent_to_glob.insert(glob, jj).is_some() {
+                return Err(anyhow::anyhow!("column {} selected more than once", glob));
+            }
+        }
+
+        let col_to_data: Vec<usize> = cells.iter().map(|&j| self.col_to_data[j]).collect();
+        let mut data_to_cols: HashMap<usize, Vec<usize>> = (0..self.data_vec.len())
+            .map(|didx| (didx, vec![]))
+            .collect();
+        for (jj, &didx) in col_to_data.iter().enumerate() {
+            data_to_cols.entry(didx).or_default().push(jj);
+        }
+
+        // keep the group indices, dropping columns outside the subset
+        let group_to_cols = self.group_to_cols.as_ref().map(|group_to_cols| {
+            group_to_cols
+                .iter()
+                .map(|cols| {
+                    cols.iter()
+                        .filter_map(|j| parent_to_glob.get(j).copied())
+                        .collect()
+                })
+                .collect()
+        });
+
+        let mut ret = Self {
+            data_vec: self.data_vec.clone(),
+            row_alignment: self.row_alignment,
+            data_row_names: self.data_row_names.clone(),
+            data_row_to_glob: self.data_row_to_glob.clone(),
+            data_glob_to_row: self.data_glob_to_row.clone(),
+            col_to_data,
+            data_to_cols,
+            col_glob_to_loc: cells.iter().map(|&j| self.col_glob_to_loc[j]).collect(),
+            col_glob_to_parent: Some(cells.to_vec()),
+            offset: cells.len(),
+            row_name_position: self.row_name_position.clone(),
+            column_names_with_data_tag: cells
+                .iter()
+                .map(|&j| self.column_names_with_data_tag[j].clone())
+                .collect(),
+            col_to_group: self
+                .col_to_group
+                .as_ref()
+                .map(|groups| cells.iter().map(|&j| groups[j]).collect()),
+            group_to_cols,
+            batch_knn_lookup: None,
+            col_to_batch: None,
+            batch_to_cols: None,
+            batch_idx_to_name: None,
+            between_batch_proximity: None,
+        };
+
+        if let (Some(lookups), Some(col_to_batch), Some(batch_names)) = (
+            self.batch_knn_lookup.as_ref(),
+            self.col_to_batch.as_ref(),
+            self.batch_idx_to_name.as_ref(),
+        ) {
+            // batches left with no column are dropped
+            let mut batch_cells: Vec<Vec<usize>> = vec![vec![]; lookups.len()];
+            for (jj, &glob) in cells.iter().enumerate() {
+                batch_cells[col_to_batch[glob]].push(jj);
+            }
+            let kept: Vec<usize> = (0..lookups.len())
+                .filter(|&b| !batch_cells[b].is_empty())
+                .collect();
+
+            // rebuild each dictionary with the features kept there
+            let dictionaries = kept
+                .par_iter()
+                .map(|&b| -> anyhow::Result<ColumnDict<usize>> {
+                    let dict = &lookups[b];
+                    let mut points = Vec::with_capacity(batch_cells[b].len());
+                    for &jj in batch_cells[b].iter() {
+                        let index = dict.name2index.get(&cells[jj]).ok_or(anyhow::anyhow!(
+                            "column {} missing in the batch dictionary",
+                            cells[jj]
+                        ))?;
+                        points.push(&dict.data_vec[*index].data);
+                    }
+                    let views = points
+                        .iter()
+                        .map(|x| nalgebra::DVectorView::from_slice(x, x.len()))
+                        .collect();
+                    Ok(ColumnDict::<usize>::from_dvector_views(
+                        views,
+                        batch_cells[b].clone(),
+                    ))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            let mut new_col_to_batch = vec![0; cells.len()];
+            for (new_b, &b) in kept.iter().enumerate() {
+                batch_cells[b]
+                    .iter()
+                    .for_each(|&jj| new_col_to_batch[jj] = new_b);
+            }
+
+            ret.batch_to_cols = Some(kept.iter().map(|&b| batch_cells[b].clone()).collect());
+            ret.batch_idx_to_name = Some(kept.iter().map(|&b| batch_names[b].clone()).collect());
+            ret.col_to_batch = Some(new_col_to_batch);
+            ret.batch_knn_lookup = Some(dictionaries);
+
+            if ret.num_batches() > 2 {
+                ret.sort_batch_proximity()?;
+            }
+        }
+
+        info!("{} of {} columns selected", cells.len(), ntot);
+        Ok(ret)
+    }
+
+    /// Global column index in the parent vector of each column, if
+    /// this vector is a subset made by `subset_columns`
+    pub fn parent_columns(&self) -> Option<&[usize]> {
+        self.col_glob_to_parent.as_deref()
+    }
+
     ////////////////////
     // access columns //
     ////////////////////
@@ -202,11 +587,7 @@ impl SparseIoVec {
         let mut ncol = 0_usize;
         // Note: each cell is a global index
         for glob in cells {
-            let didx = self.col_to_data[glob];
-            let loc = self.col_glob_to_loc[glob];
-
-            let (loc_nrow, loc_ncol, loc_triplets) =
-                self.data_vec[didx].read_triplets_by_single_column(loc)?;
+            let (loc_nrow, loc_ncol, loc_triplets) = self.read_aligned_single_column(glob)?;
 
             nrow = nrow.max(loc_nrow);
             triplets.extend(
@@ -224,39 +605,197 @@ impl SparseIoVec {
     where
         I: Iterator<Item = usize>,
     {
-        let ((nrow, ncol), triplets) = self.columns_triplets(cells)?;
-        ndarray::Array2::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        csc_to_ndarray(&self.read_columns_csc(cells)?)
     }
 
     pub fn read_columns_dmatrix<I>(&self, cells: I) -> anyhow::Result<nalgebra::DMatrix<f32>>
     where
         I: Iterator<Item = usize>,
     {
-        let ((nrow, ncol), triplets) = self.columns_triplets(cells)?;
-        DMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(DMatrix::from(&self.read_columns_csc(cells)?))
     }
 
+    /// Read the columns (cells) in one `read_columns_csc` call per
+    /// data set and stitch them together in the requested order,
+    /// moving rows to their aligned positions
+    /// * `cells` - global column indices
     pub fn read_columns_csc<I>(&self, cells: I) -> anyhow::Result<CscMatrix<f32>>
     where
         I: Iterator<Item = usize>,
     {
-        let ((nrow, ncol), triplets) = self.columns_triplets(cells)?;
-        nalgebra_sparse::CscMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        let cells: Vec<usize> = cells.collect();
+
+        // positions of the requested cells in each data set
+        let mut data_to_pos: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (jj, &glob) in cells.iter().enumerate() {
+            data_to_pos
+                .entry(self.col_to_data[glob])
+                .or_default()
+                .push(jj);
+        }
+
+        // (data set, column within its matrix) of each position
+        let mut pos_to_source = vec![(0, 0); cells.len()];
+        let mut matrices = Vec::with_capacity(data_to_pos.len());
+        let mut nrow = 0_usize;
+
+        for (didx, positions) in data_to_pos {
+            let locs: Vec<usize> = positions
+                .iter()
+                .map(|&jj| self.col_glob_to_loc[cells[jj]])
+                .collect();
+            let csc = self.data_vec[didx].read_columns_csc(locs)?;
+
+            nrow = nrow.max(match self.data_row_to_glob[didx] {
+                Some(_) => self.row_name_position.len(),
+                None => csc.nrows(),
+            });
+
+            for (k, &jj) in positions.iter().enumerate() {
+                pos_to_source[jj] = (matrices.len(), k);
+            }
+            matrices.push((didx, csc));
+        }
+
+        let nnz = matrices.iter().map(|(_, csc)| csc.nnz()).sum();
+        let mut parts = CompressedParts::with_capacity(cells.len(), nnz);
+        let mut remapped: Vec<(usize, f32)> = vec![];
+
+        for (jj, &(m, k)) in pos_to_source.iter().enumerate() {
+            let (didx, csc) = &matrices[m];
+            let col = csc.col(k);
+            let entries = col
+                .row_indices()
+                .iter()
+                .copied()
+                .zip(col.values().iter().copied());
+
+            match self.data_row_to_glob[*didx].as_ref() {
+                Some(row_to_glob) => {
+                    remapped.clear();
+                    remapped.extend(
+                        entries.filter_map(|(i, x)| row_to_glob[i].map(|i| (i as usize, x))),
+                    );
+                    remapped.sort_by_key(|&(i, _)| i);
+                    parts.append_entries(jj, remapped.iter().copied());
+                }
+                None => parts.append_entries(jj, entries),
+            }
+        }
+
+        parts.into_csc(nrow, cells.len())
     }
 
     pub fn read_columns_csr<I>(&self, cells: I) -> anyhow::Result<CsrMatrix<f32>>
     where
         I: Iterator<Item = usize>,
     {
-        let ((nrow, ncol), triplets) = self.columns_triplets(cells)?;
-        nalgebra_sparse::CsrMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+        Ok(CsrMatrix::from(&self.read_columns_csc(cells)?))
     }
 
     pub fn read_columns_tensor<I>(&self, cells: I) -> anyhow::Result<Tensor>
     where
         I: Iterator<Item = usize>,
     {
-        let ((nrow, ncol), triplets) = self.columns_triplets(cells)?;
+        csc_to_tensor(&self.read_columns_csc(cells)?)
+    }
+
+    /////////////////
+    // access rows //
+    /////////////////
+
+    /// Read the rows across all the data sets from their `by_row`
+    /// (CSR) storage and put the columns side by side, in the same
+    /// order as the global column indices
+    /// * `rows` - aligned (global) row indices
+    pub fn rows_triplets<I>(&self, rows: I) -> anyhow::Result<((usize, usize), Vec<Triplet>)>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let rows: Vec<usize> = rows.collect();
+        let mut triplets = vec![];
+
+        for (didx, data) in self.data_vec.iter().enumerate() {
+            let ncol_data = data
+                .num_columns()
+                .ok_or(anyhow::anyhow!("can't figure out the number of columns"))?;
+
+            // global column of each local column (if selected)
+            let mut loc_to_glob: Vec<Option<u64>> = vec![None; ncol_data];
+            for &glob in self.data_to_cols.get(&didx).into_iter().flatten() {
+                loc_to_glob[self.col_glob_to_loc[glob]] = Some(glob as u64);
+            }
+
+            // (position in `rows`, local row) found in this data set
+            let (positions, loc_rows): (Vec<u64>, Vec<usize>) = self.data_glob_to_row[didx]
+                .as_ref()
+                .map(|glob_to_row| {
+                    rows.iter()
+                        .enumerate()
+                        .filter_map(|(ii, &glob)| {
+                            glob_to_row
+                                .get(glob)
+                                .copied()
+                                .flatten()
+                                .map(|i| (ii as u64, i))
+                        })
+                        .unzip()
+                })
+                .unwrap_or_else(|| {
+                    rows.iter()
+                        .enumerate()
+                        .map(|(ii, &i)| (ii as u64, i))
+                        .unzip()
+                });
+
+            if !loc_rows.is_empty() && loc_to_glob.iter().any(|j| j.is_some()) {
+                let (_, _, loc_triplets) = data.read_triplets_by_rows(loc_rows)?;
+                triplets.extend(loc_triplets.into_iter().filter_map(|(i, j, v)| {
+                    loc_to_glob[j as usize].map(|glob| (positions[i as usize], glob, v))
+                }));
+            }
+        }
+
+        Ok(((rows.len(), self.num_columns()?), triplets))
+    }
+
+    pub fn read_rows_ndarray<I>(&self, rows: I) -> anyhow::Result<ndarray::Array2<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let ((nrow, ncol), triplets) = self.rows_triplets(rows)?;
+        ndarray::Array2::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+    }
+
+    pub fn read_rows_dmatrix<I>(&self, rows: I) -> anyhow::Result<nalgebra::DMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let ((nrow, ncol), triplets) = self.rows_triplets(rows)?;
+        DMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+    }
+
+    pub fn read_rows_csr<I>(&self, rows: I) -> anyhow::Result<CsrMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let ((nrow, ncol), triplets) = self.rows_triplets(rows)?;
+        CsrMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+    }
+
+    pub fn read_rows_csc<I>(&self, rows: I) -> anyhow::Result<CscMatrix<f32>>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let ((nrow, ncol), triplets) = self.rows_triplets(rows)?;
+        CscMatrix::<f32>::from_nonzero_triplets(nrow, ncol, triplets)
+    }
+
+    pub fn read_rows_tensor<I>(&self, rows: I) -> anyhow::Result<Tensor>
+    where
+        I: Iterator<Item = usize>,
+    {
+        let ((nrow, ncol), triplets) = self.rows_triplets(rows)?;
         Tensor::from_nonzero_triplets(nrow, ncol, triplets)
     }
 
@@ -338,10 +877,8 @@ impl SparseIoVec {
                     if glob == glob_matched {
                         continue; // avoid identical cell pairs
                     }
-                    let didx = self.col_to_data[glob_matched];
-                    let loc = self.col_glob_to_loc[glob_matched];
                     let (_, loc_ncol, loc_triplets) =
-                        self.data_vec[didx].read_triplets_by_single_column(loc)?;
+                        self.read_aligned_single_column(glob_matched)?;
 
                     triplets.extend(
                         loc_triplets
@@ -509,10 +1046,8 @@ impl SparseIoVec {
                         if glob == glob_matched {
                             continue; // avoid identical cell pairs
                         }
-                        let didx = self.col_to_data[glob_matched];
-                        let loc = self.col_glob_to_loc[glob_matched];
                         let (_, loc_ncol, loc_triplets) =
-                            self.data_vec[didx].read_triplets_by_single_column(loc)?;
+                            self.read_aligned_single_column(glob_matched)?;
 
                         triplets.extend(
                             loc_triplets
@@ -0,0 +1,1028 @@
+use crate::misc::read_hdf5_strings;
+use crate::sparse_io::*;
+use crate::sparse_matrix_hdf5;
+use hdf5::types::VarLenUnicode;
+use log::{info, warn};
+use matrix_util::common_io::*;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::anyhow;
+
+/// Read-only view of an existing AnnData file (cell x feature)
+///
+/// AnnData keeps cells as rows (`obs`) and features as columns
+/// (`var`), so a `csr_matrix` `X` is directly our column-wise (CSC)
+/// storage and a `csc_matrix` `X` is our row-wise (CSR) storage.
+///
+/// ```text
+/// (root)
+///     ├── X (encoding-type: csr_matrix or csc_matrix, shape)
+///     │   ├── data
+///     │   ├── indices
+///     │   └── indptr
+///     ├── layers (optional)
+///     │   └── {layer} (the same encoding as `X`)
+///     ├── obs
+///     │   └── _index (column names)
+///     └── var
+///         └── _index (row names)
+/// ```
+///
+/// The other axis is not stored in the `.h5ad` file. When it is
+/// needed for the first time, we build a sidecar index file
+/// `{h5ad}.index.h5` (HDF5 backend) next to it and reuse it later.
+///
+#[derive(Debug, Clone)]
+pub struct SparseMtxData {
+    backend: Arc<hdf5::File>,
+    file_name: String,
+    matrix_group: String,
+    sidecar_file_name: String,
+    cells_by_row: bool,
+    nrow: usize,
+    ncol: usize,
+    nnz: usize,
+    stored_indptr: Vec<u64>,
+    sidecar: Arc<OnceLock<sparse_matrix_hdf5::SparseMtxData>>,
+    sidecar_build: Arc<Mutex<()>>,
+    by_column_indicies: Option<Vec<u64>>,
+    by_column_data: Option<Vec<f32>>,
+}
+
+const X_GROUP: &str = "X";
+const OBS_GROUP: &str = "obs";
+const VAR_GROUP: &str = "var";
+const LAYERS_GROUP_NAME: &str = "layers";
+const SIDECAR_SUFFIX: &str = ".index.h5";
+
+#[allow(dead_code)]
+impl SparseMtxData {
+    /// Open an existing `.h5ad` file (read-only)
+    /// * `backend_file`: AnnData file
+    pub fn open(backend_file: &str) -> anyhow::Result<Self> {
+        Self::open_matrix_group(backend_file, X_GROUP, SIDECAR_SUFFIX)
+    }
+
+    /// Open a sparse matrix group, `X` or `layers/{layer}`, sharing
+    /// the same `obs` and `var`
+    /// * `backend_file`: AnnData file
+    /// * `group_name`: group of the sparse matrix
+    /// * `sidecar_suffix`: suffix of the sidecar index file
+    fn open_matrix_group(
+        backend_file: &str,
+        group_name: &str,
+        sidecar_suffix: &str,
+    ) -> anyhow::Result<Self> {
+        let h5ad = hdf5::File::open(backend_file)?;
+
+        let x = h5ad.group(group_name).map_err(|_| {
+            anyhow!(
+                "`{}` is not a sparse matrix in {}",
+                group_name,
+                backend_file
+            )
+        })?;
+
+        let cells_by_row = match Self::_encoding_type(&x).as_deref() {
+            Some("csr_matrix") | Some("csr") => true,
+            Some("csc_matrix") | Some("csc") => false,
+            Some(other) => anyhow::bail!("unsupported encoding of `{}`: {}", group_name, other),
+            None => anyhow::bail!("couldn't figure out the encoding of `{}`", group_name),
+        };
+
+        let stored_indptr = x.dataset("indptr")?.read_1d::<u64>()?.to_vec();
+        let nmajor = stored_indptr.len().saturating_sub(1);
+        let nnz = stored_indptr.last().copied().unwrap_or(0) as usize;
+
+        // AnnData shape is (#cells, #features)
+        let (ncells, nfeatures) = match Self::_shape(&x) {
+            Some(shape) => shape,
+            None if cells_by_row => (nmajor, Self::_index_names(&h5ad, VAR_GROUP)?.len()),
+            None => (Self::_index_names(&h5ad, OBS_GROUP)?.len(), nmajor),
+        };
+
+        let (nrow, ncol) = (nfeatures, ncells);
+
+        if (cells_by_row && nmajor != ncol) || (!cells_by_row && nmajor != nrow) {
+            anyhow::bail!("`{}/indptr` doesn't match with its shape", group_name);
+        }
+
+        info!("#rows: {}, #columns: {}, #non-zeros: {}", nrow, ncol, nnz);
+
+        Ok(Self {
+            backend: h5ad.into(),
+            file_name: backend_file.to_string(),
+            matrix_group: group_name.to_string(),
+            sidecar_file_name: backend_file.to_string() + sidecar_suffix,
+            cells_by_row,
+            nrow,
+            ncol,
+            nnz,
+            stored_indptr,
+            sidecar: Arc::new(OnceLock::new()),
+            sidecar_build: Arc::new(Mutex::new(())),
+            by_column_indicies: None,
+            by_column_data: None,
+        })
+    }
+
+    /// Use a different sidecar index file instead of `{h5ad}.index.h5`
+    /// * `sidecar_file`: HDF5 file to keep the other axis
+    pub fn set_sidecar_file(&mut self, sidecar_file: &str) {
+        self.sidecar_file_name = sidecar_file.to_string();
+        self.sidecar = Arc::new(OnceLock::new());
+        self.sidecar_build = Arc::new(Mutex::new(()));
+    }
+
+    /// Build (or open) the sidecar index for the axis not stored in
+    /// the `.h5ad` file. Nothing will happen if it exists already.
+    pub fn build_sidecar_index(&self) -> anyhow::Result<()> {
+        self.sidecar_index().map(|_| ())
+    }
+
+    /////////////////////////////
+    // purely helper functions //
+    /////////////////////////////
+
+    fn _string_attr(loc: &hdf5::Location, name: &str) -> Option<String> {
+        use hdf5::types::{VarLenAscii, VarLenUnicode};
+        let attr = loc.attr(name).ok()?;
+        if let Ok(s) = attr.read_scalar::<VarLenUnicode>() {
+            Some(s.to_string())
+        } else {
+            attr.read_scalar::<VarLenAscii>()
+                .ok()
+                .map(|s| s.to_string())
+        }
+    }
+
+    fn _encoding_type(x: &hdf5::Group) -> Option<String> {
+        Self::_string_attr(x, "encoding-type").or_else(|| Self::_string_attr(x, "h5sparse_format"))
+    }
+
+    fn _shape(x: &hdf5::Group) -> Option<(usize, usize)> {
+        let shape = x
+            .attr("shape")
+            .or_else(|_| x.attr("h5sparse_shape"))
+            .ok()?
+            .read_1d::<u64>()
+            .ok()?;
+        if shape.len() == 2 {
+            Some((shape[0] as usize, shape[1] as usize))
+        } else {
+            None
+        }
+    }
+
+    /// Names in `obs` or `var` dataframe; the `_index` attribute
+    /// tells us which dataset holds the index.
+    fn _index_names(file: &hdf5::File, group_name: &str) -> anyhow::Result<Vec<Box<str>>> {
+        let group = file.group(group_name)?;
+        let index = Self::_string_attr(&group, "_index").unwrap_or("_index".to_string());
+        read_hdf5_strings(group.dataset(&index)?)
+    }
+
+    /// Read the stored `X` vectors `majors` and return triplets
+    /// `(minor, k, value)` where `k` is the position in `majors`
+    fn _read_stored_triplets(&self, majors: &[usize]) -> anyhow::Result<Vec<(u64, u64, f32)>> {
+        let indptr = &self.stored_indptr;
+        let nmajor = indptr.len() - 1;
+
+        let preloaded = if self.cells_by_row {
+            self.by_column_data
+                .as_ref()
+                .zip(self.by_column_indicies.as_ref())
+        } else {
+            None
+        };
+
+        let mut ret = vec![];
+
+        if let Some((data, indices)) = preloaded {
+            for (k, &j) in majors.iter().enumerate() {
+                if j < nmajor {
+                    let start = indptr[j] as usize;
+                    let end = indptr[j + 1] as usize;
+                    for (&i, &x) in indices[start..end].iter().zip(data[start..end].iter()) {
+                        ret.push((i, k as u64, x));
+                    }
+                }
+            }
+        } else {
+            let x = self.backend.group(&self.matrix_group)?;
+            let data = x.dataset("data")?;
+            let indices = x.dataset("indices")?;
+
+            for (k, &j) in majors.iter().enumerate() {
+                if j < nmajor {
+                    let start = indptr[j] as usize;
+                    let end = indptr[j + 1] as usize;
+                    if start < end {
+                        let data_slice = data.read_slice_1d::<f32, _>(start..end)?;
+                        let indices_slice = indices.read_slice_1d::<u64, _>(start..end)?;
+                        for (&i, &x) in indices_slice.iter().zip(data_slice.iter()) {
+                            ret.push((i, k as u64, x));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Open or build the HDF5 backend holding the axis that `X`
+    /// doesn't provide
+    ///
+    /// Clones (and rayon workers) build it only once, and a new index
+    /// is written under a temporary name and then renamed, so nobody
+    /// opens a half-written file.
+    fn sidecar_index(&self) -> anyhow::Result<&sparse_matrix_hdf5::SparseMtxData> {
+        if let Some(sidecar) = self.sidecar.get() {
+            return Ok(sidecar);
+        }
+
+        let _building = self
+            .sidecar_build
+            .lock()
+            .map_err(|_| anyhow!("failed to lock the sidecar index"))?;
+
+        if let Some(sidecar) = self.sidecar.get() {
+            return Ok(sidecar);
+        }
+
+        let mtx_shape = (self.nrow, self.ncol, self.nnz);
+
+        let existing = if std::path::Path::new(&self.sidecar_file_name).exists() {
+            sparse_matrix_hdf5::SparseMtxData::open(&self.sidecar_file_name)
+                .ok()
+                .filter(|x| {
+                    (x.num_rows(), x.num_columns(), x.num_non_zeros())
+                        == (Some(self.nrow), Some(self.ncol), Some(self.nnz))
+                })
+        } else {
+            None
+        };
+
+        let sidecar = match existing {
+            Some(sidecar) => sidecar,
+            None => {
+                info!("building sidecar index: {}", &self.sidecar_file_name);
+                let temp_file = format!("{}.{}.tmp", self.sidecar_file_name, std::process::id());
+                remove_file(&temp_file)?;
+
+                let x = self.backend.group(&self.matrix_group)?;
+                let data = x.dataset("data")?.read_1d::<f32>()?;
+                let indices = x.dataset("indices")?.read_1d::<u64>()?;
+                let indptr = &self.stored_indptr;
+
+                let nminor = if self.cells_by_row {
+                    self.nrow
+                } else {
+                    self.ncol
+                };
+
+                // transpose by counting sort over the minor axis
+                let mut minor_ptr = vec![0_u64; nminor + 1];
+                for &i in indices.iter() {
+                    minor_ptr[i as usize + 1] += 1;
+                }
+                for i in 0..nminor {
+                    minor_ptr[i + 1] += minor_ptr[i];
+                }
+
+                let mut next = minor_ptr.clone();
+                let mut minor_indices = vec![0_u64; self.nnz];
+                let mut minor_data = vec![0_f32; self.nnz];
+
+                for j in 0..(indptr.len() - 1) {
+                    for k in (indptr[j] as usize)..(indptr[j + 1] as usize) {
+                        let i = indices[k] as usize;
+                        let pos = next[i] as usize;
+                        minor_indices[pos] = j as u64;
+                        minor_data[pos] = data[k];
+                        next[i] += 1;
+                    }
+                }
+
+                let mut sidecar = sparse_matrix_hdf5::SparseMtxData::new(Some(&temp_file))?;
+                sidecar.record_mtx_shape(Some(mtx_shape))?;
+
+                if self.cells_by_row {
+                    sidecar.record_csr_dataset_backend(&minor_indices, &minor_data, &minor_ptr)?;
+                } else {
+                    sidecar.record_csc_dataset_backend(&minor_indices, &minor_data, &minor_ptr)?;
+                }
+                drop(sidecar);
+
+                std::fs::rename(&temp_file, &self.sidecar_file_name)?;
+                let mut sidecar = sparse_matrix_hdf5::SparseMtxData::open(&self.sidecar_file_name)?;
+                if self.cells_by_row {
+                    sidecar.read_row_indptr()?;
+                } else {
+                    sidecar.read_column_indptr()?;
+                }
+                sidecar
+            }
+        };
+
+        Ok(self.sidecar.get_or_init(|| sidecar))
+    }
+
+    fn _dataframe_name(axis: AnnotationAxis) -> &'static str {
+        match axis {
+            AnnotationAxis::Obs => OBS_GROUP,
+            AnnotationAxis::Var => VAR_GROUP,
+        }
+    }
+
+    fn read_only_error(&self) -> anyhow::Error {
+        anyhow!("{} is a read-only h5ad backend", self.file_name)
+    }
+}
+
+impl SparseIo for SparseMtxData {
+    type IndexIter = Vec<usize>;
+
+    fn initialize_backend(&mut self) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn record_mtx_shape(&mut self, _: Option<(usize, usize, usize)>) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn update_mtx_shape(&mut self, _: (usize, usize, usize)) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Values are read through HDF5 type conversion as they are
+    fn value_type(&self) -> Option<ValueType> {
+        None
+    }
+
+    fn set_value_type(&mut self, _: ValueType) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Nothing to read; column pointers come with `X` or the sidecar
+    fn read_column_indptr(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Nothing to read; row pointers come with `X` or the sidecar
+    fn read_row_indptr(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn preload_columns(&mut self) -> anyhow::Result<()> {
+        if self.cells_by_row {
+            let x = self.backend.group(&self.matrix_group)?;
+            self.by_column_data = Some(x.dataset("data")?.read_1d::<f32>()?.to_vec());
+            self.by_column_indicies = Some(x.dataset("indices")?.read_1d::<u64>()?.to_vec());
+        } else {
+            info!("columns are read from the sidecar index without preloading");
+        }
+        Ok(())
+    }
+
+    fn clean_preloaded_columns(&mut self) {
+        self.by_column_data = None;
+        self.by_column_indicies = None;
+    }
+
+    /// We never remove the original `.h5ad` file
+    fn remove_backend_file(&self) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn set_backend_file_name(&mut self, _: &str) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn move_backend_file(&mut self, _: &str) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Access file name of the h5ad file
+    fn get_backend_file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Export the data to a mtx file. This will take time.
+    /// * `mtx_file`: mtx file to be written
+    fn to_mtx_file(&self, mtx_file: &str) -> anyhow::Result<()> {
+        let mut buf = open_buf_writer(mtx_file)?;
+        writeln!(buf, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(buf, "{}\t{}\t{}", self.nrow, self.ncol, self.nnz)?;
+
+        for jj in 0..self.ncol {
+            let (_, _, triplets) = self.read_triplets_by_single_column(jj)?;
+            // write them with 1-based indices
+            for (ii, _, val) in triplets {
+                writeln!(buf, "{}\t{}\t{}", ii + 1, jj + 1, val)?;
+            }
+        }
+        buf.flush()?;
+        info!(
+            "{}: {} rows, {} columns, {} non-zeros",
+            mtx_file, self.nrow, self.ncol, self.nnz
+        );
+        Ok(())
+    }
+
+    /// Names come from `var` and `obs` of the h5ad file, so these only
+    /// warn without touching the read-only file
+    fn register_row_names_file(&mut self, _: &str) {
+        warn!("{}: row names are not registered", self.read_only_error());
+    }
+
+    fn register_row_names_vec(&mut self, _: &[Box<str>]) {
+        warn!("{}: row names are not registered", self.read_only_error());
+    }
+
+    fn register_column_names_file(&mut self, _: &str) {
+        warn!(
+            "{}: column names are not registered",
+            self.read_only_error()
+        );
+    }
+
+    fn register_column_names_vec(&mut self, _: &[Box<str>]) {
+        warn!(
+            "{}: column names are not registered",
+            self.read_only_error()
+        );
+    }
+
+    /// Number of rows (features, `var`)
+    fn num_rows(&self) -> Option<usize> {
+        Some(self.nrow)
+    }
+
+    /// Number of columns (cells, `obs`)
+    fn num_columns(&self) -> Option<usize> {
+        Some(self.ncol)
+    }
+
+    /// Number of non-zero elements
+    fn num_non_zeros(&self) -> Option<usize> {
+        Some(self.nnz)
+    }
+
+    fn register_names_file(
+        &mut self,
+        _key: &str,
+        _name_file: &str,
+        _name_columns: Range<usize>,
+        _name_sep: &str,
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn register_names_vec(&mut self, _key: &str, _names: &[Box<str>]) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Feature names in `var`
+    fn row_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        self.retrieve_registered_names("/row_names")
+    }
+
+    /// Cell names in `obs`
+    fn column_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        self.retrieve_registered_names("/column_names")
+    }
+
+    /// Get back names: `/row_names` (`var` index), `/column_names`
+    /// (`obs` index), or any other string dataset, e.g., `obs/cell_type`
+    /// * `key`: key for the registered names
+    fn retrieve_registered_names(&self, key: &str) -> anyhow::Result<Vec<Box<str>>> {
+        let (group_name, ntot) = match key {
+            "/row_names" => (VAR_GROUP, self.nrow),
+            "/column_names" => (OBS_GROUP, self.ncol),
+            _ => return read_hdf5_strings(self.backend.dataset(key)?),
+        };
+
+        match Self::_index_names(&self.backend, group_name) {
+            Ok(names) if names.len() == ntot => Ok(names),
+            _ => {
+                info!("{} index not found", group_name);
+                Ok((0..ntot).map(|x| x.to_string().into_boxed_str()).collect())
+            }
+        }
+    }
+
+    fn register_annotation(
+        &mut self,
+        _: AnnotationAxis,
+        _: &str,
+        _: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Read a column of the `obs` or `var` dataframe: `categorical`
+    /// groups (code -1 for missing), `string-array` or numeric arrays
+    fn retrieve_annotation(
+        &self,
+        axis: AnnotationAxis,
+        name: &str,
+    ) -> anyhow::Result<AnnotationColumn> {
+        let dataframe = self.backend.group(Self::_dataframe_name(axis))?;
+
+        if let Ok(column) = dataframe.group(name) {
+            let categories = read_hdf5_strings(column.dataset("categories")?)?;
+            let codes = column.dataset("codes")?.read_1d::<i64>()?;
+            let positions: Vec<Option<usize>> = codes
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| (c >= 0).then_some(i))
+                .collect();
+            let codes = codes.iter().map(|&c| c.max(0) as u64).collect();
+            return Ok(AnnotationColumn::Categorical { categories, codes }.take(&positions));
+        }
+
+        let values = dataframe
+            .dataset(name)
+            .map_err(|_| anyhow!("no annotation `{}` in {}", axis.key(name), self.file_name))?;
+
+        match Self::_string_attr(&values, "encoding-type").as_deref() {
+            Some("string-array") => Ok(AnnotationColumn::String(read_hdf5_strings(values)?)),
+            _ => Ok(AnnotationColumn::Numeric(values.read_1d::<f32>()?.to_vec())),
+        }
+    }
+
+    /// Columns in the `column-order` of the `obs` or `var` dataframe
+    fn annotation_names(&self, axis: AnnotationAxis) -> anyhow::Result<Vec<Box<str>>> {
+        let Ok(dataframe) = self.backend.group(Self::_dataframe_name(axis)) else {
+            return Ok(vec![]);
+        };
+
+        let column_order = dataframe
+            .attr("column-order")
+            .and_then(|attr| attr.read_1d::<VarLenUnicode>());
+
+        Ok(match column_order {
+            Ok(names) => names
+                .iter()
+                .map(|x| x.to_string().into_boxed_str())
+                .collect(),
+            Err(_) => {
+                let index = Self::_string_attr(&dataframe, "_index").unwrap_or("_index".into());
+                dataframe
+                    .member_names()?
+                    .into_iter()
+                    .filter(|x| *x != index && !x.starts_with("__"))
+                    .map(|x| x.into_boxed_str())
+                    .collect()
+            }
+        })
+    }
+
+    /// Sparse matrices in AnnData `layers`
+    fn layer_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        let Ok(layers) = self.backend.group(LAYERS_GROUP_NAME) else {
+            return Ok(vec![]);
+        };
+        let mut ret: Vec<Box<str>> = layers
+            .member_names()?
+            .into_iter()
+            .filter(|x| layers.group(x).is_ok())
+            .map(|x| x.into_boxed_str())
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    /// Open a sparse matrix in AnnData `layers` with its own sidecar
+    /// index `{h5ad}.{layer}.index.h5`
+    /// * `layer`: name of the layer (`X` if `None`)
+    fn open_layer(
+        &self,
+        layer: Option<&str>,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Self::IndexIter>>> {
+        let ret = match layer {
+            Some(layer) => Self::open_matrix_group(
+                &self.file_name,
+                &format!("{}/{}", LAYERS_GROUP_NAME, layer),
+                &format!(".{}{}", layer, SIDECAR_SUFFIX),
+            )?,
+            None => Self::open(&self.file_name)?,
+        };
+        Ok(Box::new(ret))
+    }
+
+    fn remove_layer(&mut self, _: &str) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    /// Read a single column and return a vector of triplets (row, col, value)
+    /// * `col` : usize
+    ///
+    fn read_triplets_by_single_column(
+        &self,
+        j_data: usize,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        self.read_triplets_by_columns(vec![j_data])
+    }
+
+    /// Read columns within the range and return a vector of triplets (row, col, value)
+    /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_triplets_by_columns(
+        &self,
+        columns: Self::IndexIter,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        if self.cells_by_row {
+            let ret = self._read_stored_triplets(&columns)?;
+            Ok((self.nrow, columns.len(), ret))
+        } else {
+            self.sidecar_index()?.read_triplets_by_columns(columns)
+        }
+    }
+
+    /// Read rows within the range and return a vector of triplets (row, column, value)
+    /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_triplets_by_rows(
+        &self,
+        rows: Self::IndexIter,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        if self.cells_by_row {
+            self.sidecar_index()?.read_triplets_by_rows(rows)
+        } else {
+            let ret = self
+                ._read_stored_triplets(&rows)?
+                .into_iter()
+                .map(|(j, i, x)| (i, j, x))
+                .collect();
+            Ok((rows.len(), self.ncol, ret))
+        }
+    }
+
+    fn record_csr_dataset_backend(
+        &mut self,
+        _: &[u64],
+        _: &[f32],
+        _: &[u64],
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn record_csc_dataset_backend(
+        &mut self,
+        _: &[u64],
+        _: &[f32],
+        _: &[u64],
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn allocate_compressed_dataset_backend(
+        &mut self,
+        _: &str,
+        _: usize,
+        _: ValueType,
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn record_compressed_chunk_backend(
+        &mut self,
+        _: &str,
+        _: usize,
+        _: &[u64],
+        _: &[f32],
+    ) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn record_compressed_indptr_backend(&mut self, _: &str, _: &[u64]) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+
+    fn read_compressed_indptr_backend(&self, _: &str) -> anyhow::Result<Vec<u64>> {
+        Err(self.read_only_error())
+    }
+
+    fn compressed_dataset_len_backend(&self, _: &str) -> anyhow::Result<(usize, usize)> {
+        Err(self.read_only_error())
+    }
+
+    fn read_compressed_chunk_backend(
+        &self,
+        _: &str,
+        _: Range<usize>,
+    ) -> anyhow::Result<(Vec<u64>, Vec<f32>)> {
+        Err(self.read_only_error())
+    }
+
+    fn resize_compressed_dataset_backend(&mut self, _: &str, _: usize) -> anyhow::Result<()> {
+        Err(self.read_only_error())
+    }
+}
+
+//////////////////////////
+// exporting to AnnData //
+//////////////////////////
+
+const EXPORT_BLOCK_SIZE: usize = 100;
+const EXPORT_NUM_CHUNKS: usize = 1000;
+const EXPORT_MIN_CHUNK_SIZE: usize = 8192;
+const EXPORT_DEFLATE_LEVEL: u8 = 4;
+
+/// Additional cell-level (`obs`) information to be stored along with
+/// `X` in the `.h5ad` file
+#[derive(Default, Debug, Clone)]
+pub struct H5adAnnotations {
+    /// `obs` columns, e.g., ("batch", membership); string columns
+    /// are stored as categorical and numeric ones as float arrays
+    pub obs_columns: Vec<(Box<str>, AnnotationColumn)>,
+    /// `obsm` matrices (cells x dimensions), e.g., ("X_latent", z_nk)
+    pub obsm: Vec<(Box<str>, Array2<f32>)>,
+}
+
+/// Put the rows of a latent matrix in the order of the columns, e.g.,
+/// `{out}.latent.parquet` of `senna` with `{cell}@{data_tag}` rows,
+/// leaving `NaN` where columns have no row
+///
+/// * `row_names` - names of the rows of `z_nk`, matched exactly or by
+///   the part before the last `@` otherwise
+/// * `z_nk` - latent matrix
+/// * `column_names` - names of the columns (cells)
+///
+/// Returns the aligned matrix and the number of matched rows
+pub fn align_obsm_rows(
+    row_names: &[Box<str>],
+    z_nk: &Array2<f32>,
+    column_names: &[Box<str>],
+) -> (Array2<f32>, usize) {
+    let name2col = build_name2index_map(column_names);
+
+    let mut ret = Array2::<f32>::from_elem((column_names.len(), z_nk.ncols()), f32::NAN);
+    let mut nmatched = 0;
+    for (r, name) in row_names.iter().enumerate() {
+        let j = name2col.get(name).or_else(|| {
+            name.rsplit_once(COLUMN_SEP)
+                .and_then(|(base, _)| name2col.get(base))
+        });
+        if let Some(&j) = j {
+            ret.row_mut(j).assign(&z_nk.row(r));
+            nmatched += 1;
+        }
+    }
+    (ret, nmatched)
+}
+
+/// Write `data` into an AnnData file where cells are rows and `X`
+/// is encoded as `csr_matrix`, i.e., our column-wise storage.
+///
+/// ```text
+/// (root)
+///     ├── X (csr_matrix)
+///     ├── obs (_index: column names, categorical/numeric columns)
+///     ├── var (_index: row names)
+///     ├── obsm (optional matrices)
+///     └── layers, obsp, varm, varp, uns (empty)
+/// ```
+///
+/// * `data` - any sparse matrix backend
+/// * `h5ad_file` - output file (overwritten)
+/// * `annotations` - `obs` columns and `obsm` matrices
+pub fn write_h5ad<S>(data: &S, h5ad_file: &str, annotations: &H5adAnnotations) -> anyhow::Result<()>
+where
+    S: SparseIo + ?Sized,
+{
+    let (nrow, ncol, nnz) = match (data.num_rows(), data.num_columns(), data.num_non_zeros()) {
+        (Some(nrow), Some(ncol), Some(nnz)) => (nrow, ncol, nnz),
+        _ => anyhow::bail!("Unable to figure out the size of the backend data"),
+    };
+
+    let row_names = data.row_names()?;
+    let column_names = data.column_names()?;
+
+    if row_names.len() != nrow || column_names.len() != ncol {
+        anyhow::bail!("names don't match with the size of the backend data");
+    }
+
+    remove_file(h5ad_file)?;
+    let file = hdf5::File::create(h5ad_file)?;
+    set_encoding(&file, "anndata", "0.1.0")?;
+
+    ////////////////////////////////////
+    // X: cells x features, CSR       //
+    ////////////////////////////////////
+
+    let x = file.create_group(X_GROUP)?;
+    set_encoding(&x, "csr_matrix", "0.1.0")?;
+    x.new_attr::<i64>()
+        .shape(2)
+        .create("shape")?
+        .write_raw(&[ncol as i64, nrow as i64])?;
+
+    let chunk_size = (nnz / EXPORT_NUM_CHUNKS)
+        .max(EXPORT_MIN_CHUNK_SIZE)
+        .min(nnz)
+        .max(1);
+
+    let x_data = x
+        .new_dataset::<f32>()
+        .shape(nnz)
+        .chunk([chunk_size])
+        .deflate(EXPORT_DEFLATE_LEVEL)
+        .create("data")?;
+
+    let x_indices = x
+        .new_dataset::<i64>()
+        .shape(nnz)
+        .chunk([chunk_size])
+        .deflate(EXPORT_DEFLATE_LEVEL)
+        .create("indices")?;
+
+    let mut indptr: Vec<i64> = Vec::with_capacity(ncol + 1);
+    indptr.push(0);
+
+    let mut offset = 0_usize;
+
+    for lb in (0..ncol).step_by(EXPORT_BLOCK_SIZE) {
+        let ub = (lb + EXPORT_BLOCK_SIZE).min(ncol);
+        let (_, _, mut triplets) = data.read_triplets_by_column_range(lb..ub)?;
+        triplets.sort_by_key(|&(i, j, _)| (j, i));
+
+        let end = offset + triplets.len();
+        if end > nnz {
+            anyhow::bail!("found more non-zero elements than {}", nnz);
+        }
+
+        let values: Vec<f32> = triplets.iter().map(|&(_, _, x)| x).collect();
+        let indices: Vec<i64> = triplets.iter().map(|&(i, _, _)| i as i64).collect();
+
+        if !values.is_empty() {
+            x_data.write_slice(values.as_slice(), offset..end)?;
+            x_indices.write_slice(indices.as_slice(), offset..end)?;
+        }
+
+        let mut counts = vec![0_i64; ub - lb];
+        for &(_, j, _) in triplets.iter() {
+            counts[j as usize] += 1;
+        }
+        for c in counts {
+            let last = *indptr.last().expect("indptr");
+            indptr.push(last + c);
+        }
+        offset = end;
+    }
+
+    if offset != nnz {
+        anyhow::bail!("expected {} non-zero elements, but found {}", nnz, offset);
+    }
+
+    x.new_dataset_builder()
+        .with_data(indptr.as_slice())
+        .create("indptr")?;
+
+    info!("wrote X: {} cells x {} features", ncol, nrow);
+
+    /////////////////////
+    // obs and var     //
+    /////////////////////
+
+    let obs_column_names: Vec<Box<str>> = annotations
+        .obs_columns
+        .iter()
+        .map(|(k, _)| k.clone())
+        .collect();
+
+    let obs = create_dataframe(&file, OBS_GROUP, &column_names, &obs_column_names)?;
+
+    for (key, column) in annotations.obs_columns.iter() {
+        if column.len() != ncol {
+            anyhow::bail!("`obs` column {} doesn't match with #columns", key);
+        }
+
+        let (categories, codes): (Vec<Box<str>>, Vec<i32>) = match column {
+            AnnotationColumn::Numeric(values) => {
+                let ds = obs
+                    .new_dataset_builder()
+                    .with_data(values.as_slice())
+                    .create(key.as_ref())?;
+                set_encoding(&ds, "array", "0.2.0")?;
+                continue;
+            }
+            AnnotationColumn::Categorical { categories, codes } => (
+                categories.clone(),
+                codes.iter().map(|&c| c as i32).collect(),
+            ),
+            AnnotationColumn::String(values) => {
+                let mut categories = values.clone();
+                categories.sort();
+                categories.dedup();
+
+                let cat2code = build_name2index_map(&categories);
+                let codes = values.iter().map(|v| cat2code[v] as i32).collect();
+                (categories, codes)
+            }
+        };
+
+        let column = obs.create_group(key)?;
+        set_encoding(&column, "categorical", "0.2.0")?;
+        column
+            .new_attr::<bool>()
+            .create("ordered")?
+            .write_scalar(&false)?;
+        write_string_array(&column, "categories", &categories)?;
+        column
+            .new_dataset_builder()
+            .with_data(codes.as_slice())
+            .create("codes")?;
+    }
+
+    create_dataframe(&file, VAR_GROUP, &row_names, &[])?;
+
+    /////////////////////////////
+    // obsm and empty mappings //
+    /////////////////////////////
+
+    let obsm = file.create_group("obsm")?;
+    set_encoding(&obsm, "dict", "0.1.0")?;
+
+    for (key, mat) in annotations.obsm.iter() {
+        if mat.nrows() != ncol {
+            anyhow::bail!("`obsm` {} doesn't match with #columns", key);
+        }
+        let mat = mat.as_standard_layout().to_owned();
+        let ds = obsm
+            .new_dataset_builder()
+            .with_data(&mat)
+            .create(key.as_ref())?;
+        set_encoding(&ds, "array", "0.2.0")?;
+        info!("wrote obsm/{}: {} x {}", key, mat.nrows(), mat.ncols());
+    }
+
+    for key in ["layers", "obsp", "varm", "varp", "uns"] {
+        let group = file.create_group(key)?;
+        set_encoding(&group, "dict", "0.1.0")?;
+    }
+
+    file.flush()?;
+    info!("wrote {}", h5ad_file);
+    Ok(())
+}
+
+/// Set `encoding-type` and `encoding-version` attributes
+fn set_encoding(loc: &hdf5::Location, kind: &str, version: &str) -> anyhow::Result<()> {
+    for (name, value) in [("encoding-type", kind), ("encoding-version", version)] {
+        loc.new_attr::<VarLenUnicode>()
+            .create(name)?
+            .write_scalar(&value.parse::<VarLenUnicode>()?)?;
+    }
+    Ok(())
+}
+
+/// Convert names to HDF5 strings, which can't have interior NULs
+fn to_varlen_unicode(names: &[Box<str>]) -> anyhow::Result<Vec<VarLenUnicode>> {
+    names
+        .iter()
+        .map(|x| {
+            x.parse()
+                .map_err(|e| anyhow!("can't store `{}` in HDF5: {}", x.escape_debug(), e))
+        })
+        .collect()
+}
+
+/// Write a `string-array` dataset
+fn write_string_array(group: &hdf5::Group, key: &str, names: &[Box<str>]) -> anyhow::Result<()> {
+    let names = to_varlen_unicode(names)?;
+    let ds = group
+        .new_dataset::<VarLenUnicode>()
+        .shape(names.len())
+        .create(key)?;
+    ds.write(&names)?;
+    set_encoding(&ds, "string-array", "0.2.0")
+}
+
+/// Create a `dataframe` group with its `_index`
+fn create_dataframe(
+    file: &hdf5::File,
+    key: &str,
+    index: &[Box<str>],
+    column_order: &[Box<str>],
+) -> anyhow::Result<hdf5::Group> {
+    let group = file.create_group(key)?;
+    set_encoding(&group, "dataframe", "0.2.0")?;
+    group
+        .new_attr::<VarLenUnicode>()
+        .create("_index")?
+        .write_scalar(&"_index".parse::<VarLenUnicode>()?)?;
+
+    let column_order = to_varlen_unicode(column_order)?;
+    group
+        .new_attr::<VarLenUnicode>()
+        .shape(column_order.len())
+        .create("column-order")?
+        .write(&column_order)?;
+
+    write_string_array(&group, "_index", index)?;
+    Ok(group)
+}
@@ -1,3 +1,9 @@
+use crate::annotation::ENCODING_ATTR;
+use crate::chunk_cache::*;
+use crate::compressed_parts::CompressedParts;
+use crate::external_sort::STREAM_CHUNK_SIZE;
+use crate::misc::read_hdf5_strings;
+use crate::mmap_columns::*;
 use crate::sparse_io::*;
 use hdf5::filters::blosc_set_nthreads;
 use log::info;
@@ -11,20 +17,86 @@ const NUM_CHUNKS: usize = 1000;
 const MIN_CHUNK_SIZE: usize = 8192;
 const COMPRESSION_LEVEL: u8 = 5;
 
+/// Type of the values stored in `dataset`
+fn dataset_value_type(dataset: &hdf5::Dataset) -> anyhow::Result<ValueType> {
+    let dtype = dataset.dtype()?;
+    Ok(if dtype.is::<u16>() {
+        ValueType::U16
+    } else if dtype.is::<u32>() {
+        ValueType::U32
+    } else if dtype.is::<f64>() {
+        ValueType::F64
+    } else {
+        ValueType::F32
+    })
+}
+
+/// Replace a fixed-size dataset `name` in the `group` by a
+/// resizable copy with room for `nnz` elements, streaming the values
+/// without holding them all in memory
+fn resizable_copy(group: &hdf5::Group, name: &str, nnz: usize) -> anyhow::Result<()> {
+    fn copy<T: hdf5::H5Type>(
+        group: &hdf5::Group,
+        name: &str,
+        temp: &str,
+        nnz: usize,
+    ) -> anyhow::Result<()> {
+        let source = group.dataset(name)?;
+        let len = source.size();
+        let chunk_size = (nnz / NUM_CHUNKS).max(MIN_CHUNK_SIZE).min(nnz.max(1));
+
+        let target = group
+            .new_dataset::<T>()
+            .shape(len..)
+            .chunk([chunk_size])
+            .blosc_blosclz(COMPRESSION_LEVEL, true)
+            .create(temp)?;
+
+        for lb in (0..len).step_by(STREAM_CHUNK_SIZE) {
+            let ub = (lb + STREAM_CHUNK_SIZE).min(len);
+            let values = source.read_slice_1d::<T, _>(lb..ub)?;
+            target.write_slice(&values, lb..ub)?;
+        }
+        Ok(())
+    }
+
+    let temp = format!("{}.resizable", name);
+    if group.link_exists(&temp) {
+        group.unlink(&temp)?;
+    }
+
+    match (name, dataset_value_type(&group.dataset(name)?)?) {
+        ("indices", _) => copy::<u64>(group, name, &temp, nnz)?,
+        (_, ValueType::U16) => copy::<u16>(group, name, &temp, nnz)?,
+        (_, ValueType::U32) => copy::<u32>(group, name, &temp, nnz)?,
+        (_, ValueType::F32) => copy::<f32>(group, name, &temp, nnz)?,
+        (_, ValueType::F64) => copy::<f64>(group, name, &temp, nnz)?,
+    }
+
+    group.unlink(name)?;
+    group.relink(&temp, name)?;
+    Ok(())
+}
+
 /// 10x-like cell-feature matrix with hdf5 (feature x cell)
 ///
 /// ```text
 /// (root)
 ///     ├── nrow
 ///     ├── ncell
+///     ├── value_type (u16, u32, f32, or f64 of `data`)
 ///     ├── by_column
 ///     │   ├── data
 ///     │   ├── indices (row indices)
 ///     │   └── indptr (column pointers)
-///     └── by_row
-///         ├── data
-///         ├── indices (column indices)
-///         └── indptr (row pointers)
+///     ├── by_row
+///     │   ├── data
+///     │   ├── indices (column indices)
+///     │   └── indptr (row pointers)
+///     └── layers (optional, e.g., spliced, unspliced)
+///         └── {layer}
+///             ├── by_column
+///             └── by_row
 /// ```
 ///
 #[derive(Debug, Clone)]
@@ -35,8 +107,10 @@ pub struct SparseMtxData {
     max_column_name_idx: usize,
     by_column_indptr: Vec<u64>,
     by_row_indptr: Vec<u64>,
-    by_column_indicies: Option<Vec<u64>>,
-    by_column_data: Option<Vec<f32>>,
+    by_column_preloaded: Option<Arc<PreloadedColumns>>,
+    layer: Option<Box<str>>,
+    column_cache: Arc<ChunkCache>,
+    value_type: Option<ValueType>,
 }
 
 #[allow(dead_code)]
@@ -71,6 +145,7 @@ impl SparseMtxData {
             anyhow::bail!("Couldn't figure out the size of this sparse matrix data");
         }
 
+        let value_type = Self::_value_type(&hdf5_backend);
         let mut ret = Self {
             backend: hdf5_backend.into(),
             file_name: backend_file.to_string(),
@@ -78,8 +153,10 @@ impl SparseMtxData {
             max_column_name_idx: MAX_COLUMN_NAME_IDX,
             by_column_indptr: vec![],
             by_row_indptr: vec![],
-            by_column_indicies: None,
-            by_column_data: None,
+            by_column_preloaded: None,
+            layer: None,
+            column_cache: Arc::new(ChunkCache::default()),
+            value_type,
         };
 
         ret.read_column_indptr()?;
@@ -94,10 +171,12 @@ impl SparseMtxData {
     /// * `mtx_file`: mtx file to be read into HDF5 backend
     /// * `backend_file`: HDF5 file to be associated with
     /// * `index_by_row`: if true, the matrix will be indexed by row
+    /// * `value_type`: type of the stored values (detected if `None`)
     pub fn from_mtx_file(
         mtx_file: &str,
         backend_file: Option<&str>,
         index_by_row: Option<bool>,
+        value_type: Option<ValueType>,
     ) -> anyhow::Result<Self> {
         // create an object
         let mut ret = match backend_file {
@@ -112,6 +191,10 @@ impl SparseMtxData {
             }
         };
 
+        if let Some(value_type) = value_type {
+            ret.set_value_type(value_type)?;
+        }
+
         // populate data from mtx file
         info!("importing mtx file by column");
         ret.import_mtx_file_by_col(mtx_file)?;
@@ -207,6 +290,134 @@ impl SparseMtxData {
         file.attr("nnz").ok()?.read_scalar().ok()
     }
 
+    fn _value_type(file: &hdf5::File) -> Option<ValueType> {
+        let value_type = file
+            .attr(VALUE_TYPE_ATTR)
+            .ok()?
+            .read_scalar::<hdf5::types::VarLenUnicode>()
+            .ok()?;
+        ValueType::from_name(value_type.as_str()).ok()
+    }
+
+    /// Helper function to create the dataset `name` of `values`,
+    /// stored as the value type of this backend
+    fn new_value_dataset(
+        &mut self,
+        group: &hdf5::Group,
+        name: &str,
+        values: &[f32],
+    ) -> anyhow::Result<()> {
+        fn create<T: hdf5::H5Type>(
+            group: &hdf5::Group,
+            name: &str,
+            values: &[T],
+        ) -> anyhow::Result<()> {
+            let nelem = values.len();
+            let chunk_size = (nelem / NUM_CHUNKS).max(MIN_CHUNK_SIZE).min(nelem);
+            // resizable to append columns later
+            group
+                .new_dataset::<T>()
+                .shape(nelem..)
+                .chunk([chunk_size])
+                .blosc_blosclz(COMPRESSION_LEVEL, true)
+                .create(name)?
+                .write(values)?;
+            Ok(())
+        }
+
+        match self.value_type_for(values)? {
+            ValueType::U16 => {
+                let values = ValueType::U16.checked_values(values, |x| x as u16)?;
+                create(group, name, &values)
+            }
+            ValueType::U32 => {
+                let values = ValueType::U32.checked_values(values, |x| x as u32)?;
+                create(group, name, &values)
+            }
+            ValueType::F32 => create(group, name, values),
+            ValueType::F64 => {
+                let values: Vec<f64> = values.iter().map(|&x| x as f64).collect();
+                create(group, name, &values)
+            }
+        }
+    }
+
+    /// Helper function to forget the cached columns, in memory and
+    /// on disk, after the backend has changed
+    fn invalidate_column_caches(&self) -> anyhow::Result<()> {
+        self.column_cache.clear();
+        remove_mmap_cache(&self.file_name)
+    }
+
+    /// Key of the compressed data `name` (e.g., `by_column`) in the
+    /// layer of this matrix
+    fn _matrix_key(&self, name: &str) -> String {
+        layer_key(self.layer.as_deref(), name)
+    }
+
+    /// (nrow, ncol) if the columns are indexed
+    fn column_shape(&self) -> anyhow::Result<(usize, usize)> {
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow!("can't figure out the number of rows"))?;
+        let ncol = self
+            .num_columns()
+            .ok_or(anyhow!("can't figure out the number of columns"))?;
+        if self.by_column_indptr.len() <= ncol {
+            return Err(anyhow!("columns are not indexed"));
+        }
+        Ok((nrow, ncol))
+    }
+
+    /// (nrow, ncol) if the rows are indexed
+    fn row_shape(&self) -> anyhow::Result<(usize, usize)> {
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow!("can't figure out the number of rows"))?;
+        let ncol = self
+            .num_columns()
+            .ok_or(anyhow!("can't figure out the number of columns"))?;
+        if self.by_row_indptr.len() <= nrow {
+            return Err(anyhow!("rows are not indexed"));
+        }
+        Ok((nrow, ncol))
+    }
+
+    /// Read the columns (rows) `outer` of the compressed group `key`
+    /// through `cache`, opening `data` and `indices` only once
+    ///
+    /// * `cache` - block cache (a zero-sized one keeps nothing)
+    /// * `key` - e.g., `/by_column` (of a layer)
+    /// * `indptr` - pointers of the group
+    /// * `outer` - columns (rows) to read
+    fn read_compressed_parts(
+        &self,
+        cache: &ChunkCache,
+        key: &str,
+        indptr: &[u64],
+        outer: &[usize],
+    ) -> anyhow::Result<CompressedParts> {
+        let group = self.backend.group(key)?;
+        let data = group.dataset("data")?;
+        let indices = group.dataset("indices")?;
+
+        // blocks aligned with the larger of the two chunks
+        let chunk_len = |dataset: &hdf5::Dataset| {
+            dataset
+                .chunk()
+                .and_then(|x| x.first().copied())
+                .unwrap_or(DEFAULT_BLOCK_SIZE)
+        };
+        let block_size = chunk_len(&data).max(chunk_len(&indices));
+
+        read_compressed_parts_through_cache(cache, key, indptr, outer, block_size, |range| {
+            Ok((
+                indices.read_slice_1d::<u64, _>(range.clone())?.to_vec(),
+                data.read_slice_1d::<f32, _>(range)?.to_vec(),
+            ))
+        })
+    }
+
     fn set_attrs(&mut self, attr_name: &str, value: usize) -> anyhow::Result<()> {
         if self.backend.attr(attr_name).is_err() {
             self.backend
@@ -235,8 +446,10 @@ impl SparseMtxData {
             max_column_name_idx: MAX_COLUMN_NAME_IDX,
             by_column_indptr: vec![],
             by_row_indptr: vec![],
-            by_column_indicies: None,
-            by_column_data: None,
+            by_column_preloaded: None,
+            layer: None,
+            column_cache: Arc::new(ChunkCache::default()),
+            value_type: None,
         })
     }
 }
@@ -252,7 +465,11 @@ impl SparseIo for SparseMtxData {
         self.max_row_name_idx = MAX_ROW_NAME_IDX;
         self.by_column_indptr = vec![];
         self.by_row_indptr = vec![];
+        self.column_cache.clear();
 
+        if let Some(value_type) = self.value_type {
+            self.set_value_type(value_type)?;
+        }
         Ok(())
     }
 
@@ -268,9 +485,48 @@ impl SparseIo for SparseMtxData {
         Ok(())
     }
 
+    /// Overwrite the shape in the HDF5 backend
+    fn update_mtx_shape(&mut self, mtx_shape: (usize, usize, usize)) -> anyhow::Result<()> {
+        let (nrow, ncol, nnz) = mtx_shape;
+        for (attr_name, value) in [("nrow", nrow), ("ncol", ncol), ("nnz", nnz)] {
+            match self.backend.attr(attr_name) {
+                Ok(attr) => attr.write_scalar(&value)?,
+                Err(_) => self
+                    .backend
+                    .new_attr::<usize>()
+                    .create(attr_name)?
+                    .write_scalar(&value)?,
+            }
+        }
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    fn value_type(&self) -> Option<ValueType> {
+        self.value_type
+    }
+
+    /// Keep the value type in the file attributes
+    fn set_value_type(&mut self, value_type: ValueType) -> anyhow::Result<()> {
+        use hdf5::types::VarLenUnicode;
+
+        let name = value_type.name().parse::<VarLenUnicode>()?;
+        match self.backend.attr(VALUE_TYPE_ATTR) {
+            Ok(attr) => attr.write_scalar(&name)?,
+            Err(_) => self
+                .backend
+                .new_attr::<VarLenUnicode>()
+                .create(VALUE_TYPE_ATTR)?
+                .write_scalar(&name)?,
+        }
+        self.value_type = Some(value_type);
+        Ok(())
+    }
+
     /// Read column index pointers
     fn read_column_indptr(&mut self) -> anyhow::Result<()> {
-        if let Ok(by_column) = self.backend.group("/by_column") {
+        self.column_cache.clear();
+        if let Ok(by_column) = self.backend.group(&self._matrix_key("by_column")) {
             let indptr = by_column.dataset("indptr")?.read_1d::<u64>()?;
             self.by_column_indptr.clear();
             self.by_column_indptr.extend(indptr);
@@ -280,7 +536,7 @@ impl SparseIo for SparseMtxData {
 
     /// Read row index pointers
     fn read_row_indptr(&mut self) -> anyhow::Result<()> {
-        if let Ok(by_row) = self.backend.group("/by_row") {
+        if let Ok(by_row) = self.backend.group(&self._matrix_key("by_row")) {
             let indptr = by_row.dataset("indptr")?.read_1d::<u64>()?;
             self.by_row_indptr.clear();
             self.by_row_indptr.extend(indptr);
@@ -289,18 +545,23 @@ impl SparseIo for SparseMtxData {
     }
 
     fn preload_columns(&mut self) -> anyhow::Result<()> {
-        let by_column = self.backend.group("/by_column")?;
+        let by_column = self.backend.group(&self._matrix_key("by_column"))?;
         let data = by_column.dataset("data")?.read_1d::<f32>()?.to_vec();
         let indices = by_column.dataset("indices")?.read_1d::<u64>()?.to_vec();
 
-        self.by_column_data = Some(data);
-        self.by_column_indicies = Some(indices);
+        self.by_column_preloaded = Some(Arc::new(PreloadedColumns::Heap { indices, data }));
+        Ok(())
+    }
+
+    fn preload_columns_mmap(&mut self) -> anyhow::Result<()> {
+        let key = self._matrix_key("by_column");
+        let preloaded = mmap_compressed_columns(self, &key, &self.by_column_indptr)?;
+        self.by_column_preloaded = Some(Arc::new(preloaded));
         Ok(())
     }
 
     fn clean_preloaded_columns(&mut self) {
-        self.by_column_data = None;
-        self.by_column_indicies = None;
+        self.by_column_preloaded = None;
     }
 
     /// Remove backend file to free up disk space
@@ -309,6 +570,25 @@ impl SparseIo for SparseMtxData {
         if backend.exists() {
             std::fs::remove_file(backend)?;
         }
+        remove_mmap_cache(&self.file_name)?;
+        Ok(())
+    }
+
+    /// Direct the next `initialize_backend` to another HDF5 file
+    fn set_backend_file_name(&mut self, file_name: &str) -> anyhow::Result<()> {
+        self.file_name = file_name.to_string();
+        Ok(())
+    }
+
+    /// Move the HDF5 file and reopen it there
+    fn move_backend_file(&mut self, file_name: &str) -> anyhow::Result<()> {
+        self.backend.flush()?;
+        replace_backend_path(&self.file_name, file_name)?;
+        remove_mmap_cache(file_name)?;
+
+        self.backend = hdf5::File::open_rw(file_name)?.into();
+        self.file_name = file_name.to_string();
+        self.column_cache.clear();
         Ok(())
     }
 
@@ -320,7 +600,7 @@ impl SparseIo for SparseMtxData {
     /// Export the data to a mtx file. This will take time.
     /// * `mtx_file`: mtx file to be written
     fn to_mtx_file(&self, mtx_file: &str) -> anyhow::Result<()> {
-        let by_column = self.backend.group("/by_column")?;
+        let by_column = self.backend.group(&self._matrix_key("by_column"))?;
 
         let indptr = by_column.dataset("indptr")?.read_1d::<u64>()?;
         let data = by_column.dataset("data")?;
@@ -408,7 +688,10 @@ impl SparseIo for SparseMtxData {
 
     /// Number of non-zero elements
     fn num_non_zeros(&self) -> Option<usize> {
-        Self::_num_nnz(&self.backend)
+        match self.layer {
+            Some(_) => self.by_column_indptr.last().map(|&nnz| nnz as usize),
+            None => Self::_num_nnz(&self.backend),
+        }
     }
 
     /// Add arbitrary names (a vector of strings)
@@ -444,6 +727,9 @@ impl SparseIo for SparseMtxData {
             .collect();
 
         let root = self.backend.group("/")?;
+        if root.link_exists(key) {
+            root.unlink(key)?;
+        }
         root.new_dataset::<VarLenUnicode>()
             .shape(_names.len())
             .chunk([_names.len()])
@@ -465,6 +751,9 @@ impl SparseIo for SparseMtxData {
             .collect::<Vec<_>>();
 
         let root = self.backend.group("/")?;
+        if root.link_exists(key) {
+            root.unlink(key)?;
+        }
         root.new_dataset::<VarLenUnicode>()
             .shape(_names.len())
             .chunk([_names.len()])
@@ -494,6 +783,168 @@ impl SparseIo for SparseMtxData {
         Ok(ret.iter().map(|x| x.to_string().into_boxed_str()).collect())
     }
 
+    /// Add (or replace) an annotation column
+    ///
+    /// ```text
+    ///     └── obs or var
+    ///         └── {name} (encoding-type)
+    ///             ├── categories, codes (categorical)
+    ///             └── values (numeric or string)
+    /// ```
+    fn register_annotation(
+        &mut self,
+        axis: AnnotationAxis,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        use hdf5::types::VarLenUnicode;
+
+        let group_name = axis.group_name();
+        if self.backend.group(group_name).is_err() {
+            self.backend.create_group(group_name)?;
+        }
+
+        let parent = self.backend.group(group_name)?;
+        if parent.link_exists(name) {
+            parent.unlink(name)?;
+        }
+
+        let group = parent.create_group(name)?;
+        group
+            .new_attr::<VarLenUnicode>()
+            .create(ENCODING_ATTR)?
+            .write_scalar(&column.encoding_type().parse::<VarLenUnicode>()?)?;
+
+        let write_strings = |key: &str, values: &[Box<str>]| -> anyhow::Result<()> {
+            let values = values
+                .iter()
+                .map(|x| {
+                    x.parse::<VarLenUnicode>()
+                        .map_err(|e| anyhow!("can't store `{}` in HDF5: {}", x.escape_debug(), e))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            group
+                .new_dataset::<VarLenUnicode>()
+                .shape(values.len())
+                .create(key)?
+                .write(&values)?;
+            Ok(())
+        };
+
+        match column {
+            AnnotationColumn::Categorical { categories, codes } => {
+                write_strings("categories", categories)?;
+                group
+                    .new_dataset_builder()
+                    .with_data(codes.as_slice())
+                    .create("codes")?;
+            }
+            AnnotationColumn::Numeric(values) => {
+                group
+                    .new_dataset_builder()
+                    .with_data(values.as_slice())
+                    .create("values")?;
+            }
+            AnnotationColumn::String(values) => {
+                write_strings("values", values)?;
+            }
+        }
+
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    fn retrieve_annotation(
+        &self,
+        axis: AnnotationAxis,
+        name: &str,
+    ) -> anyhow::Result<AnnotationColumn> {
+        use hdf5::types::VarLenUnicode;
+
+        let key = axis.key(name);
+        let group = self
+            .backend
+            .group(&key)
+            .map_err(|_| anyhow!("no annotation `{}` in {}", key, self.file_name))?;
+
+        let encoding = group
+            .attr(ENCODING_ATTR)?
+            .read_scalar::<VarLenUnicode>()?
+            .to_string();
+
+        match encoding.as_str() {
+            AnnotationColumn::CATEGORICAL => Ok(AnnotationColumn::Categorical {
+                categories: read_hdf5_strings(group.dataset("categories")?)?,
+                codes: group.dataset("codes")?.read_1d::<u64>()?.to_vec(),
+            }),
+            AnnotationColumn::NUMERIC => Ok(AnnotationColumn::Numeric(
+                group.dataset("values")?.read_1d::<f32>()?.to_vec(),
+            )),
+            AnnotationColumn::STRING => Ok(AnnotationColumn::String(read_hdf5_strings(
+                group.dataset("values")?,
+            )?)),
+            other => Err(anyhow!("unknown annotation type: {}", other)),
+        }
+    }
+
+    fn annotation_names(&self, axis: AnnotationAxis) -> anyhow::Result<Vec<Box<str>>> {
+        let Ok(group) = self.backend.group(axis.group_name()) else {
+            return Ok(vec![]);
+        };
+        let mut ret: Vec<Box<str>> = group
+            .member_names()?
+            .into_iter()
+            .map(|x| x.into_boxed_str())
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    fn layer_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        let Ok(group) = self.backend.group(LAYERS_GROUP) else {
+            return Ok(vec![]);
+        };
+        let mut ret: Vec<Box<str>> = group
+            .member_names()?
+            .into_iter()
+            .map(|x| x.into_boxed_str())
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    /// Open the layer sharing the same file
+    /// * `layer`: name of the layer (the main matrix if `None`)
+    fn open_layer(
+        &self,
+        layer: Option<&str>,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Self::IndexIter>>> {
+        if let Some(layer) = layer {
+            if !self.has_layer(layer)? {
+                return Err(anyhow!("no layer `{}` in {}", layer, self.file_name));
+            }
+        }
+
+        let mut ret = self.clone();
+        ret.layer = layer.map(Box::from);
+        ret.by_column_indptr.clear();
+        ret.by_row_indptr.clear();
+        ret.clean_preloaded_columns();
+        ret.read_column_indptr()?;
+        ret.read_row_indptr()?;
+        Ok(Box::new(ret))
+    }
+
+    fn remove_layer(&mut self, layer: &str) -> anyhow::Result<()> {
+        if !self.has_layer(layer)? {
+            return Err(anyhow!("no layer `{}` in {}", layer, self.file_name));
+        }
+        self.backend.group(LAYERS_GROUP)?.unlink(layer)?;
+        self.backend.flush()?;
+        self.invalidate_column_caches()?;
+        Ok(())
+    }
+
     /// Read columns within the range and return a vector of triplets (row, col, value)
     /// * `col` : usize
     ///
@@ -501,7 +952,7 @@ impl SparseIo for SparseMtxData {
         &self,
         j_data: usize,
     ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
-        let by_column = self.backend.group("/by_column")?;
+        let by_column = self.backend.group(&self._matrix_key("by_column"))?;
         debug_assert!(!self.by_column_indptr.is_empty());
 
         let indptr = &self.by_column_indptr;
@@ -511,7 +962,8 @@ impl SparseIo for SparseMtxData {
             .num_rows()
             .ok_or(anyhow!("can't figure out the number of rows"))?;
 
-        if let (Some(data), Some(indices)) = (&self.by_column_data, &self.by_column_indicies) {
+        if let Some(preloaded) = self.by_column_preloaded.as_ref() {
+            let (indices, data) = (preloaded.indices(), preloaded.data());
             let ncol_out = 1;
             let jj = 0;
 
@@ -563,7 +1015,7 @@ impl SparseIo for SparseMtxData {
     ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
         // need to open backend again?
         // let backend = hdf5::File::open(&self.file_name)?;
-        let by_column = self.backend.group("/by_column")?;
+        let by_column = self.backend.group(&self._matrix_key("by_column"))?;
 
         debug_assert!(!self.by_column_indptr.is_empty());
 
@@ -591,7 +1043,8 @@ impl SparseIo for SparseMtxData {
             .max()
             .unwrap_or(0);
 
-        if let (Some(data), Some(indices)) = (&self.by_column_data, &self.by_column_indicies) {
+        if let Some(preloaded) = self.by_column_preloaded.as_ref() {
+            let (indices, data) = (preloaded.indices(), preloaded.data());
             let ncol_out = columns_vec.len();
 
             let mut ret: Vec<(u64, u64, f32)> = Vec::with_capacity((max_end - min_start) as usize);
@@ -614,28 +1067,30 @@ impl SparseIo for SparseMtxData {
 
             let ncol_out = columns_vec.len();
 
-            let mut ret: Vec<(u64, u64, f32)> = Vec::with_capacity((max_end - min_start) as usize);
+            // blocks aligned with the larger of the two chunks
+            let chunk_len = |dataset: &hdf5::Dataset| {
+                dataset
+                    .chunk()
+                    .and_then(|x| x.first().copied())
+                    .unwrap_or(DEFAULT_BLOCK_SIZE)
+            };
+            let block_size = chunk_len(&data).max(chunk_len(&indices));
+
+            let ret = read_compressed_through_cache(
+                &self.column_cache,
+                &self._matrix_key("by_column"),
+                &indptr[..=ncol],
+                &columns_vec,
+                block_size,
+                |range| {
+                    Ok((
+                        indices.read_slice_1d::<u64, _>(range.clone())?.to_vec(),
+                        data.read_slice_1d::<f32, _>(range)?.to_vec(),
+                    ))
+                },
+            )?;
+            debug_assert!(ret.iter().all(|&(ii, _, _)| (ii as usize) < nrow));
 
-            for (jj, &j_data) in columns_vec.iter().enumerate() {
-                let jj = jj as u64;
-                if j_data < ncol {
-                    // [start, end)
-                    let start = indptr[j_data] as usize;
-                    let end = indptr[j_data + 1] as usize;
-
-                    if start < end {
-                        let data_slice = data.read_slice_1d::<f32, _>(start..end)?;
-                        let indices_slice = indices.read_slice_1d::<u64, _>(start..end)?;
-
-                        for k in 0..(end - start) {
-                            let x_ij = data_slice[k];
-                            let ii = indices_slice[k];
-                            debug_assert!((ii as usize) < nrow);
-                            ret.push((ii, jj, x_ij));
-                        }
-                    }
-                }
-            }
             Ok((nrow, ncol_out, ret))
         }
     }
@@ -649,7 +1104,7 @@ impl SparseIo for SparseMtxData {
     ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
         // need to open backend again?
         // let backend = hdf5::File::open(&self.file_name)?;
-        let by_row = self.backend.group("/by_row")?;
+        let by_row = self.backend.group(&self._matrix_key("by_row"))?;
         debug_assert!(!self.by_row_indptr.is_empty());
         let indptr = &self.by_row_indptr;
         let data = by_row.dataset("data")?;
@@ -687,6 +1142,82 @@ impl SparseIo for SparseMtxData {
         }
     }
 
+    /// Read columns and return sparse `CscMatrix` straight from the
+    /// column pointers, without the triplets in between
+    /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_columns_csc(&self, columns: Self::IndexIter) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, ncol) = self.column_shape()?;
+        let indptr = &self.by_column_indptr[..=ncol];
+
+        let parts = match self.by_column_preloaded.as_ref() {
+            Some(preloaded) => CompressedParts::from_slices(
+                indptr,
+                preloaded.indices(),
+                preloaded.data(),
+                columns.iter().copied(),
+            ),
+            None => self.read_compressed_parts(
+                &self.column_cache,
+                &self._matrix_key("by_column"),
+                indptr,
+                &columns,
+            )?,
+        };
+        parts.into_csc(nrow, columns.len())
+    }
+
+    /// Read a contiguous range of columns in one go
+    /// * `columns` : range e.g., 0..3
+    ///
+    fn read_column_range_csc(&self, columns: Range<usize>) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, ncol) = self.column_shape()?;
+        let indptr = &self.by_column_indptr[..=ncol];
+        let ncol_out = columns.len();
+
+        let parts = match self.by_column_preloaded.as_ref() {
+            Some(preloaded) => {
+                CompressedParts::from_slices(indptr, preloaded.indices(), preloaded.data(), columns)
+            }
+            None => {
+                let key = self._matrix_key("by_column");
+                CompressedParts::from_contiguous(indptr, columns, |range| {
+                    self.read_compressed_chunk_backend(&key, range)
+                })?
+            }
+        };
+        parts.into_csc(nrow, ncol_out)
+    }
+
+    /// Read rows and return sparse `CsrMatrix` straight from the row
+    /// pointers, coalescing the chunks shared by the rows
+    /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_rows_csr(&self, rows: Self::IndexIter) -> anyhow::Result<CsrMatrix<f32>> {
+        let (nrow, ncol) = self.row_shape()?;
+        let parts = self.read_compressed_parts(
+            &ChunkCache::new(0),
+            &self._matrix_key("by_row"),
+            &self.by_row_indptr[..=nrow],
+            &rows,
+        )?;
+        parts.into_csr(rows.len(), ncol)
+    }
+
+    /// Read a contiguous range of rows in one go
+    /// * `rows` : range e.g., 0..3
+    ///
+    fn read_row_range_csr(&self, rows: Range<usize>) -> anyhow::Result<CsrMatrix<f32>> {
+        let (nrow, ncol) = self.row_shape()?;
+        let key = self._matrix_key("by_row");
+        let nrow_out = rows.len();
+        let parts =
+            CompressedParts::from_contiguous(&self.by_row_indptr[..=nrow], rows, |range| {
+                self.read_compressed_chunk_backend(&key, range)
+            })?;
+        parts.into_csr(nrow_out, ncol)
+    }
+
     /// Helper function to add CSR dataset to HDF5 backend
     ///
     /// ```text
@@ -702,8 +1233,8 @@ impl SparseIo for SparseMtxData {
         csr_rowptr: &[u64],
     ) -> anyhow::Result<()> {
         // Populate them into HDF5
-        if self.backend.group("/by_row").is_err() {
-            let _root = self.backend.create_group("/by_row")?;
+        if self.backend.group(&self._matrix_key("by_row")).is_err() {
+            let _root = self.backend.create_group(&self._matrix_key("by_row"))?;
             // info!("Group: {:?} created", root);
         }
 
@@ -712,18 +1243,9 @@ impl SparseIo for SparseMtxData {
             blosc_set_nthreads(num_threads as u8); // Set the number of threads for Blosc
         }
 
-        let csr = self.backend.group("/by_row")?;
+        let csr = self.backend.group(&self._matrix_key("by_row"))?;
 
-        let nelem = csr_vals.len();
-        let nchunks = NUM_CHUNKS;
-        let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
-
-        csr.new_dataset::<f32>()
-            .shape(csr_vals.len())
-            .chunk([chunk_size])
-            .blosc_blosclz(COMPRESSION_LEVEL, true)
-            .create("data")?
-            .write(&csr_vals)?;
+        self.new_value_dataset(&csr, "data", csr_vals)?;
 
         let nelem = csr_rowptr.len();
         let nchunks = NUM_CHUNKS;
@@ -741,7 +1263,7 @@ impl SparseIo for SparseMtxData {
         let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
 
         csr.new_dataset::<u64>()
-            .shape(csr_cols.len())
+            .shape(csr_cols.len()..)
             .chunk([chunk_size])
             .blosc_blosclz(COMPRESSION_LEVEL, true)
             .create("indices")?
@@ -768,8 +1290,8 @@ impl SparseIo for SparseMtxData {
         csc_colptr: &[u64],
     ) -> anyhow::Result<()> {
         // Populate them into HDF5
-        if self.backend.group("/by_column").is_err() {
-            let _root = self.backend.create_group("/by_column")?;
+        if self.backend.group(&self._matrix_key("by_column")).is_err() {
+            let _root = self.backend.create_group(&self._matrix_key("by_column"))?;
             // info!("Group: {:?} created", root);
         }
 
@@ -778,18 +1300,9 @@ impl SparseIo for SparseMtxData {
             blosc_set_nthreads(num_threads as u8); // Set the number of threads for Blosc
         }
 
-        let csc = self.backend.group("/by_column")?;
-
-        let nelem = csc_vals.len();
-        let nchunks = NUM_CHUNKS;
-        let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
+        let csc = self.backend.group(&self._matrix_key("by_column"))?;
 
-        csc.new_dataset::<f32>()
-            .shape(csc_vals.len())
-            .chunk([chunk_size])
-            .blosc_blosclz(COMPRESSION_LEVEL, true)
-            .create("data")?
-            .write(&csc_vals)?;
+        self.new_value_dataset(&csc, "data", csc_vals)?;
 
         let nelem = csc_colptr.len();
         let nchunks = NUM_CHUNKS;
@@ -807,14 +1320,162 @@ impl SparseIo for SparseMtxData {
         let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
 
         csc.new_dataset::<u64>()
-            .shape(csc_rows.len())
+            .shape(csc_rows.len()..)
             .chunk([chunk_size])
             .blosc_blosclz(COMPRESSION_LEVEL, true)
             .create("indices")?
             .write(&csc_rows)?;
 
         self.backend.flush()?;
+        self.invalidate_column_caches()?;
 
         Ok(())
     }
+
+    fn allocate_compressed_dataset_backend(
+        &mut self,
+        key: &str,
+        nnz: usize,
+        value_type: ValueType,
+    ) -> anyhow::Result<()> {
+        if self.backend.group(key).is_err() {
+            let _root = self.backend.create_group(key)?;
+        }
+
+        {
+            let num_threads = num_cpus::get(); // Gets the number of logical CPUs
+            blosc_set_nthreads(num_threads as u8); // Set the number of threads for Blosc
+        }
+
+        let group = self.backend.group(key)?;
+        for name in ["data", "indices"] {
+            if group.link_exists(name) {
+                group.unlink(name)?;
+            }
+        }
+        self.invalidate_column_caches()?;
+
+        let nchunks = NUM_CHUNKS;
+        let chunk_size = (nnz / nchunks).max(MIN_CHUNK_SIZE).min(nnz);
+
+        // resizable to append columns later
+        let data = match value_type {
+            ValueType::U16 => group.new_dataset::<u16>(),
+            ValueType::U32 => group.new_dataset::<u32>(),
+            ValueType::F32 => group.new_dataset::<f32>(),
+            ValueType::F64 => group.new_dataset::<f64>(),
+        };
+        data.shape(nnz..)
+            .chunk([chunk_size])
+            .blosc_blosclz(COMPRESSION_LEVEL, true)
+            .create("data")?;
+
+        group
+            .new_dataset::<u64>()
+            .shape(nnz..)
+            .chunk([chunk_size])
+            .blosc_blosclz(COMPRESSION_LEVEL, true)
+            .create("indices")?;
+
+        Ok(())
+    }
+
+    fn record_compressed_chunk_backend(
+        &mut self,
+        key: &str,
+        offset: usize,
+        indices: &[u64],
+        values: &[f32],
+    ) -> anyhow::Result<()> {
+        let group = self.backend.group(key)?;
+        let range = offset..(offset + values.len());
+        let data = group.dataset("data")?;
+        match dataset_value_type(&data)? {
+            ValueType::U16 => {
+                let values = ValueType::U16.checked_values(values, |x| x as u16)?;
+                data.write_slice(&values, range.clone())?;
+            }
+            ValueType::U32 => {
+                let values = ValueType::U32.checked_values(values, |x| x as u32)?;
+                data.write_slice(&values, range.clone())?;
+            }
+            ValueType::F32 => data.write_slice(values, range.clone())?,
+            ValueType::F64 => {
+                let values: Vec<f64> = values.iter().map(|&x| x as f64).collect();
+                data.write_slice(&values, range.clone())?;
+            }
+        }
+        group.dataset("indices")?.write_slice(indices, range)?;
+        self.invalidate_column_caches()?;
+        Ok(())
+    }
+
+    fn record_compressed_indptr_backend(
+        &mut self,
+        key: &str,
+        indptr: &[u64],
+    ) -> anyhow::Result<()> {
+        let group = self.backend.group(key)?;
+        if group.link_exists("indptr") {
+            group.unlink("indptr")?;
+        }
+
+        let nelem = indptr.len();
+        let nchunks = NUM_CHUNKS;
+        let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
+
+        group
+            .new_dataset::<u64>()
+            .shape(nelem)
+            .chunk([chunk_size])
+            .blosc_blosclz(COMPRESSION_LEVEL, true)
+            .create("indptr")?
+            .write(indptr)?;
+
+        self.backend.flush()?;
+        Ok(())
+    }
+
+    fn read_compressed_indptr_backend(&self, key: &str) -> anyhow::Result<Vec<u64>> {
+        let group = self.backend.group(key)?;
+        Ok(group.dataset("indptr")?.read_1d::<u64>()?.to_vec())
+    }
+
+    fn compressed_dataset_len_backend(&self, key: &str) -> anyhow::Result<(usize, usize)> {
+        let group = self.backend.group(key)?;
+        Ok((
+            group.dataset("indices")?.size(),
+            group.dataset("data")?.size(),
+        ))
+    }
+
+    fn read_compressed_chunk_backend(
+        &self,
+        key: &str,
+        range: Range<usize>,
+    ) -> anyhow::Result<(Vec<u64>, Vec<f32>)> {
+        let group = self.backend.group(key)?;
+        let indices = group
+            .dataset("indices")?
+            .read_slice_1d::<u64, _>(range.clone())?;
+        let data = group.dataset("data")?.read_slice_1d::<f32, _>(range)?;
+        Ok((indices.to_vec(), data.to_vec()))
+    }
+
+    /// Datasets created before they became resizable are copied
+    /// block by block into new resizable ones
+    fn resize_compressed_dataset_backend(&mut self, key: &str, nnz: usize) -> anyhow::Result<()> {
+        let group = self.backend.group(key)?;
+
+        for name in ["data", "indices"] {
+            if !group.dataset(name)?.is_resizable() {
+                resizable_copy(&group, name, nnz)?;
+            }
+            group.dataset(name)?.resize(nnz)?;
+        }
+
+        self.backend.flush()?;
+        self.invalidate_column_caches()?;
+        Ok(())
+    }
 }
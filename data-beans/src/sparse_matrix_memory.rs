@@ -0,0 +1,718 @@
+use crate::compressed_parts::CompressedParts;
+use crate::sparse_io::*;
+use log::info;
+use matrix_util::common_io::*;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::anyhow;
+
+/// Cell-feature matrix kept in RAM (feature x cell) without any
+/// backing file. It keeps the same layout as the file backends:
+///
+/// ```text
+/// (memory)
+///     ├── nrow, ncol, nnz
+///     ├── by_column
+///     │   ├── data
+///     │   ├── indices (row indices)
+///     │   └── indptr (column pointers)
+///     ├── by_row
+///     │   ├── data
+///     │   ├── indices (column indices)
+///     │   └── indptr (row pointers)
+///     ├── names (e.g., /row_names, /column_names)
+///     ├── annotations (obs and var)
+///     └── layers (by_column and by_row of each layer)
+/// ```
+///
+/// Use `persist` to write it to a zarr or HDF5 backend later.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SparseMtxData {
+    mtx_shape: Option<(usize, usize, usize)>,
+    by_column_indptr: Vec<u64>,
+    by_column_indices: Vec<u64>,
+    by_column_data: Vec<f32>,
+    by_row_indptr: Vec<u64>,
+    by_row_indices: Vec<u64>,
+    by_row_data: Vec<f32>,
+    names: HashMap<Box<str>, Vec<Box<str>>>,
+    annotations: HashMap<(AnnotationAxis, Box<str>), AnnotationColumn>,
+    layers: HashMap<Box<str>, SparseMtxData>,
+    value_type: Option<ValueType>,
+}
+
+#[allow(dead_code)]
+impl SparseMtxData {
+    /// Create an empty new `SparseMtxData` in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create `SparseMtxData` from mtx file
+    /// * `mtx_file`: mtx file to be read into memory
+    /// * `index_by_row`: if true, the matrix will be indexed by row
+    /// * `value_type`: type of the stored values (detected if `None`)
+    pub fn from_mtx_file(
+        mtx_file: &str,
+        index_by_row: Option<bool>,
+        value_type: Option<ValueType>,
+    ) -> anyhow::Result<Self> {
+        let mut ret = Self::new();
+
+        info!("importing mtx file by column");
+        ret.import_mtx_file_by_col(mtx_file)?;
+
+        if Some(true) == index_by_row {
+            info!("importing mtx file by row");
+            ret.import_mtx_file_by_row(mtx_file)?;
+        }
+
+        if let Some(value_type) = value_type {
+            ret.set_value_type(value_type)?;
+        }
+
+        info!("created sparse data from {}", mtx_file);
+        Ok(ret)
+    }
+
+    /// Create a new `SparseMtxData` from ndarray
+    /// * `array`: 2D array to be kept in memory
+    /// * `index_by_row`: if true, the matrix will be indexed by row
+    pub fn from_ndarray(array: &Array2<f32>, index_by_row: Option<bool>) -> anyhow::Result<Self> {
+        let mut ret = Self::new();
+        ret.import_ndarray_by_col(array)?;
+        if Some(true) == index_by_row {
+            ret.import_ndarray_by_row(array)?;
+        }
+        Ok(ret)
+    }
+
+    /// Create a new `SparseMtxData` from DMatrix
+    /// * `matrix`: 2D matrix to be kept in memory
+    /// * `index_by_row`: if true, the matrix will be indexed by row
+    pub fn from_dmatrix(matrix: &DMatrix<f32>, index_by_row: Option<bool>) -> anyhow::Result<Self> {
+        let mut ret = Self::new();
+        ret.import_dmatrix_by_col(matrix)?;
+        if Some(true) == index_by_row {
+            ret.import_dmatrix_by_row(matrix)?;
+        }
+        Ok(ret)
+    }
+
+    /// Write everything to a new file backend and return it
+    /// * `backend_file`: zarr or HDF5 file to be created
+    /// * `backend`: backend type (HDF5 or Zarr)
+    pub fn persist(
+        &self,
+        backend_file: &str,
+        backend: &SparseIoBackend,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+        let mtx_shape = self.mtx_shape.ok_or(anyhow!("missing shape information"))?;
+        let (_, ncol, _) = mtx_shape;
+        let (_, _, triplets) = self.read_triplets_by_column_range(0..ncol)?;
+
+        let options = SparseCreateOptions {
+            value_type: self.value_type,
+            ..Default::default()
+        };
+        let mut ret = create_sparse_from_triplets_with_options(
+            triplets,
+            mtx_shape,
+            Some(backend_file),
+            Some(backend),
+            &options,
+        )?;
+
+        for (key, names) in self.names.iter() {
+            ret.register_names_vec(key, names)?;
+        }
+
+        for ((axis, name), column) in self.annotations.iter() {
+            ret.register_annotation(*axis, name, column)?;
+        }
+
+        for layer in self.layer_names()? {
+            let data = self.open_layer(Some(&layer))?;
+            let (_, _, mut triplets) = data.read_triplets_by_column_range(0..ncol)?;
+            ret.register_layer_triplets(&layer, &mut triplets)?;
+        }
+
+        info!("persisted memory backend to {}", backend_file);
+        Ok(ret)
+    }
+
+    /////////////////////////////
+    // purely helper functions //
+    /////////////////////////////
+
+    fn _shape(&self) -> anyhow::Result<(usize, usize, usize)> {
+        self.mtx_shape
+            .ok_or(anyhow!("Unable to figure out the size of the data"))
+    }
+
+    /// Take the columns (rows) `outer` of `/by_column` (`/by_row`)
+    /// straight from the compressed arrays
+    fn compressed_parts<I>(&self, key: &str, outer: I) -> anyhow::Result<CompressedParts>
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let (indices, data, indptr) = self.compressed_dataset(key)?;
+        if indptr.is_empty() {
+            return Err(anyhow!("{} is not indexed", key));
+        }
+        Ok(CompressedParts::from_slices(indptr, indices, data, outer))
+    }
+
+    /// Split `/layers/{layer}/{name}` into (`layer`, `/{name}`)
+    fn split_layer_key(key: &str) -> Option<(&str, String)> {
+        key.strip_prefix(LAYERS_GROUP)
+            .and_then(|x| x.trim_start_matches('/').split_once('/'))
+            .map(|(layer, name)| (layer, format!("/{}", name)))
+    }
+
+    /// (indices, data, indptr) under `/by_column` or `/by_row` of
+    /// the main matrix or `/layers/{layer}`
+    fn compressed_dataset_mut(
+        &mut self,
+        key: &str,
+    ) -> anyhow::Result<(&mut Vec<u64>, &mut Vec<f32>, &mut Vec<u64>)> {
+        if let Some((layer, sub_key)) = Self::split_layer_key(key) {
+            return self
+                .layers
+                .entry(layer.into())
+                .or_default()
+                .compressed_dataset_mut(&sub_key);
+        }
+
+        match key {
+            "/by_column" => Ok((
+                &mut self.by_column_indices,
+                &mut self.by_column_data,
+                &mut self.by_column_indptr,
+            )),
+            "/by_row" => Ok((
+                &mut self.by_row_indices,
+                &mut self.by_row_data,
+                &mut self.by_row_indptr,
+            )),
+            _ => Err(anyhow!("unknown compressed dataset: {}", key)),
+        }
+    }
+
+    /// (indices, data, indptr) under `/by_column` or `/by_row` of
+    /// the main matrix or `/layers/{layer}` to read back
+    fn compressed_dataset(&self, key: &str) -> anyhow::Result<(&Vec<u64>, &Vec<f32>, &Vec<u64>)> {
+        if let Some((layer, sub_key)) = Self::split_layer_key(key) {
+            return self
+                .layers
+                .get(layer)
+                .ok_or(anyhow!("no layer `{}`", layer))?
+                .compressed_dataset(&sub_key);
+        }
+
+        match key {
+            "/by_column" => Ok((
+                &self.by_column_indices,
+                &self.by_column_data,
+                &self.by_column_indptr,
+            )),
+            "/by_row" => Ok((&self.by_row_indices, &self.by_row_data, &self.by_row_indptr)),
+            _ => Err(anyhow!("unknown compressed dataset: {}", key)),
+        }
+    }
+}
+
+impl SparseIo for SparseMtxData {
+    type IndexIter = Vec<usize>;
+
+    /// Drop everything
+    fn initialize_backend(&mut self) -> anyhow::Result<()> {
+        *self = Self::default();
+        Ok(())
+    }
+
+    /// Record the shape of the data
+    fn record_mtx_shape(&mut self, mtx_shape: Option<(usize, usize, usize)>) -> anyhow::Result<()> {
+        if let Some(mtx_shape) = mtx_shape {
+            match self.mtx_shape {
+                Some(old) if old != mtx_shape => return Err(anyhow!("shape mismatch")),
+                _ => self.mtx_shape = Some(mtx_shape),
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrite the shape of the data
+    fn update_mtx_shape(&mut self, mtx_shape: (usize, usize, usize)) -> anyhow::Result<()> {
+        self.mtx_shape = Some(mtx_shape);
+        Ok(())
+    }
+
+    fn value_type(&self) -> Option<ValueType> {
+        self.value_type
+    }
+
+    /// Values are kept as `f32` in memory; the type is only passed
+    /// on to the backend written by `persist`, but the values stored
+    /// so far should fit in it
+    fn set_value_type(&mut self, value_type: ValueType) -> anyhow::Result<()> {
+        value_type.check(&self.by_column_data)?;
+        value_type.check(&self.by_row_data)?;
+        self.value_type = Some(value_type);
+        Ok(())
+    }
+
+    /// Column index pointers are always in memory
+    fn read_column_indptr(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Row index pointers are always in memory
+    fn read_row_indptr(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Columns are always in memory
+    fn preload_columns(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn clean_preloaded_columns(&mut self) {}
+
+    /// Nothing to remove
+    fn remove_backend_file(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Nothing on disk to redirect
+    fn set_backend_file_name(&mut self, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Nothing on disk to move
+    fn move_backend_file(&mut self, _: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn get_backend_file_name(&self) -> &str {
+        MEMORY_BACKEND_NAME
+    }
+
+    /// Export the data to a mtx file
+    /// * `mtx_file`: mtx file to be written
+    fn to_mtx_file(&self, mtx_file: &str) -> anyhow::Result<()> {
+        let (nrow, ncol, nnz) = self._shape()?;
+        let indptr = &self.by_column_indptr;
+
+        let mut buf = open_buf_writer(mtx_file)?;
+        writeln!(buf, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(buf, "{}\t{}\t{}", nrow, ncol, nnz)?;
+
+        for jj in 0..ncol {
+            let start = indptr[jj] as usize;
+            let end = indptr[jj + 1] as usize;
+            // write them with 1-based indices
+            for k in start..end {
+                let ii = self.by_column_indices[k];
+                let val = self.by_column_data[k];
+                writeln!(buf, "{}\t{}\t{}", ii + 1, jj + 1, val)?;
+            }
+        }
+        buf.flush()?;
+        info!(
+            "{}: {} rows, {} columns, {} non-zeros",
+            mtx_file, nrow, ncol, nnz
+        );
+        Ok(())
+    }
+
+    /// Set row names for the matrix
+    /// * `row_name_file`: a file each line contains row name words
+    fn register_row_names_file(&mut self, row_name_file: &str) {
+        self.register_names_file("/row_names", row_name_file, 0..MAX_ROW_NAME_IDX, ROW_SEP)
+            .expect("failed to add row names");
+    }
+
+    /// Set row names for the matrix
+    /// * `rows`: a vector of row names
+    fn register_row_names_vec(&mut self, rows: &[Box<str>]) {
+        self.register_names_vec("/row_names", rows)
+            .expect("failed to add row names");
+    }
+
+    /// Set column names for the matrix
+    /// * `column_name_file`: a file each line contains column name words
+    fn register_column_names_file(&mut self, column_name_file: &str) {
+        self.register_names_file(
+            "/column_names",
+            column_name_file,
+            0..MAX_COLUMN_NAME_IDX,
+            COLUMN_SEP,
+        )
+        .expect("failed to add column names");
+    }
+
+    /// Set column names for the matrix
+    /// * `columns`: a vector of column names
+    fn register_column_names_vec(&mut self, columns: &[Box<str>]) {
+        self.register_names_vec("/column_names", columns)
+            .expect("failed to add column names");
+    }
+
+    /// Number of rows in the underlying data matrix
+    fn num_rows(&self) -> Option<usize> {
+        self.mtx_shape.map(|(nrow, _, _)| nrow)
+    }
+
+    /// Number of columns in the underlying data matrix
+    fn num_columns(&self) -> Option<usize> {
+        self.mtx_shape.map(|(_, ncol, _)| ncol)
+    }
+
+    /// Number of non-zero elements
+    fn num_non_zeros(&self) -> Option<usize> {
+        self.mtx_shape.map(|(_, _, nnz)| nnz)
+    }
+
+    /// Add arbitrary names (a vector of strings)
+    /// * `key`: key for the names
+    /// * `name_file`: a file each line contains name words
+    /// * `name_columns`: range of columns to be used for name
+    /// * `name_sep`: separator for name columns
+    fn register_names_file(
+        &mut self,
+        key: &str,
+        name_file: &str,
+        name_columns: Range<usize>,
+        name_sep: &str,
+    ) -> anyhow::Result<()> {
+        let (_names, _) = read_lines_of_words(name_file, -1)?;
+
+        let name_columns = name_columns.collect::<Vec<_>>();
+
+        let _names: Vec<Box<str>> = _names
+            .iter()
+            .map(|x| {
+                name_columns
+                    .iter()
+                    .filter_map(|&i| x.get(i))
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join(name_sep)
+                    .into_boxed_str()
+            })
+            .collect();
+
+        self.names.insert(key.into(), _names);
+        Ok(())
+    }
+
+    /// Add arbitrary names (a vector of strings)
+    /// * `key`: key for the names
+    /// * `names`: a vector of names
+    fn register_names_vec(&mut self, key: &str, names: &[Box<str>]) -> anyhow::Result<()> {
+        self.names.insert(key.into(), names.to_vec());
+        Ok(())
+    }
+
+    fn row_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        self.retrieve_registered_names("/row_names")
+    }
+
+    fn column_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        self.retrieve_registered_names("/column_names")
+    }
+
+    /// Get back the registered names
+    /// * `key`: key for the registered names
+    fn retrieve_registered_names(&self, key: &str) -> anyhow::Result<Vec<Box<str>>> {
+        self.names
+            .get(key)
+            .cloned()
+            .ok_or(anyhow!("no names registered for {}", key))
+    }
+
+    /// Add (or replace) an annotation column
+    fn register_annotation(
+        &mut self,
+        axis: AnnotationAxis,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        self.annotations.insert((axis, name.into()), column.clone());
+        Ok(())
+    }
+
+    fn retrieve_annotation(
+        &self,
+        axis: AnnotationAxis,
+        name: &str,
+    ) -> anyhow::Result<AnnotationColumn> {
+        self.annotations
+            .get(&(axis, name.into()))
+            .cloned()
+            .ok_or(anyhow!("no annotation `{}`", axis.key(name)))
+    }
+
+    fn annotation_names(&self, axis: AnnotationAxis) -> anyhow::Result<Vec<Box<str>>> {
+        let mut ret: Vec<Box<str>> = self
+            .annotations
+            .keys()
+            .filter(|(a, _)| *a == axis)
+            .map(|(_, name)| name.clone())
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    fn layer_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        let mut ret: Vec<Box<str>> = self.layers.keys().cloned().collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    /// Copy the layer with the same names and annotations
+    /// * `layer`: name of the layer (the main matrix if `None`)
+    fn open_layer(
+        &self,
+        layer: Option<&str>,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Self::IndexIter>>> {
+        let Some(layer) = layer else {
+            return Ok(Box::new(self.clone()));
+        };
+
+        let stored = self
+            .layers
+            .get(layer)
+            .ok_or(anyhow!("no layer `{}`", layer))?;
+
+        let (nrow, ncol, _) = self._shape()?;
+        let nnz = stored.by_column_indptr.last().copied().unwrap_or(0) as usize;
+
+        Ok(Box::new(Self {
+            mtx_shape: Some((nrow, ncol, nnz)),
+            by_column_indptr: stored.by_column_indptr.clone(),
+            by_column_indices: stored.by_column_indices.clone(),
+            by_column_data: stored.by_column_data.clone(),
+            by_row_indptr: stored.by_row_indptr.clone(),
+            by_row_indices: stored.by_row_indices.clone(),
+            by_row_data: stored.by_row_data.clone(),
+            names: self.names.clone(),
+            annotations: self.annotations.clone(),
+            layers: HashMap::new(),
+            value_type: self.value_type,
+        }))
+    }
+
+    fn remove_layer(&mut self, layer: &str) -> anyhow::Result<()> {
+        self.layers
+            .remove(layer)
+            .map(|_| ())
+            .ok_or(anyhow!("no layer `{}`", layer))
+    }
+
+    /// Read a single column and return a vector of triplets (row, col, value)
+    /// * `col` : usize
+    ///
+    fn read_triplets_by_single_column(
+        &self,
+        j_data: usize,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        self.read_triplets_by_columns(vec![j_data])
+    }
+
+    /// Read columns within the range and return a vector of triplets (row, col, value)
+    /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_triplets_by_columns(
+        &self,
+        columns: Self::IndexIter,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        let (nrow, ncol, _) = self._shape()?;
+        let indptr = &self.by_column_indptr;
+
+        if indptr.is_empty() {
+            return Err(anyhow!("columns are not indexed"));
+        }
+
+        let mut ret = vec![];
+        for (jj, &j_data) in columns.iter().enumerate() {
+            if j_data < ncol {
+                let start = indptr[j_data] as usize;
+                let end = indptr[j_data + 1] as usize;
+                for k in start..end {
+                    ret.push((self.by_column_indices[k], jj as u64, self.by_column_data[k]));
+                }
+            }
+        }
+        Ok((nrow, columns.len(), ret))
+    }
+
+    /// Read rows within the range and return a vector of triplets (row, column, value)
+    /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_triplets_by_rows(
+        &self,
+        rows: Self::IndexIter,
+    ) -> anyhow::Result<(usize, usize, Vec<(u64, u64, f32)>)> {
+        let (nrow, ncol, _) = self._shape()?;
+        let indptr = &self.by_row_indptr;
+
+        if indptr.is_empty() {
+            return Err(anyhow!("rows are not indexed"));
+        }
+
+        let mut ret = vec![];
+        for (ii, &i_data) in rows.iter().enumerate() {
+            if i_data < nrow {
+                let start = indptr[i_data] as usize;
+                let end = indptr[i_data + 1] as usize;
+                for k in start..end {
+                    ret.push((ii as u64, self.by_row_indices[k], self.by_row_data[k]));
+                }
+            }
+        }
+        Ok((rows.len(), ncol, ret))
+    }
+
+    /// Read columns and return sparse `CscMatrix` straight from the
+    /// column pointers
+    /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_columns_csc(&self, columns: Self::IndexIter) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, _, _) = self._shape()?;
+        let ncol_out = columns.len();
+        self.compressed_parts("/by_column", columns)?
+            .into_csc(nrow, ncol_out)
+    }
+
+    /// Read a contiguous range of columns
+    /// * `columns` : range e.g., 0..3
+    ///
+    fn read_column_range_csc(&self, columns: Range<usize>) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, _, _) = self._shape()?;
+        let ncol_out = columns.len();
+        self.compressed_parts("/by_column", columns)?
+            .into_csc(nrow, ncol_out)
+    }
+
+    /// Read rows and return sparse `CsrMatrix` straight from the row
+    /// pointers
+    /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_rows_csr(&self, rows: Self::IndexIter) -> anyhow::Result<CsrMatrix<f32>> {
+        let (_, ncol, _) = self._shape()?;
+        let nrow_out = rows.len();
+        self.compressed_parts("/by_row", rows)?
+            .into_csr(nrow_out, ncol)
+    }
+
+    /// Read a contiguous range of rows
+    /// * `rows` : range e.g., 0..3
+    ///
+    fn read_row_range_csr(&self, rows: Range<usize>) -> anyhow::Result<CsrMatrix<f32>> {
+        let (_, ncol, _) = self._shape()?;
+        let nrow_out = rows.len();
+        self.compressed_parts("/by_row", rows)?
+            .into_csr(nrow_out, ncol)
+    }
+
+    /// Keep CSR arrays in memory
+    fn record_csr_dataset_backend(
+        &mut self,
+        csr_cols: &[u64],
+        csr_vals: &[f32],
+        csr_rowptr: &[u64],
+    ) -> anyhow::Result<()> {
+        if let Some(value_type) = self.value_type {
+            value_type.check(csr_vals)?;
+        }
+        self.by_row_indices = csr_cols.to_vec();
+        self.by_row_data = csr_vals.to_vec();
+        self.by_row_indptr = csr_rowptr.to_vec();
+        Ok(())
+    }
+
+    /// Keep CSC arrays in memory
+    fn record_csc_dataset_backend(
+        &mut self,
+        csc_rows: &[u64],
+        csc_vals: &[f32],
+        csc_colptr: &[u64],
+    ) -> anyhow::Result<()> {
+        if let Some(value_type) = self.value_type {
+            value_type.check(csc_vals)?;
+        }
+        self.by_column_indices = csc_rows.to_vec();
+        self.by_column_data = csc_vals.to_vec();
+        self.by_column_indptr = csc_colptr.to_vec();
+        Ok(())
+    }
+
+    fn allocate_compressed_dataset_backend(
+        &mut self,
+        key: &str,
+        nnz: usize,
+        _: ValueType,
+    ) -> anyhow::Result<()> {
+        let (indices, data, _) = self.compressed_dataset_mut(key)?;
+        *indices = vec![0; nnz];
+        *data = vec![0.; nnz];
+        Ok(())
+    }
+
+    fn record_compressed_chunk_backend(
+        &mut self,
+        key: &str,
+        offset: usize,
+        indices: &[u64],
+        values: &[f32],
+    ) -> anyhow::Result<()> {
+        let (stored_indices, stored_data, _) = self.compressed_dataset_mut(key)?;
+        let range = offset..(offset + values.len());
+        stored_indices[range.clone()].copy_from_slice(indices);
+        stored_data[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    fn record_compressed_indptr_backend(
+        &mut self,
+        key: &str,
+        indptr: &[u64],
+    ) -> anyhow::Result<()> {
+        let (_, _, stored_indptr) = self.compressed_dataset_mut(key)?;
+        *stored_indptr = indptr.to_vec();
+        Ok(())
+    }
+
+    fn read_compressed_indptr_backend(&self, key: &str) -> anyhow::Result<Vec<u64>> {
+        let (_, _, indptr) = self.compressed_dataset(key)?;
+        Ok(indptr.clone())
+    }
+
+    fn compressed_dataset_len_backend(&self, key: &str) -> anyhow::Result<(usize, usize)> {
+        let (indices, data, _) = self.compressed_dataset(key)?;
+        Ok((indices.len(), data.len()))
+    }
+
+    fn read_compressed_chunk_backend(
+        &self,
+        key: &str,
+        range: Range<usize>,
+    ) -> anyhow::Result<(Vec<u64>, Vec<f32>)> {
+        let (indices, data, _) = self.compressed_dataset(key)?;
+        match (indices.get(range.clone()), data.get(range.clone())) {
+            (Some(indices), Some(data)) => Ok((indices.to_vec(), data.to_vec())),
+            _ => Err(anyhow!("{:?} out of `{}`", range, key)),
+        }
+    }
+
+    fn resize_compressed_dataset_backend(&mut self, key: &str, nnz: usize) -> anyhow::Result<()> {
+        let (indices, data, _) = self.compressed_dataset_mut(key)?;
+        indices.resize(nnz, 0);
+        data.resize(nnz, 0.);
+        Ok(())
+    }
+}
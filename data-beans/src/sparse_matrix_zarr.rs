@@ -1,4 +1,9 @@
+use crate::annotation::ENCODING_ATTR;
+use crate::chunk_cache::*;
+use crate::compressed_parts::CompressedParts;
+use crate::mmap_columns::*;
 use crate::sparse_io::*;
+use crate::zarr_zip_store::*;
 use log::info;
 use matrix_util::common_io::*;
 use std::ops::Range;
@@ -9,25 +14,256 @@ use zarrs::filesystem::FilesystemStore;
 use zarrs::storage::ReadableWritableListableStorageTraits as ZStorageTraits;
 
 use anyhow::anyhow;
+use clap::{Args, ValueEnum};
+use zarrs::array::{ArrayBuilder, FillValue};
+
+/// A store and the zip store behind it, if any
+type OpenedStore = (Arc<dyn ZStorageTraits>, Option<Arc<ZipStore>>);
 
 const NUM_CHUNKS: usize = 1000;
 const MIN_CHUNK_SIZE: usize = 8192;
 const COMPRESSION_LEVEL: i32 = 5;
 
+/// Compression codec of zarr arrays
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ZarrCodec {
+    #[default]
+    Zstd,
+    Blosc,
+    Gzip,
+    None,
+}
+
+/// How to lay out zarr arrays: chunking, sharding, and compression.
+/// The default splits each array into ~1000 chunks of at least
+/// 8192 elements compressed by `zstd` (level 5).
+#[derive(Args, Clone, Debug, PartialEq)]
+pub struct ZarrWriteOptions {
+    /// zarr chunk size in elements (default: ~1000 chunks per array,
+    /// each with at least 8192 elements)
+    #[arg(long = "zarr-chunk-size", conflicts_with = "chunk_bytes")]
+    pub chunk_size: Option<usize>,
+
+    /// zarr chunk size in (uncompressed) bytes
+    #[arg(long = "zarr-chunk-bytes")]
+    pub chunk_bytes: Option<usize>,
+
+    /// pack this many chunks into one shard (file) using the zarr v3
+    /// sharding codec to avoid too many small files
+    #[arg(long = "zarr-chunks-per-shard")]
+    pub chunks_per_shard: Option<usize>,
+
+    /// zarr compression codec
+    #[arg(long = "zarr-codec", value_enum, default_value = "zstd")]
+    pub codec: ZarrCodec,
+
+    /// zarr compression level (zstd: -7 to 22, blosc/gzip: 0 to 9)
+    #[arg(long = "zarr-level", default_value_t = COMPRESSION_LEVEL, allow_hyphen_values = true)]
+    pub level: i32,
+}
+
+impl Default for ZarrWriteOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: None,
+            chunk_bytes: None,
+            chunks_per_shard: None,
+            codec: ZarrCodec::default(),
+            level: COMPRESSION_LEVEL,
+        }
+    }
+}
+
+impl ZarrWriteOptions {
+    /// Number of elements in a chunk along a dimension of `nelem`
+    /// * `nelem` - the number of elements along the dimension
+    /// * `elem_size` - bytes per element
+    fn chunk_length(&self, nelem: usize, elem_size: usize) -> usize {
+        let chunk = match (self.chunk_size, self.chunk_bytes) {
+            (Some(chunk_size), _) => chunk_size,
+            (None, Some(chunk_bytes)) => chunk_bytes / elem_size.max(1),
+            (None, None) => (nelem / NUM_CHUNKS).max(MIN_CHUNK_SIZE),
+        };
+        chunk.min(nelem).max(1)
+    }
+
+    /// Recover the options used to write a 1D array from its zarr
+    /// metadata (`zarr.json`)
+    /// * `meta` - array metadata
+    /// * `nelem` - the number of elements in the array
+    fn from_array_metadata(meta: &serde_json::Value, nelem: usize) -> Option<Self> {
+        let chunk_shape = |x: &serde_json::Value| x.get(0)?.as_u64();
+        let outer = chunk_shape(meta.pointer("/chunk_grid/configuration/chunk_shape")?)?;
+        let mut codecs = meta.get("codecs")?.as_array()?;
+
+        let mut ret = Self::default();
+        let mut chunk = outer;
+        if let Some(sharding) = codecs
+            .iter()
+            .find(|c| c.get("name").and_then(|n| n.as_str()) == Some("sharding_indexed"))
+        {
+            let conf = sharding.get("configuration")?;
+            chunk = chunk_shape(conf.get("chunk_shape")?)?.max(1);
+            ret.chunks_per_shard = Some((outer / chunk) as usize);
+            codecs = conf.get("codecs")?.as_array()?;
+        }
+        if chunk as usize != ret.chunk_length(nelem, 1) {
+            ret.chunk_size = Some(chunk as usize);
+        }
+
+        ret.codec = ZarrCodec::None;
+        for codec in codecs {
+            let level = |key: &str| codec.pointer(&format!("/configuration/{}", key))?.as_i64();
+            match codec.get("name").and_then(|n| n.as_str()) {
+                Some("zstd") => {
+                    ret.codec = ZarrCodec::Zstd;
+                    ret.level = level("level")? as i32;
+                }
+                Some("gzip") => {
+                    ret.codec = ZarrCodec::Gzip;
+                    ret.level = level("level")? as i32;
+                }
+                Some("blosc") => {
+                    ret.codec = ZarrCodec::Blosc;
+                    ret.level = level("clevel")? as i32;
+                }
+                _ => {}
+            }
+        }
+        Some(ret)
+    }
+
+    fn bytes_to_bytes_codecs(
+        &self,
+        elem_size: Option<usize>,
+    ) -> anyhow::Result<Vec<Arc<dyn zarrs::array::codec::BytesToBytesCodecTraits>>> {
+        use zarrs::array::codec::*;
+
+        Ok(match self.codec {
+            ZarrCodec::Zstd => vec![Arc::new(ZstdCodec::new(self.level, false))],
+            ZarrCodec::Blosc => {
+                let level = u8::try_from(self.level)
+                    .ok()
+                    .and_then(|x| BloscCompressionLevel::try_from(x).ok())
+                    .ok_or(anyhow!("invalid blosc level: {}", self.level))?;
+                let shuffle = match elem_size {
+                    Some(_) => BloscShuffleMode::Shuffle,
+                    None => BloscShuffleMode::NoShuffle,
+                };
+                vec![Arc::new(BloscCodec::new(
+                    BloscCompressor::LZ4,
+                    level,
+                    None,
+                    shuffle,
+                    elem_size,
+                )?)]
+            }
+            ZarrCodec::Gzip => {
+                let level = u32::try_from(self.level)
+                    .map_err(|_| anyhow!("invalid gzip level: {}", self.level))?;
+                vec![Arc::new(GzipCodec::new(level)?)]
+            }
+            ZarrCodec::None => vec![],
+        })
+    }
+
+    /// Array builder with the chunk grid and codecs of these options
+    /// * `array_shape` - shape of the array
+    /// * `dt` - the data type among `DataType`
+    /// * `fill` - fill value
+    fn array_builder(
+        &self,
+        array_shape: Vec<u64>,
+        dt: DataType,
+        fill: FillValue,
+    ) -> anyhow::Result<ArrayBuilder> {
+        let elem_size = dt.fixed_size();
+        let chunk_shape: Vec<u64> = array_shape
+            .iter()
+            .map(|&d| self.chunk_length(d as usize, elem_size.unwrap_or(8)) as u64)
+            .collect();
+        let codecs = self.bytes_to_bytes_codecs(elem_size)?;
+
+        let ret = match self.chunks_per_shard {
+            Some(nchunks) if nchunks > 1 => {
+                use zarrs::array::codec::ShardingCodecBuilder;
+
+                // a shard stacks `nchunks` chunks along the first axis
+                let mut shard_shape = chunk_shape.clone();
+                shard_shape[0] *= nchunks as u64;
+
+                let mut sharding = ShardingCodecBuilder::new(chunk_shape.try_into()?);
+                sharding.bytes_to_bytes_codecs(codecs);
+
+                let mut builder = ArrayBuilder::new(array_shape, dt, shard_shape.try_into()?, fill);
+                builder.array_to_bytes_codec(Arc::new(sharding.build()));
+                builder
+            }
+            _ => {
+                let mut builder = ArrayBuilder::new(array_shape, dt, chunk_shape.try_into()?, fill);
+                builder.bytes_to_bytes_codecs(codecs);
+                builder
+            }
+        };
+        Ok(ret)
+    }
+}
+
+/// Zarr data type of the values stored as `value_type`
+fn value_data_type(value_type: ValueType) -> DataType {
+    match value_type {
+        ValueType::U16 => DataType::UInt16,
+        ValueType::U32 => DataType::UInt32,
+        ValueType::F32 => DataType::Float32,
+        ValueType::F64 => DataType::Float64,
+    }
+}
+
+/// Read the values in `subset` as `f32` whatever type they are
+/// stored as
+fn retrieve_values(
+    data: &zarrs::array::Array<dyn ZStorageTraits>,
+    subset: &ArraySubset,
+) -> anyhow::Result<Vec<f32>> {
+    Ok(match data.data_type() {
+        DataType::UInt16 => data
+            .retrieve_array_subset_elements::<u16>(subset)?
+            .into_iter()
+            .map(|x| x as f32)
+            .collect(),
+        DataType::UInt32 => data
+            .retrieve_array_subset_elements::<u32>(subset)?
+            .into_iter()
+            .map(|x| x as f32)
+            .collect(),
+        DataType::Float64 => data
+            .retrieve_array_subset_elements::<f64>(subset)?
+            .into_iter()
+            .map(|x| x as f32)
+            .collect(),
+        _ => data.retrieve_array_subset_elements::<f32>(subset)?,
+    })
+}
+
 /// 10x-like cell-feature matrix with `zarr` backend (feature x cell)
 ///
 /// ```text
 /// (root)
 ///     ├── nrow
 ///     ├── ncell
+///     ├── value_type (u16, u32, f32, or f64 of `data`)
 ///     ├── by_column
 ///     │   ├── data
 ///     │   ├── indices (row indices)
 ///     │   └── indptr (column pointers)
-///     └── by_row
-///         ├── data
-///         ├── indices (column indices)
-///         └── indptr (row pointers)
+///     ├── by_row
+///     │   ├── data
+///     │   ├── indices (column indices)
+///     │   └── indptr (row pointers)
+///     └── layers (optional, e.g., spliced, unspliced)
+///         └── {layer}
+///             ├── by_column
+///             └── by_row
 /// ```
 ///
 #[derive(Clone)]
@@ -38,8 +274,12 @@ pub struct SparseMtxData {
     max_column_name_idx: usize,
     by_column_indptr: Vec<u64>,
     by_row_indptr: Vec<u64>,
-    by_column_indicies: Option<Vec<u64>>,
-    by_column_data: Option<Vec<f32>>,
+    by_column_preloaded: Option<Arc<PreloadedColumns>>,
+    layer: Option<Box<str>>,
+    write_options: ZarrWriteOptions,
+    zip_store: Option<Arc<ZipStore>>,
+    column_cache: Arc<ChunkCache>,
+    value_type: Option<ValueType>,
 }
 
 #[allow(dead_code)]
@@ -49,22 +289,29 @@ impl SparseMtxData {
     /// file will be created.
     ///
     /// * `backend_file` - Optional zarr backend file
-    pub fn new(zarr_file: Option<&str>) -> anyhow::Result<Self> {
+    /// * `write_options` - chunking, sharding, and codecs of arrays
+    pub fn new(zarr_file: Option<&str>, write_options: &ZarrWriteOptions) -> anyhow::Result<Self> {
         let ret = match zarr_file {
-            Some(backend_file) => Self::register_backend_file(backend_file)?,
+            Some(backend_file) => Self::register_backend_file(backend_file, write_options)?,
             None => {
                 let backend_file = create_temp_dir_file(".zarr")?;
                 let backend_file = backend_file.to_str().expect("to_str failed");
-                Self::register_backend_file(backend_file)?
+                Self::register_backend_file(backend_file, write_options)?
             }
         };
         Ok(ret)
     }
 
-    /// Create `SparseMtxData` instance from an existing zarr backend file
+    /// Create `SparseMtxData` instance from an existing zarr backend
+    /// file, either a directory (`.zarr`) or a single zip archive
+    /// (`.zarr.zip`)
     /// * `zarr_file` - zarr backend file
     pub fn open(backend_file: &str) -> anyhow::Result<Self> {
-        let store = Arc::new(FilesystemStore::new(backend_file)?);
+        if is_zarr_zip(backend_file) && !std::path::Path::new(backend_file).is_file() {
+            anyhow::bail!("No such zarr zip file: {}", backend_file);
+        }
+
+        let (store, zip_store) = Self::open_store(backend_file)?;
 
         if let (Some(nrow), Some(ncol), Some(nnz)) = (
             Self::_num_rows(store.clone()),
@@ -83,8 +330,13 @@ impl SparseMtxData {
             max_column_name_idx: MAX_COLUMN_NAME_IDX,
             by_column_indptr: vec![],
             by_row_indptr: vec![],
-            by_column_indicies: None,
-            by_column_data: None,
+            by_column_preloaded: None,
+            layer: None,
+            write_options: Self::_get_write_options(store.clone()),
+            zip_store,
+            column_cache: Arc::new(ChunkCache::default()),
+            value_type: Self::_get_group_attr::<String>(store.clone(), "/", VALUE_TYPE_ATTR)
+                .and_then(|x| ValueType::from_name(&x).ok()),
         };
 
         ret.read_column_indptr()?;
@@ -99,23 +351,31 @@ impl SparseMtxData {
     /// * `mtx_file`: mtx file to be read into HDF5 backend
     /// * `backend_file`: HDF5 file to be associated with
     /// * `index_by_row`: if true, the matrix will be indexed by row
+    /// * `write_options`: chunking, sharding, and codecs of arrays
+    /// * `value_type`: type of the stored values (detected if `None`)
     pub fn from_mtx_file(
         mtx_file: &str,
         backend_file: Option<&str>,
         index_by_row: Option<bool>,
+        write_options: &ZarrWriteOptions,
+        value_type: Option<ValueType>,
     ) -> anyhow::Result<Self> {
         let mut ret = match backend_file {
             Some(backend_file) => {
                 info!("backend file : {}", backend_file);
-                Self::register_backend_file(backend_file)?
+                Self::register_backend_file(backend_file, write_options)?
             }
             None => {
                 let backend_file = mtx_file.to_string() + ".zarr";
                 info!("backend file : {}", backend_file);
-                Self::register_backend_file(&backend_file)?
+                Self::register_backend_file(&backend_file, write_options)?
             }
         };
 
+        if let Some(value_type) = value_type {
+            ret.set_value_type(value_type)?;
+        }
+
         // populate data from mtx file
         info!("importing mtx file by column");
         ret.import_mtx_file_by_col(mtx_file)?;
@@ -135,17 +395,19 @@ impl SparseMtxData {
     /// * `array` - 2D array to be added to the backend
     /// * `backend_file` - Optional zarr backend file
     /// * `index_by_row` - Optional flag to index by row (CSR format)
+    /// * `write_options` - chunking, sharding, and codecs of arrays
     pub fn from_ndarray(
         array: &Array2<f32>,
         zarr_file: Option<&str>,
         index_by_row: Option<bool>,
+        write_options: &ZarrWriteOptions,
     ) -> anyhow::Result<Self> {
         let mut ret = match zarr_file {
-            Some(backend_file) => Self::register_backend_file(backend_file)?,
+            Some(backend_file) => Self::register_backend_file(backend_file, write_options)?,
             None => {
                 let backend_file = create_temp_dir_file(".zarr")?;
                 let backend_file = backend_file.to_str().expect("to_str failed");
-                Self::register_backend_file(backend_file)?
+                Self::register_backend_file(backend_file, write_options)?
             }
         };
 
@@ -163,17 +425,19 @@ impl SparseMtxData {
     /// * `array` - 2D array to be added to the backend
     /// * `backend_file` - Optional zarr backend file
     /// * `index_by_row` - Optional flag to index by row (CSR format)
+    /// * `write_options` - chunking, sharding, and codecs of arrays
     pub fn from_dmatrix(
         matrix: &DMatrix<f32>,
         zarr_file: Option<&str>,
         index_by_row: Option<bool>,
+        write_options: &ZarrWriteOptions,
     ) -> anyhow::Result<Self> {
         let mut ret = match zarr_file {
-            Some(backend_file) => Self::register_backend_file(backend_file)?,
+            Some(backend_file) => Self::register_backend_file(backend_file, write_options)?,
             None => {
                 let backend_file = create_temp_dir_file(".zarr")?;
                 let backend_file = backend_file.to_str().expect("to_str failed");
-                Self::register_backend_file(backend_file)?
+                Self::register_backend_file(backend_file, write_options)?
             }
         };
 
@@ -196,10 +460,13 @@ impl SparseMtxData {
     }
 
     /// Helper function to create a new zarr backend file
-    fn register_backend_file(zarr_file: &str) -> anyhow::Result<Self> {
+    fn register_backend_file(
+        zarr_file: &str,
+        write_options: &ZarrWriteOptions,
+    ) -> anyhow::Result<Self> {
         // dbg!(zarr_file);
         use zarrs::group::GroupBuilder;
-        let store = Arc::new(FilesystemStore::new(zarr_file)?);
+        let (store, zip_store) = Self::open_store(zarr_file)?;
         let root = GroupBuilder::new().build(store.clone(), "/")?;
         root.store_metadata()?;
 
@@ -210,11 +477,33 @@ impl SparseMtxData {
             max_column_name_idx: MAX_COLUMN_NAME_IDX,
             by_column_indptr: vec![],
             by_row_indptr: vec![],
-            by_column_indicies: None,
-            by_column_data: None,
+            by_column_preloaded: None,
+            layer: None,
+            write_options: write_options.clone(),
+            zip_store,
+            column_cache: Arc::new(ChunkCache::default()),
+            value_type: None,
         })
     }
 
+    /// Helper function to forget the cached columns, in memory and
+    /// on disk, after the backend has changed
+    fn invalidate_column_caches(&self) -> anyhow::Result<()> {
+        self.column_cache.clear();
+        remove_mmap_cache(&self.file_name)
+    }
+
+    /// Helper function to open a directory store, or a zip store if
+    /// the file name ends with `.zarr.zip`
+    fn open_store(zarr_file: &str) -> anyhow::Result<OpenedStore> {
+        if is_zarr_zip(zarr_file) {
+            let zip_store = Arc::new(ZipStore::new(zarr_file)?);
+            Ok((zip_store.clone(), Some(zip_store)))
+        } else {
+            Ok((Arc::new(FilesystemStore::new(zarr_file)?), None))
+        }
+    }
+
     //////////////////////
     // backend related  //
     //////////////////////
@@ -238,34 +527,24 @@ impl SparseMtxData {
         S: ndarray::Data<Elem = V> + Clone,
         D: ndarray::Dimension + ndarray::RemoveAxis,
     {
-        use zarrs::array::codec::ZstdCodec;
-        use zarrs::array::ArrayBuilder;
         use zarrs::array::DataType;
-        use zarrs::array::FillValue;
 
         let fill = match dt {
             DataType::Float32 => FillValue::from(zarrs::array::ZARR_NAN_F32),
+            DataType::Float64 => FillValue::from(zarrs::array::ZARR_NAN_F64),
+            DataType::UInt16 => FillValue::from(0u16),
+            DataType::UInt32 => FillValue::from(0u32),
             DataType::UInt64 => FillValue::from(0u64),
             DataType::String => FillValue::from(""),
             _ => FillValue::from(0),
         };
 
-        let nchunks = NUM_CHUNKS;
         let array_shape: Vec<u64> = data.shape().iter().map(|&x| x as u64).collect();
-        let chunk_size: Vec<u64> = data
-            .shape()
-            .iter()
-            .map(|&d| (d / nchunks).max(MIN_CHUNK_SIZE).min(d) as u64)
-            .collect();
 
-        let array = ArrayBuilder::new(
-            array_shape,            // array shape
-            dt,                     // data type
-            chunk_size.try_into()?, // chunk shape
-            fill,                   //
-        )
-        .bytes_to_bytes_codecs(vec![Arc::new(ZstdCodec::new(COMPRESSION_LEVEL, false))])
-        .build(self.store.clone(), key)?;
+        let array = self
+            .write_options
+            .array_builder(array_shape, dt, fill)?
+            .build(self.store.clone(), key)?;
 
         array.store_array_subset_ndarray(array.subset_all().start(), data.to_owned())?;
         array.store_metadata()?;
@@ -284,47 +563,185 @@ impl SparseMtxData {
     where
         V: zarrs::array::Element,
     {
-        use zarrs::array::codec::ZstdCodec;
-        use zarrs::array::ArrayBuilder;
+        self.new_empty_vector(key, dt, vec.len())?;
+        self.store_vector_range(key, 0, vec)
+    }
+
+    /// Helper function to create a 1D array of `nelem` elements to
+    /// be filled later by `store_vector_range`. Any existing array
+    /// under `key` will be removed first.
+    ///
+    /// * `key` - the key name
+    /// * `dt` - the data type among `DataType`
+    /// * `nelem` - the number of elements
+    ///
+    fn new_empty_vector(&mut self, key: &str, dt: DataType, nelem: usize) -> anyhow::Result<()> {
         use zarrs::array::DataType;
-        use zarrs::array::FillValue;
         // use zarrs::array::ZARR_NAN_F32;
 
-        let nelem = vec.len();
-        let nchunks = NUM_CHUNKS;
-        let chunk_size = (nelem / nchunks).max(MIN_CHUNK_SIZE).min(nelem);
+        let prefix = zarrs::storage::StorePrefix::new(format!("{}/", key.trim_matches('/')))?;
+        self.store.erase_prefix(&prefix)?;
+        self.invalidate_column_caches()?;
 
         let fill = match dt {
             DataType::Float32 => FillValue::from(zarrs::array::ZARR_NAN_F32),
+            DataType::Float64 => FillValue::from(zarrs::array::ZARR_NAN_F64),
+            DataType::UInt16 => FillValue::from(0u16),
+            DataType::UInt32 => FillValue::from(0u32),
             DataType::UInt64 => FillValue::from(0u64),
             DataType::String => FillValue::from(""),
             _ => FillValue::from(0),
         };
 
-        let array = ArrayBuilder::new(
-            vec![vec.len() as u64],              // array shape
-            dt,                                  // data type
-            vec![chunk_size as u64].try_into()?, // chunk shape
-            fill,                                //
-        )
-        .bytes_to_bytes_codecs(vec![Arc::new(ZstdCodec::new(COMPRESSION_LEVEL, false))])
-        .build(self.store.clone(), key)?;
+        let array = self
+            .write_options
+            .array_builder(vec![nelem as u64], dt, fill)?
+            .build(self.store.clone(), key)?;
 
         array.store_metadata()?;
+        Ok(())
+    }
 
-        let ntot = vec.len() as u64;
-        let subset = ArraySubset::new_with_ranges(&[0..ntot]);
+    /// Helper function to store `vec` in the existing 1D array
+    /// starting from the `offset` position
+    ///
+    /// * `key` - the key name
+    /// * `offset` - the first position to be filled
+    /// * `vec` - the vector to be stored
+    ///
+    fn store_vector_range<V>(&self, key: &str, offset: usize, vec: &[V]) -> anyhow::Result<()>
+    where
+        V: zarrs::array::Element,
+    {
+        let array = self._open_vector(key)?;
+        let lb = offset as u64;
+        let ub = (offset + vec.len()) as u64;
+        let subset = ArraySubset::new_with_ranges(&[lb..ub]);
         array.store_array_subset_elements(&subset, vec)?;
-
+        self.invalidate_column_caches()?;
         Ok(())
     }
 
+    /// Helper function to create a 1D array of `values`, stored as
+    /// the value type of this backend
+    ///
+    /// * `key` - the key name
+    /// * `values` - the values to be stored
+    ///
+    fn new_value_vector(&mut self, key: &str, values: &[f32]) -> anyhow::Result<()> {
+        match self.value_type_for(values)? {
+            ValueType::U16 => {
+                let values = ValueType::U16.checked_values(values, |x| x as u16)?;
+                self.new_filled_vector(key, DataType::UInt16, &values)
+            }
+            ValueType::U32 => {
+                let values = ValueType::U32.checked_values(values, |x| x as u32)?;
+                self.new_filled_vector(key, DataType::UInt32, &values)
+            }
+            ValueType::F32 => self.new_filled_vector(key, DataType::Float32, values),
+            ValueType::F64 => {
+                let values: Vec<f64> = values.iter().map(|&x| x as f64).collect();
+                self.new_filled_vector(key, DataType::Float64, &values)
+            }
+        }
+    }
+
+    /// Helper function to store `values` in the existing 1D array
+    /// starting from the `offset` position, converted to the data
+    /// type of the array
+    ///
+    /// * `key` - the key name
+    /// * `offset` - the first position to be filled
+    /// * `values` - the values to be stored
+    ///
+    fn store_value_range(&self, key: &str, offset: usize, values: &[f32]) -> anyhow::Result<()> {
+        match self._open_vector(key)?.data_type() {
+            DataType::UInt16 => {
+                let values = ValueType::U16.checked_values(values, |x| x as u16)?;
+                self.store_vector_range(key, offset, &values)
+            }
+            DataType::UInt32 => {
+                let values = ValueType::U32.checked_values(values, |x| x as u32)?;
+                self.store_vector_range(key, offset, &values)
+            }
+            DataType::Float64 => {
+                let values: Vec<f64> = values.iter().map(|&x| x as f64).collect();
+                self.store_vector_range(key, offset, &values)
+            }
+            _ => self.store_vector_range(key, offset, values),
+        }
+    }
+
     fn _open_vector(&self, key: &str) -> anyhow::Result<zarrs::array::Array<dyn ZStorageTraits>> {
         use zarrs::array::Array as ZArray;
         let ret = ZArray::open(self.store.clone(), key)?;
         Ok(ret)
     }
 
+    /// (nrow, ncol) if the columns are indexed
+    fn column_shape(&self) -> anyhow::Result<(usize, usize)> {
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow!("can't figure out the number of rows"))?;
+        let ncol = self
+            .num_columns()
+            .ok_or(anyhow!("can't figure out the number of columns"))?;
+        if self.by_column_indptr.len() <= ncol {
+            return Err(anyhow!("columns are not indexed"));
+        }
+        Ok((nrow, ncol))
+    }
+
+    /// (nrow, ncol) if the rows are indexed
+    fn row_shape(&self) -> anyhow::Result<(usize, usize)> {
+        let nrow = self
+            .num_rows()
+            .ok_or(anyhow!("can't figure out the number of rows"))?;
+        let ncol = self
+            .num_columns()
+            .ok_or(anyhow!("can't figure out the number of columns"))?;
+        if self.by_row_indptr.len() <= nrow {
+            return Err(anyhow!("rows are not indexed"));
+        }
+        Ok((nrow, ncol))
+    }
+
+    /// Read the columns (rows) `outer` of the compressed group `key`
+    /// through `cache`, opening `data` and `indices` only once
+    ///
+    /// * `cache` - block cache (a zero-sized one keeps nothing)
+    /// * `key` - e.g., `/by_column` (of a layer)
+    /// * `indptr` - pointers of the group
+    /// * `outer` - columns (rows) to read
+    fn read_compressed_parts(
+        &self,
+        cache: &ChunkCache,
+        key: &str,
+        indptr: &[u64],
+        outer: &[usize],
+    ) -> anyhow::Result<CompressedParts> {
+        let data = self._open_vector(&format!("{}/data", key))?;
+        let indices = self._open_vector(&format!("{}/indices", key))?;
+
+        // blocks aligned with the larger of the two chunks
+        let chunk_len = |array: &zarrs::array::Array<dyn ZStorageTraits>| {
+            array
+                .chunk_shape(&[0])
+                .map(|x| x[0].get() as usize)
+                .unwrap_or(DEFAULT_BLOCK_SIZE)
+        };
+        let block_size = chunk_len(&data).max(chunk_len(&indices));
+
+        read_compressed_parts_through_cache(cache, key, indptr, outer, block_size, |range| {
+            let (lb, ub) = (range.start as u64, range.end as u64);
+            let subset = ArraySubset::new_with_ranges(&[lb..ub]);
+            Ok((
+                indices.retrieve_array_subset_elements::<u64>(&subset)?,
+                retrieve_values(&data, &subset)?,
+            ))
+        })
+    }
+
     fn open_csc_triplets(
         &self,
     ) -> anyhow::Result<(
@@ -333,9 +750,9 @@ impl SparseMtxData {
         zarrs::array::Array<dyn ZStorageTraits>,
     )> {
         Ok((
-            self._open_vector("/by_column/indptr")?,
-            self._open_vector("/by_column/data")?,
-            self._open_vector("/by_column/indices")?,
+            self._open_vector(&self._matrix_key("by_column/indptr"))?,
+            self._open_vector(&self._matrix_key("by_column/data"))?,
+            self._open_vector(&self._matrix_key("by_column/indices"))?,
         ))
     }
 
@@ -347,9 +764,9 @@ impl SparseMtxData {
         zarrs::array::Array<dyn ZStorageTraits>,
     )> {
         Ok((
-            self._open_vector("/by_row/indptr")?,
-            self._open_vector("/by_row/data")?,
-            self._open_vector("/by_row/indices")?,
+            self._open_vector(&self._matrix_key("by_row/indptr"))?,
+            self._open_vector(&self._matrix_key("by_row/data"))?,
+            self._open_vector(&self._matrix_key("by_row/indices"))?,
         ))
     }
 
@@ -404,6 +821,20 @@ impl SparseMtxData {
             .and_then(|attr| serde_json::from_value(attr.clone()).ok())
     }
 
+    /// Helper function to recover the chunking, sharding, and codecs
+    /// of an existing backend from its `by_column/data` array, so
+    /// that arrays written later keep the same layout
+    fn _get_write_options(store: Arc<dyn ZStorageTraits>) -> ZarrWriteOptions {
+        zarrs::array::Array::open(store, "/by_column/data")
+            .ok()
+            .and_then(|array| {
+                let nelem = array.shape().first().copied()? as usize;
+                let meta = serde_json::to_value(array.metadata()).ok()?;
+                ZarrWriteOptions::from_array_metadata(&meta, nelem)
+            })
+            .unwrap_or_default()
+    }
+
     fn _num_nnz(store: Arc<dyn ZStorageTraits>) -> Option<usize> {
         Self::_get_group_attr::<usize>(store.clone(), "/", "nnz")
     }
@@ -415,6 +846,26 @@ impl SparseMtxData {
     fn _num_columns(store: Arc<dyn ZStorageTraits>) -> Option<usize> {
         Self::_get_group_attr::<usize>(store.clone(), "/", "ncol")
     }
+    /// Key of the compressed data `name` (e.g., `by_column/data`) in
+    /// the layer of this matrix
+    fn _matrix_key(&self, name: &str) -> String {
+        layer_key(self.layer.as_deref(), name)
+    }
+
+    /// Helper function to add a group and its missing parents
+    fn _add_group_all(&mut self, group_name: &str) -> anyhow::Result<()> {
+        use zarrs::group::Group;
+
+        let mut key = String::new();
+        for name in group_name.split('/').filter(|x| !x.is_empty()) {
+            key = format!("{}/{}", key, name);
+            if Group::open(self.store.clone(), &key).is_err() {
+                self._add_group(&key)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Helper function to add a group in `self.store`
     fn _add_group(&mut self, group_name: &str) -> anyhow::Result<()> {
         use zarrs::group::Group;
@@ -437,7 +888,7 @@ impl SparseIo for SparseMtxData {
     /// Read row index pointers
     fn read_row_indptr(&mut self) -> anyhow::Result<()> {
         use zarrs::array::Array as Zarray;
-        let key = "/by_row/indptr";
+        let key = &self._matrix_key("by_row/indptr");
         if let Ok(indptr) = Zarray::open(self.store.clone(), key) {
             let indptr_vec = indptr.retrieve_array_subset_elements::<u64>(&indptr.subset_all())?;
             self.by_row_indptr.clear();
@@ -449,7 +900,8 @@ impl SparseIo for SparseMtxData {
     /// Read column index pointers
     fn read_column_indptr(&mut self) -> anyhow::Result<()> {
         use zarrs::array::Array as ZArray;
-        let key = "/by_column/indptr";
+        let key = &self._matrix_key("by_column/indptr");
+        self.column_cache.clear();
         if let Ok(indptr) = ZArray::open(self.store.clone(), key) {
             let indptr_vec = indptr.retrieve_array_subset_elements::<u64>(&indptr.subset_all())?;
             self.by_column_indptr.clear();
@@ -459,24 +911,31 @@ impl SparseIo for SparseMtxData {
     }
 
     fn clean_preloaded_columns(&mut self) {
-        self.by_column_data = None;
-        self.by_column_indicies = None;
+        self.by_column_preloaded = None;
     }
 
     /// preload columns' values and indices
     fn preload_columns(&mut self) -> anyhow::Result<()> {
         use zarrs::array::Array as ZArray;
 
-        let key = "/by_column/data";
+        let key = &self._matrix_key("by_column/data");
         let data = ZArray::open(self.store.clone(), key)?;
-        let key = "/by_column/indices";
+        let key = &self._matrix_key("by_column/indices");
         let indices = ZArray::open(self.store.clone(), key)?;
 
-        let data = data.retrieve_array_subset_elements::<f32>(&data.subset_all())?;
+        let data = retrieve_values(&data, &data.subset_all())?;
         let indices = indices.retrieve_array_subset_elements::<u64>(&indices.subset_all())?;
 
-        self.by_column_indicies = Some(indices);
-        self.by_column_data = Some(data);
+        self.by_column_preloaded = Some(Arc::new(PreloadedColumns::Heap { indices, data }));
+        Ok(())
+    }
+
+    /// memory-map columns' values and indices from the uncompressed
+    /// on-disk cache next to the backend file
+    fn preload_columns_mmap(&mut self) -> anyhow::Result<()> {
+        let key = self._matrix_key("by_column");
+        let preloaded = mmap_compressed_columns(self, &key, &self.by_column_indptr)?;
+        self.by_column_preloaded = Some(Arc::new(preloaded));
         Ok(())
     }
 
@@ -507,32 +966,103 @@ impl SparseIo for SparseMtxData {
         Ok(())
     }
 
+    /// Overwrite the matrix shape
+    fn update_mtx_shape(&mut self, mtx_shape: (usize, usize, usize)) -> anyhow::Result<()> {
+        let (nrow, ncol, nnz) = mtx_shape;
+        Self::_set_group_attr(self.store.clone(), "/", "nrow", &nrow)?;
+        Self::_set_group_attr(self.store.clone(), "/", "ncol", &ncol)?;
+        Self::_set_group_attr(self.store.clone(), "/", "nnz", &nnz)?;
+        Ok(())
+    }
+
+    fn value_type(&self) -> Option<ValueType> {
+        self.value_type
+    }
+
+    fn create_options(&self) -> SparseCreateOptions {
+        SparseCreateOptions {
+            value_type: self.value_type,
+            zarr: self.write_options.clone(),
+        }
+    }
+
+    /// Keep the value type in the root attributes
+    fn set_value_type(&mut self, value_type: ValueType) -> anyhow::Result<()> {
+        Self::_set_group_attr(self.store.clone(), "/", VALUE_TYPE_ATTR, &value_type.name())?;
+        self.value_type = Some(value_type);
+        Ok(())
+    }
+
     /// Helper function to create a new zarr backend file
     fn initialize_backend(&mut self) -> anyhow::Result<()> {
         use zarrs::group::GroupBuilder;
 
         self.remove_backend_file()?;
         let zarr_file = &self.file_name;
-        let store = Arc::new(FilesystemStore::new(zarr_file)?);
+        let (store, zip_store) = Self::open_store(zarr_file)?;
         let root = GroupBuilder::new().build(store.clone(), "/")?;
         root.store_metadata()?;
 
         self.store = store.clone();
+        self.zip_store = zip_store;
+        self.column_cache.clear();
         self.file_name = zarr_file.to_string().clone();
         self.max_column_name_idx = MAX_COLUMN_NAME_IDX;
         self.max_row_name_idx = MAX_ROW_NAME_IDX;
         self.by_column_indptr = vec![];
         self.by_row_indptr = vec![];
 
+        if let Some(value_type) = self.value_type {
+            self.set_value_type(value_type)?;
+        }
         Ok(())
     }
 
     /// Clean up the backend file
     fn remove_backend_file(&self) -> anyhow::Result<()> {
+        if let Some(zip_store) = self.zip_store.as_ref() {
+            // not to write back the archive later
+            zip_store.discard();
+        }
+
         let backend = std::path::Path::new(&self.file_name);
-        if backend.exists() {
+        if backend.is_file() {
+            std::fs::remove_file(backend)?;
+        } else if backend.exists() {
             std::fs::remove_dir_all(backend)?;
         }
+        remove_mmap_cache(&self.file_name)?;
+        Ok(())
+    }
+
+    /// Direct the next `initialize_backend` to another zarr file
+    fn set_backend_file_name(&mut self, file_name: &str) -> anyhow::Result<()> {
+        self.file_name = file_name.to_string();
+        Ok(())
+    }
+
+    /// Move the zarr backend, writing out a zip archive first, and
+    /// reopen the store there
+    fn move_backend_file(&mut self, file_name: &str) -> anyhow::Result<()> {
+        if let Some(zip_store) = self.zip_store.as_ref() {
+            zip_store.flush()?;
+        }
+        replace_backend_path(&self.file_name, file_name)?;
+        remove_mmap_cache(file_name)?;
+
+        let (store, zip_store) = Self::open_store(file_name)?;
+        self.store = store;
+        self.zip_store = zip_store;
+        self.file_name = file_name.to_string();
+        self.column_cache.clear();
+        Ok(())
+    }
+
+    /// Rewrite the zip archive, if any, with the changes so far
+    fn flush_backend(&self) -> anyhow::Result<()> {
+        if let Some(zip_store) = self.zip_store.as_ref() {
+            zip_store.flush()?;
+        }
         Ok(())
     }
 
@@ -560,7 +1090,7 @@ impl SparseIo for SparseMtxData {
             for jj in 0..ncol {
                 let (start, end) = (indptr[jj], indptr[jj + 1]);
                 let subset = ArraySubset::new_with_ranges(&[start..end]);
-                let data_slice = data.retrieve_array_subset_ndarray::<f32>(&subset)?;
+                let data_slice = retrieve_values(&data, &subset)?;
                 let indices_slice = indices.retrieve_array_subset_ndarray::<u64>(&subset)?;
 
                 // write them with 1-based indices
@@ -625,9 +1155,12 @@ impl SparseIo for SparseMtxData {
         Self::_num_columns(self.store.clone())
     }
 
-    /// Number of non-zero elements in the matrix
+    /// Number of non-zero elements in the matrix (or the layer)
     fn num_non_zeros(&self) -> Option<usize> {
-        Self::_num_nnz(self.store.clone())
+        match self.layer {
+            Some(_) => self.by_column_indptr.last().map(|&nnz| nnz as usize),
+            None => Self::_num_nnz(self.store.clone()),
+        }
     }
 
     /// Add arbitrary names (a vector of strings)
@@ -691,6 +1224,157 @@ impl SparseIo for SparseMtxData {
             .collect())
     }
 
+    /// Add (or replace) an annotation column
+    ///
+    /// ```text
+    ///     └── obs or var
+    ///         └── {name} (encoding-type)
+    ///             ├── categories, codes (categorical)
+    ///             └── values (numeric or string)
+    /// ```
+    fn register_annotation(
+        &mut self,
+        axis: AnnotationAxis,
+        name: &str,
+        column: &AnnotationColumn,
+    ) -> anyhow::Result<()> {
+        use zarrs::group::Group;
+        use zarrs::storage::StorePrefix;
+
+        self._add_group(axis.group_name())?;
+
+        let key = axis.key(name);
+        if Group::open(self.store.clone(), &key).is_ok() {
+            let prefix = StorePrefix::new(format!("{}/", key.trim_start_matches('/')))?;
+            self.store.erase_prefix(&prefix)?;
+        }
+        self._add_group(&key)?;
+        Self::_set_group_attr(
+            self.store.clone(),
+            &key,
+            ENCODING_ATTR,
+            &column.encoding_type(),
+        )?;
+
+        match column {
+            AnnotationColumn::Categorical { categories, codes } => {
+                let categories: Vec<String> = categories.iter().map(|x| x.to_string()).collect();
+                let categories_key = format!("{}/categories", key);
+                self.new_filled_vector(&categories_key, DataType::String, &categories)?;
+                let codes_key = format!("{}/codes", key);
+                self.new_filled_vector(&codes_key, DataType::UInt64, codes)?;
+            }
+            AnnotationColumn::Numeric(values) => {
+                let values_key = format!("{}/values", key);
+                self.new_filled_vector(&values_key, DataType::Float32, values)?;
+            }
+            AnnotationColumn::String(values) => {
+                let values: Vec<String> = values.iter().map(|x| x.to_string()).collect();
+                let values_key = format!("{}/values", key);
+                self.new_filled_vector(&values_key, DataType::String, &values)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn retrieve_annotation(
+        &self,
+        axis: AnnotationAxis,
+        name: &str,
+    ) -> anyhow::Result<AnnotationColumn> {
+        let key = axis.key(name);
+        let encoding: String = Self::_get_group_attr(self.store.clone(), &key, ENCODING_ATTR)
+            .ok_or(anyhow!("no annotation `{}` in {}", key, self.file_name))?;
+
+        let strings = |k: &str| -> anyhow::Result<Vec<Box<str>>> {
+            Ok(self
+                ._retrieve_vector::<String>(&format!("{}/{}", key, k))?
+                .into_iter()
+                .map(|s| s.into_boxed_str())
+                .collect())
+        };
+
+        match encoding.as_str() {
+            AnnotationColumn::CATEGORICAL => Ok(AnnotationColumn::Categorical {
+                categories: strings("categories")?,
+                codes: self._retrieve_vector::<u64>(&format!("{}/codes", key))?,
+            }),
+            AnnotationColumn::NUMERIC => Ok(AnnotationColumn::Numeric(
+                self._retrieve_vector::<f32>(&format!("{}/values", key))?,
+            )),
+            AnnotationColumn::STRING => Ok(AnnotationColumn::String(strings("values")?)),
+            other => Err(anyhow!("unknown annotation type: {}", other)),
+        }
+    }
+
+    fn annotation_names(&self, axis: AnnotationAxis) -> anyhow::Result<Vec<Box<str>>> {
+        use zarrs::group::Group;
+
+        let Ok(group) = Group::open(self.store.clone(), axis.group_name()) else {
+            return Ok(vec![]);
+        };
+
+        let mut ret: Vec<Box<str>> = group
+            .child_group_paths()?
+            .iter()
+            .filter_map(|path| path.as_str().rsplit('/').next().map(Box::from))
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    fn layer_names(&self) -> anyhow::Result<Vec<Box<str>>> {
+        use zarrs::group::Group;
+
+        let Ok(group) = Group::open(self.store.clone(), LAYERS_GROUP) else {
+            return Ok(vec![]);
+        };
+
+        let mut ret: Vec<Box<str>> = group
+            .child_group_paths()?
+            .iter()
+            .filter_map(|path| path.as_str().rsplit('/').next().map(Box::from))
+            .collect();
+        ret.sort();
+        Ok(ret)
+    }
+
+    /// Open the layer sharing the same store
+    /// * `layer`: name of the layer (the main matrix if `None`)
+    fn open_layer(
+        &self,
+        layer: Option<&str>,
+    ) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Self::IndexIter>>> {
+        if let Some(layer) = layer {
+            if !self.has_layer(layer)? {
+                return Err(anyhow!("no layer `{}` in {}", layer, self.file_name));
+            }
+        }
+
+        let mut ret = self.clone();
+        ret.layer = layer.map(Box::from);
+        ret.by_column_indptr.clear();
+        ret.by_row_indptr.clear();
+        ret.clean_preloaded_columns();
+        ret.read_column_indptr()?;
+        ret.read_row_indptr()?;
+        Ok(Box::new(ret))
+    }
+
+    fn remove_layer(&mut self, layer: &str) -> anyhow::Result<()> {
+        use zarrs::storage::StorePrefix;
+
+        if !self.has_layer(layer)? {
+            return Err(anyhow!("no layer `{}` in {}", layer, self.file_name));
+        }
+
+        let key = layer_key(Some(layer), "");
+        let prefix = StorePrefix::new(key.trim_start_matches('/'))?;
+        self.store.erase_prefix(&prefix)?;
+        self.invalidate_column_caches()?;
+        Ok(())
+    }
+
     /// Read columns within the range and return a vector of triplets (row, col, value)
     /// * `col` : usize
     ///
@@ -713,7 +1397,8 @@ impl SparseIo for SparseMtxData {
             .num_rows()
             .ok_or(anyhow!("can't figure out the number of rows"))?;
 
-        if let (Some(data), Some(indices)) = (&self.by_column_data, &self.by_column_indicies) {
+        if let Some(preloaded) = self.by_column_preloaded.as_ref() {
+            let (indices, data) = (preloaded.indices(), preloaded.data());
             let ncol_out = 1;
             let jj = 0;
 
@@ -728,9 +1413,9 @@ impl SparseIo for SparseMtxData {
 
             Ok((nrow, ncol_out, ret))
         } else {
-            let key = "/by_column/data";
+            let key = &self._matrix_key("by_column/data");
             let data = ZArray::open(self.store.clone(), key)?;
-            let key = "/by_column/indices";
+            let key = &self._matrix_key("by_column/indices");
             let indices = ZArray::open(self.store.clone(), key)?;
 
             let ncol_out = 1;
@@ -745,7 +1430,7 @@ impl SparseIo for SparseMtxData {
             if start < end {
                 let subset = ArraySubset::new_with_ranges(&[start..end]);
 
-                let data_slice = data.retrieve_array_subset_elements::<f32>(&subset)?;
+                let data_slice = retrieve_values(&data, &subset)?;
                 let indices_slice = indices.retrieve_array_subset_elements::<u64>(&subset)?;
 
                 for k in 0..(end - start) {
@@ -796,7 +1481,8 @@ impl SparseIo for SparseMtxData {
             .max()
             .unwrap_or(0);
 
-        if let (Some(data), Some(indices)) = (&self.by_column_data, &self.by_column_indicies) {
+        if let Some(preloaded) = self.by_column_preloaded.as_ref() {
+            let (indices, data) = (preloaded.indices(), preloaded.data());
             let ncol_out = columns_vec.len();
 
             let mut ret: Vec<(u64, u64, f32)> = Vec::with_capacity((max_end - min_start) as usize);
@@ -812,38 +1498,39 @@ impl SparseIo for SparseMtxData {
 
             Ok((nrow, ncol_out, ret))
         } else {
-            let key = "/by_column/data";
+            let key = &self._matrix_key("by_column/data");
             let data = ZArray::open(self.store.clone(), key)?;
-            let key = "/by_column/indices";
+            let key = &self._matrix_key("by_column/indices");
             let indices = ZArray::open(self.store.clone(), key)?;
 
             let ncol_out = columns_vec.len();
 
-            let mut ret: Vec<(u64, u64, f32)> = Vec::with_capacity((max_end - min_start) as usize);
-
-            for (jj, &j_data) in columns_vec.iter().enumerate() {
-                let jj = jj as u64;
-                if j_data < ncol {
-                    // [start, end)
-                    let start = indptr[j_data];
-                    let end = indptr[j_data + 1];
+            // blocks aligned with the larger of the two chunks
+            let chunk_len = |array: &ZArray<dyn ZStorageTraits>| {
+                array
+                    .chunk_shape(&[0])
+                    .map(|x| x[0].get() as usize)
+                    .unwrap_or(DEFAULT_BLOCK_SIZE)
+            };
+            let block_size = chunk_len(&data).max(chunk_len(&indices));
+
+            let ret = read_compressed_through_cache(
+                &self.column_cache,
+                &self._matrix_key("by_column"),
+                &indptr[..=ncol],
+                &columns_vec,
+                block_size,
+                |range| {
+                    let (lb, ub) = (range.start as u64, range.end as u64);
+                    let subset = ArraySubset::new_with_ranges(&[lb..ub]);
+                    Ok((
+                        indices.retrieve_array_subset_elements::<u64>(&subset)?,
+                        retrieve_values(&data, &subset)?,
+                    ))
+                },
+            )?;
+            debug_assert!(ret.iter().all(|&(ii, _, _)| (ii as usize) < nrow));
 
-                    if start < end {
-                        let subset = ArraySubset::new_with_ranges(&[start..end]);
-
-                        let data_slice = data.retrieve_array_subset_elements::<f32>(&subset)?;
-                        let indices_slice =
-                            indices.retrieve_array_subset_elements::<u64>(&subset)?;
-
-                        for k in 0..(end - start) {
-                            let x_ij = data_slice[k as usize];
-                            let ii = indices_slice[k as usize];
-                            debug_assert!((ii as usize) < nrow);
-                            ret.push((ii, jj, x_ij));
-                        }
-                    }
-                }
-            }
             Ok((nrow, ncol_out, ret))
         }
     }
@@ -864,9 +1551,9 @@ impl SparseIo for SparseMtxData {
 
         let rows_vec = rows.into_iter().collect::<Vec<_>>();
 
-        let key = "/by_row/data";
+        let key = &self._matrix_key("by_row/data");
         let data = ZArray::open(self.store.clone(), key)?;
-        let key = "/by_row/indices";
+        let key = &self._matrix_key("by_row/indices");
         let indices = ZArray::open(self.store.clone(), key)?;
 
         if let (Some(nrow), Some(ncol)) = (self.num_rows(), self.num_columns()) {
@@ -897,7 +1584,7 @@ impl SparseIo for SparseMtxData {
 
                     if start < end {
                         let subset = ArraySubset::new_with_ranges(&[start..end]);
-                        let data_slice = data.retrieve_array_subset_elements::<f32>(&subset)?;
+                        let data_slice = retrieve_values(&data, &subset)?;
                         let indices_slice =
                             indices.retrieve_array_subset_elements::<u64>(&subset)?;
 
@@ -915,6 +1602,82 @@ impl SparseIo for SparseMtxData {
             Err(anyhow!("Unable to figure out the size of the backend data"))
         }
     }
+    /// Read columns and return sparse `CscMatrix` straight from the
+    /// column pointers, without the triplets in between
+    /// * `columns` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_columns_csc(&self, columns: Self::IndexIter) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, ncol) = self.column_shape()?;
+        let indptr = &self.by_column_indptr[..=ncol];
+
+        let parts = match self.by_column_preloaded.as_ref() {
+            Some(preloaded) => CompressedParts::from_slices(
+                indptr,
+                preloaded.indices(),
+                preloaded.data(),
+                columns.iter().copied(),
+            ),
+            None => self.read_compressed_parts(
+                &self.column_cache,
+                &self._matrix_key("by_column"),
+                indptr,
+                &columns,
+            )?,
+        };
+        parts.into_csc(nrow, columns.len())
+    }
+
+    /// Read a contiguous range of columns in one go
+    /// * `columns` : range e.g., 0..3
+    ///
+    fn read_column_range_csc(&self, columns: Range<usize>) -> anyhow::Result<CscMatrix<f32>> {
+        let (nrow, ncol) = self.column_shape()?;
+        let indptr = &self.by_column_indptr[..=ncol];
+        let ncol_out = columns.len();
+
+        let parts = match self.by_column_preloaded.as_ref() {
+            Some(preloaded) => {
+                CompressedParts::from_slices(indptr, preloaded.indices(), preloaded.data(), columns)
+            }
+            None => {
+                let key = self._matrix_key("by_column");
+                CompressedParts::from_contiguous(indptr, columns, |range| {
+                    self.read_compressed_chunk_backend(&key, range)
+                })?
+            }
+        };
+        parts.into_csc(nrow, ncol_out)
+    }
+
+    /// Read rows and return sparse `CsrMatrix` straight from the row
+    /// pointers, coalescing the chunks shared by the rows
+    /// * `rows` : range e.g., 0..3 -> [0, 1, 2] or vec![0, 1, 2]
+    ///
+    fn read_rows_csr(&self, rows: Self::IndexIter) -> anyhow::Result<CsrMatrix<f32>> {
+        let (nrow, ncol) = self.row_shape()?;
+        let parts = self.read_compressed_parts(
+            &ChunkCache::new(0),
+            &self._matrix_key("by_row"),
+            &self.by_row_indptr[..=nrow],
+            &rows,
+        )?;
+        parts.into_csr(rows.len(), ncol)
+    }
+
+    /// Read a contiguous range of rows in one go
+    /// * `rows` : range e.g., 0..3
+    ///
+    fn read_row_range_csr(&self, rows: Range<usize>) -> anyhow::Result<CsrMatrix<f32>> {
+        let (nrow, ncol) = self.row_shape()?;
+        let key = self._matrix_key("by_row");
+        let nrow_out = rows.len();
+        let parts =
+            CompressedParts::from_contiguous(&self.by_row_indptr[..=nrow], rows, |range| {
+                self.read_compressed_chunk_backend(&key, range)
+            })?;
+        parts.into_csr(nrow_out, ncol)
+    }
+
     /// CSR data structure in Zarr backend
     ///
     /// ```text
@@ -930,14 +1693,14 @@ impl SparseIo for SparseMtxData {
         csr_rowptr: &[u64],
     ) -> anyhow::Result<()> {
         // open or create the group "/by_row"
-        let key = "/by_row";
-        self._add_group(key)?;
+        let key = &self._matrix_key("by_row");
+        self._add_group_all(key)?;
 
-        let key = "/by_row/data";
-        self.new_filled_vector(key, DataType::Float32, csr_vals)?;
-        let key = "/by_row/indices";
+        let key = &self._matrix_key("by_row/data");
+        self.new_value_vector(key, csr_vals)?;
+        let key = &self._matrix_key("by_row/indices");
         self.new_filled_vector(key, DataType::UInt64, csr_cols)?;
-        let key = "/by_row/indptr";
+        let key = &self._matrix_key("by_row/indptr");
         self.new_filled_vector(key, DataType::UInt64, csr_rowptr)?;
 
         Ok(())
@@ -959,19 +1722,88 @@ impl SparseIo for SparseMtxData {
         csc_colptr: &[u64],
     ) -> anyhow::Result<()> {
         // open or create the group "/by_column"
-        let key = "/by_column";
-        self._add_group(key)?;
+        let key = &self._matrix_key("by_column");
+        self._add_group_all(key)?;
 
-        let key = "/by_column/data";
-        self.new_filled_vector(key, DataType::Float32, csc_vals)?;
-        let key = "/by_column/indices";
+        let key = &self._matrix_key("by_column/data");
+        self.new_value_vector(key, csc_vals)?;
+        let key = &self._matrix_key("by_column/indices");
         // dbg!(key);
         self.new_filled_vector(key, DataType::UInt64, csc_rows)?;
 
-        let key = "/by_column/indptr";
+        let key = &self._matrix_key("by_column/indptr");
         // dbg!(key);
         self.new_filled_vector(key, DataType::UInt64, csc_colptr)?;
 
         Ok(())
     }
+
+    fn allocate_compressed_dataset_backend(
+        &mut self,
+        key: &str,
+        nnz: usize,
+        value_type: ValueType,
+    ) -> anyhow::Result<()> {
+        self._add_group_all(key)?;
+        let dt = value_data_type(value_type);
+        self.new_empty_vector(&format!("{}/data", key), dt, nnz)?;
+        self.new_empty_vector(&format!("{}/indices", key), DataType::UInt64, nnz)?;
+        Ok(())
+    }
+
+    fn record_compressed_chunk_backend(
+        &mut self,
+        key: &str,
+        offset: usize,
+        indices: &[u64],
+        values: &[f32],
+    ) -> anyhow::Result<()> {
+        self.store_value_range(&format!("{}/data", key), offset, values)?;
+        self.store_vector_range(&format!("{}/indices", key), offset, indices)?;
+        Ok(())
+    }
+
+    fn record_compressed_indptr_backend(
+        &mut self,
+        key: &str,
+        indptr: &[u64],
+    ) -> anyhow::Result<()> {
+        self.new_filled_vector(&format!("{}/indptr", key), DataType::UInt64, indptr)
+    }
+
+    fn read_compressed_indptr_backend(&self, key: &str) -> anyhow::Result<Vec<u64>> {
+        self._retrieve_vector::<u64>(&format!("{}/indptr", key))
+    }
+
+    fn compressed_dataset_len_backend(&self, key: &str) -> anyhow::Result<(usize, usize)> {
+        let indices = self._open_vector(&format!("{}/indices", key))?;
+        let data = self._open_vector(&format!("{}/data", key))?;
+        Ok((indices.shape()[0] as usize, data.shape()[0] as usize))
+    }
+
+    fn read_compressed_chunk_backend(
+        &self,
+        key: &str,
+        range: Range<usize>,
+    ) -> anyhow::Result<(Vec<u64>, Vec<f32>)> {
+        let indices = self._open_vector(&format!("{}/indices", key))?;
+        let data = self._open_vector(&format!("{}/data", key))?;
+        let (lb, ub) = (range.start as u64, range.end as u64);
+        let subset = ArraySubset::new_with_ranges(&[lb..ub]);
+        Ok((
+            indices.retrieve_array_subset_elements::<u64>(&subset)?,
+            retrieve_values(&data, &subset)?,
+        ))
+    }
+
+    /// Only the shapes change; the existing chunks stay as they are
+    fn resize_compressed_dataset_backend(&mut self, key: &str, nnz: usize) -> anyhow::Result<()> {
+        for name in ["data", "indices"] {
+            let mut array = self._open_vector(&format!("{}/{}", key, name))?;
+            array.set_shape(vec![nnz as u64]);
+            array.store_metadata()?;
+        }
+        self.invalidate_column_caches()?;
+        Ok(())
+    }
 }
@@ -0,0 +1,507 @@
+use clap::ValueEnum;
+use log::info;
+use matrix_util::common_io::open_buf_reader;
+use matrix_util::traits::IoOps;
+use ndarray::Array2;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::sync::Arc;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+#[clap(rename_all = "lowercase")]
+pub enum TranscriptAggregation {
+    /// molecules sharing the same cell ID
+    Cell,
+    /// square bins of `bin_size` on a side
+    Square,
+    /// hexagonal bins whose neighbouring centres are `bin_size` apart
+    Hex,
+}
+
+/// Column names of a molecule table (Xenium by default)
+///
+/// ```text
+///              gene          x            y            cell      qv
+/// Xenium       feature_name  x_location   y_location   cell_id   qv
+/// MERSCOPE     gene          global_x     global_y     cell_id   -
+/// CosMx        target        x_global_px  y_global_px  cell      -
+/// ```
+#[derive(Clone, Debug)]
+pub struct TranscriptColumns {
+    pub gene: Box<str>,
+    pub x: Box<str>,
+    pub y: Box<str>,
+    pub cell: Option<Box<str>>,
+    pub qv: Option<Box<str>>,
+}
+
+impl Default for TranscriptColumns {
+    fn default() -> Self {
+        Self {
+            gene: "feature_name".into(),
+            x: "x_location".into(),
+            y: "y_location".into(),
+            cell: Some("cell_id".into()),
+            qv: Some("qv".into()),
+        }
+    }
+}
+
+/// A detected molecule
+#[derive(Clone, Debug)]
+pub struct Transcript {
+    pub gene: Box<str>,
+    pub x: f64,
+    pub y: f64,
+    pub cell: Option<Box<str>>,
+    pub qv: Option<f64>,
+}
+
+/// Which molecules to keep
+#[derive(Clone, Debug, Default)]
+pub struct TranscriptFilter {
+    /// minimum quality value (phred-scaled), e.g., 20 for Xenium
+    pub min_qv: Option<f64>,
+    /// drop the genes matched, e.g., `^(NegControl|BLANK|Unassigned)`
+    pub exclude_genes: Option<regex::Regex>,
+    /// cell IDs standing for molecules outside of any cell
+    pub unassigned_cells: Vec<Box<str>>,
+}
+
+impl TranscriptFilter {
+    fn keep(&self, tx: &Transcript) -> bool {
+        if let (Some(min_qv), Some(qv)) = (self.min_qv, tx.qv) {
+            if qv < min_qv {
+                return false;
+            }
+        }
+        !self
+            .exclude_genes
+            .as_ref()
+            .is_some_and(|re| re.is_match(&tx.gene))
+    }
+
+    fn is_unassigned(&self, cell: &str) -> bool {
+        cell.is_empty() || self.unassigned_cells.iter().any(|x| x.as_ref() == cell)
+    }
+}
+
+/// Gene (row) by cell or bin (column) counts with the `x` and `y`
+/// coordinates of each column
+pub struct AggregatedTranscripts {
+    pub row_names: Vec<Box<str>>,
+    pub column_names: Vec<Box<str>>,
+    /// column x (x, y): cell centroids or bin centres
+    pub coordinates: Array2<f32>,
+    pub triplets: Vec<(u64, u64, f32)>,
+}
+
+impl AggregatedTranscripts {
+    /// Write the coordinates of the columns in the form `pinto
+    /// --coord` reads
+    ///
+    /// ```text
+    /// row     x       y
+    /// c1      2.0     1.0
+    /// ```
+    ///
+    /// * `coord_file` - output parquet file
+    pub fn write_coordinates(&self, coord_file: &str) -> anyhow::Result<()> {
+        self.coordinates.to_parquet(
+            Some(&self.column_names),
+            Some(&["x".into(), "y".into()]),
+            coord_file,
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum ColumnKey {
+    Cell(Box<str>),
+    Bin(i64, i64),
+}
+
+/// Index of the square bin
+fn square_bin(x: f64, y: f64, size: f64) -> (i64, i64) {
+    ((x / size).floor() as i64, (y / size).floor() as i64)
+}
+
+fn square_centre(i: i64, j: i64, size: f64) -> (f64, f64) {
+    ((i as f64 + 0.5) * size, (j as f64 + 0.5) * size)
+}
+
+/// Axial coordinates of the pointy-top hexagonal bin; `size` is the
+/// distance between neighbouring centres
+fn hex_bin(x: f64, y: f64, size: f64) -> (i64, i64) {
+    let radius = size / 3_f64.sqrt();
+    let q = (3_f64.sqrt() / 3. * x - y / 3.) / radius;
+    let r = (2. / 3. * y) / radius;
+
+    // round in the cube coordinates (q, r, -q-r)
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i64, rr as i64)
+}
+
+fn hex_centre(q: i64, r: i64, size: f64) -> (f64, f64) {
+    let radius = size / 3_f64.sqrt();
+    let (q, r) = (q as f64, r as f64);
+    (radius * 3_f64.sqrt() * (q + r / 2.), radius * 1.5 * r)
+}
+
+/// Aggregate molecules into a gene by cell (or bin) count matrix
+///
+/// * `transcript_file` - molecule table (`.parquet`, `.csv(.gz)`, or `.tsv(.gz)`)
+/// * `columns` - column names of the table
+/// * `filter` - which molecules to keep
+/// * `aggregation` - by cell IDs or into square/hexagonal bins
+/// * `bin_size` - size of the bins in the unit of `x` and `y`
+pub fn aggregate_transcripts(
+    transcript_file: &str,
+    columns: &TranscriptColumns,
+    filter: &TranscriptFilter,
+    aggregation: TranscriptAggregation,
+    bin_size: Option<f64>,
+) -> anyhow::Result<AggregatedTranscripts> {
+    let bin_size = match (aggregation, bin_size) {
+        (TranscriptAggregation::Cell, _) => 0.,
+        (_, Some(size)) if size > 0. => size,
+        _ => return Err(anyhow::anyhow!("need a positive bin size")),
+    };
+
+    if aggregation == TranscriptAggregation::Cell && columns.cell.is_none() {
+        return Err(anyhow::anyhow!(
+            "need a cell ID column to aggregate by cells"
+        ));
+    }
+
+    if filter.min_qv.is_some() && columns.qv.is_none() {
+        return Err(anyhow::anyhow!("need a quality value column to filter by"));
+    }
+
+    let mut gene_index: HashMap<Box<str>, usize> = HashMap::new();
+    let mut column_index: HashMap<ColumnKey, usize> = HashMap::new();
+    let mut counts: HashMap<(usize, usize), f32> = HashMap::new();
+    let mut centroids: Vec<(f64, f64, usize)> = vec![];
+    let (mut nfiltered, mut nunassigned) = (0_usize, 0_usize);
+
+    let ntot = visit_transcripts(transcript_file, columns, &mut |tx: Transcript| {
+        if !filter.keep(&tx) {
+            nfiltered += 1;
+            return Ok(());
+        }
+
+        let key = match aggregation {
+            TranscriptAggregation::Cell => match tx.cell.as_ref() {
+                Some(cell) if !filter.is_unassigned(cell) => ColumnKey::Cell(cell.clone()),
+                _ => {
+                    nunassigned += 1;
+                    return Ok(());
+                }
+            },
+            TranscriptAggregation::Square => {
+                let (i, j) = square_bin(tx.x, tx.y, bin_size);
+                ColumnKey::Bin(i, j)
+            }
+            TranscriptAggregation::Hex => {
+                let (q, r) = hex_bin(tx.x, tx.y, bin_size);
+                ColumnKey::Bin(q, r)
+            }
+        };
+
+        let ng = gene_index.len();
+        let g = *gene_index.entry(tx.gene).or_insert(ng);
+        let nc = column_index.len();
+        let c = *column_index.entry(key).or_insert(nc);
+        if c == centroids.len() {
+            centroids.push((0., 0., 0));
+        }
+        *counts.entry((g, c)).or_insert(0.) += 1.;
+
+        let centroid = &mut centroids[c];
+        centroid.0 += tx.x;
+        centroid.1 += tx.y;
+        centroid.2 += 1;
+        Ok(())
+    })?;
+
+    info!(
+        "{} molecules: {} filtered, {} outside of cells",
+        ntot, nfiltered, nunassigned
+    );
+
+    if counts.is_empty() {
+        return Err(anyhow::anyhow!("no molecules left in {}", transcript_file));
+    }
+
+    // sort genes by names and columns by cell IDs or bin indices
+    let mut genes: Vec<(Box<str>, usize)> = gene_index.into_iter().collect();
+    genes.sort();
+    let mut old2new_gene = vec![0; genes.len()];
+    for (new, (_, old)) in genes.iter().enumerate() {
+        old2new_gene[*old] = new;
+    }
+
+    let mut keys: Vec<(ColumnKey, usize)> = column_index.into_iter().collect();
+    keys.sort();
+    let mut old2new_column = vec![0; keys.len()];
+    for (new, (_, old)) in keys.iter().enumerate() {
+        old2new_column[*old] = new;
+    }
+
+    let mut coordinates = Array2::<f32>::zeros((keys.len(), 2));
+    let column_names: Vec<Box<str>> = keys
+        .iter()
+        .enumerate()
+        .map(|(new, (key, old))| {
+            let (name, (x, y)) = match key {
+                ColumnKey::Cell(cell) => {
+                    let (sx, sy, n) = centroids[*old];
+                    (cell.clone(), (sx / n as f64, sy / n as f64))
+                }
+                ColumnKey::Bin(i, j) if aggregation == TranscriptAggregation::Hex => (
+                    format!("hex_{}_{}", i, j).into_boxed_str(),
+                    hex_centre(*i, *j, bin_size),
+                ),
+                ColumnKey::Bin(i, j) => (
+                    format!("bin_{}_{}", i, j).into_boxed_str(),
+                    square_centre(*i, *j, bin_size),
+                ),
+            };
+            coordinates[(new, 0)] = x as f32;
+            coordinates[(new, 1)] = y as f32;
+            name
+        })
+        .collect();
+
+    let triplets: Vec<(u64, u64, f32)> = counts
+        .into_iter()
+        .map(|((g, c), x)| (old2new_gene[g] as u64, old2new_column[c] as u64, x))
+        .collect();
+
+    info!(
+        "{} genes x {} columns with {} non-zeros",
+        genes.len(),
+        column_names.len(),
+        triplets.len()
+    );
+
+    Ok(AggregatedTranscripts {
+        row_names: genes.into_iter().map(|(name, _)| name).collect(),
+        column_names,
+        coordinates,
+        triplets,
+    })
+}
+
+/// Visit each molecule of a table and return the number of molecules
+/// visited
+/// * `transcript_file` - `.parquet`, `.csv(.gz)`, or `.tsv(.gz)`
+/// * `columns` - column names of the table
+/// * `visitor` - called for each molecule
+pub fn visit_transcripts<F>(
+    transcript_file: &str,
+    columns: &TranscriptColumns,
+    visitor: &mut F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Transcript) -> anyhow::Result<()>,
+{
+    if transcript_file.ends_with(".parquet") {
+        visit_transcripts_parquet(transcript_file, columns, visitor)
+    } else {
+        visit_transcripts_text(transcript_file, columns, visitor)
+    }
+}
+
+/// Positions of (gene, x, y, cell, qv) columns among `names`
+type ColumnPositions = (usize, usize, usize, Option<usize>, Option<usize>);
+
+fn column_positions(
+    names: &[Box<str>],
+    columns: &TranscriptColumns,
+    transcript_file: &str,
+) -> anyhow::Result<ColumnPositions> {
+    let find = |name: &str| -> anyhow::Result<usize> {
+        names
+            .iter()
+            .position(|x| x.as_ref() == name)
+            .ok_or(anyhow::anyhow!(
+                "no `{}` column in {}; found {:?}",
+                name,
+                transcript_file,
+                names
+            ))
+    };
+    Ok((
+        find(&columns.gene)?,
+        find(&columns.x)?,
+        find(&columns.y)?,
+        columns.cell.as_deref().map(find).transpose()?,
+        columns.qv.as_deref().map(find).transpose()?,
+    ))
+}
+
+fn field_to_str(field: &Field) -> Option<Box<str>> {
+    match field {
+        Field::Str(x) => Some(x.as_str().into()),
+        Field::Bytes(x) => Some(String::from_utf8_lossy(x.data()).into()),
+        Field::Null => None,
+        _ => Some(field.to_string().into_boxed_str()),
+    }
+}
+
+fn field_to_f64(field: &Field) -> Option<f64> {
+    match *field {
+        Field::Float(x) => Some(x as f64),
+        Field::Double(x) => Some(x),
+        Field::Byte(x) => Some(x as f64),
+        Field::Short(x) => Some(x as f64),
+        Field::Int(x) => Some(x as f64),
+        Field::Long(x) => Some(x as f64),
+        Field::UByte(x) => Some(x as f64),
+        Field::UShort(x) => Some(x as f64),
+        Field::UInt(x) => Some(x as f64),
+        Field::ULong(x) => Some(x as f64),
+        _ => None,
+    }
+}
+
+fn visit_transcripts_parquet<F>(
+    transcript_file: &str,
+    columns: &TranscriptColumns,
+    visitor: &mut F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Transcript) -> anyhow::Result<()>,
+{
+    let reader = SerializedFileReader::new(std::fs::File::open(transcript_file)?)?;
+    let schema = reader.metadata().file_metadata().schema();
+
+    // read only the columns needed
+    let names: Vec<Box<str>> = schema
+        .get_fields()
+        .iter()
+        .map(|f| f.name().into())
+        .collect();
+    let (gene, x, y, cell, qv) = column_positions(&names, columns, transcript_file)?;
+
+    let mut selected = vec![gene, x, y];
+    selected.extend(cell.iter().chain(qv.iter()));
+    selected.sort();
+    selected.dedup();
+
+    let fields: Vec<Arc<Type>> = selected
+        .iter()
+        .map(|&j| schema.get_fields()[j].clone())
+        .collect();
+    let projection = Type::group_type_builder(schema.name())
+        .with_fields(fields)
+        .build()?;
+    let pos = |j: usize| selected.iter().position(|&k| k == j).expect("selected");
+    let (gene, x, y) = (pos(gene), pos(x), pos(y));
+    let (cell, qv) = (cell.map(pos), qv.map(pos));
+
+    let mut ntot = 0;
+    for row in reader.get_row_iter(Some(projection))? {
+        let row = row?;
+        let fields: Vec<&Field> = row.get_column_iter().map(|(_, f)| f).collect();
+
+        let (gene_name, x, y) = match (
+            field_to_str(fields[gene]),
+            field_to_f64(fields[x]),
+            field_to_f64(fields[y]),
+        ) {
+            (Some(gene_name), Some(x), Some(y)) => (gene_name, x, y),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "invalid record {} in {}",
+                    row,
+                    transcript_file
+                ))
+            }
+        };
+
+        visitor(Transcript {
+            gene: gene_name,
+            x,
+            y,
+            cell: cell.and_then(|j| field_to_str(fields[j])),
+            qv: qv.and_then(|j| field_to_f64(fields[j])),
+        })?;
+        ntot += 1;
+    }
+    Ok(ntot)
+}
+
+fn visit_transcripts_text<F>(
+    transcript_file: &str,
+    columns: &TranscriptColumns,
+    visitor: &mut F,
+) -> anyhow::Result<usize>
+where
+    F: FnMut(Transcript) -> anyhow::Result<()>,
+{
+    let delim = if transcript_file.contains(".tsv") {
+        '\t'
+    } else {
+        ','
+    };
+
+    let split = |line: &str| -> Vec<Box<str>> {
+        line.split(delim)
+            .map(|x| x.trim().trim_matches('"').into())
+            .collect()
+    };
+
+    let mut lines = open_buf_reader(transcript_file)?.lines();
+
+    let header = match lines.next() {
+        Some(line) => split(&line?),
+        None => return Err(anyhow::anyhow!("empty file {}", transcript_file)),
+    };
+    let (gene, x, y, cell, qv) = column_positions(&header, columns, transcript_file)?;
+
+    let mut ntot = 0;
+    for line in lines {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let words = split(&line);
+
+        let parse = |j: usize| -> anyhow::Result<f64> {
+            words
+                .get(j)
+                .and_then(|x| x.parse::<f64>().ok())
+                .ok_or(anyhow::anyhow!(
+                    "invalid line `{}` in {}",
+                    line,
+                    transcript_file
+                ))
+        };
+
+        visitor(Transcript {
+            gene: words.get(gene).cloned().ok_or(anyhow::anyhow!(
+                "invalid line `{}` in {}",
+                line,
+                transcript_file
+            ))?,
+            x: parse(x)?,
+            y: parse(y)?,
+            cell: cell.and_then(|j| words.get(j).cloned()),
+            qv: qv.map(parse).transpose()?,
+        })?;
+        ntot += 1;
+    }
+    Ok(ntot)
+}
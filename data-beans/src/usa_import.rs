@@ -0,0 +1,320 @@
+use crate::sparse_io::*;
+
+use log::info;
+use matrix_util::common_io::read_lines;
+use matrix_util::mtx_io::read_mtx_triplets;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub const SPLICED_LAYER: &str = "spliced";
+pub const UNSPLICED_LAYER: &str = "unspliced";
+pub const AMBIGUOUS_LAYER: &str = "ambiguous";
+pub const COUNTS_LAYER: &str = "counts";
+
+/// Spliced, unspliced, and ambiguous layers in the order of the
+/// alevin-fry `USA` column blocks
+pub const USA_LAYERS: [&str; 3] = [SPLICED_LAYER, UNSPLICED_LAYER, AMBIGUOUS_LAYER];
+
+type Triplets = Vec<(u64, u64, f32)>;
+
+/// Splice-aware counts of genes (rows) by barcodes (columns) with
+/// one set of triplets per layer
+pub struct UsaCounts {
+    pub row_names: Vec<Box<str>>,
+    pub column_names: Vec<Box<str>>,
+    pub layers: Vec<(Box<str>, Triplets)>,
+}
+
+impl UsaCounts {
+    pub fn num_rows(&self) -> usize {
+        self.row_names.len()
+    }
+
+    pub fn num_columns(&self) -> usize {
+        self.column_names.len()
+    }
+
+    pub fn layer_names(&self) -> Vec<Box<str>> {
+        self.layers.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Sum up the triplets of the `layers` element-wise
+    /// * `layers` - names of the layers to sum
+    pub fn summed_triplets(&self, layers: &[Box<str>]) -> anyhow::Result<Triplets> {
+        let mut ret = vec![];
+        for name in layers {
+            let (_, triplets) = self
+                .layers
+                .iter()
+                .find(|(x, _)| x == name)
+                .ok_or(anyhow::anyhow!("no `{}` layer in the input", name))?;
+            ret.extend(triplets.iter().copied());
+        }
+
+        ret.par_sort_by_key(|&(i, j, _)| (j, i));
+        let mut summed: Triplets = Vec::with_capacity(ret.len());
+        for (i, j, x) in ret {
+            match summed.last_mut() {
+                Some(last) if last.0 == i && last.1 == j => last.2 += x,
+                _ => summed.push((i, j, x)),
+            }
+        }
+        summed.retain(|&(_, _, x)| x != 0.);
+        Ok(summed)
+    }
+}
+
+/// Find `name` or `name.gz` within `dir`
+fn find_file(dir: &str, name: &str) -> Option<String> {
+    [name.to_string(), format!("{}.gz", name)]
+        .into_iter()
+        .map(|x| format!("{}/{}", dir, x))
+        .find(|x| Path::new(x).exists())
+}
+
+fn require_file(dir: &str, name: &str) -> anyhow::Result<String> {
+    find_file(dir, name).ok_or(anyhow::anyhow!("no `{}` in {}", name, dir))
+}
+
+/// Read splice-aware counts from an alevin-fry or a kallisto-bustools
+/// output directory
+///
+/// ```text
+/// alevin-fry (USA mode):
+/// {dir}/alevin/quants_mat.mtx
+/// {dir}/alevin/quants_mat_rows.txt
+/// {dir}/alevin/quants_mat_cols.txt
+///
+/// kb (velocity):
+/// {dir}/spliced.{mtx,barcodes.txt,genes.txt}
+/// {dir}/unspliced.{mtx,barcodes.txt,genes.txt}
+/// {dir}/ambiguous.{mtx,barcodes.txt,genes.txt}    (optional)
+/// ```
+///
+/// * `dir` - output directory of the quantification
+pub fn read_usa_counts(dir: &str) -> anyhow::Result<UsaCounts> {
+    let fry_dir = [format!("{}/alevin", dir), dir.to_string()]
+        .into_iter()
+        .find(|x| find_file(x, "quants_mat.mtx").is_some());
+
+    if let Some(fry_dir) = fry_dir {
+        info!("reading alevin-fry USA-mode output in {}", &fry_dir);
+        read_alevin_fry_usa(&fry_dir)
+    } else if find_file(dir, &format!("{}.mtx", SPLICED_LAYER)).is_some() {
+        info!("reading kb spliced/unspliced output in {}", dir);
+        read_kb_splice_layers(dir)
+    } else {
+        Err(anyhow::anyhow!(
+            "neither `quants_mat.mtx` nor `spliced.mtx` found in {}",
+            dir
+        ))
+    }
+}
+
+/// Read alevin-fry `quants_mat.mtx` whose columns are spliced,
+/// unspliced, and ambiguous blocks of the genes
+///
+/// ```text
+/// quants_mat.mtx: cells x [S genes | U genes | A genes]
+/// ```
+///
+/// * `fry_dir` - directory with `quants_mat.mtx`,
+///   `quants_mat_rows.txt` (barcodes), and `quants_mat_cols.txt` (genes)
+pub fn read_alevin_fry_usa(fry_dir: &str) -> anyhow::Result<UsaCounts> {
+    let mtx_file = require_file(fry_dir, "quants_mat.mtx")?;
+    let column_names = read_lines(&require_file(fry_dir, "quants_mat_rows.txt")?)?;
+    let mut row_names = read_lines(&require_file(fry_dir, "quants_mat_cols.txt")?)?;
+
+    let (triplets, shape) = read_mtx_triplets(&mtx_file)?;
+    let (ncell, nfeature, _) = shape.ok_or(anyhow::anyhow!("no shape in {}", &mtx_file))?;
+
+    if ncell != column_names.len() {
+        return Err(anyhow::anyhow!(
+            "{} rows in {}, but {} barcodes",
+            ncell,
+            &mtx_file,
+            column_names.len()
+        ));
+    }
+
+    if nfeature % USA_LAYERS.len() != 0 {
+        return Err(anyhow::anyhow!(
+            "{} columns in {} can't be split into USA blocks",
+            nfeature,
+            &mtx_file
+        ));
+    }
+
+    let ngene = nfeature / USA_LAYERS.len();
+
+    // the gene list may or may not repeat for each block
+    if row_names.len() == nfeature {
+        row_names.truncate(ngene);
+    } else if row_names.len() != ngene {
+        return Err(anyhow::anyhow!(
+            "{} genes for {} USA columns in {}",
+            row_names.len(),
+            nfeature,
+            &mtx_file
+        ));
+    }
+
+    let mut layers: Vec<Triplets> = vec![vec![]; USA_LAYERS.len()];
+    for (cell, feature, x) in triplets {
+        let (block, gene) = (feature as usize / ngene, feature % ngene as u64);
+        layers[block].push((gene, cell, x));
+    }
+
+    Ok(UsaCounts {
+        row_names,
+        column_names,
+        layers: USA_LAYERS
+            .iter()
+            .map(|&x| Box::from(x))
+            .zip(layers)
+            .collect(),
+    })
+}
+
+/// Read kb `{layer}.mtx` sets and align them to the union of their
+/// barcodes and genes. `spliced` and `unspliced` are required while
+/// `ambiguous` is optional.
+///
+/// ```text
+/// {layer}.mtx: cells x genes
+/// {layer}.barcodes.txt
+/// {layer}.genes.txt
+/// ```
+///
+/// * `dir` - directory with the `{layer}` file sets
+pub fn read_kb_splice_layers(dir: &str) -> anyhow::Result<UsaCounts> {
+    let mut row_pos: HashMap<Box<str>, u64> = HashMap::new();
+    let mut column_pos: HashMap<Box<str>, u64> = HashMap::new();
+    let mut row_names = vec![];
+    let mut column_names = vec![];
+    let mut layers = vec![];
+
+    for &layer in USA_LAYERS.iter() {
+        let mtx_file = match find_file(dir, &format!("{}.mtx", layer)) {
+            Some(x) => x,
+            None if layer == AMBIGUOUS_LAYER => continue,
+            None => return Err(anyhow::anyhow!("no `{}.mtx` in {}", layer, dir)),
+        };
+
+        let barcodes = read_lines(&require_file(dir, &format!("{}.barcodes.txt", layer))?)?;
+        let genes = read_lines(&require_file(dir, &format!("{}.genes.txt", layer))?)?;
+
+        let (triplets, shape) = read_mtx_triplets(&mtx_file)?;
+        let (nrow, ncol, _) = shape.ok_or(anyhow::anyhow!("no shape in {}", &mtx_file))?;
+
+        let cells_as_rows = match (nrow, ncol) {
+            (nr, nc) if nr == barcodes.len() && nc == genes.len() => true,
+            (nr, nc) if nr == genes.len() && nc == barcodes.len() => false,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "{} x {} in {}, but {} barcodes and {} genes",
+                    nrow,
+                    ncol,
+                    &mtx_file,
+                    barcodes.len(),
+                    genes.len()
+                ))
+            }
+        };
+
+        let genes: Vec<u64> = genes
+            .into_iter()
+            .map(|x| {
+                let n = row_pos.len() as u64;
+                *row_pos.entry(x.clone()).or_insert_with(|| {
+                    row_names.push(x);
+                    n
+                })
+            })
+            .collect();
+
+        let barcodes: Vec<u64> = barcodes
+            .into_iter()
+            .map(|x| {
+                let n = column_pos.len() as u64;
+                *column_pos.entry(x.clone()).or_insert_with(|| {
+                    column_names.push(x);
+                    n
+                })
+            })
+            .collect();
+
+        let triplets: Triplets = triplets
+            .into_iter()
+            .map(|(i, j, x)| {
+                let (cell, gene) = if cells_as_rows { (i, j) } else { (j, i) };
+                (genes[gene as usize], barcodes[cell as usize], x)
+            })
+            .collect();
+
+        info!("{}: {} non-zeros", &mtx_file, triplets.len());
+        layers.push((Box::from(layer), triplets));
+    }
+
+    Ok(UsaCounts {
+        row_names,
+        column_names,
+        layers,
+    })
+}
+
+/// Create a backend whose main matrix holds the counts summed over
+/// `sum_layers` and whose named layers hold the splice-aware counts
+/// sharing the same rows and columns
+///
+/// * `usa` - splice-aware counts
+/// * `sum_layers` - layers to sum up for the main matrix (all if `None`)
+/// * `counts_layer` - also keep the summed counts as the `counts` layer
+/// * `backend_file` - file path to the backend
+/// * `backend` - backend type
+/// * `options` - zarr layout and the type of the stored values
+pub fn create_sparse_from_usa(
+    usa: UsaCounts,
+    sum_layers: Option<&[Box<str>]>,
+    counts_layer: bool,
+    backend_file: Option<&str>,
+    backend: Option<&SparseIoBackend>,
+    options: &SparseCreateOptions,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let sum_layers = match sum_layers {
+        Some(x) => x.to_vec(),
+        None => usa.layer_names(),
+    };
+    let summed = usa.summed_triplets(&sum_layers)?;
+    let shape = (usa.num_rows(), usa.num_columns(), summed.len());
+
+    if summed.is_empty() {
+        return Err(anyhow::anyhow!("no counts in the {:?} layers", sum_layers));
+    }
+
+    let mut data = create_sparse_from_triplets_with_options(
+        summed.clone(),
+        shape,
+        backend_file,
+        backend,
+        options,
+    )?;
+
+    data.register_row_names_vec(&usa.row_names);
+    data.register_column_names_vec(&usa.column_names);
+
+    if counts_layer {
+        data.register_layer_triplets(COUNTS_LAYER, &mut summed.clone())?;
+    }
+
+    for (name, mut triplets) in usa.layers {
+        if triplets.is_empty() {
+            info!("skipping the empty `{}` layer", name);
+            continue;
+        }
+        data.register_layer_triplets(&name, &mut triplets)?;
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,330 @@
+use crate::external_sort::*;
+use crate::sparse_io::*;
+
+use log::info;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// A single integrity check on the main matrix or a layer
+#[derive(Clone, Debug)]
+pub struct ValidationCheck {
+    /// `None` for the main matrix, otherwise the name of the layer
+    pub layer: Option<Box<str>>,
+    /// what has been checked, e.g., `by_row`
+    pub name: Box<str>,
+    /// `None` if passed, otherwise what went wrong
+    pub error: Option<Box<str>>,
+}
+
+impl ValidationCheck {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    pub fn target(&self) -> String {
+        match self.layer.as_ref() {
+            Some(layer) => format!("layers/{}", layer),
+            None => "main".to_string(),
+        }
+    }
+}
+
+/// Structured outcome of [`validate_backend`]
+///
+/// ```text
+/// ok      main            shape
+/// ok      main            row_names
+/// ok      main            by_column
+/// FAIL    main            by_row          `indptr` decreases at 3
+/// FAIL    main            csr_csc         cannot compare
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    pub checks: Vec<ValidationCheck>,
+}
+
+impl ValidationReport {
+    /// All the checks passed
+    pub fn is_valid(&self) -> bool {
+        self.checks.iter().all(|c| c.passed())
+    }
+
+    /// Checks that did not pass
+    pub fn failures(&self) -> Vec<&ValidationCheck> {
+        self.checks.iter().filter(|c| !c.passed()).collect()
+    }
+
+    /// Main matrix (`None`) or layers whose CSR side is broken while
+    /// the CSC side is intact, hence can be rebuilt from the CSC side
+    pub fn repairable_layers(&self) -> Vec<Option<Box<str>>> {
+        let mut ret: Vec<Option<Box<str>>> = vec![];
+        for check in self.checks.iter() {
+            if check.passed() || !matches!(check.name.as_ref(), "by_row" | "csr_csc") {
+                continue;
+            }
+
+            let csc_ok = self
+                .checks
+                .iter()
+                .any(|c| c.layer == check.layer && c.name.as_ref() == "by_column" && c.passed());
+
+            if csc_ok && !ret.contains(&check.layer) {
+                ret.push(check.layer.clone());
+            }
+        }
+        ret
+    }
+
+    fn record(&mut self, layer: Option<&str>, name: &str, result: anyhow::Result<()>) {
+        self.checks.push(ValidationCheck {
+            layer: layer.map(Box::from),
+            name: name.into(),
+            error: result.err().map(|e| e.to_string().into_boxed_str()),
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.checks.iter() {
+            let status = if check.passed() { "ok" } else { "FAIL" };
+            write!(f, "{}\t{}\t{}", status, check.target(), check.name)?;
+            if let Some(error) = check.error.as_ref() {
+                write!(f, "\t{}", error)?;
+            }
+            writeln!(f)?;
+        }
+        write!(
+            f,
+            "{} of {} checks failed",
+            self.failures().len(),
+            self.checks.len()
+        )
+    }
+}
+
+/// Check the integrity of the main matrix and all the layers
+///
+/// * `nrow`, `ncol`, `nnz` attributes
+/// * lengths of row and column names
+/// * monotone `indptr` under `by_column` and `by_row`
+/// * in-range and sorted indices within each column (row)
+/// * CSR and CSC sides hold the same number of triplets and the
+///   same multiset of triplets (order-independent checksum)
+///
+/// Failures are collected in the report rather than returned as
+/// errors.
+pub fn validate_backend<T>(data: &T) -> anyhow::Result<ValidationReport>
+where
+    T: SparseIo + ?Sized,
+{
+    let mut report = ValidationReport::default();
+
+    let (nrow, ncol, nnz) = match (data.num_rows(), data.num_columns(), data.num_non_zeros()) {
+        (Some(nrow), Some(ncol), Some(nnz)) => {
+            report.record(None, "shape", Ok(()));
+            (nrow, ncol, nnz)
+        }
+        _ => {
+            report.record(
+                None,
+                "shape",
+                Err(anyhow::anyhow!("missing `nrow`, `ncol`, or `nnz`")),
+            );
+            return Ok(report);
+        }
+    };
+
+    let names_check = |names: anyhow::Result<Vec<Box<str>>>, ntot: usize| {
+        let n = names?.len();
+        if n != ntot {
+            return Err(anyhow::anyhow!("{} names for {} elements", n, ntot));
+        }
+        Ok(())
+    };
+    report.record(None, "row_names", names_check(data.row_names(), nrow));
+    report.record(None, "column_names", names_check(data.column_names(), ncol));
+
+    let mut targets: Vec<Option<Box<str>>> = vec![None];
+    targets.extend(data.layer_names()?.into_iter().map(Some));
+
+    for layer in targets.iter().map(|x| x.as_deref()) {
+        // only the main matrix carries its own `nnz` attribute
+        let nnz = if layer.is_none() { Some(nnz) } else { None };
+
+        let csc = scan_compressed(data, &layer_key(layer, "by_column"), ncol, nrow, nnz, true);
+        let csr = scan_compressed(data, &layer_key(layer, "by_row"), nrow, ncol, nnz, false);
+
+        let consistent = match (csc.as_ref(), csr.as_ref()) {
+            (Ok(csc), Ok(csr)) if csc.0 != csr.0 => Err(anyhow::anyhow!(
+                "{} triplets by column, but {} by row",
+                csc.0,
+                csr.0
+            )),
+            (Ok(csc), Ok(csr)) if csc.1 != csr.1 => Err(anyhow::anyhow!(
+                "checksums differ: {:x} vs. {:x}",
+                csc.1,
+                csr.1
+            )),
+            (Ok(_), Ok(_)) => Ok(()),
+            _ => Err(anyhow::anyhow!("cannot compare")),
+        };
+
+        report.record(layer, "by_column", csc.map(|_| ()));
+        report.record(layer, "by_row", csr.map(|_| ()));
+        report.record(layer, "csr_csc", consistent);
+    }
+
+    Ok(report)
+}
+
+/// Rebuild the CSR side (`by_row`) of the main matrix or a layer
+/// from its CSC side (`by_column`) within the memory budget
+///
+/// * `layer` - `None` for the main matrix
+/// * `memory_budget` - bytes of triplets to hold in memory before spilling
+pub fn repair_csr_from_csc<T>(
+    data: &mut T,
+    layer: Option<&str>,
+    memory_budget: usize,
+) -> anyhow::Result<()>
+where
+    T: SparseIo + ?Sized,
+{
+    let nrow = data
+        .num_rows()
+        .ok_or(anyhow::anyhow!("should have `nrow`"))?;
+
+    let column_key = layer_key(layer, "by_column");
+    let indptr = data.read_compressed_indptr_backend(&column_key)?;
+    let nnz = *indptr
+        .last()
+        .ok_or(anyhow::anyhow!("empty `{}/indptr`", column_key))? as usize;
+
+    let mut by_row = ExternalSorter::with_temp_dir(SortOrder::ByRow, memory_budget)?;
+    let mut col = 0;
+
+    for lb in (0..nnz).step_by(STREAM_CHUNK_SIZE) {
+        let ub = (lb + STREAM_CHUNK_SIZE).min(nnz);
+        let (indices, values) = data.read_compressed_chunk_backend(&column_key, lb..ub)?;
+        for (pos, (&row, &x)) in (lb..ub).zip(indices.iter().zip(values.iter())) {
+            while indptr[col + 1] as usize <= pos {
+                col += 1;
+            }
+            by_row.push((row, col as u64, x))?;
+        }
+    }
+
+    let row_key = layer_key(layer, "by_row");
+    info!("rebuilding {} triplets under {}", nnz, row_key);
+    let mut by_row = by_row.into_sorted()?;
+    let value_type = data.value_type().unwrap_or(ValueType::F32);
+    data.record_sorted_triplets_backend(&row_key, nrow, nnz, value_type, &mut by_row)
+}
+
+/// Stream through a compressed (CSC or CSR) group, checking its
+/// structure, and return (number of triplets, checksum)
+///
+/// * `key` - `/by_column` or `/by_row` (of a layer)
+/// * `nouter` - number of columns (CSC) or rows (CSR)
+/// * `ninner` - number of rows (CSC) or columns (CSR)
+/// * `nnz` - expected number of triplets if known
+/// * `outer_is_column` - CSC if true, CSR otherwise
+fn scan_compressed<T>(
+    data: &T,
+    key: &str,
+    nouter: usize,
+    ninner: usize,
+    nnz: Option<usize>,
+    outer_is_column: bool,
+) -> anyhow::Result<(usize, u64)>
+where
+    T: SparseIo + ?Sized,
+{
+    let indptr = data.read_compressed_indptr_backend(key)?;
+
+    if indptr.len() != nouter + 1 {
+        return Err(anyhow::anyhow!(
+            "`indptr` has {} elements, expected {}",
+            indptr.len(),
+            nouter + 1
+        ));
+    }
+
+    if indptr[0] != 0 {
+        return Err(anyhow::anyhow!("`indptr` starts at {}", indptr[0]));
+    }
+
+    if let Some(k) = indptr.windows(2).position(|w| w[0] > w[1]) {
+        return Err(anyhow::anyhow!("`indptr` decreases at {}", k + 1));
+    }
+
+    let ntot = indptr[nouter] as usize;
+
+    if let Some(nnz) = nnz {
+        if ntot != nnz {
+            return Err(anyhow::anyhow!(
+                "`indptr` ends at {}, but nnz = {}",
+                ntot,
+                nnz
+            ));
+        }
+    }
+
+    let (nindices, nvalues) = data.compressed_dataset_len_backend(key)?;
+    if nindices != ntot || nvalues != ntot {
+        return Err(anyhow::anyhow!(
+            "{} indices and {} values for {} triplets",
+            nindices,
+            nvalues,
+            ntot
+        ));
+    }
+
+    let mut checksum = 0_u64;
+    let mut outer = 0;
+    let mut prev: Option<u64> = None;
+
+    for lb in (0..ntot).step_by(STREAM_CHUNK_SIZE) {
+        let ub = (lb + STREAM_CHUNK_SIZE).min(ntot);
+        let (indices, values) = data.read_compressed_chunk_backend(key, lb..ub)?;
+
+        for (pos, (&inner, &x)) in (lb..ub).zip(indices.iter().zip(values.iter())) {
+            while indptr[outer + 1] as usize <= pos {
+                outer += 1;
+                prev = None;
+            }
+
+            if inner as usize >= ninner {
+                return Err(anyhow::anyhow!(
+                    "index {} out of range [0, {}) at {}",
+                    inner,
+                    ninner,
+                    pos
+                ));
+            }
+
+            if prev.is_some_and(|p| p >= inner) {
+                return Err(anyhow::anyhow!("indices not sorted within {}", outer));
+            }
+            prev = Some(inner);
+
+            let (row, col) = if outer_is_column {
+                (inner, outer as u64)
+            } else {
+                (outer as u64, inner)
+            };
+            checksum = checksum.wrapping_add(triplet_hash(row, col, x));
+        }
+    }
+
+    Ok((ntot, checksum))
+}
+
+/// hash of a triplet to be summed up regardless of the order
+fn triplet_hash(row: u64, col: u64, x: f32) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (row, col, x.to_bits()).hash(&mut hasher);
+    hasher.finish()
+}
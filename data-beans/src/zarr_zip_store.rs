@@ -0,0 +1,354 @@
+use log::{info, warn};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tempfile::TempDir;
+use zarrs::filesystem::FilesystemStore;
+use zarrs::storage::byte_range::{extract_byte_ranges, ByteRange};
+use zarrs::storage::{
+    store_set_partial_values, Bytes, ListableStorageTraits, MaybeBytes, ReadableStorageTraits,
+    StorageError, StoreKey, StoreKeyOffsetValue, StoreKeys, StoreKeysPrefixes, StorePrefix,
+    WritableStorageTraits,
+};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Suffix of a zarr store packed in a single zip archive
+pub const ZARR_ZIP_SUFFIX: &str = ".zarr.zip";
+
+/// Check if the file name points to a zarr store in a zip archive
+/// * `file` - file name, e.g., `data.zarr.zip`
+pub fn is_zarr_zip(file: &str) -> bool {
+    file.ends_with(ZARR_ZIP_SUFFIX)
+}
+
+/// A zarr store kept in a single zip archive (`.zarr.zip`)
+///
+/// ```text
+/// data.zarr.zip
+///     ├── zarr.json
+///     ├── by_column/...
+///     └── by_row/...
+/// ```
+///
+/// Values are read directly from the archive. Writes go to a
+/// staging directory next to the archive, created on the first
+/// write and shadowing the archived values, and the archive is
+/// rewritten (stored, not compressed, since the chunks are
+/// compressed already) by `flush`, or when the store is dropped
+/// as a last resort.
+pub struct ZipStore {
+    zip_file: PathBuf,
+    archive: Option<Mutex<ZipArchive<File>>>,
+    archive_keys: HashSet<String>,
+    staging: OnceLock<(TempDir, FilesystemStore)>,
+    shadowed: Mutex<HashSet<String>>,
+    dirty: AtomicBool,
+    discarded: AtomicBool,
+}
+
+impl ZipStore {
+    /// Open the zip archive `zip_file` as a zarr store; a new empty
+    /// store if the archive doesn't exist yet
+    /// * `zip_file` - e.g., `data.zarr.zip`
+    pub fn new(zip_file: &str) -> anyhow::Result<Self> {
+        let zip_file = PathBuf::from(zip_file);
+
+        let (archive, archive_keys) = if zip_file.is_file() {
+            let archive = ZipArchive::new(File::open(&zip_file)?)?;
+            let keys = archive
+                .file_names()
+                .filter(|x| !x.ends_with('/'))
+                .map(|x| x.to_string())
+                .collect::<HashSet<_>>();
+            (Some(Mutex::new(archive)), keys)
+        } else {
+            (None, HashSet::new())
+        };
+
+        Ok(Self {
+            zip_file,
+            archive,
+            archive_keys,
+            staging: OnceLock::new(),
+            shadowed: Mutex::new(HashSet::new()),
+            dirty: AtomicBool::new(false),
+            discarded: AtomicBool::new(false),
+        })
+    }
+
+    /// Rewrite the zip archive if anything has changed
+    pub fn flush(&self) -> anyhow::Result<()> {
+        if self.discarded.load(Ordering::SeqCst) || !self.dirty.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let keys = self.list()?;
+        let partial = self.zip_file.with_extension("zip.partial");
+        write_zip(&partial, &keys, |key| self.get(key))?;
+        std::fs::rename(&partial, &self.zip_file)?;
+
+        self.dirty.store(false, Ordering::SeqCst);
+        info!("wrote {} keys to {}", keys.len(), self.zip_file.display());
+        Ok(())
+    }
+
+    /// Forget all the changes, e.g., after the archive was removed
+    pub fn discard(&self) {
+        self.discarded.store(true, Ordering::SeqCst);
+    }
+
+    /// The staging directory, if anything has been written yet
+    fn staged(&self) -> Option<&FilesystemStore> {
+        self.staging.get().map(|(_, store)| store)
+    }
+
+    /// The staging directory next to the archive, created on the
+    /// first write so that read-only uses need no write permission
+    fn staging(&self) -> Result<&FilesystemStore, StorageError> {
+        if let Some(store) = self.staged() {
+            return Ok(store);
+        }
+
+        let parent = match self.zip_file.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        std::fs::create_dir_all(&parent)?;
+
+        let staging_dir = tempfile::Builder::new()
+            .prefix(".zarr-zip-staging")
+            .tempdir_in(parent)?;
+        let store = FilesystemStore::new(staging_dir.path())
+            .map_err(|e| StorageError::from(e.to_string()))?;
+        let _ = self.staging.set((staging_dir, store));
+        Ok(self.staged().expect("staging directory"))
+    }
+
+    fn is_shadowed(&self, key: &str) -> bool {
+        !self.archive_keys.contains(key)
+            || self
+                .shadowed
+                .lock()
+                .expect("failed to lock shadowed keys")
+                .contains(key)
+    }
+
+    fn shadow(&self, keys: impl Iterator<Item = String>) {
+        self.shadowed
+            .lock()
+            .expect("failed to lock shadowed keys")
+            .extend(keys);
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+
+    fn read_archived(&self, key: &str) -> Result<Vec<u8>, StorageError> {
+        let archive = self
+            .archive
+            .as_ref()
+            .ok_or(StorageError::from("no zip archive"))?;
+        let mut archive = archive.lock().expect("failed to lock zip archive");
+        let mut entry = archive
+            .by_name(key)
+            .map_err(|e| StorageError::from(e.to_string()))?;
+        let mut ret = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut ret)?;
+        Ok(ret)
+    }
+}
+
+impl Drop for ZipStore {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("failed to write {}: {}", self.zip_file.display(), e);
+        }
+    }
+}
+
+impl ReadableStorageTraits for ZipStore {
+    fn get_partial_values_key(
+        &self,
+        key: &StoreKey,
+        byte_ranges: &[ByteRange],
+    ) -> Result<Option<Vec<Bytes>>, StorageError> {
+        if self.is_shadowed(key.as_str()) {
+            return match self.staged() {
+                Some(staging) => staging.get_partial_values_key(key, byte_ranges),
+                None => Ok(None),
+            };
+        }
+
+        let bytes = self.read_archived(key.as_str())?;
+        let ret = extract_byte_ranges(&bytes, byte_ranges)
+            .map_err(|e| StorageError::from(e.to_string()))?;
+        Ok(Some(ret.into_iter().map(Bytes::from).collect()))
+    }
+
+    fn size_key(&self, key: &StoreKey) -> Result<Option<u64>, StorageError> {
+        if self.is_shadowed(key.as_str()) {
+            return match self.staged() {
+                Some(staging) => staging.size_key(key),
+                None => Ok(None),
+            };
+        }
+
+        let archive = self
+            .archive
+            .as_ref()
+            .ok_or(StorageError::from("no zip archive"))?;
+        let mut archive = archive.lock().expect("failed to lock zip archive");
+        let entry = archive
+            .by_name(key.as_str())
+            .map_err(|e| StorageError::from(e.to_string()))?;
+        Ok(Some(entry.size()))
+    }
+}
+
+impl WritableStorageTraits for ZipStore {
+    fn set(&self, key: &StoreKey, value: Bytes) -> Result<(), StorageError> {
+        self.staging()?.set(key, value)?;
+        self.shadow(std::iter::once(key.to_string()));
+        Ok(())
+    }
+
+    fn set_partial_values(
+        &self,
+        key_offset_values: &[StoreKeyOffsetValue],
+    ) -> Result<(), StorageError> {
+        store_set_partial_values(self, key_offset_values)
+    }
+
+    fn erase(&self, key: &StoreKey) -> Result<(), StorageError> {
+        if let Some(staging) = self.staged() {
+            staging.erase(key)?;
+        }
+        self.shadow(std::iter::once(key.to_string()));
+        Ok(())
+    }
+
+    fn erase_prefix(&self, prefix: &StorePrefix) -> Result<(), StorageError> {
+        if let Some(staging) = self.staged() {
+            staging.erase_prefix(prefix)?;
+        }
+        let archived = self
+            .archive_keys
+            .iter()
+            .filter(|x| x.starts_with(prefix.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.shadow(archived.into_iter());
+        Ok(())
+    }
+}
+
+impl ListableStorageTraits for ZipStore {
+    fn list(&self) -> Result<StoreKeys, StorageError> {
+        self.list_prefix(&StorePrefix::root())
+    }
+
+    fn list_prefix(&self, prefix: &StorePrefix) -> Result<StoreKeys, StorageError> {
+        let mut keys: BTreeSet<String> = {
+            let shadowed = self.shadowed.lock().expect("failed to lock shadowed keys");
+            self.archive_keys
+                .iter()
+                .filter(|x| x.starts_with(prefix.as_str()) && !shadowed.contains(*x))
+                .cloned()
+                .collect()
+        };
+
+        if let Some(staging) = self.staged() {
+            keys.extend(
+                staging
+                    .list_prefix(prefix)?
+                    .into_iter()
+                    .map(|x| x.to_string()),
+            );
+        }
+
+        keys.into_iter()
+            .map(|x| StoreKey::new(x).map_err(|e| StorageError::from(e.to_string())))
+            .collect()
+    }
+
+    fn list_dir(&self, prefix: &StorePrefix) -> Result<StoreKeysPrefixes, StorageError> {
+        let mut keys = vec![];
+        let mut prefixes = BTreeSet::new();
+
+        for key in self.list_prefix(prefix)? {
+            let rest = &key.as_str()[prefix.as_str().len()..];
+            match rest.find('/') {
+                Some(pos) => {
+                    prefixes.insert(format!("{}{}", prefix.as_str(), &rest[..=pos]));
+                }
+                None => keys.push(key),
+            }
+        }
+
+        let prefixes = prefixes
+            .into_iter()
+            .map(|x| StorePrefix::new(x).map_err(|e| StorageError::from(e.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(StoreKeysPrefixes::new(keys, prefixes))
+    }
+
+    fn size_prefix(&self, prefix: &StorePrefix) -> Result<u64, StorageError> {
+        let mut ret = 0;
+        for key in self.list_prefix(prefix)? {
+            ret += self.size_key(&key)?.unwrap_or(0);
+        }
+        Ok(ret)
+    }
+}
+
+/// Pack a zarr directory into a single zip archive
+/// * `zarr_dir` - e.g., `data.zarr`
+/// * `zip_file` - e.g., `data.zarr.zip`
+pub fn pack_zarr_directory(zarr_dir: &str, zip_file: &str) -> anyhow::Result<()> {
+    if !Path::new(zarr_dir).is_dir() {
+        return Err(anyhow::anyhow!("not a zarr directory: {}", zarr_dir));
+    }
+
+    let store = FilesystemStore::new(zarr_dir)?;
+    let keys = store.list()?;
+    write_zip(Path::new(zip_file), &keys, |key| store.get(key))?;
+    info!("packed {} keys into {}", keys.len(), zip_file);
+    Ok(())
+}
+
+/// Unpack a zip archive into a zarr directory
+/// * `zip_file` - e.g., `data.zarr.zip`
+/// * `zarr_dir` - e.g., `data.zarr`
+pub fn unpack_zarr_zip(zip_file: &str, zarr_dir: &str) -> anyhow::Result<()> {
+    let mut archive = ZipArchive::new(File::open(zip_file)?)?;
+    std::fs::create_dir_all(zarr_dir)?;
+    archive.extract(zarr_dir)?;
+    info!("unpacked {} entries into {}", archive.len(), zarr_dir);
+    Ok(())
+}
+
+/// Write the values of `keys` into a new zip archive without
+/// compression
+fn write_zip<F>(zip_file: &Path, keys: &[StoreKey], get: F) -> anyhow::Result<()>
+where
+    F: Fn(&StoreKey) -> Result<MaybeBytes, StorageError>,
+{
+    let mut writer = ZipWriter::new(File::create(zip_file)?);
+
+    for key in keys {
+        let Some(value) = get(key)? else {
+            continue;
+        };
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(value.len() as u64 >= u32::MAX as u64);
+        writer.start_file(key.as_str(), options)?;
+        writer.write_all(&value)?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
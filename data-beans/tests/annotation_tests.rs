@@ -0,0 +1,135 @@
+use data_beans::annotation::read_annotation_table;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::SparseIoVec;
+use matrix_util::common_io::{create_temp_dir_file, remove_file, write_lines};
+use matrix_util::traits::SampleOps;
+use std::sync::Arc;
+
+fn toy_data(
+    backend: &SparseIoBackend,
+    ncol: usize,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let x = Array2::<f32>::runif(4, ncol);
+    let backend_file = match backend {
+        SparseIoBackend::Memory => None,
+        _ => {
+            let header = create_temp_dir_file("")?;
+            let header = header.to_str().expect("to_str failed");
+            Some(backend_output_file(header, backend)?)
+        }
+    };
+    let mut data = create_sparse_from_ndarray(&x, backend_file.as_deref(), Some(backend))?;
+    let rows: Vec<Box<str>> = (0..4).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..ncol).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn annotation_register_retrieve_subset() -> anyhow::Result<()> {
+    for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+        let mut data = toy_data(&backend, 5)?;
+
+        let donor = AnnotationColumn::categorical(&[
+            "b".into(),
+            "a".into(),
+            "b".into(),
+            "c".into(),
+            "a".into(),
+        ]);
+        let depth = AnnotationColumn::Numeric(vec![1., 2., 3., 4., 5.]);
+        let symbol = AnnotationColumn::String(vec!["x".into(), "y".into(), "z".into(), "w".into()]);
+
+        data.register_column_annotation("donor", &donor)?;
+        data.register_column_annotation("depth", &depth)?;
+        data.register_row_annotation("symbol", &symbol)?;
+
+        // wrong length
+        assert!(data
+            .register_column_annotation("bad", &AnnotationColumn::Numeric(vec![0.]))
+            .is_err());
+
+        assert_eq!(
+            data.annotation_names(AnnotationAxis::Obs)?,
+            vec![Box::from("depth"), Box::from("donor")]
+        );
+        assert_eq!(data.column_annotation("donor")?, donor);
+        assert_eq!(data.column_annotation("depth")?, depth);
+        assert_eq!(data.row_annotation("symbol")?, symbol);
+
+        // replace
+        let depth = AnnotationColumn::Numeric(vec![5., 4., 3., 2., 1.]);
+        data.register_column_annotation("depth", &depth)?;
+        assert_eq!(data.column_annotation("depth")?, depth);
+
+        // annotations follow the subset
+        data.subset_columns_rows(Some(&vec![3, 0]), Some(&vec![2, 1]))?;
+        assert_eq!(
+            data.column_annotation("donor")?.to_strings(),
+            vec![Box::from("c"), Box::from("b")]
+        );
+        assert_eq!(
+            data.column_annotation("depth")?,
+            AnnotationColumn::Numeric(vec![2., 5.])
+        );
+        assert_eq!(
+            data.row_annotation("symbol")?,
+            AnnotationColumn::String(vec!["z".into(), "y".into()])
+        );
+
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn annotation_table_and_concatenation() -> anyhow::Result<()> {
+    let mut data_vec = SparseIoVec::new();
+    let mut backend_files = vec![];
+
+    let table_file = create_temp_dir_file(".tsv.gz")?;
+    let table_file = table_file.to_str().expect("to_str failed");
+    let lines: Vec<Box<str>> = vec![
+        "cell\tdonor\tdepth".into(),
+        "c2\td1\t0.5".into(),
+        "c0\td2\t1.5".into(),
+        "c1\td2\tNA".into(),
+    ];
+    write_lines(&lines, table_file)?;
+
+    for (d, ncol) in [3, 2].into_iter().enumerate() {
+        let mut data = toy_data(&SparseIoBackend::Zarr, ncol)?;
+        let names = data.column_names()?;
+        for (name, column) in read_annotation_table(table_file, &names, &[])? {
+            data.register_column_annotation(&name, &column)?;
+        }
+        backend_files.push(data.get_backend_file_name().to_string());
+        data_vec.push(Arc::from(data), Some(d.to_string().into()))?;
+    }
+
+    let donor = data_vec.column_annotation("donor")?;
+    assert_eq!(
+        donor.to_strings(),
+        ["d2", "d2", "d1", "d2", "d2"]
+            .into_iter()
+            .map(Box::from)
+            .collect::<Vec<Box<str>>>()
+    );
+
+    match data_vec.column_annotation("depth")? {
+        AnnotationColumn::Numeric(depth) => {
+            assert_eq!(depth.len(), 5);
+            assert_eq!(depth[0], 1.5);
+            assert!(depth[1].is_nan());
+            assert_eq!(depth[2], 0.5);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    for backend_file in backend_files {
+        remove_file(&backend_file)?;
+    }
+    remove_file(table_file)?;
+    Ok(())
+}
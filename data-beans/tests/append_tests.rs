@@ -0,0 +1,94 @@
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+fn toy_data(
+    backend: &SparseIoBackend,
+    x: &Array2<f32>,
+    column_prefix: &str,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let backend_file = match backend {
+        SparseIoBackend::Memory => None,
+        _ => {
+            let header = create_temp_dir_file("")?;
+            let header = header.to_str().expect("to_str failed");
+            Some(backend_output_file(header, backend)?)
+        }
+    };
+    let mut data = create_sparse_from_ndarray(x, backend_file.as_deref(), Some(backend))?;
+    let rows: Vec<Box<str>> = (0..x.nrows()).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols())
+        .map(|j| format!("{}{}", column_prefix, j).into())
+        .collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn append_columns_with_layers_and_annotations() -> anyhow::Result<()> {
+    for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+        let x = Array2::<f32>::runif(6, 5);
+        let z = Array2::<f32>::runif(6, 3);
+
+        let mut data = toy_data(&backend, &x, "a")?;
+        let mut other = toy_data(&backend, &z, "b")?;
+
+        data.register_layer_triplets("raw", &mut ndarray_to_triplets(&x.mapv(|v| v * 2.)))?;
+        data.register_column_annotation(
+            "donor",
+            &AnnotationColumn::categorical(&[
+                "d1".into(),
+                "d1".into(),
+                "d2".into(),
+                "d2".into(),
+                "d2".into(),
+            ]),
+        )?;
+
+        // the source should carry the same layers
+        assert!(data.append_columns(other.as_ref(), 1 << 20).is_err());
+
+        other.register_layer_triplets("raw", &mut ndarray_to_triplets(&z.mapv(|v| v * 2.)))?;
+        other.register_column_annotation("size", &AnnotationColumn::Numeric(vec![1., 2., 3.]))?;
+
+        data.append_columns(other.as_ref(), 1 << 20)?;
+
+        let expected = ndarray::concatenate(Axis(1), &[x.view(), z.view()])?;
+        assert_eq!(data.num_columns(), Some(8));
+        assert_eq!(data.num_non_zeros(), Some(48));
+        assert_eq!(data.read_columns_ndarray((0..8).collect())?, expected);
+        assert_eq!(data.read_rows_ndarray((0..6).collect())?, expected);
+
+        let raw = data.open_layer(Some("raw"))?;
+        let expected_raw = expected.mapv(|v| v * 2.);
+        assert_eq!(raw.read_columns_ndarray((0..8).collect())?, expected_raw);
+        assert_eq!(raw.read_rows_ndarray((0..6).collect())?, expected_raw);
+
+        let columns = data.column_names()?;
+        assert_eq!(columns.len(), 8);
+        assert_eq!(columns[4].as_ref(), "a4");
+        assert_eq!(columns[5].as_ref(), "b0");
+
+        // missing annotations are filled with NA
+        let donor = data.column_annotation("donor")?.to_strings();
+        assert_eq!(donor.len(), 8);
+        assert_eq!(donor[4].as_ref(), "d2");
+        assert_eq!(donor[5].as_ref(), "NA");
+
+        let AnnotationColumn::Numeric(size) = data.column_annotation("size")? else {
+            panic!("should be numeric");
+        };
+        assert!(size[0].is_nan());
+        assert_eq!(size[7], 3.);
+
+        // rows should match
+        let mismatched = toy_data(&backend, &Array2::<f32>::runif(5, 2), "c")?;
+        assert!(data.append_columns(mismatched.as_ref(), 1 << 20).is_err());
+
+        mismatched.remove_backend_file()?;
+        other.remove_backend_file()?;
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use data_beans::chunk_cache::*;
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[test]
+fn coalesced_reads_through_cache() -> anyhow::Result<()> {
+    // 6 columns with 0, 3, 5, 2, 4, 6 elements
+    let indptr: Vec<u64> = vec![0, 0, 3, 8, 10, 14, 20];
+    let indices: Vec<u64> = (0..20).collect();
+    let data: Vec<f32> = (0..20).map(|x| x as f32).collect();
+
+    let nloads = AtomicUsize::new(0);
+    let load = |range: std::ops::Range<usize>| {
+        nloads.fetch_add(1, Ordering::SeqCst);
+        Ok((indices[range.clone()].to_vec(), data[range].to_vec()))
+    };
+
+    let cache = ChunkCache::new(1 << 20);
+    let outer = vec![4, 1, 2, 4, 0];
+    let ret = read_compressed_through_cache(&cache, "/by_column", &indptr, &outer, 4, load)?;
+
+    let mut expected = vec![];
+    for (jj, &j) in outer.iter().enumerate() {
+        for k in indptr[j]..indptr[j + 1] {
+            expected.push((k, jj as u64, k as f32));
+        }
+    }
+    assert_eq!(ret, expected);
+
+    // elements [0, 8) and [10, 14) span the blocks 0 to 3: one read
+    assert_eq!(nloads.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.num_bytes(), 16 * 12);
+
+    // everything comes from the cache
+    let ret = read_compressed_through_cache(&cache, "/by_column", &indptr, &outer, 4, load)?;
+    assert_eq!(ret, expected);
+    assert_eq!(nloads.load(Ordering::SeqCst), 1);
+
+    // only the last block is kept within the bound
+    let small = ChunkCache::new(4 * 12);
+    read_compressed_through_cache(&small, "/by_column", &indptr, &[5, 3], 4, load)?;
+    assert_eq!(small.num_bytes(), 4 * 12);
+
+    cache.clear();
+    assert_eq!(cache.num_bytes(), 0);
+    Ok(())
+}
+
+#[test]
+fn default_caches_share_one_bound() -> anyhow::Result<()> {
+    let indptr: Vec<u64> = vec![0, 4, 8];
+    let indices: Vec<u64> = (0..8).collect();
+    let data: Vec<f32> = (0..8).map(|x| x as f32).collect();
+    let load =
+        |range: std::ops::Range<usize>| Ok((indices[range.clone()].to_vec(), data[range].to_vec()));
+
+    let (a, b) = (ChunkCache::default(), ChunkCache::default());
+    assert_eq!(a.max_bytes(), b.max_bytes());
+
+    // the same dataset key of two backends doesn't collide
+    read_compressed_through_cache(&a, "/by_column", &indptr, &[0], 4, load)?;
+    read_compressed_through_cache(&b, "/by_column", &indptr, &[0, 1], 4, load)?;
+    assert_eq!(a.num_bytes(), 4 * 12);
+    assert_eq!(b.num_bytes(), 8 * 12);
+
+    // clearing one keeps the blocks of the other
+    a.clear();
+    assert_eq!(a.num_bytes(), 0);
+    assert_eq!(b.num_bytes(), 8 * 12);
+    b.clear();
+    Ok(())
+}
+
+#[test]
+fn scattered_column_reads() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(50, 300);
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    let options = SparseCreateOptions {
+        zarr: ZarrWriteOptions {
+            chunk_size: Some(64),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let nnz = x.iter().filter(|&&x| x != 0.).count();
+    let mut data = create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (50, 300, nnz),
+        Some(backend_file),
+        Some(&SparseIoBackend::Zarr),
+        &options,
+    )?;
+
+    let rows: Vec<Box<str>> = (0..50).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..300).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let columns = vec![250, 3, 17, 16, 299, 3, 120, 0];
+    let expected = x.select(ndarray::Axis(1), &columns);
+
+    // twice: once from the storage, once from the cache
+    for _ in 0..2 {
+        assert_eq!(data.read_columns_ndarray(columns.clone())?, expected);
+    }
+
+    // writes should not leave stale blocks behind
+    data.subset_columns_rows(Some(&(0..200).collect()), None)?;
+    let columns = vec![150, 3, 199];
+    let expected = x.select(ndarray::Axis(1), &columns);
+    assert_eq!(data.read_columns_ndarray(columns)?, expected);
+
+    data.remove_backend_file()?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::common_io::{create_temp_dir_file, write_lines};
+use matrix_util::traits::SampleOps;
+use std::sync::Arc;
+
+fn toy_data(
+    x: &Array2<f32>,
+    tag: &str,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut data = create_sparse_from_ndarray(x, None, Some(&SparseIoBackend::Memory))?;
+    let rows: Vec<Box<str>> = (0..x.nrows()).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols())
+        .map(|j| format!("{}{}", tag, j).into())
+        .collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn subset_columns_by_names() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(5, 4);
+    let z = Array2::<f32>::runif(5, 6);
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(toy_data(&x, "x")?), Some("first".into()))?;
+    data_vec.push(Arc::from(toy_data(&z, "z")?), Some("second".into()))?;
+
+    // batches: x0..x3 in `a`, z0..z2 in `b`, z3..z5 in `c`
+    let batches: Vec<Box<str>> = ["a", "a", "a", "a", "b", "b", "b", "c", "c", "c"]
+        .iter()
+        .map(|&b| b.into())
+        .collect();
+    let features = DMatrix::<f32>::runif(3, 10);
+    data_vec.register_batches_dmatrix(&features, &batches)?;
+    data_vec.assign_groups(vec![0, 1, 0, 1, 0, 1, 0, 1, 0, 1], None);
+
+    // with or without the data tag; unknown names are skipped
+    let names: Vec<Box<str>> = ["z4", "x1@first", "nope", "z5@second", "x3"]
+        .iter()
+        .map(|&n| n.into())
+        .collect();
+    let cells = data_vec.columns_by_names(&names)?;
+    assert_eq!(cells, vec![8, 1, 9, 3]);
+
+    let subset = data_vec.subset_columns(&cells)?;
+    assert_eq!(subset.num_columns()?, 4);
+    assert_eq!(subset.parent_columns(), Some(&cells[..]));
+    assert_eq!(subset.num_columns_by_data()?, vec![2, 2]);
+    assert_eq!(
+        subset.column_names()?,
+        vec![
+            Box::from("z4@second"),
+            Box::from("x1@first"),
+            Box::from("z5@second"),
+            Box::from("x3@first")
+        ]
+    );
+
+    // columns and rows read through the original data sets
+    let whole = ndarray::concatenate(Axis(1), &[x.view(), z.view()])?;
+    let expected = whole.select(Axis(1), &cells);
+    assert_eq!(subset.read_columns_ndarray(0..4)?, expected);
+    assert_eq!(subset.read_rows_ndarray(0..5)?, expected);
+
+    // batch `b` is left out; groups keep their indices
+    assert_eq!(subset.num_batches(), 2);
+    let batch_index = subset.batch_name_map().expect("batches");
+    let (a, c) = (batch_index["a"], batch_index["c"]);
+    assert_eq!(batch_index.len(), 2);
+    assert_eq!(subset.get_batch_membership(0..4), vec![c, a, c, a]);
+    assert_eq!(subset.batch_to_columns(a), Some(&vec![1, 3]));
+    assert_eq!(subset.take_groups(), Some(&vec![0, 1, 1, 1]));
+
+    let (y, source, _) = subset.read_matched_columns_dmatrix(0..4, &[a], 1, true)?;
+    assert_eq!(y.nrows(), 5);
+    assert_eq!(source, vec![0, 2]);
+
+    assert!(data_vec.subset_columns(&[1, 1]).is_err());
+    assert!(data_vec.subset_columns(&[10]).is_err());
+    Ok(())
+}
+
+#[test]
+fn select_columns_in_a_file() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(5, 4);
+    let data_vec = || -> anyhow::Result<SparseIoVec> {
+        let mut ret = SparseIoVec::new();
+        ret.push(Arc::from(toy_data(&x, "x")?), Some("first".into()))?;
+        Ok(ret)
+    };
+
+    let (all, cells) = ColumnSelectArgs::default().select_columns(data_vec()?)?;
+    assert_eq!(all.num_columns()?, 4);
+    assert!(cells.is_none());
+
+    let columns_file = create_temp_dir_file(".txt")?;
+    let columns_file = columns_file.to_str().expect("to_str failed");
+    let names: Vec<Box<str>> = vec!["x2".into(), "x0@first".into()];
+    write_lines(&names, columns_file)?;
+
+    let args = ColumnSelectArgs {
+        columns_file: Some(columns_file.into()),
+    };
+    let (subset, cells) = args.select_columns(data_vec()?)?;
+    assert_eq!(cells, Some(vec![2, 0]));
+    assert_eq!(
+        subset.read_columns_ndarray(0..2)?,
+        x.select(Axis(1), &[2, 0])
+    );
+
+    std::fs::remove_file(columns_file)?;
+    Ok(())
+}
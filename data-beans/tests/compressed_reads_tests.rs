@@ -0,0 +1,135 @@
+use data_beans::compressed_parts::*;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+use std::sync::Arc;
+
+#[test]
+fn compressed_parts_assembly() -> anyhow::Result<()> {
+    // 4 columns of a 5 x 4 matrix
+    let indptr: Vec<u64> = vec![0, 2, 2, 5, 6];
+    let indices: Vec<u64> = vec![0, 3, 1, 2, 4, 3];
+    let values: Vec<f32> = vec![1., 2., 3., 4., 5., 6.];
+
+    // out-of-range columns come out empty
+    let csc =
+        CompressedParts::from_slices(&indptr, &indices, &values, vec![3, 9, 0]).into_csc(5, 3)?;
+    assert_eq!(csc.col_offsets(), &[0, 1, 1, 3]);
+    assert_eq!(csc.row_indices(), &[3, 0, 3]);
+    assert_eq!(csc.values(), &[6., 1., 2.]);
+
+    let csc = CompressedParts::from_contiguous(&indptr, 1..3, |range| {
+        assert_eq!(range, 2..5);
+        Ok((indices[range.clone()].to_vec(), values[range].to_vec()))
+    })?
+    .into_csc(5, 2)?;
+    assert_eq!(csc.col_offsets(), &[0, 0, 3]);
+    assert_eq!(csc.values(), &[3., 4., 5.]);
+
+    // unsorted and duplicate indices are summed up
+    let mut parts = CompressedParts::default();
+    parts.append(1, &[2, 0, 2], &[1., 2., 3.]);
+    let csr = parts.into_csr(3, 4)?;
+    assert_eq!(csr.row_offsets(), &[0, 0, 2, 2]);
+    assert_eq!(csr.col_indices(), &[0, 2]);
+    assert_eq!(csr.values(), &[2., 4.]);
+
+    let mut parts = CompressedParts::default();
+    parts.append(0, &[7], &[1.]);
+    assert!(parts.into_csc(5, 1).is_err());
+    Ok(())
+}
+
+#[test]
+fn compressed_reads_match_dense() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(30, 70);
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    let options = SparseCreateOptions {
+        zarr: ZarrWriteOptions {
+            chunk_size: Some(32),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let nnz = x.iter().filter(|&&x| x != 0.).count();
+    let mut zarr = create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (30, 70, nnz),
+        Some(backend_file),
+        Some(&SparseIoBackend::Zarr),
+        &options,
+    )?;
+    let memory = create_sparse_from_ndarray(&x, None, Some(&SparseIoBackend::Memory))?;
+
+    let rows: Vec<Box<str>> = (0..30).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..70).map(|j| format!("c{}", j).into()).collect();
+    zarr.register_row_names_vec(&rows);
+    zarr.register_column_names_vec(&cols);
+
+    let columns = vec![69, 3, 40, 3, 0, 12];
+    let row_vec = vec![29, 0, 17, 17];
+
+    let check = |data: &dyn SparseIo<IndexIter = Vec<usize>>| -> anyhow::Result<()> {
+        let expected = x.select(Axis(1), &columns);
+        let csc = data.read_columns_csc(columns.clone())?;
+        assert_eq!(csc_to_ndarray(&csc)?, expected);
+        assert_eq!(data.read_columns_ndarray(columns.clone())?, expected);
+        assert_eq!(
+            data.read_columns_dmatrix(columns.clone())?,
+            DMatrix::from_row_iterator(30, columns.len(), expected.iter().copied())
+        );
+        let csr = data.read_columns_csr(columns.clone())?;
+        assert_eq!(csr_to_ndarray(&csr)?, expected);
+
+        let csc = data.read_column_range_csc(20..45)?;
+        assert_eq!(csc_to_ndarray(&csc)?, x.slice(s![.., 20..45]));
+
+        let expected = x.select(Axis(0), &row_vec);
+        let csr = data.read_rows_csr(row_vec.clone())?;
+        assert_eq!(csr_to_ndarray(&csr)?, expected);
+        assert_eq!(data.read_rows_ndarray(row_vec.clone())?, expected);
+
+        let csr = data.read_row_range_csr(5..11)?;
+        assert_eq!(csr_to_ndarray(&csr)?, x.slice(s![5..11, ..]));
+        Ok(())
+    };
+
+    check(memory.as_ref())?;
+    check(zarr.as_ref())?;
+    zarr.preload_columns()?;
+    check(zarr.as_ref())?;
+
+    zarr.remove_backend_file()?;
+    Ok(())
+}
+
+#[test]
+fn stitch_columns_across_data() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(10, 6);
+    let z = Array2::<f32>::runif(10, 8);
+
+    let mut data_vec = SparseIoVec::new();
+    for (k, mat) in [&x, &z].into_iter().enumerate() {
+        let mut data = create_sparse_from_ndarray(mat, None, Some(&SparseIoBackend::Memory))?;
+        let rows: Vec<Box<str>> = (0..10).map(|i| format!("g{}", i).into()).collect();
+        let cols: Vec<Box<str>> = (0..mat.ncols())
+            .map(|j| format!("{}_{}", k, j).into())
+            .collect();
+        data.register_row_names_vec(&rows);
+        data.register_column_names_vec(&cols);
+        data_vec.push(Arc::from(data), None)?;
+    }
+
+    // interleave the columns of the two data sets
+    let cells = vec![7, 0, 13, 5, 6, 7];
+    let whole = ndarray::concatenate(Axis(1), &[x.view(), z.view()])?;
+    let expected = whole.select(Axis(1), &cells);
+
+    let csc = data_vec.read_columns_csc(cells.iter().copied())?;
+    assert_eq!(csc_to_ndarray(&csc)?, expected);
+    assert_eq!(data_vec.read_columns_ndarray(cells.into_iter())?, expected);
+    Ok(())
+}
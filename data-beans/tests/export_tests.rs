@@ -0,0 +1,163 @@
+use data_beans::export::*;
+use data_beans::feature_types::*;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::common_io::{create_temp_dir_file, read_lines, write_lines};
+use matrix_util::mtx_io::read_mtx_triplets;
+use matrix_util::traits::SampleOps;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::record::RowAccessor;
+use std::sync::Arc;
+
+/// (data, row names, column names)
+type ToyData = (SparseIoVec, Vec<Box<str>>, Vec<Box<str>>);
+
+fn toy_data(x: &Array2<f32>) -> anyhow::Result<ToyData> {
+    let mut data = create_sparse_from_ndarray(x, None, Some(&SparseIoBackend::Memory))?;
+    let rows: Vec<Box<str>> = (0..x.nrows())
+        .map(|i| format!("ENSG{}_G{}", i, i).into())
+        .collect();
+    let cols: Vec<Box<str>> = (0..x.ncols()).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(data), None)?;
+    Ok((data_vec, rows, cols))
+}
+
+fn sparse_toy(nrow: usize, ncol: usize) -> Array2<f32> {
+    let mut x = Array2::<f32>::runif(nrow, ncol);
+    x.indexed_iter_mut()
+        .filter(|((i, j), _)| (i + j) % 3 == 0)
+        .for_each(|(_, x_ij)| *x_ij = 0.);
+    x
+}
+
+fn temp_output_dir() -> anyhow::Result<String> {
+    let output_dir = create_temp_dir_file(".export")?;
+    Ok(output_dir.to_str().expect("to_str failed").to_string())
+}
+
+#[test]
+fn export_10x_by_blocks() -> anyhow::Result<()> {
+    let x = sparse_toy(7, 11);
+    let (data_vec, rows, cols) = toy_data(&x)?;
+    let output_dir = temp_output_dir()?;
+
+    export_10x(&data_vec, &rows, &cols, None, &output_dir, Some(3))?;
+
+    let (triplets, shape) = read_mtx_triplets(&format!("{}/matrix.mtx.gz", output_dir))?;
+    let nnz = x.iter().filter(|&&x_ij| x_ij != 0.).count();
+    assert_eq!(shape, Some((7, 11, nnz)));
+    assert_eq!(triplets.len(), nnz);
+
+    let mut y = Array2::<f32>::zeros((7, 11));
+    for (i, j, x_ij) in triplets {
+        y[(i as usize, j as usize)] = x_ij;
+    }
+    assert_eq!(y, x);
+
+    let features = read_lines(&format!("{}/features.tsv.gz", output_dir))?;
+    assert_eq!(features.len(), 7);
+    assert_eq!(features[2].as_ref(), "ENSG2\tG2\tGene Expression");
+    assert_eq!(
+        read_lines(&format!("{}/barcodes.tsv.gz", output_dir))?,
+        cols
+    );
+
+    // no block files are left behind
+    let leftover = std::fs::read_dir(&output_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(".matrix-"))
+        .count();
+    assert_eq!(leftover, 0);
+
+    std::fs::remove_dir_all(&output_dir)?;
+    Ok(())
+}
+
+#[test]
+fn export_10x_features_imported_from_10x() -> anyhow::Result<()> {
+    let features: Vec<Box<str>> = vec![
+        "ENSG00000243485\tMIR1302-2HG\tGene Expression".into(),
+        "ENSG00000075624\tACTB\tGene Expression".into(),
+        "ENSG00000288602\tHLA_A\tGene Expression".into(),
+        "CD3\tCD3\tAntibody Capture".into(),
+    ];
+    let features_file = create_temp_dir_file(".tsv.gz")?;
+    let features_file = features_file.to_str().expect("to_str failed");
+    write_lines(&features, features_file)?;
+
+    let x = sparse_toy(4, 5);
+    let mut data = create_sparse_from_ndarray(&x, None, Some(&SparseIoBackend::Memory))?;
+    data.register_row_names_file(features_file);
+    let cols: Vec<Box<str>> = (0..5).map(|j| format!("c{}", j).into()).collect();
+    data.register_column_names_vec(&cols);
+    let rows = data.row_names()?;
+    assert_eq!(rows[1].as_ref(), "ENSG00000075624_ACTB_Gene");
+
+    // without feature types, the trailing words are still dropped
+    assert_eq!(registered_feature_types(data.as_ref())?, None);
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(data.open_layer(None)?), None)?;
+    let output_dir = temp_output_dir()?;
+    export_10x(&data_vec, &rows, &cols, None, &output_dir, None)?;
+    let exported = read_lines(&format!("{}/features.tsv.gz", output_dir))?;
+    assert_eq!(exported[..3], features[..3]);
+    assert_eq!(exported[3].as_ref(), "CD3\tCD3\tGene Expression");
+    std::fs::remove_dir_all(&output_dir)?;
+
+    let feature_types = read_feature_types_tsv(features_file)?.expect("feature types");
+    register_feature_types(data.as_mut(), &feature_types)?;
+    let feature_types = registered_feature_types(data.as_ref())?;
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(data), None)?;
+    let output_dir = temp_output_dir()?;
+    export_10x(
+        &data_vec,
+        &rows,
+        &cols,
+        feature_types.as_deref(),
+        &output_dir,
+        None,
+    )?;
+    assert_eq!(
+        read_lines(&format!("{}/features.tsv.gz", output_dir))?,
+        features
+    );
+
+    assert!(export_10x(&data_vec, &rows, &cols, Some(&rows[..2]), &output_dir, None).is_err());
+
+    std::fs::remove_dir_all(&output_dir)?;
+    std::fs::remove_file(features_file)?;
+    Ok(())
+}
+
+#[test]
+fn export_parquet_by_blocks() -> anyhow::Result<()> {
+    let x = sparse_toy(5, 8);
+    let (data_vec, rows, cols) = toy_data(&x)?;
+    let output_dir = temp_output_dir()?;
+
+    let part_files = export_parquet_long(&data_vec, &rows, &cols, &output_dir, Some(3))?;
+    assert_eq!(part_files.len(), 3);
+
+    let mut y = Array2::<f32>::zeros((5, 8));
+    for part_file in part_files {
+        let reader = SerializedFileReader::new(std::fs::File::open(part_file.as_ref())?)?;
+        for row in reader.get_row_iter(None)? {
+            let row = row?;
+            let (r, c) = (row.get_string(0)?, row.get_string(1)?);
+            let i = rows.iter().position(|name| name.as_ref() == r);
+            let j = cols.iter().position(|name| name.as_ref() == c);
+            y[(i.expect("row"), j.expect("column"))] = row.get_float(2)?;
+        }
+    }
+    assert_eq!(y, x);
+
+    assert!(export_parquet_long(&data_vec, &cols, &cols, &output_dir, None).is_err());
+
+    std::fs::remove_dir_all(&output_dir)?;
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use data_beans::external_sort::*;
+use data_beans::sparse_io::*;
+use matrix_util::common_io::{create_temp_dir_file, remove_file, write_lines};
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn external_sort_merges_spilled_runs() -> anyhow::Result<()> {
+    // large enough to spill more than one run with a tiny budget
+    let (nrow, ncol) = (300, 500);
+    // away from zero, not to drop any in the mtx
+    let whole_mat = Array2::<f32>::runif(nrow, ncol) + 1.;
+
+    let memory = create_sparse_from_ndarray(&whole_mat, None, Some(&SparseIoBackend::Memory))?;
+    let mtx_file = create_temp_dir_file(".mtx.gz")?;
+    let mtx_file = mtx_file.to_str().expect("to_str failed");
+    memory.to_mtx_file(mtx_file)?;
+
+    let mut triplets = ExternalTriplets::new(1)?;
+    let shape = visit_mtx_triplets(mtx_file, &mut |x| triplets.push(x))?;
+    assert_eq!(shape, (nrow, ncol, nrow * ncol));
+    assert_eq!(triplets.len(), nrow * ncol);
+
+    let (by_column, by_row) = triplets.into_sorted()?;
+    let by_column = by_column.collect::<anyhow::Result<Vec<_>>>()?;
+    assert!(by_column
+        .windows(2)
+        .all(|w| (w[0].1, w[0].0) < (w[1].1, w[1].0)));
+    let by_row = by_row.collect::<anyhow::Result<Vec<_>>>()?;
+    assert!(by_row
+        .windows(2)
+        .all(|w| (w[0].0, w[0].1) < (w[1].0, w[1].1)));
+
+    for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+        let data = create_sparse_from_mtx_file_external(
+            mtx_file,
+            None,
+            Some(&backend),
+            1,
+            &SparseCreateOptions::default(),
+        )?;
+        assert_eq!(data.num_non_zeros(), Some(nrow * ncol));
+        assert_eq!(data.read_columns_ndarray((0..ncol).collect())?, whole_mat);
+        assert_eq!(
+            data.read_rows_ndarray(vec![299, 3, 150])?,
+            whole_mat.select(Axis(0), &[299, 3, 150])
+        );
+        data.remove_backend_file()?;
+    }
+
+    remove_file(mtx_file)?;
+    Ok(())
+}
+
+#[test]
+fn external_sort_merges_runs_in_passes() -> anyhow::Result<()> {
+    // seven full runs and a partial one, merged two at a time
+    let ntot = 7 * (1 << 16) + 123;
+    let mut sorter = ExternalSorter::with_temp_dir(SortOrder::ByColumn, 1)?;
+    sorter.set_max_fan_in(2);
+    for k in 0..ntot {
+        let k = (k * 7919) % ntot;
+        sorter.push(((k % 1000) as u64, (k / 1000) as u64, k as f32))?;
+    }
+    assert_eq!(sorter.len(), ntot);
+
+    let sorted = sorter.into_sorted()?.collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(sorted.len(), ntot);
+    assert!(sorted
+        .iter()
+        .enumerate()
+        .all(|(k, &(i, j, x))| (i, j, x) == ((k % 1000) as u64, (k / 1000) as u64, k as f32)));
+    Ok(())
+}
+
+#[test]
+fn malformed_mtx_indices_are_errors() -> anyhow::Result<()> {
+    let mtx_file = create_temp_dir_file(".mtx.gz")?;
+    let mtx_file = mtx_file.to_str().expect("to_str failed");
+
+    for bad in ["0\t1\t1.0", "4\t1\t1.0", "1\t3\t1.0", "1\t-1\t1.0", "1\t2"] {
+        let lines: Vec<Box<str>> = vec![
+            "%%MatrixMarket matrix coordinate real general".into(),
+            "3\t2\t2".into(),
+            "1\t1\t1.0".into(),
+            bad.into(),
+        ];
+        write_lines(&lines, mtx_file)?;
+
+        let err = visit_mtx_triplets(mtx_file, &mut |_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains("line 4"));
+
+        for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+            assert!(create_sparse_from_mtx_file_external(
+                mtx_file,
+                None,
+                Some(&backend),
+                1,
+                &SparseCreateOptions::default()
+            )
+            .is_err());
+        }
+    }
+
+    remove_file(mtx_file)?;
+    Ok(())
+}
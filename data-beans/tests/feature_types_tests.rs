@@ -0,0 +1,97 @@
+use data_beans::feature_types::*;
+use data_beans::sparse_io::*;
+use matrix_util::common_io::{create_temp_dir_file, write_lines};
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn read_feature_types_of_10x_features() -> anyhow::Result<()> {
+    let features_file = create_temp_dir_file(".tsv.gz")?;
+    let features_file = features_file.to_str().expect("to_str failed");
+
+    let lines: Vec<Box<str>> = vec![
+        "ENSG1\tACTB\tGene Expression".into(),
+        "CD3\tCD3_TotalSeqB\tAntibody Capture".into(),
+        "ENSG2\tGAPDH\tGene Expression".into(),
+    ];
+    write_lines(&lines, features_file)?;
+
+    let feature_types = read_feature_types_tsv(features_file)?.expect("feature types");
+    assert_eq!(feature_types[1].as_ref(), "Antibody Capture");
+    assert_eq!(
+        feature_type_tag(&feature_types[1]).as_ref(),
+        "antibody_capture"
+    );
+
+    let groups = rows_by_feature_type(&feature_types);
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].1, vec![0, 2]);
+    assert_eq!(groups[1].1, vec![1]);
+
+    // two-column `genes.tsv`
+    let genes: Vec<Box<str>> = vec!["ENSG1\tACTB".into(), "ENSG2\tGAPDH".into()];
+    write_lines(&genes, features_file)?;
+    assert!(read_feature_types_tsv(features_file)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn split_backend_by_feature_types() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(6, 9).mapv(|x| (x * 20.).floor() + 1.);
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+
+    let mut data = create_sparse_from_ndarray(&x, Some(zarr_file), None)?;
+    let row_names: Vec<Box<str>> = (0..6).map(|i| format!("f{}", i).into()).collect();
+    data.register_row_names_vec(&row_names);
+    let column_names: Vec<Box<str>> = (0..9).map(|j| format!("c{}", j).into()).collect();
+    data.register_column_names_vec(&column_names);
+
+    let feature_types: Vec<Box<str>> = (0..6)
+        .map(|i| match i % 3 {
+            2 => "Antibody Capture".into(),
+            _ => "Gene Expression".into(),
+        })
+        .collect();
+    register_feature_types(data.as_mut(), &feature_types)?;
+    let raw = x.mapv(|v| v * 2.);
+    data.register_layer_triplets("raw", &mut ndarray_to_triplets(&raw))?;
+    let value_type = data.value_type();
+    drop(data);
+
+    let output = zarr_file.trim_end_matches(".zarr");
+    let split = split_by_feature_type(zarr_file, &SparseIoBackend::Zarr, output)?;
+    assert_eq!(split.len(), 2);
+    assert_eq!(
+        split[0].1.as_ref(),
+        format!("{}.gene_expression.zarr", output)
+    );
+
+    for ((feature_type, split_file), rows) in split.iter().zip([vec![0, 1, 3, 4], vec![2, 5]]) {
+        let modality = open_sparse_matrix(split_file, &SparseIoBackend::Zarr)?;
+        let names: Vec<Box<str>> = rows.iter().map(|&i| row_names[i].clone()).collect();
+        assert_eq!(modality.row_names()?, names);
+        assert_eq!(
+            modality.read_columns_ndarray((0..9).collect())?,
+            x.select(ndarray::Axis(0), &rows)
+        );
+        let tagged = modality
+            .row_annotation(FEATURE_TYPE_ANNOTATION)?
+            .to_strings();
+        assert!(tagged.iter().all(|x| x == feature_type));
+        assert_eq!(modality.value_type(), value_type);
+        assert_eq!(modality.column_names()?, column_names);
+        assert_eq!(
+            modality
+                .open_layer(Some("raw"))?
+                .read_columns_ndarray((0..9).collect())?,
+            raw.select(ndarray::Axis(0), &rows)
+        );
+        modality.remove_backend_file()?;
+    }
+
+    // the data file is left as it is
+    let data = open_sparse_matrix(zarr_file, &SparseIoBackend::Zarr)?;
+    assert_eq!(data.num_rows(), Some(6));
+    data.remove_backend_file()?;
+    Ok(())
+}
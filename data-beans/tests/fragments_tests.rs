@@ -0,0 +1,260 @@
+use data_beans::fragments::*;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::common_io::{create_temp_dir_file, write_lines};
+use std::sync::Arc;
+
+/// (chrom, start, end, barcode)
+const FRAGMENTS: [(&str, u64, u64, &str); 6] = [
+    ("chr1", 100, 300, "AAAC-1"),
+    ("chr1", 250, 280, "CCCT-1"),
+    ("chr1", 4900, 5200, "AAAC-1"),
+    ("chr1", 5100, 5200, "CCCT-1"),
+    ("chr2", 10, 20, "CCCT-1"),
+    ("chr2", 50, 90, "GGGA-1"), // not in the whitelist
+];
+
+const MEMORY_BUDGET: usize = 1 << 20;
+
+fn write_fragments(file: &str) -> anyhow::Result<()> {
+    let mut lines: Vec<Box<str>> = vec!["# id=pbmc".into()];
+    lines.extend(
+        FRAGMENTS
+            .iter()
+            .map(|(c, s, e, b)| format!("{}\t{}\t{}\t{}\t1", c, s, e, b).into()),
+    );
+    write_lines(&lines, file)
+}
+
+/// Counts with the triplets read back in memory
+struct Counted {
+    row_names: Vec<Box<str>>,
+    column_names: Vec<Box<str>>,
+    triplets: Vec<(u64, u64, f32)>,
+}
+
+fn count_in_memory(
+    fragment_file: &str,
+    barcodes: &[Box<str>],
+    regions: &FragmentRegions,
+    count: FragmentCount,
+) -> anyhow::Result<Counted> {
+    let counts = count_fragments(fragment_file, barcodes, regions, count, MEMORY_BUDGET)?;
+    let (by_column, _) = counts.triplets.into_sorted()?;
+    Ok(Counted {
+        row_names: counts.row_names,
+        column_names: counts.column_names,
+        triplets: by_column.collect::<anyhow::Result<_>>()?,
+    })
+}
+
+fn count(counts: &Counted, region: &str, barcode: &str) -> f32 {
+    let i = counts.row_names.iter().position(|x| x.as_ref() == region);
+    let j = counts
+        .column_names
+        .iter()
+        .position(|x| x.as_ref() == barcode);
+    counts
+        .triplets
+        .iter()
+        .find(|&&(r, c, _)| Some(r as usize) == i && Some(c as usize) == j)
+        .map(|&(_, _, x)| x)
+        .unwrap_or(0.)
+}
+
+#[test]
+fn count_fragments_in_tiles_and_peaks() -> anyhow::Result<()> {
+    let fragment_file = create_temp_dir_file(".tsv.gz")?;
+    let fragment_file = fragment_file.to_str().expect("to_str failed");
+    write_fragments(fragment_file)?;
+
+    let barcode_file = create_temp_dir_file(".tsv.gz")?;
+    let barcode_file = barcode_file.to_str().expect("to_str failed");
+    let lines: Vec<Box<str>> = vec!["CCCT-1".into(), "AAAC-1".into(), "CCCT-1".into()];
+    write_lines(&lines, barcode_file)?;
+    let barcodes = read_barcode_whitelist(barcode_file)?;
+    assert_eq!(barcodes, lines[..2]);
+
+    assert!(count_in_memory(
+        fragment_file,
+        &lines,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Insertions
+    )
+    .is_err());
+
+    let tiles = count_in_memory(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Insertions,
+    )?;
+    assert_eq!(
+        tiles.row_names,
+        vec![
+            "chr1:0-5000".into(),
+            "chr1:5000-10000".into(),
+            "chr2:0-5000".into()
+        ]
+    );
+    assert_eq!(tiles.column_names, barcodes);
+    assert_eq!(count(&tiles, "chr1:0-5000", "AAAC-1"), 3.);
+    assert_eq!(count(&tiles, "chr1:5000-10000", "AAAC-1"), 1.);
+    assert_eq!(count(&tiles, "chr1:5000-10000", "CCCT-1"), 2.);
+    assert_eq!(count(&tiles, "chr2:0-5000", "CCCT-1"), 2.);
+
+    // a fragment counted once even if both ends fall in the tile
+    let tiles = count_in_memory(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Fragments,
+    )?;
+    assert_eq!(count(&tiles, "chr1:0-5000", "AAAC-1"), 2.);
+    assert_eq!(count(&tiles, "chr2:0-5000", "CCCT-1"), 1.);
+
+    // overlapping peaks, and one without any counts
+    let bed_file = create_temp_dir_file(".bed")?;
+    let bed_file = bed_file.to_str().expect("to_str failed");
+    let bed: Vec<Box<str>> = vec![
+        "track name=peaks".into(),
+        "chr1\t0\t260\tp1".into(),
+        "chr1\t240\t400".into(),
+        "chr1\t5150\t5250".into(),
+        "chr3\t0\t100".into(),
+    ];
+    write_lines(&bed, bed_file)?;
+    let peaks = read_bed_regions(bed_file)?;
+    assert_eq!(peaks.len(), 4);
+
+    let peak_counts = count_in_memory(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Peaks(peaks),
+        FragmentCount::Insertions,
+    )?;
+    assert_eq!(
+        peak_counts.row_names,
+        vec![
+            "chr1:0-260".into(),
+            "chr1:240-400".into(),
+            "chr1:5150-5250".into(),
+            "chr3:0-100".into()
+        ]
+    );
+    assert_eq!(count(&peak_counts, "chr1:0-260", "AAAC-1"), 1.);
+    assert_eq!(count(&peak_counts, "chr1:240-400", "AAAC-1"), 1.);
+    assert_eq!(count(&peak_counts, "chr1:0-260", "CCCT-1"), 1.);
+    assert_eq!(count(&peak_counts, "chr1:240-400", "CCCT-1"), 2.);
+    assert_eq!(count(&peak_counts, "chr1:5150-5250", "AAAC-1"), 1.);
+    assert_eq!(count(&peak_counts, "chr1:5150-5250", "CCCT-1"), 1.);
+    assert_eq!(peak_counts.triplets.len(), 6);
+
+    assert!(count_in_memory(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(0),
+        FragmentCount::Insertions
+    )
+    .is_err());
+
+    std::fs::remove_file(fragment_file)?;
+    std::fs::remove_file(barcode_file)?;
+    std::fs::remove_file(bed_file)?;
+    Ok(())
+}
+
+#[test]
+fn build_backend_from_fragments() -> anyhow::Result<()> {
+    let fragment_file = create_temp_dir_file(".tsv.gz")?;
+    let fragment_file = fragment_file.to_str().expect("to_str failed");
+    write_fragments(fragment_file)?;
+
+    let barcodes: Vec<Box<str>> = vec!["AAAC-1".into(), "CCCT-1".into()];
+    let counts = count_fragments(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Insertions,
+        MEMORY_BUDGET,
+    )?;
+    let row_names = counts.row_names.clone();
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    let nnz = counts.triplets.len();
+    let mut data = create_sparse_from_external_triplets(
+        counts.triplets,
+        (counts.row_names.len(), counts.column_names.len(), nnz),
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions::default(),
+    )?;
+    data.register_row_names_vec(&counts.row_names);
+    data.register_column_names_vec(&counts.column_names);
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(open_sparse_matrix_by_extension(zarr_file)?), None)?;
+    assert_eq!(data_vec.row_names()?, row_names);
+    assert_eq!(data_vec.num_columns()?, 2);
+
+    let x = data_vec.read_columns_ndarray(0..2)?;
+    assert_eq!(x.column(0).to_vec(), vec![3., 1., 0.]);
+    assert_eq!(x.column(1).to_vec(), vec![2., 2., 2.]);
+
+    data.remove_backend_file()?;
+    std::fs::remove_file(fragment_file)?;
+    Ok(())
+}
+
+#[test]
+fn unsorted_fragments_and_bad_regions_are_errors() -> anyhow::Result<()> {
+    let barcodes: Vec<Box<str>> = vec!["AAAC-1".into()];
+
+    // chr1 again after chr2
+    let fragment_file = create_temp_dir_file(".tsv.gz")?;
+    let fragment_file = fragment_file.to_str().expect("to_str failed");
+    let lines: Vec<Box<str>> = vec![
+        "chr1\t100\t300\tAAAC-1\t1".into(),
+        "chr2\t10\t20\tAAAC-1\t1".into(),
+        "chr1\t250\t280\tAAAC-1\t1".into(),
+    ];
+    write_lines(&lines, fragment_file)?;
+    let err = count_fragments(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Insertions,
+        MEMORY_BUDGET,
+    )
+    .err()
+    .expect("unsorted fragments");
+    assert!(err.to_string().contains("line 3"));
+
+    let lines: Vec<Box<str>> = vec![
+        "chr1\t100\t300\tAAAC-1\t1".into(),
+        "chr1\t250\tx\tAAAC-1\t1".into(),
+    ];
+    write_lines(&lines, fragment_file)?;
+    let err = count_fragments(
+        fragment_file,
+        &barcodes,
+        &FragmentRegions::Tiles(5000),
+        FragmentCount::Insertions,
+        MEMORY_BUDGET,
+    )
+    .err()
+    .expect("invalid end");
+    assert!(err.to_string().contains("line 2"));
+
+    let bed_file = create_temp_dir_file(".bed")?;
+    let bed_file = bed_file.to_str().expect("to_str failed");
+    let bed: Vec<Box<str>> = vec!["chr1\t0\t260".into(), "chr1\t-5\t400".into()];
+    write_lines(&bed, bed_file)?;
+    let err = read_bed_regions(bed_file).unwrap_err().to_string();
+    assert!(err.contains(bed_file) && err.contains("line 2"));
+
+    std::fs::remove_file(fragment_file)?;
+    std::fs::remove_file(bed_file)?;
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_matrix_h5ad::SparseMtxData;
+use hdf5::types::VarLenUnicode;
+use matrix_util::common_io::{create_temp_dir_file, remove_file};
+
+/// Write a small AnnData file with `X` in the `csr_matrix` (cells
+/// are rows) or `csc_matrix` encoding and return its name.
+fn write_toy_h5ad(x_cell_gene: &Array2<f32>, csr: bool) -> anyhow::Result<String> {
+    let h5ad_file = create_temp_dir_file(".h5ad")?;
+    let h5ad_file = h5ad_file.to_str().expect("to_str failed").to_string();
+    let file = hdf5::File::create(&h5ad_file)?;
+
+    let (ncells, ngenes) = x_cell_gene.dim();
+
+    let (major, encoding) = if csr {
+        (Axis(0), "csr_matrix")
+    } else {
+        (Axis(1), "csc_matrix")
+    };
+
+    let mut data = vec![];
+    let mut indices = vec![];
+    let mut indptr = vec![0_i64];
+    for x in x_cell_gene.axis_iter(major) {
+        for (i, &x_i) in x.iter().enumerate() {
+            if x_i > 0. {
+                data.push(x_i);
+                indices.push(i as i32);
+            }
+        }
+        indptr.push(data.len() as i64);
+    }
+
+    let x = file.create_group("X")?;
+    x.new_attr::<VarLenUnicode>()
+        .create("encoding-type")?
+        .write_scalar(&encoding.parse::<VarLenUnicode>()?)?;
+    x.new_attr::<i64>()
+        .shape(2)
+        .create("shape")?
+        .write_raw(&[ncells as i64, ngenes as i64])?;
+    x.new_dataset_builder().with_data(&data).create("data")?;
+    x.new_dataset_builder()
+        .with_data(&indices)
+        .create("indices")?;
+    x.new_dataset_builder()
+        .with_data(&indptr)
+        .create("indptr")?;
+
+    for (group_name, prefix, n) in [("obs", "cell", ncells), ("var", "gene", ngenes)] {
+        let names: Vec<VarLenUnicode> = (0..n)
+            .map(|i| format!("{}{}", prefix, i).parse().expect("invalid name"))
+            .collect();
+        let group = file.create_group(group_name)?;
+        group
+            .new_attr::<VarLenUnicode>()
+            .create("_index")?
+            .write_scalar(&"_index".parse::<VarLenUnicode>()?)?;
+        group
+            .new_dataset_builder()
+            .with_data(&names)
+            .create("_index")?;
+    }
+
+    file.flush()?;
+    Ok(h5ad_file)
+}
+
+#[test]
+fn h5ad_read_columns_and_rows() -> anyhow::Result<()> {
+    let x_cell_gene = array![[1., 0., 2.], [0., 0., 3.], [4., 5., 0.], [0., 6., 0.]];
+
+    for csr in [true, false] {
+        let h5ad_file = write_toy_h5ad(&x_cell_gene, csr)?;
+        let mut data = SparseMtxData::open(&h5ad_file)?;
+
+        // features x cells
+        let expected = x_cell_gene.t().to_owned();
+        assert_eq!(data.num_rows(), Some(3));
+        assert_eq!(data.num_columns(), Some(4));
+        assert_eq!(data.num_non_zeros(), Some(6));
+
+        // columns come from `X` (CSR) or the sidecar index (CSC)
+        let columns = data.read_columns_ndarray(vec![3, 0, 2])?;
+        assert_eq!(columns, expected.select(Axis(1), &[3, 0, 2]));
+
+        // and the rows the other way around
+        let rows = data.read_rows_ndarray(vec![2, 1])?;
+        assert_eq!(rows, expected.select(Axis(0), &[2, 1]));
+
+        // clones share the sidecar index built once
+        let other = data.clone();
+        assert_eq!(
+            other.read_rows_ndarray(vec![0])?,
+            expected.select(Axis(0), &[0])
+        );
+        assert_eq!(
+            other.read_columns_ndarray(vec![1])?,
+            expected.select(Axis(1), &[1])
+        );
+
+        assert_eq!(data.row_names()?[2].as_ref(), "gene2");
+        assert_eq!(data.column_names()?[3].as_ref(), "cell3");
+
+        // read-only
+        assert!(data.remove_backend_file().is_err());
+        data.register_row_names_vec(&vec!["other".into(); 3]);
+        assert_eq!(data.row_names()?[2].as_ref(), "gene2");
+
+        remove_file(&(h5ad_file.clone() + ".index.h5"))?;
+        remove_file(&h5ad_file)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn h5ad_export_round_trip() -> anyhow::Result<()> {
+    use data_beans::sparse_matrix_h5ad::H5adAnnotations;
+
+    let x_gene_cell = array![[1., 0., 4., 0.], [0., 0., 5., 6.], [2., 3., 0., 0.]];
+    let mut data = create_sparse_from_ndarray(&x_gene_cell, None, None)?;
+
+    let rows: Vec<Box<str>> = (0..3).map(|i| format!("gene{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..4).map(|j| format!("cell{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let batch: Vec<Box<str>> = vec!["b".into(), "a".into(), "b".into(), "c".into()];
+    let depth = vec![5., 3., 9., 6.];
+    let z = Array2::from_shape_fn((4, 2), |(i, k)| (i * 2 + k) as f32);
+
+    let annotations = H5adAnnotations {
+        obs_columns: vec![
+            ("batch".into(), AnnotationColumn::categorical(&batch)),
+            ("depth".into(), AnnotationColumn::Numeric(depth.clone())),
+        ],
+        obsm: vec![("X_latent".into(), z.clone())],
+    };
+
+    let h5ad_file = create_temp_dir_file(".h5ad")?;
+    let h5ad_file = h5ad_file.to_str().expect("to_str failed").to_string();
+    data.to_h5ad_file(&h5ad_file, &annotations)?;
+
+    let h5ad = SparseMtxData::open(&h5ad_file)?;
+    let columns = h5ad.read_columns_ndarray((0..4).collect())?;
+    assert_eq!(columns, x_gene_cell);
+    assert_eq!(h5ad.row_names()?, rows);
+    assert_eq!(h5ad.column_names()?, cols);
+
+    assert_eq!(
+        h5ad.annotation_names(AnnotationAxis::Obs)?,
+        vec!["batch".into(), "depth".into()]
+    );
+    assert_eq!(
+        h5ad.retrieve_annotation(AnnotationAxis::Obs, "batch")?
+            .to_strings(),
+        batch
+    );
+    assert_eq!(
+        h5ad.retrieve_annotation(AnnotationAxis::Obs, "depth")?,
+        AnnotationColumn::Numeric(depth)
+    );
+
+    let obsm = hdf5::File::open(&h5ad_file)?
+        .dataset("obsm/X_latent")?
+        .read_2d::<f32>()?;
+    assert_eq!(obsm, z);
+
+    remove_file(&(h5ad_file.clone() + ".index.h5"))?;
+
+    // names HDF5 can't store are errors, not panics
+    let mut bad = cols.clone();
+    bad[1] = "cell\01".into();
+    data.register_column_names_vec(&bad);
+    assert!(data.to_h5ad_file(&h5ad_file, &annotations).is_err());
+
+    remove_file(&h5ad_file)?;
+    data.remove_backend_file()?;
+    Ok(())
+}
+
+#[test]
+fn latent_rows_matched_by_cell_names() {
+    use data_beans::sparse_matrix_h5ad::align_obsm_rows;
+
+    let cols: Vec<Box<str>> = vec!["AAAC-1".into(), "CCCT-1".into(), "GGGA-1".into()];
+
+    // `senna` rows are `{cell}@{data_tag}`, in any order
+    let rows: Vec<Box<str>> = vec!["GGGA-1@pbmc".into(), "AAAC-1@pbmc".into()];
+    let z = array![[1., 2.], [3., 4.]];
+
+    let (z_full, nmatched) = align_obsm_rows(&rows, &z, &cols);
+    assert_eq!(nmatched, 2);
+    assert_eq!(z_full.row(0).to_vec(), vec![3., 4.]);
+    assert!(z_full.row(1).iter().all(|x| x.is_nan()));
+    assert_eq!(z_full.row(2).to_vec(), vec![1., 2.]);
+
+    // exact names first, even with `@` in them
+    let cols: Vec<Box<str>> = vec!["a@x".into(), "a".into()];
+    let rows: Vec<Box<str>> = vec!["a@x".into()];
+    let (z_full, nmatched) = align_obsm_rows(&rows, &array![[7.]], &cols);
+    assert_eq!(nmatched, 1);
+    assert_eq!(z_full[(0, 0)], 7.);
+    assert!(z_full[(1, 0)].is_nan());
+}
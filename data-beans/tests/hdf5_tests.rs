@@ -82,8 +82,9 @@ fn random_mtx_loading() -> anyhow::Result<()> {
         measure_time(|| data.to_mtx_file(mtx_file.to_str().unwrap()))?;
 
         // 3. create another data from the mtx file
-        let data =
-            measure_time(|| SparseMtxData::from_mtx_file(mtx_file.to_str().unwrap(), None, None));
+        let data = measure_time(|| {
+            SparseMtxData::from_mtx_file(mtx_file.to_str().unwrap(), None, None, None)
+        });
         let data = data?;
 
         // 4. read the column 2
@@ -172,7 +173,7 @@ fn simulate() -> anyhow::Result<()> {
         &memb_file,
     )?;
 
-    let data = measure_time(|| SparseMtxData::from_mtx_file(&mtx_file, None, Some(true)));
+    let data = measure_time(|| SparseMtxData::from_mtx_file(&mtx_file, None, Some(true), None));
     let data = data?;
 
     let n = data.num_columns().expect("failed to get #col") as usize;
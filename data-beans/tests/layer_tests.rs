@@ -0,0 +1,85 @@
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+fn toy_data(
+    backend: &SparseIoBackend,
+    x: &Array2<f32>,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let backend_file = match backend {
+        SparseIoBackend::Memory => None,
+        _ => {
+            let header = create_temp_dir_file("")?;
+            let header = header.to_str().expect("to_str failed");
+            Some(backend_output_file(header, backend)?)
+        }
+    };
+    let mut data = create_sparse_from_ndarray(x, backend_file.as_deref(), Some(backend))?;
+    let rows: Vec<Box<str>> = (0..x.nrows()).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols()).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn layer_register_open_remove() -> anyhow::Result<()> {
+    for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+        let x = Array2::<f32>::runif(4, 5);
+        let y = x.mapv(|v| v * 2.);
+        let mut data = toy_data(&backend, &x)?;
+
+        assert!(data.layer_names()?.is_empty());
+
+        let mut triplets = ndarray_to_triplets(&y);
+        data.register_layer_triplets("spliced", &mut triplets)?;
+
+        // out of the shape or a bad name
+        assert!(data
+            .register_layer_triplets("bad", &mut vec![(4, 0, 1.)])
+            .is_err());
+        assert!(data
+            .register_layer_triplets("a/b", &mut vec![(0, 0, 1.)])
+            .is_err());
+
+        assert_eq!(data.layer_names()?, vec![Box::from("spliced")]);
+
+        let spliced = data.open_layer(Some("spliced"))?;
+        assert_eq!(spliced.read_columns_ndarray((0..5).collect())?, y);
+        assert_eq!(spliced.read_rows_ndarray((0..4).collect())?, y);
+        assert_eq!(spliced.row_names()?, data.row_names()?);
+        assert_eq!(spliced.column_names()?, data.column_names()?);
+        assert_eq!(spliced.num_non_zeros(), Some(20));
+
+        // the main matrix stays
+        assert_eq!(data.read_columns_ndarray((0..5).collect())?, x);
+        let main = data.open_layer(None)?;
+        assert_eq!(main.read_columns_ndarray((0..5).collect())?, x);
+        assert!(data.open_layer(Some("unspliced")).is_err());
+
+        // copy another backend with the same names
+        let z = Array2::<f32>::runif(4, 5);
+        let other = toy_data(&backend, &z)?;
+        data.register_layer_from_sparse("unspliced", other.as_ref(), 1 << 20)?;
+        assert_eq!(
+            data.layer_names()?,
+            vec![Box::from("spliced"), Box::from("unspliced")]
+        );
+        let unspliced = data.open_layer(Some("unspliced"))?;
+        assert_eq!(unspliced.read_columns_ndarray((0..5).collect())?, z);
+
+        // layers follow the subset
+        data.subset_columns_rows(Some(&vec![3, 1]), Some(&vec![2, 0]))?;
+        let spliced = data.open_layer(Some("spliced"))?;
+        let expected = y.select(Axis(1), &[3, 1]).select(Axis(0), &[2, 0]);
+        assert_eq!(spliced.read_columns_ndarray((0..2).collect())?, expected);
+
+        data.remove_layer("spliced")?;
+        assert_eq!(data.layer_names()?, vec![Box::from("unspliced")]);
+        assert!(data.remove_layer("spliced").is_err());
+
+        other.remove_backend_file()?;
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_matrix_memory::SparseMtxData;
+use matrix_util::common_io::{create_temp_dir_file, remove_file};
+use matrix_util::mtx_io::read_mtx_triplets;
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn memory_read_subset_reorder() -> anyhow::Result<()> {
+    let whole_mat = Array2::<f32>::runif(17, 99);
+
+    let mut data = SparseMtxData::from_ndarray(&whole_mat, Some(true))?;
+
+    let rows: Vec<Box<str>> = (0..17).map(|x| x.to_string().into_boxed_str()).collect();
+    let cols: Vec<Box<str>> = (0..99).map(|x| x.to_string().into_boxed_str()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let b = data.read_columns_ndarray(vec![3, 50, 7])?;
+    assert_eq!(b, whole_mat.select(Axis(1), &[3, 50, 7]));
+
+    let b = data.read_rows_ndarray(vec![16, 0])?;
+    assert_eq!(b, whole_mat.select(Axis(0), &[16, 0]));
+
+    data.subset_columns_rows(Some(&vec![9, 10, 50, 1]), Some(&vec![1, 7, 16]))?;
+    let a = whole_mat
+        .select(Axis(1), &[9, 10, 50, 1])
+        .select(Axis(0), &[1, 7, 16]);
+    let b = data.read_columns_ndarray((0..4).collect())?;
+    assert_eq!(a, b);
+    assert_eq!(data.column_names()?.len(), 4);
+
+    let new_row_names: Vec<Box<str>> = vec!["16".into(), "1".into()];
+    data.reorder_rows(&new_row_names)?;
+    let b = data.read_columns_ndarray((0..4).collect())?;
+    assert_eq!(b, a.select(Axis(0), &[2, 0]));
+    assert_eq!(data.row_names()?, new_row_names);
+
+    Ok(())
+}
+
+#[test]
+fn memory_to_mtx_and_persist() -> anyhow::Result<()> {
+    let whole_mat = Array2::<f32>::runif(5, 12);
+    let mut data = create_sparse_from_ndarray(&whole_mat, None, Some(&SparseIoBackend::Memory))?;
+
+    let rows: Vec<Box<str>> = (0..5).map(|x| format!("r{}", x).into_boxed_str()).collect();
+    let cols: Vec<Box<str>> = (0..12)
+        .map(|x| format!("c{}", x).into_boxed_str())
+        .collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let mtx_file = create_temp_dir_file(".mtx.gz")?;
+    let mtx_file = mtx_file.to_str().expect("to_str failed");
+    data.to_mtx_file(mtx_file)?;
+    let (triplets, shape) = read_mtx_triplets(mtx_file)?;
+    assert_eq!(shape, Some((5, 12, triplets.len())));
+    remove_file(mtx_file)?;
+
+    let mut memory = SparseMtxData::from_ndarray(&whole_mat, Some(true))?;
+    memory.register_row_names_vec(&rows);
+    memory.register_column_names_vec(&cols);
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    let zarr = memory.persist(zarr_file, &SparseIoBackend::Zarr)?;
+
+    assert_eq!(zarr.read_columns_ndarray((0..12).collect())?, whole_mat);
+    assert_eq!(zarr.row_names()?, rows);
+    assert_eq!(zarr.column_names()?, cols);
+    zarr.remove_backend_file()?;
+
+    Ok(())
+}
+
+#[test]
+fn memory_value_type_holds_the_values() -> anyhow::Result<()> {
+    let whole_mat = Array2::<f32>::runif(4, 6);
+    let mut memory = SparseMtxData::from_ndarray(&whole_mat, Some(true))?;
+
+    assert!(memory.set_value_type(ValueType::U16).is_err());
+    assert_eq!(memory.value_type(), None);
+    memory.set_value_type(ValueType::F32)?;
+    assert_eq!(memory.value_type(), Some(ValueType::F32));
+
+    // checked when recorded as well
+    let options = SparseCreateOptions {
+        value_type: Some(ValueType::U32),
+        ..Default::default()
+    };
+    assert!(create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&whole_mat),
+        (4, 6, 24),
+        None,
+        Some(&SparseIoBackend::Memory),
+        &options,
+    )
+    .is_err());
+
+    let counts = whole_mat.mapv(|x| (x * 10.).floor() + 1.);
+    let memory = create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&counts),
+        (4, 6, 24),
+        None,
+        Some(&SparseIoBackend::Memory),
+        &options,
+    )?;
+    assert_eq!(memory.value_type(), Some(ValueType::U32));
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use data_beans::mmap_columns::*;
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn preload_columns_mmap() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(20, 40);
+    let raw = x.mapv(|v| v * 3.);
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    let mut data =
+        create_sparse_from_ndarray(&x, Some(backend_file), Some(&SparseIoBackend::Zarr))?;
+    let rows: Vec<Box<str>> = (0..20).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..40).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    data.register_layer_triplets("raw", &mut ndarray_to_triplets(&raw))?;
+
+    let cache_dir = mmap_cache_dir(backend_file);
+    assert!(!cache_dir.exists());
+
+    data.preload_columns_mmap()?;
+    assert!(cache_dir.join("by_column").join("data.f32").is_file());
+
+    let columns = vec![39, 0, 7, 7, 22];
+    let expected = x.select(Axis(1), &columns);
+    assert_eq!(data.read_columns_ndarray(columns.clone())?, expected);
+    assert_eq!(data.read_columns_ndarray((0..40).collect())?, x);
+
+    // a layer keeps its own cache and reuses it once written
+    let mut layer = data.open_layer(Some("raw"))?;
+    layer.preload_columns_mmap()?;
+    assert!(cache_dir.join("layers/raw/by_column/indices.u64").is_file());
+    assert_eq!(layer.read_columns_ndarray((0..40).collect())?, raw);
+
+    let mut reopened = open_sparse_matrix(backend_file, &SparseIoBackend::Zarr)?;
+    reopened.preload_columns_mmap()?;
+    assert_eq!(reopened.read_columns_ndarray(columns.clone())?, expected);
+
+    // modifying the backend drops the on-disk cache
+    data.subset_columns_rows(Some(&(0..10).collect()), None)?;
+    assert!(!cache_dir.exists());
+    data.preload_columns_mmap()?;
+    assert_eq!(
+        data.read_columns_ndarray((0..10).collect())?,
+        x.slice(s![.., 0..10])
+    );
+
+    data.remove_backend_file()?;
+    assert!(!cache_dir.exists());
+    Ok(())
+}
+
+#[test]
+fn mmap_cache_of_a_rewritten_backend() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(20, 40);
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    let mut data =
+        create_sparse_from_ndarray(&x, Some(backend_file), Some(&SparseIoBackend::Zarr))?;
+    data.preload_columns_mmap()?;
+
+    // keep the cache aside while the backend is rebuilt with the
+    // same columns' pointers, e.g., by another process
+    let cache_dir = mmap_cache_dir(backend_file);
+    let stale_dir = format!("{}.stale", cache_dir.display());
+    std::fs::rename(&cache_dir, &stale_dir)?;
+
+    let y = x.mapv(|v| v * 2.);
+    let mut data =
+        create_sparse_from_ndarray(&y, Some(backend_file), Some(&SparseIoBackend::Zarr))?;
+    std::fs::rename(&stale_dir, &cache_dir)?;
+
+    data.preload_columns_mmap()?;
+    assert_eq!(data.read_columns_ndarray((0..40).collect())?, y);
+
+    data.remove_backend_file()?;
+    Ok(())
+}
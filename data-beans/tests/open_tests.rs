@@ -0,0 +1,52 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+use std::sync::Arc;
+
+#[test]
+fn open_by_extension() -> anyhow::Result<()> {
+    assert_eq!(
+        SparseIoBackend::from_file_name("a/b.zarr")?,
+        SparseIoBackend::Zarr
+    );
+    assert_eq!(
+        SparseIoBackend::from_file_name("b.h5")?,
+        SparseIoBackend::HDF5
+    );
+    assert_eq!(
+        SparseIoBackend::from_file_name("b.h5ad")?,
+        SparseIoBackend::H5ad
+    );
+
+    assert_eq!(
+        backend_output_file("a/b", &SparseIoBackend::Zarr)?,
+        "a/b.zarr"
+    );
+    assert_eq!(backend_output_file("b", &SparseIoBackend::HDF5)?, "b.h5");
+    assert!(backend_output_file("b", &SparseIoBackend::H5ad).is_err());
+    assert!(backend_output_file("b", &SparseIoBackend::Memory).is_err());
+
+    // a clean error instead of a panic
+    assert!(SparseIoBackend::from_file_name("b.mtx.gz").is_err());
+    assert!(SparseIoBackend::from_file_name("b").is_err());
+    assert!(open_sparse_matrix_by_extension("b.tsv").is_err());
+
+    let x = Array2::<f32>::runif(4, 3);
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    let mut data = create_sparse_from_ndarray(&x, Some(zarr_file), Some(&SparseIoBackend::Zarr))?;
+    let rows: Vec<Box<str>> = (0..4).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..3).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+
+    let mut data_vec = SparseIoVec::new();
+    data_vec.push(Arc::from(open_sparse_matrix_by_extension(zarr_file)?), None)?;
+    data_vec.push(Arc::from(data.open_layer(None)?), None)?;
+    let y = data_vec.read_columns_ndarray(0..6)?;
+    assert_eq!(y, ndarray::concatenate(Axis(1), &[x.view(), x.view()])?);
+
+    data.remove_backend_file()?;
+    Ok(())
+}
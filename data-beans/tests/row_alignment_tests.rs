@@ -0,0 +1,74 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::traits::SampleOps;
+use std::sync::Arc;
+
+fn toy_data(
+    x: &Array2<f32>,
+    rows: &[&str],
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut data = create_sparse_from_ndarray(x, None, Some(&SparseIoBackend::Memory))?;
+    let rows: Vec<Box<str>> = rows.iter().map(|&r| r.into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols()).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn align_rows_across_data() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(3, 2);
+    let z = Array2::<f32>::runif(3, 4);
+
+    let data_vec = |alignment: RowAlignment| -> anyhow::Result<SparseIoVec> {
+        let mut ret = SparseIoVec::new();
+        ret.set_row_alignment(alignment)?;
+        ret.push(Arc::from(toy_data(&x, &["a", "b", "c"])?), None)?;
+        ret.push(Arc::from(toy_data(&z, &["d", "c", "a"])?), None)?;
+        Ok(ret)
+    };
+
+    assert!(data_vec(RowAlignment::Strict).is_err());
+
+    // a rejected data set leaves the columns as they were
+    let mut strict = SparseIoVec::new();
+    strict.push(Arc::from(toy_data(&x, &["a", "b", "c"])?), None)?;
+    assert!(strict
+        .push(Arc::from(toy_data(&z, &["d", "c", "a"])?), None)
+        .is_err());
+    assert_eq!(strict.num_columns()?, 2);
+    assert_eq!(strict.column_names()?.len(), 2);
+    strict.push(Arc::from(toy_data(&z, &["a", "b", "c"])?), None)?;
+    assert_eq!(strict.num_columns_by_data()?, vec![2, 4]);
+    let y = strict.read_columns_ndarray(0..6)?;
+    assert_eq!(y, ndarray::concatenate(Axis(1), &[x.view(), z.view()])?);
+
+    // a, c
+    let inter = data_vec(RowAlignment::Intersection)?;
+    assert_eq!(inter.num_rows()?, 2);
+    assert_eq!(inter.row_names()?, vec![Box::from("a"), Box::from("c")]);
+    let y = inter.read_columns_ndarray(0..6)?;
+    assert_eq!(y.dim(), (2, 6));
+    assert_eq!(y.row(0).to_vec()[..2], x.row(0).to_vec()[..]);
+    assert_eq!(y.row(1).to_vec()[..2], x.row(2).to_vec()[..]);
+    assert_eq!(y.row(0).to_vec()[2..], z.row(2).to_vec()[..]);
+    assert_eq!(y.row(1).to_vec()[2..], z.row(1).to_vec()[..]);
+
+    // a, b, c, d
+    let union = data_vec(RowAlignment::Union)?;
+    assert_eq!(union.num_rows()?, 4);
+    let y = union.read_columns_ndarray(0..6)?;
+    assert_eq!(y.dim(), (4, 6));
+    assert_eq!(y.slice(s![0..3, 0..2]), x);
+    assert_eq!(y.row(3).to_vec()[..2], [0., 0.]);
+    assert_eq!(y.row(1).to_vec()[2..], [0.; 4]);
+    assert_eq!(y.row(3).to_vec()[2..], z.row(0).to_vec()[..]);
+
+    let csc = union.read_columns_csc(2..4)?;
+    assert_eq!((csc.nrows(), csc.ncols()), (4, 2));
+
+    // switching back to strict fails after mixed rows
+    let mut union = union;
+    assert!(union.set_row_alignment(RowAlignment::Strict).is_err());
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use data_beans::sparse_data_visitors::*;
+use data_beans::sparse_io::*;
+use data_beans::sparse_io_vector::*;
+use matrix_util::traits::SampleOps;
+use std::sync::{Arc, Mutex};
+
+fn toy_data(
+    x: &Array2<f32>,
+    rows: &[&str],
+    tag: &str,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut data = create_sparse_from_ndarray(x, None, Some(&SparseIoBackend::Memory))?;
+    let rows: Vec<Box<str>> = rows.iter().map(|&r| r.into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols())
+        .map(|j| format!("{}{}", tag, j).into())
+        .collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn visit_rows_across_data() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(3, 4);
+    let z = Array2::<f32>::runif(3, 5);
+
+    // a, b, c, d
+    let mut data_vec = SparseIoVec::new();
+    data_vec.set_row_alignment(RowAlignment::Union)?;
+    data_vec.push(Arc::from(toy_data(&x, &["a", "b", "c"], "x")?), None)?;
+    data_vec.push(Arc::from(toy_data(&z, &["d", "c", "a"], "z")?), None)?;
+
+    let y = data_vec.read_rows_ndarray(vec![2, 3, 0].into_iter())?;
+    assert_eq!(y.dim(), (3, 9));
+    assert_eq!(y.row(0).to_vec()[..4], x.row(2).to_vec()[..]);
+    assert_eq!(y.row(0).to_vec()[4..], z.row(1).to_vec()[..]);
+    assert_eq!(y.row(1).to_vec()[..4], [0.; 4]);
+    assert_eq!(y.row(1).to_vec()[4..], z.row(0).to_vec()[..]);
+    assert_eq!(y.row(2).to_vec()[4..], z.row(2).to_vec()[..]);
+
+    // the rows agree with the columns read the other way
+    let by_column = data_vec.read_columns_ndarray(0..9)?;
+    assert_eq!(data_vec.read_rows_ndarray(0..4)?, by_column);
+
+    let row_sum_visitor = |(lb, ub): (usize, usize),
+                           data_vec: &SparseIoVec,
+                           _: &(),
+                           out: Arc<Mutex<&mut Vec<f32>>>|
+     -> anyhow::Result<()> {
+        let rows = data_vec.read_rows_csr(lb..ub)?;
+        let mut out = out.lock().expect("lock");
+        for (i, row) in rows.row_iter().enumerate() {
+            out[lb + i] = row.values().iter().sum();
+        }
+        Ok(())
+    };
+
+    let mut row_sums = vec![0_f32; 4];
+    data_vec.visit_rows_by_block(&row_sum_visitor, &(), &mut row_sums, Some(3))?;
+
+    for (i, s) in row_sums.iter().enumerate() {
+        approx::assert_abs_diff_eq!(*s, by_column.row(i).sum(), epsilon = 1e-4);
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+use std::path::Path;
+
+fn toy_backend(
+    x: &Array2<f32>,
+    backend_file: &str,
+) -> anyhow::Result<Box<dyn SparseIo<IndexIter = Vec<usize>>>> {
+    let mut data = create_sparse_from_ndarray(x, Some(backend_file), Some(&SparseIoBackend::Zarr))?;
+    let rows: Vec<Box<str>> = (0..x.nrows()).map(|i| format!("g{}", i).into()).collect();
+    let cols: Vec<Box<str>> = (0..x.ncols()).map(|j| format!("c{}", j).into()).collect();
+    data.register_row_names_vec(&rows);
+    data.register_column_names_vec(&cols);
+    Ok(data)
+}
+
+#[test]
+fn subset_replaces_backend_after_staging() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(8, 12);
+    let rows = vec![6, 1, 3];
+    let columns = vec![11, 0, 5, 2];
+    let expected = x.select(Axis(0), &rows).select(Axis(1), &columns);
+
+    for suffix in [".zarr", ".zarr.zip"] {
+        let backend_file = create_temp_dir_file(suffix)?;
+        let backend_file = backend_file.to_str().expect("to_str failed");
+        let mut data = toy_backend(&x, backend_file)?;
+
+        // a stale staging directory left by a crash is cleaned up
+        let staging_dir = format!("{}.partial", backend_file);
+        std::fs::create_dir_all(Path::new(&staging_dir).join("junk"))?;
+
+        data.subset_columns_rows(Some(&columns), Some(&rows))?;
+        assert!(!Path::new(&staging_dir).exists());
+        assert_eq!(data.get_backend_file_name(), backend_file);
+        assert_eq!(data.read_columns_ndarray((0..4).collect())?, expected);
+
+        let reopened = open_sparse_matrix(backend_file, &SparseIoBackend::Zarr)?;
+        assert_eq!(reopened.read_columns_ndarray((0..4).collect())?, expected);
+        assert_eq!(
+            reopened.row_names()?,
+            vec![Box::from("g6"), "g1".into(), "g3".into()]
+        );
+
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn subset_into_another_backend() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(8, 12);
+    let rows = vec![0, 2, 4, 6];
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    let output_file = format!("{}.out.zarr", backend_file.trim_end_matches(".zarr"));
+    let mut data = toy_backend(&x, backend_file)?;
+
+    data.set_backend_file_name(&output_file)?;
+    data.subset_columns_rows(None, Some(&rows))?;
+    assert_eq!(data.get_backend_file_name(), output_file);
+    assert_eq!(
+        data.read_columns_ndarray((0..12).collect())?,
+        x.select(Axis(0), &rows)
+    );
+
+    // the original stays as it was
+    let original = open_sparse_matrix(backend_file, &SparseIoBackend::Zarr)?;
+    assert_eq!(original.num_rows(), Some(8));
+    assert_eq!(original.read_columns_ndarray((0..12).collect())?, x);
+
+    data.remove_backend_file()?;
+    original.remove_backend_file()?;
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use data_beans::transcripts::*;
+use matrix_util::common_io::{create_temp_dir_file, write_lines};
+use matrix_util::traits::IoOps;
+use ndarray::Array2;
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, FloatType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use std::sync::Arc;
+
+/// (gene, x, y, cell, qv)
+type Molecule = (&'static str, f32, f32, &'static str, f32);
+
+const MOLECULES: [Molecule; 7] = [
+    ("ACTB", 1., 1., "c1", 30.),
+    ("ACTB", 3., 1., "c1", 30.),
+    ("GAPDH", 2., 4., "c1", 10.),
+    ("GAPDH", 12., 12., "c2", 40.),
+    ("ACTB", 12., 14., "c2", 40.),
+    ("BLANK_0001", 13., 13., "c2", 40.),
+    ("ACTB", 25., 2., "UNASSIGNED", 40.),
+];
+
+fn write_csv(file: &str) -> anyhow::Result<()> {
+    let mut lines: Vec<Box<str>> =
+        vec!["\"cell_id\",\"feature_name\",\"x_location\",\"y_location\",\"qv\"".into()];
+    lines.extend(
+        MOLECULES
+            .iter()
+            .map(|(g, x, y, c, qv)| format!("\"{}\",\"{}\",{},{},{}", c, g, x, y, qv).into()),
+    );
+    write_lines(&lines, file)
+}
+
+fn write_parquet(file: &str) -> anyhow::Result<()> {
+    let string_field = |name: &str| {
+        Arc::new(
+            Type::primitive_type_builder(name, PhysicalType::BYTE_ARRAY)
+                .with_repetition(Repetition::REQUIRED)
+                .with_converted_type(ConvertedType::UTF8)
+                .build()
+                .unwrap(),
+        )
+    };
+    let float_field = |name: &str| {
+        Arc::new(
+            Type::primitive_type_builder(name, PhysicalType::FLOAT)
+                .with_repetition(Repetition::REQUIRED)
+                .build()
+                .unwrap(),
+        )
+    };
+    let schema = Type::group_type_builder("schema")
+        .with_fields(vec![
+            string_field("cell_id"),
+            string_field("feature_name"),
+            float_field("x_location"),
+            float_field("y_location"),
+            float_field("qv"),
+        ])
+        .build()?;
+
+    let mut writer = SerializedFileWriter::new(
+        std::fs::File::create(file)?,
+        Arc::new(schema),
+        Arc::new(WriterProperties::builder().build()),
+    )?;
+    let mut row_group = writer.next_row_group()?;
+
+    let strings = |f: fn(&Molecule) -> &str| -> Vec<ByteArray> {
+        MOLECULES.iter().map(|m| ByteArray::from(f(m))).collect()
+    };
+    let floats = |f: fn(&Molecule) -> f32| -> Vec<f32> { MOLECULES.iter().map(f).collect() };
+
+    for values in [strings(|m| m.3), strings(|m| m.0)] {
+        let mut column = row_group.next_column()?.expect("column");
+        column
+            .typed::<ByteArrayType>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+    }
+    for values in [floats(|m| m.1), floats(|m| m.2), floats(|m| m.4)] {
+        let mut column = row_group.next_column()?.expect("column");
+        column
+            .typed::<FloatType>()
+            .write_batch(&values, None, None)?;
+        column.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+fn count(aggregated: &AggregatedTranscripts, gene: &str, column: &str) -> f32 {
+    let i = aggregated.row_names.iter().position(|x| x.as_ref() == gene);
+    let j = aggregated
+        .column_names
+        .iter()
+        .position(|x| x.as_ref() == column);
+    aggregated
+        .triplets
+        .iter()
+        .find(|&&(r, c, _)| Some(r as usize) == i && Some(c as usize) == j)
+        .map(|&(_, _, x)| x)
+        .unwrap_or(0.)
+}
+
+#[test]
+fn aggregate_molecules_by_cells() -> anyhow::Result<()> {
+    let parquet_file = create_temp_dir_file(".parquet")?;
+    let parquet_file = parquet_file.to_str().expect("to_str failed");
+    write_parquet(parquet_file)?;
+
+    let csv_file = create_temp_dir_file(".csv.gz")?;
+    let csv_file = csv_file.to_str().expect("to_str failed");
+    write_csv(csv_file)?;
+
+    let filter = TranscriptFilter {
+        min_qv: Some(20.),
+        exclude_genes: Some(regex::Regex::new("^BLANK")?),
+        unassigned_cells: vec!["UNASSIGNED".into()],
+    };
+
+    for file in [parquet_file, csv_file] {
+        let aggregated = aggregate_transcripts(
+            file,
+            &TranscriptColumns::default(),
+            &filter,
+            TranscriptAggregation::Cell,
+            None,
+        )?;
+
+        assert_eq!(aggregated.row_names, vec!["ACTB".into(), "GAPDH".into()]);
+        assert_eq!(aggregated.column_names, vec!["c1".into(), "c2".into()]);
+        assert_eq!(count(&aggregated, "ACTB", "c1"), 2.);
+        assert_eq!(count(&aggregated, "GAPDH", "c1"), 0.); // low QV
+        assert_eq!(count(&aggregated, "GAPDH", "c2"), 1.);
+        assert_eq!(aggregated.triplets.len(), 3);
+
+        // centroids of the kept molecules
+        assert_eq!(aggregated.coordinates.row(0).to_vec(), vec![2., 1.]);
+        assert_eq!(aggregated.coordinates.row(1).to_vec(), vec![12., 13.]);
+    }
+
+    // read back as `pinto --coord` does with its default column names
+    let aggregated = aggregate_transcripts(
+        parquet_file,
+        &TranscriptColumns::default(),
+        &filter,
+        TranscriptAggregation::Cell,
+        None,
+    )?;
+    let coord_file = create_temp_dir_file(".coord.parquet")?;
+    let coord_file = coord_file.to_str().expect("to_str failed");
+    aggregated.write_coordinates(coord_file)?;
+
+    let default_names: Vec<Box<str>> =
+        vec!["pxl_row_in_fullres".into(), "pxl_col_in_fullres".into()];
+    let (cells, _, coordinates) = Array2::<f32>::from_parquet_with_indices_names(
+        coord_file,
+        Some(0),
+        None,
+        Some(&default_names),
+    )?;
+    assert_eq!(cells, aggregated.column_names);
+    assert_eq!(coordinates, aggregated.coordinates);
+    std::fs::remove_file(coord_file)?;
+
+    std::fs::remove_file(parquet_file)?;
+    std::fs::remove_file(csv_file)?;
+    Ok(())
+}
+
+#[test]
+fn aggregate_molecules_in_bins() -> anyhow::Result<()> {
+    let csv_file = create_temp_dir_file(".csv.gz")?;
+    let csv_file = csv_file.to_str().expect("to_str failed");
+    write_csv(csv_file)?;
+
+    let columns = TranscriptColumns {
+        cell: None,
+        qv: None,
+        ..Default::default()
+    };
+    let filter = TranscriptFilter::default();
+
+    let square = aggregate_transcripts(
+        csv_file,
+        &columns,
+        &filter,
+        TranscriptAggregation::Square,
+        Some(10.),
+    )?;
+    assert_eq!(
+        square.column_names,
+        vec!["bin_0_0".into(), "bin_1_1".into(), "bin_2_0".into()]
+    );
+    assert_eq!(count(&square, "ACTB", "bin_0_0"), 2.);
+    assert_eq!(count(&square, "BLANK_0001", "bin_1_1"), 1.);
+    assert_eq!(square.coordinates.row(2).to_vec(), vec![25., 5.]);
+
+    let hex = aggregate_transcripts(
+        csv_file,
+        &columns,
+        &filter,
+        TranscriptAggregation::Hex,
+        Some(10.),
+    )?;
+    let total: f32 = hex.triplets.iter().map(|&(_, _, x)| x).sum();
+    assert_eq!(total, MOLECULES.len() as f32);
+
+    // every molecule lies within a hexagon around one of the centres
+    let radius = 10. / 3_f32.sqrt();
+    for (_, x, y, _, _) in MOLECULES.iter() {
+        assert!(hex
+            .coordinates
+            .rows()
+            .into_iter()
+            .any(|c| ((x - c[0]).powi(2) + (y - c[1]).powi(2)).sqrt() <= radius));
+    }
+
+    assert!(aggregate_transcripts(
+        csv_file,
+        &columns,
+        &filter,
+        TranscriptAggregation::Cell,
+        None
+    )
+    .is_err());
+
+    std::fs::remove_file(csv_file)?;
+    Ok(())
+}
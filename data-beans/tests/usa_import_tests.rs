@@ -0,0 +1,151 @@
+use data_beans::sparse_io::*;
+use data_beans::usa_import::*;
+use matrix_util::common_io::{create_temp_dir_file, write_lines};
+use matrix_util::traits::SampleOps;
+
+fn counts(nrow: usize, ncol: usize) -> Array2<f32> {
+    let mut x = Array2::<f32>::runif(nrow, ncol).mapv(|x| (x * 10.).floor());
+    x.indexed_iter_mut()
+        .filter(|((i, j), _)| (i + j) % 4 == 0)
+        .for_each(|(_, x_ij)| *x_ij = 0.);
+    x
+}
+
+fn names(prefix: &str, n: usize) -> Vec<Box<str>> {
+    (0..n).map(|i| format!("{}{}", prefix, i).into()).collect()
+}
+
+fn write_mtx(x: &Array2<f32>, mtx_file: &str) -> anyhow::Result<()> {
+    let data = create_sparse_from_ndarray(x, None, Some(&SparseIoBackend::Memory))?;
+    data.to_mtx_file(mtx_file)
+}
+
+fn temp_dir(suffix: &str) -> anyhow::Result<String> {
+    let dir = create_temp_dir_file(suffix)?;
+    let dir = dir.to_str().expect("to_str failed").to_string();
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+fn temp_backend() -> anyhow::Result<String> {
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    Ok(zarr_file.to_str().expect("to_str failed").to_string())
+}
+
+#[test]
+fn import_alevin_fry_usa_blocks() -> anyhow::Result<()> {
+    let (ngene, ncell) = (6, 9);
+    let (s, u, a) = (
+        counts(ngene, ncell),
+        counts(ngene, ncell),
+        counts(ngene, ncell),
+    );
+
+    // cells x [S | U | A]
+    let mut quants = Array2::<f32>::zeros((ncell, 3 * ngene));
+    for (b, x) in [&s, &u, &a].into_iter().enumerate() {
+        quants
+            .slice_mut(ndarray::s![.., (b * ngene)..((b + 1) * ngene)])
+            .assign(&x.t());
+    }
+
+    let fry_dir = temp_dir(".fry")?;
+    let alevin_dir = format!("{}/alevin", fry_dir);
+    std::fs::create_dir_all(&alevin_dir)?;
+    write_mtx(&quants, &format!("{}/quants_mat.mtx", alevin_dir))?;
+    let genes = names("G", ngene);
+    let cells = names("C", ncell);
+    let usa_genes: Vec<Box<str>> = [genes.clone(), names("U", ngene), names("A", ngene)].concat();
+    write_lines(&usa_genes, &format!("{}/quants_mat_cols.txt", alevin_dir))?;
+    write_lines(&cells, &format!("{}/quants_mat_rows.txt", alevin_dir))?;
+
+    let usa = read_usa_counts(&fry_dir)?;
+    assert_eq!(usa.row_names, genes);
+    assert_eq!(usa.column_names, cells);
+
+    let zarr_file = temp_backend()?;
+    let sum_layers: Vec<Box<str>> = vec![SPLICED_LAYER.into(), AMBIGUOUS_LAYER.into()];
+    let data = create_sparse_from_usa(
+        usa,
+        Some(&sum_layers),
+        true,
+        Some(&zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions::default(),
+    )?;
+
+    let columns: Vec<usize> = (0..ncell).collect();
+    assert_eq!(data.read_columns_ndarray(columns.clone())?, &s + &a);
+    assert_eq!(data.row_names()?, genes);
+    for (layer, x) in [
+        (SPLICED_LAYER, &s),
+        (UNSPLICED_LAYER, &u),
+        (AMBIGUOUS_LAYER, &a),
+        (COUNTS_LAYER, &(&s + &a)),
+    ] {
+        let layer = data.open_layer(Some(layer))?;
+        assert_eq!(&layer.read_columns_ndarray(columns.clone())?, x);
+    }
+
+    data.remove_backend_file()?;
+    std::fs::remove_dir_all(&fry_dir)?;
+    Ok(())
+}
+
+#[test]
+fn import_kb_layers_on_shared_barcodes() -> anyhow::Result<()> {
+    let ngene = 5;
+    let s = counts(ngene, 8);
+    let u = counts(ngene, 6);
+
+    // unspliced barcodes overlap with the last four spliced ones
+    let spliced_cells = names("C", 8);
+    let unspliced_cells: Vec<Box<str>> = (4..10).map(|j| format!("C{}", j).into()).collect();
+
+    let kb_dir = temp_dir(".kb")?;
+    for (layer, x, cells) in [
+        (SPLICED_LAYER, &s, &spliced_cells),
+        (UNSPLICED_LAYER, &u, &unspliced_cells),
+    ] {
+        write_mtx(&x.t().to_owned(), &format!("{}/{}.mtx", kb_dir, layer))?;
+        write_lines(cells, &format!("{}/{}.barcodes.txt", kb_dir, layer))?;
+        write_lines(
+            &names("G", ngene),
+            &format!("{}/{}.genes.txt", kb_dir, layer),
+        )?;
+    }
+
+    let usa = read_usa_counts(&kb_dir)?;
+    assert_eq!(usa.column_names, names("C", 10));
+    assert_eq!(usa.layer_names().len(), 2);
+
+    let zarr_file = temp_backend()?;
+    let data = create_sparse_from_usa(
+        usa,
+        None,
+        false,
+        Some(&zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions::default(),
+    )?;
+
+    let mut expected_s = Array2::<f32>::zeros((ngene, 10));
+    expected_s.slice_mut(ndarray::s![.., 0..8]).assign(&s);
+    let mut expected_u = Array2::<f32>::zeros((ngene, 10));
+    expected_u.slice_mut(ndarray::s![.., 4..10]).assign(&u);
+
+    let columns: Vec<usize> = (0..10).collect();
+    assert_eq!(
+        data.read_columns_ndarray(columns.clone())?,
+        &expected_s + &expected_u
+    );
+    let spliced = data.open_layer(Some(SPLICED_LAYER))?;
+    assert_eq!(spliced.read_columns_ndarray(columns.clone())?, expected_s);
+    let unspliced = data.open_layer(Some(UNSPLICED_LAYER))?;
+    assert_eq!(unspliced.read_columns_ndarray(columns)?, expected_u);
+    assert!(!data.has_layer(COUNTS_LAYER)?);
+
+    data.remove_backend_file()?;
+    std::fs::remove_dir_all(&kb_dir)?;
+    Ok(())
+}
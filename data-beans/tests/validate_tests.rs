@@ -0,0 +1,66 @@
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn validate_and_repair_csr() -> anyhow::Result<()> {
+    for backend in [SparseIoBackend::Memory, SparseIoBackend::Zarr] {
+        let x = Array2::<f32>::runif(7, 5);
+        let backend_file = create_temp_dir_file(".zarr")?;
+        let backend_file = backend_file.to_str().expect("to_str failed");
+        let mut data = create_sparse_from_ndarray(&x, Some(backend_file), Some(&backend))?;
+        let rows: Vec<Box<str>> = (0..7).map(|i| format!("g{}", i).into()).collect();
+        let cols: Vec<Box<str>> = (0..5).map(|j| format!("c{}", j).into()).collect();
+        data.register_row_names_vec(&rows);
+        data.register_column_names_vec(&cols);
+        data.register_layer_triplets("raw", &mut ndarray_to_triplets(&x.mapv(|v| v * 2.)))?;
+
+        let report = data.validate()?;
+        assert!(report.is_valid(), "{}", report);
+
+        // a value changed on the CSR side only
+        let (indices, mut values) = data.read_compressed_chunk_backend("/by_row", 0..3)?;
+        values[1] += 1.;
+        data.record_compressed_chunk_backend("/by_row", 0, &indices, &values)?;
+
+        // a broken `indptr` of the layer
+        let key = layer_key(Some("raw"), "by_row");
+        let mut indptr = data.read_compressed_indptr_backend(&key)?;
+        indptr.swap(2, 3);
+        indptr[3] = 0;
+        data.record_compressed_indptr_backend(&key, &indptr)?;
+
+        let report = data.validate()?;
+        assert!(!report.is_valid());
+        let failed: Vec<_> = report
+            .failures()
+            .iter()
+            .map(|c| (c.target(), c.name.to_string()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                ("main".to_string(), "csr_csc".to_string()),
+                ("layers/raw".to_string(), "by_row".to_string()),
+                ("layers/raw".to_string(), "csr_csc".to_string()),
+            ]
+        );
+        assert_eq!(
+            report.repairable_layers(),
+            vec![None, Some(Box::from("raw"))]
+        );
+
+        for layer in report.repairable_layers() {
+            data.repair_csr_from_csc(layer.as_deref(), 1 << 20)?;
+        }
+
+        let report = data.validate()?;
+        assert!(report.is_valid(), "{}", report);
+
+        let csr = data.read_rows_ndarray((0..7).collect())?;
+        assert_eq!(csr, x);
+
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use data_beans::sparse_io::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+fn counts(nrow: usize, ncol: usize, scale: f32) -> Array2<f32> {
+    Array2::<f32>::runif(nrow, ncol).mapv(|x| (x * scale).floor())
+}
+
+fn stored_data_type(zarr_file: &str) -> anyhow::Result<String> {
+    let meta = std::fs::read_to_string(format!("{}/by_column/data/zarr.json", zarr_file))?;
+    let meta: serde_json::Value = serde_json::from_str(&meta)?;
+    Ok(meta["data_type"].as_str().unwrap_or_default().to_string())
+}
+
+#[test]
+fn detect_value_types() {
+    assert_eq!(ValueType::detect(&[0., 3., 65535.]), ValueType::U16);
+    assert_eq!(ValueType::detect(&[0., 3., 65536.]), ValueType::U32);
+    assert_eq!(ValueType::detect(&[0., 3., 1.5]), ValueType::F32);
+    assert_eq!(ValueType::detect(&[-1., 3.]), ValueType::F32);
+    assert_eq!(ValueType::U32.widen(2.), ValueType::U32);
+    assert_eq!(ValueType::F64.widen(0.5), ValueType::F64);
+    assert!(ValueType::U16
+        .checked_values(&[1., 2.5], |x| x as u16)
+        .is_err());
+    assert_eq!(ValueType::from_name("u32").ok(), Some(ValueType::U32));
+}
+
+#[test]
+fn store_counts_as_integers() -> anyhow::Result<()> {
+    for (x, expected, dtype) in [
+        (counts(20, 30, 100.), ValueType::U16, "uint16"),
+        (counts(20, 30, 1e6), ValueType::U32, "uint32"),
+        (Array2::<f32>::runif(20, 30), ValueType::F32, "float32"),
+    ] {
+        let zarr_file = create_temp_dir_file(".zarr")?;
+        let zarr_file = zarr_file.to_str().expect("to_str failed");
+
+        let data = create_sparse_from_triplets(
+            ndarray_to_triplets(&x),
+            (20, 30, x.iter().filter(|&&x| x != 0.).count()),
+            Some(zarr_file),
+            Some(&SparseIoBackend::Zarr),
+        )?;
+        assert_eq!(data.value_type(), Some(expected));
+        assert_eq!(stored_data_type(zarr_file)?, dtype);
+
+        let reopened = open_sparse_matrix(zarr_file, &SparseIoBackend::Zarr)?;
+        assert_eq!(reopened.value_type(), Some(expected));
+        assert_eq!(reopened.read_columns_ndarray((0..30).collect())?, x);
+        assert_eq!(reopened.read_rows_ndarray((0..20).collect())?, x);
+
+        data.remove_backend_file()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn override_and_widen_value_types() -> anyhow::Result<()> {
+    let x = counts(10, 12, 50.);
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+
+    let mut data = create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (10, 12, x.iter().filter(|&&x| x != 0.).count()),
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions {
+            value_type: Some(ValueType::F64),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(stored_data_type(zarr_file)?, "float64");
+    assert_eq!(data.read_columns_ndarray((0..12).collect())?, x);
+
+    // a layer of non-integer values on a count matrix stays exact
+    data.set_value_type(ValueType::U16)?;
+    let y = Array2::<f32>::runif(10, 12);
+    data.register_layer_triplets("normalized", &mut ndarray_to_triplets(&y))?;
+    let layer = data.open_layer(Some("normalized"))?;
+    assert_eq!(layer.read_columns_ndarray((0..12).collect())?, y);
+    assert_eq!(data.value_type(), Some(ValueType::F32));
+    let reopened = open_sparse_matrix(zarr_file, &SparseIoBackend::Zarr)?;
+    assert_eq!(reopened.value_type(), Some(ValueType::F32));
+
+    data.remove_backend_file()?;
+    Ok(())
+}
+
+#[test]
+fn reject_values_out_of_the_requested_type() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(10, 12);
+    let nnz = x.iter().filter(|&&x| x != 0.).count();
+    let options = SparseCreateOptions {
+        value_type: Some(ValueType::U16),
+        ..Default::default()
+    };
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    assert!(create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (10, 12, nnz),
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &options,
+    )
+    .is_err());
+    std::fs::remove_dir_all(zarr_file).ok();
+
+    let memory = create_sparse_from_ndarray(&x, None, Some(&SparseIoBackend::Memory))?;
+    let mtx_file = create_temp_dir_file(".mtx.gz")?;
+    let mtx_file = mtx_file.to_str().expect("to_str failed");
+    memory.to_mtx_file(mtx_file)?;
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    assert!(create_sparse_from_mtx_file_external(
+        mtx_file,
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        1 << 10,
+        &options,
+    )
+    .is_err());
+    std::fs::remove_dir_all(zarr_file).ok();
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    assert!(create_sparse_from_mtx_file_with_options(
+        mtx_file,
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        &options,
+    )
+    .is_err());
+    std::fs::remove_dir_all(zarr_file).ok();
+
+    std::fs::remove_file(mtx_file)?;
+    Ok(())
+}
+
+#[test]
+fn detect_counts_from_mtx_out_of_core() -> anyhow::Result<()> {
+    let x = counts(40, 50, 10.);
+    let memory = create_sparse_from_ndarray(&x, None, Some(&SparseIoBackend::Memory))?;
+    let mtx_file = create_temp_dir_file(".mtx.gz")?;
+    let mtx_file = mtx_file.to_str().expect("to_str failed");
+    memory.to_mtx_file(mtx_file)?;
+
+    let zarr_file = create_temp_dir_file(".zarr")?;
+    let zarr_file = zarr_file.to_str().expect("to_str failed");
+    let data = create_sparse_from_mtx_file_external(
+        mtx_file,
+        Some(zarr_file),
+        Some(&SparseIoBackend::Zarr),
+        1 << 10,
+        &SparseCreateOptions::default(),
+    )?;
+    assert_eq!(data.value_type(), Some(ValueType::U16));
+    assert_eq!(stored_data_type(zarr_file)?, "uint16");
+    assert_eq!(data.read_columns_ndarray((0..50).collect())?, x);
+
+    data.remove_backend_file()?;
+    Ok(())
+}
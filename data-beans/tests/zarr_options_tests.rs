@@ -0,0 +1,131 @@
+use data_beans::sparse_io::*;
+use data_beans::sparse_matrix_zarr::ZarrCodec;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn zarr_chunking_sharding_codecs() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(30, 20);
+    let nnz = x.iter().filter(|&&x| x != 0.).count();
+
+    let options = [
+        ZarrWriteOptions::default(),
+        ZarrWriteOptions {
+            chunk_size: Some(16),
+            chunks_per_shard: Some(4),
+            codec: ZarrCodec::Blosc,
+            ..Default::default()
+        },
+        ZarrWriteOptions {
+            chunk_bytes: Some(256),
+            codec: ZarrCodec::Gzip,
+            level: 9,
+            ..Default::default()
+        },
+        ZarrWriteOptions {
+            codec: ZarrCodec::None,
+            ..Default::default()
+        },
+    ];
+
+    for opt in options.iter() {
+        let backend_file = create_temp_dir_file(".zarr")?;
+        let backend_file = backend_file.to_str().expect("to_str failed");
+        create_sparse_from_triplets_with_options(
+            ndarray_to_triplets(&x),
+            (30, 20, nnz),
+            Some(backend_file),
+            Some(&SparseIoBackend::Zarr),
+            &SparseCreateOptions {
+                zarr: opt.clone(),
+                ..Default::default()
+            },
+        )?;
+
+        let data = open_sparse_matrix(backend_file, &SparseIoBackend::Zarr)?;
+        assert_eq!(data.read_columns_ndarray((0..20).collect())?, x);
+        assert_eq!(data.read_rows_ndarray((0..30).collect())?, x);
+
+        let meta = std::fs::read_to_string(format!("{}/by_column/data/zarr.json", backend_file))?;
+        assert_eq!(
+            meta.contains("sharding_indexed"),
+            opt.chunks_per_shard.is_some()
+        );
+        match opt.codec {
+            ZarrCodec::Zstd => assert!(meta.contains("zstd")),
+            ZarrCodec::Blosc => assert!(meta.contains("blosc")),
+            ZarrCodec::Gzip => assert!(meta.contains("gzip")),
+            ZarrCodec::None => assert!(!meta.contains("zstd")),
+        }
+
+        data.remove_backend_file()?;
+    }
+
+    // out-of-range levels are rejected
+    let bad = ZarrWriteOptions {
+        codec: ZarrCodec::Gzip,
+        level: 42,
+        ..Default::default()
+    };
+    assert!(create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (30, 20, nnz),
+        None,
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions {
+            zarr: bad,
+            ..Default::default()
+        },
+    )
+    .is_err());
+    Ok(())
+}
+
+#[test]
+fn reopened_zarr_keeps_layout() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(30, 20);
+    let nnz = x.iter().filter(|&&x| x != 0.).count();
+
+    let backend_file = create_temp_dir_file(".zarr")?;
+    let backend_file = backend_file.to_str().expect("to_str failed");
+    create_sparse_from_triplets_with_options(
+        ndarray_to_triplets(&x),
+        (30, 20, nnz),
+        Some(backend_file),
+        Some(&SparseIoBackend::Zarr),
+        &SparseCreateOptions {
+            zarr: ZarrWriteOptions {
+                chunk_size: Some(16),
+                chunks_per_shard: Some(4),
+                codec: ZarrCodec::Blosc,
+                level: 7,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )?;
+
+    // arrays written after reopening use the same layout
+    let mut data = open_sparse_matrix(backend_file, &SparseIoBackend::Zarr)?;
+    data.register_layer_triplets("raw", &mut ndarray_to_triplets(&x))?;
+
+    let meta = std::fs::read_to_string(format!(
+        "{}/layers/raw/by_column/data/zarr.json",
+        backend_file
+    ))?;
+    let meta: serde_json::Value = serde_json::from_str(&meta)?;
+    assert_eq!(
+        meta.pointer("/chunk_grid/configuration/chunk_shape/0"),
+        Some(&64.into())
+    );
+    let sharding = &meta["codecs"][0];
+    assert_eq!(sharding["name"], "sharding_indexed");
+    assert_eq!(sharding["configuration"]["chunk_shape"][0], 16);
+    let codecs = sharding["configuration"]["codecs"].as_array().unwrap();
+    assert!(codecs
+        .iter()
+        .any(|c| c["name"] == "blosc" && c["configuration"]["clevel"] == 7));
+
+    data.remove_backend_file()?;
+    Ok(())
+}
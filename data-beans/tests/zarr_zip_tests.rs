@@ -0,0 +1,67 @@
+use data_beans::sparse_io::*;
+use data_beans::zarr_zip_store::*;
+use matrix_util::common_io::create_temp_dir_file;
+use matrix_util::traits::SampleOps;
+
+#[test]
+fn zarr_zip_backend() -> anyhow::Result<()> {
+    let x = Array2::<f32>::runif(12, 9);
+
+    let zip_file = create_temp_dir_file(".zarr.zip")?;
+    let zip_file = zip_file.to_str().expect("to_str failed");
+
+    assert_eq!(
+        SparseIoBackend::from_file_name(zip_file)?,
+        SparseIoBackend::Zarr
+    );
+    assert!(open_sparse_matrix(zip_file, &SparseIoBackend::Zarr).is_err());
+
+    {
+        let mut data =
+            create_sparse_from_ndarray(&x, Some(zip_file), Some(&SparseIoBackend::Zarr))?;
+        let rows: Vec<Box<str>> = (0..12).map(|i| format!("g{}", i).into()).collect();
+        let cols: Vec<Box<str>> = (0..9).map(|j| format!("c{}", j).into()).collect();
+        data.register_row_names_vec(&rows);
+        data.register_column_names_vec(&cols);
+        data.flush_backend()?;
+        assert!(std::path::Path::new(zip_file).is_file());
+    }
+
+    // write back a new layer into the existing archive
+    {
+        let mut data = open_sparse_matrix_by_extension(zip_file)?;
+        assert_eq!(data.read_columns_ndarray((0..9).collect())?, x);
+        assert_eq!(data.read_rows_ndarray((0..12).collect())?, x);
+        data.register_layer_triplets("raw", &mut ndarray_to_triplets(&x.mapv(|v| v * 2.)))?;
+        data.flush_backend()?;
+    }
+
+    // reading needs no staging directory next to the archive
+    let data = open_sparse_matrix_by_extension(zip_file)?;
+    let parent = std::path::Path::new(zip_file).parent().expect("parent");
+    assert!(!std::fs::read_dir(parent)?.filter_map(|x| x.ok()).any(|x| x
+        .file_name()
+        .to_string_lossy()
+        .starts_with(".zarr-zip-staging")));
+    assert_eq!(data.layer_names()?, vec![Box::from("raw")]);
+    assert_eq!(data.row_names()?[3].as_ref(), "g3");
+    let report = data.validate()?;
+    assert!(report.is_valid(), "{}", report);
+    drop(data);
+
+    // zip -> directory -> zip
+    let zarr_dir = zip_file.strip_suffix(".zip").expect("zip");
+    unpack_zarr_zip(zip_file, zarr_dir)?;
+    let data = open_sparse_matrix(zarr_dir, &SparseIoBackend::Zarr)?;
+    assert_eq!(data.read_columns_ndarray((0..9).collect())?, x);
+
+    let repacked = format!("{}.repacked{}", zarr_dir, ZARR_ZIP_SUFFIX);
+    pack_zarr_directory(zarr_dir, &repacked)?;
+    let data = open_sparse_matrix_by_extension(&repacked)?;
+    assert_eq!(data.read_rows_ndarray((0..12).collect())?, x);
+    assert_eq!(data.layer_names()?, vec![Box::from("raw")]);
+
+    data.remove_backend_file()?;
+    assert!(!std::path::Path::new(&repacked).exists());
+    Ok(())
+}
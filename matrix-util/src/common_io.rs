@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use flate2::read::GzDecoder;
+use flate2::read::MultiGzDecoder;
 use rayon::prelude::*;
 use std::ffi::OsStr;
 use std::fs::File;
@@ -235,8 +235,9 @@ pub fn open_buf_reader(input_file: &str) -> anyhow::Result<Box<dyn BufRead>> {
     match ext {
         Some("gz") => {
             // dbg!(input_file);
+            // concatenated gzip members are read through like `zcat`
             let input_file = File::open(input_file)?;
-            let decoder = GzDecoder::new(input_file);
+            let decoder = MultiGzDecoder::new(input_file);
             Ok(Box::new(BufReader::new(decoder)))
         }
         _ => {
@@ -1,6 +1,6 @@
 use parquet::basic::Type as ParquetType;
 use parquet::basic::{Compression, ConvertedType, ZstdLevel};
-use parquet::data_type::ByteArray;
+use parquet::data_type::{ByteArray, ByteArrayType, FloatType};
 use parquet::file::properties::WriterProperties;
 use parquet::file::reader::{FileReader, SerializedFileReader};
 use parquet::file::writer::SerializedFileWriter;
@@ -229,3 +229,81 @@ fn build_columns_schema(
 
     Ok(schema)
 }
+
+/// Write the elements of a matrix in the long format, one record for
+/// each (row, column, value) triplet
+///
+/// ```text
+/// row        column     value
+/// ACTB       AAACCT-1   3.0
+/// GAPDH      AAACCT-1   1.0
+/// ```
+///
+/// * `file_path` - output parquet file
+/// * `triplets` - (row name, column name, value)
+pub fn write_long_format_parquet<'a, I>(file_path: &str, triplets: I) -> anyhow::Result<()>
+where
+    I: IntoIterator<Item = (&'a str, &'a str, f32)>,
+{
+    let mut rows = vec![];
+    let mut columns = vec![];
+    let mut values = vec![];
+    for (row, column, value) in triplets {
+        rows.push(ByteArray::from(row));
+        columns.push(ByteArray::from(column));
+        values.push(value);
+    }
+
+    let utf8_field = |name: &str| -> anyhow::Result<Arc<Type>> {
+        Ok(Arc::new(
+            Type::primitive_type_builder(name, ParquetType::BYTE_ARRAY)
+                .with_repetition(parquet::basic::Repetition::REQUIRED)
+                .with_converted_type(ConvertedType::UTF8)
+                .build()?,
+        ))
+    };
+
+    let schema = Arc::new(
+        Type::group_type_builder("longMatrix")
+            .with_fields(vec![
+                utf8_field("row")?,
+                utf8_field("column")?,
+                Arc::new(
+                    Type::primitive_type_builder("value", ParquetType::FLOAT)
+                        .with_repetition(parquet::basic::Repetition::REQUIRED)
+                        .build()?,
+                ),
+            ])
+            .build()?,
+    );
+
+    let writer_properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::try_new(5)?))
+            .build(),
+    );
+
+    let mut writer =
+        SerializedFileWriter::new(File::create(file_path)?, schema, writer_properties)?;
+    let mut row_group_writer = writer.next_row_group()?;
+
+    for names in [&rows, &columns] {
+        if let Some(mut column_writer) = row_group_writer.next_column()? {
+            column_writer
+                .typed::<ByteArrayType>()
+                .write_batch(names, None, None)?;
+            column_writer.close()?;
+        }
+    }
+
+    if let Some(mut column_writer) = row_group_writer.next_column()? {
+        column_writer
+            .typed::<FloatType>()
+            .write_batch(&values, None, None)?;
+        column_writer.close()?;
+    }
+
+    row_group_writer.close()?;
+    writer.close()?;
+    Ok(())
+}
@@ -35,8 +35,8 @@ enum ComputeDevice {
 /// Embedding spatially resolved transcriptomic (SRT) data.
 ///
 struct SRTArgs {
-    /// Data files of either `.zarr` or `.h5` format. All the formats
-    /// in the given list should be identical. We can convert `.mtx`
+    /// Data files of either `.zarr` or `.h5` format. The formats can
+    /// be mixed in the given list. We can convert `.mtx`
     /// to `.zarr` or `.h5` using `data-beans from-mtx` command.
     #[arg(required = true, value_delimiter(','))]
     data_files: Vec<Box<str>>,
@@ -64,6 +64,19 @@ struct SRTArgs {
     #[arg(long, short = 'b', value_delimiter(','))]
     batch_files: Option<Vec<Box<str>>>,
 
+    /// per-column annotation stored in the data files (e.g.,
+    /// `donor`) to be used as batch membership instead of batch files
+    #[arg(long, conflicts_with = "batch_files")]
+    batch_column: Option<Box<str>>,
+
+    /// how to line up rows (genes) across data files: `strict` (same
+    /// rows), `intersection` (shared rows), or `union` (zero-filled)
+    #[arg(long, value_enum, default_value = "strict")]
+    row_alignment: RowAlignment,
+
+    #[command(flatten)]
+    columns: ColumnSelectArgs,
+
     /// Random projection dimension to project the data.
     #[arg(long, short = 'p', default_value_t = 50)]
     proj_dim: usize,
@@ -132,9 +145,8 @@ struct SRTArgs {
     #[arg(long, value_enum, default_value = "cpu")]
     device: ComputeDevice,
 
-    /// preload all the columns data
-    #[arg(long, default_value_t = false)]
-    preload_data: bool,
+    #[command(flatten)]
+    preload: PreloadArgs,
 
     /// verbosity
     #[arg(long, short)]
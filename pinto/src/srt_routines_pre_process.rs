@@ -1,49 +1,32 @@
 // #![allow(dead_code)]
 
-use crate::SRTArgs;
 use crate::srt_common::*;
+use crate::SRTArgs;
 
 pub fn read_data_vec(args: SRTArgs) -> anyhow::Result<(SparseIoVec, Mat, Vec<Box<str>>)> {
     // push data files and collect batch membership
-    let file = args.data_files[0].as_ref();
-    let backend = match extension(file)?.to_string().as_str() {
-        "h5" => SparseIoBackend::HDF5,
-        "zarr" => SparseIoBackend::Zarr,
-        _ => SparseIoBackend::Zarr,
-    };
-
     let mut data_vec = SparseIoVec::new();
+    data_vec.set_row_alignment(args.row_alignment)?;
 
     for data_file in args.data_files.iter() {
         info!("Importing data file: {}", data_file);
 
-        match extension(data_file)?.as_ref() {
-            "zarr" => {
-                assert_eq!(backend, SparseIoBackend::Zarr);
-            }
-            "h5" => {
-                assert_eq!(backend, SparseIoBackend::HDF5);
-            }
-            _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-        };
-
         let data_name = basename(data_file)?;
-        let mut data = open_sparse_matrix(data_file, &backend)?;
-
-        if args.preload_data {
-            data.preload_columns()?;
-        }
+        let mut data = open_sparse_matrix_by_extension(data_file)?;
+        args.preload.preload(data.as_mut())?;
 
         data_vec.push(Arc::from(data), Some(data_name))?;
     }
 
-    // check if row names are the same across data
-    let row_names = data_vec[0].row_names()?;
+    // check if row names are the same across data unless aligned
+    if args.row_alignment == RowAlignment::Strict {
+        let row_names = data_vec[0].row_names()?;
 
-    for j in 1..data_vec.len() {
-        let row_names_j = data_vec[j].row_names()?;
-        if row_names != row_names_j {
-            return Err(anyhow::anyhow!("Row names are not the same"));
+        for j in 1..data_vec.len() {
+            let row_names_j = data_vec[j].row_names()?;
+            if row_names != row_names_j {
+                return Err(anyhow::anyhow!("Row names are not the same"));
+            }
         }
     }
 
@@ -110,7 +93,10 @@ pub fn read_data_vec(args: SRTArgs) -> anyhow::Result<(SparseIoVec, Mat, Vec<Box
     // check batch membership
     let mut batch_membership = Vec::with_capacity(data_vec.len());
 
-    if let Some(batch_files) = &args.batch_files {
+    if let Some(batch_column) = &args.batch_column {
+        info!("Reading batch column: {}", batch_column);
+        batch_membership.extend(data_vec.column_annotation(batch_column)?.to_strings());
+    } else if let Some(batch_files) = &args.batch_files {
         if batch_files.len() != args.data_files.len() {
             return Err(anyhow::anyhow!("# batch files != # of data files"));
         }
@@ -135,6 +121,15 @@ pub fn read_data_vec(args: SRTArgs) -> anyhow::Result<(SparseIoVec, Mat, Vec<Box
         ));
     }
 
+    let (data_vec, cells) = args.columns.select_columns(data_vec)?;
+    let (coord_nk, batch_membership) = match cells {
+        Some(cells) => (
+            coord_nk.select_rows(cells.iter()),
+            cells.iter().map(|&j| batch_membership[j].clone()).collect(),
+        ),
+        None => (coord_nk, batch_membership),
+    };
+
     // use batch index as another coordinate
     let uniq_batches = batch_membership.par_iter().collect::<HashSet<_>>();
     let n_batches = uniq_batches.len();
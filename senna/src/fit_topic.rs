@@ -40,6 +40,19 @@ pub struct TopicArgs {
     #[arg(long, short, value_delimiter(','))]
     batch_files: Option<Vec<Box<str>>>,
 
+    /// per-column annotation stored in the data files (e.g.,
+    /// `donor`) to be used as batch membership instead of batch files
+    #[arg(long, conflicts_with = "batch_files")]
+    batch_column: Option<Box<str>>,
+
+    /// how to line up rows (genes) across data files: `strict` (same
+    /// rows), `intersection` (shared rows), or `union` (zero-filled)
+    #[arg(long, value_enum, default_value = "strict")]
+    row_alignment: RowAlignment,
+
+    #[command(flatten)]
+    columns: ColumnSelectArgs,
+
     #[arg(long, default_value_t = false)]
     ignore_batch_effects: bool,
 
@@ -126,6 +139,9 @@ pub fn fit_topic_model(args: &TopicArgs) -> anyhow::Result<()> {
     let (mut data_vec, batch_membership) = read_data_vec_membership(ReadArgs {
         data_files: args.data_files.clone(),
         batch_files: args.batch_files.clone(),
+        batch_column: args.batch_column.clone(),
+        row_alignment: args.row_alignment,
+        columns: args.columns.clone(),
     })?;
 
     // 2. Random projection
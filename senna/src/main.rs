@@ -13,8 +13,8 @@ use fit_topic::*;
 /// Single cell embedding routines with nearest neighbourhood-based
 /// adjustment
 ///
-/// Data files of either `.zarr` or `.h5` format. All the formats in
-/// the given list should be identical. We can convert `.mtx` to
+/// Data files of either `.zarr` or `.h5` format. The formats can be
+/// mixed in the given list. We can convert `.mtx` to
 /// `.zarr` or `.h5` using `data-beans from-mtx` or similar commands.
 ///
 #[derive(Parser, Debug)]
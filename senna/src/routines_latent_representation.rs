@@ -1,37 +1,25 @@
 use crate::embed_common::*;
 
-use data_beans::sparse_data_visitors::VisitColumnsOps;
-use data_beans_alg::normalization::*;
+use data_beans::sparse_data_visitors::{ReadColumnsOps, VisitColumnsOps};
+use data_beans_alg::transformed_view::SparseIoVecView;
 
 use candle_util::candle_data_loader::*;
 use candle_util::candle_inference::TrainConfig;
 use candle_util::candle_model_traits::*;
 use candle_util::candle_vae_inference::*;
 
-fn nystrom_proj_visitor(
+fn nystrom_proj_visitor<D>(
     job: (usize, usize),
-    full_data_vec: &SparseIoVec,
-    proj_basis: &NystromParam,
+    data: &D,
+    u_dk: &Mat,
     arc_proj_kn: Arc<Mutex<&mut Mat>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
     let (lb, ub) = job;
-    let u_dk = proj_basis.dictionary_dk;
-    let delta_db = proj_basis.batch_db;
-
-    let mut x_dn = full_data_vec.read_columns_csc(lb..ub)?;
-
-    x_dn.normalize_columns_inplace();
-
-    if let Some(delta_db) = delta_db {
-        let batches = full_data_vec.get_batch_membership(lb..ub);
-        x_dn.adjust_by_division(delta_db, &batches);
-    }
-
-    x_dn.values_mut().iter_mut().for_each(|x| {
-        *x = (*x + 1.).ln();
-    });
 
-    x_dn.scale_columns_inplace();
+    let x_dn = data.read_columns_csc(lb..ub)?;
 
     let chunk = (x_dn.transpose() * u_dk).transpose();
 
@@ -41,11 +29,6 @@ fn nystrom_proj_visitor(
     Ok(())
 }
 
-struct NystromParam<'a> {
-    dictionary_dk: &'a Mat,
-    batch_db: Option<&'a Mat>,
-}
-
 pub struct NystromOut {
     pub dictionary_dk: Mat,
     pub latent_nk: Mat,
@@ -83,19 +66,15 @@ pub fn do_nystrom_proj(
     let ntot = full_data_vec.num_columns()?;
     let kk = rank;
 
-    let nystrom_param = NystromParam {
-        dictionary_dk: &u_dk,
-        batch_db: delta_db,
-    };
+    let mut view = SparseIoVecView::new(full_data_vec)?.normalize();
+    if let Some(delta_db) = delta_db {
+        view = view.adjust_by_batch(delta_db)?;
+    }
+    let view = view.log1p().standardize();
 
     let mut proj_kn = Mat::zeros(kk, ntot);
 
-    full_data_vec.visit_columns_by_block(
-        &nystrom_proj_visitor,
-        &nystrom_param,
-        &mut proj_kn,
-        block_size,
-    )?;
+    view.visit_columns_by_block(&nystrom_proj_visitor, &u_dk, &mut proj_kn, block_size)?;
 
     let z_nk = proj_kn.transpose();
 
@@ -1,7 +1,7 @@
 use crate::embed_common::*;
 
-use data_beans::sparse_data_visitors::VisitColumnsOps;
-use data_beans_alg::normalization::*;
+use data_beans::sparse_data_visitors::{ReadColumnsOps, VisitColumnsOps};
+use data_beans_alg::transformed_view::SparseIoVecView;
 
 use candle_util::candle_inference::TrainConfig;
 use candle_util::candle_model_traits::*;
@@ -9,18 +9,18 @@ use candle_util::candle_model_traits::*;
 use indicatif::ParallelProgressIterator;
 use rayon::prelude::*;
 
-fn adjust_triplets_visitor(
+fn adjust_triplets_visitor<D>(
     job: (usize, usize),
-    full_data_vec: &SparseIoVec,
-    delta_db: &Mat,
+    data: &D,
+    _: &(),
     triplets: Arc<Mutex<&mut Vec<(u64, u64, f32)>>>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<()>
+where
+    D: ReadColumnsOps,
+{
     let (lb, ub) = job;
-    let batches = full_data_vec.get_batch_membership(lb..ub);
-
-    let mut x_dn = full_data_vec.read_columns_csc(lb..ub)?;
 
-    x_dn.adjust_by_division(delta_db, &batches);
+    let x_dn = data.read_columns_csc(lb..ub)?;
 
     let new_triplets = x_dn
         .triplet_iter()
@@ -53,8 +53,9 @@ pub fn triplets_adjusted_by_batch(
     data_vec: &SparseIoVec,
     delta_db: &Mat,
 ) -> anyhow::Result<Vec<(u64, u64, f32)>> {
+    let view = SparseIoVecView::new(data_vec)?.adjust_by_batch(delta_db)?;
     let mut triplets = vec![];
-    data_vec.visit_columns_by_block(&adjust_triplets_visitor, delta_db, &mut triplets, None)?;
+    view.visit_columns_by_block(&adjust_triplets_visitor, &(), &mut triplets, None)?;
     Ok(triplets)
 }
 
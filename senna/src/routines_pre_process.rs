@@ -1,5 +1,5 @@
 use crate::embed_common::*;
-use matrix_util::common_io::{self, basename, extension, read_lines};
+use matrix_util::common_io::{self, basename, read_lines};
 
 //////////////////////////////////////////
 // read data files and batch membership //
@@ -7,50 +7,42 @@ use matrix_util::common_io::{self, basename, extension, read_lines};
 pub struct ReadArgs {
     pub data_files: Vec<Box<str>>,
     pub batch_files: Option<Vec<Box<str>>>,
+    pub batch_column: Option<Box<str>>,
+    pub row_alignment: RowAlignment,
+    pub columns: ColumnSelectArgs,
 }
 
 pub fn read_data_vec_membership(args: ReadArgs) -> anyhow::Result<(SparseIoVec, Vec<Box<str>>)> {
     // push data files and collect batch membership
-    let file = args.data_files[0].as_ref();
-    let backend = match extension(file)?.to_string().as_str() {
-        "h5" => SparseIoBackend::HDF5,
-        "zarr" => SparseIoBackend::Zarr,
-        _ => SparseIoBackend::Zarr,
-    };
-
     let mut data_vec = SparseIoVec::new();
+    data_vec.set_row_alignment(args.row_alignment)?;
     for data_file in args.data_files.iter() {
         info!("Importing data file: {}", data_file);
 
-        match extension(data_file)?.as_ref() {
-            "zarr" => {
-                assert_eq!(backend, SparseIoBackend::Zarr);
-            }
-            "h5" => {
-                assert_eq!(backend, SparseIoBackend::HDF5);
-            }
-            _ => return Err(anyhow::anyhow!("Unknown file format: {}", data_file)),
-        };
-
-        let data = open_sparse_matrix(data_file, &backend)?;
+        let data = open_sparse_matrix_by_extension(data_file)?;
         let data_name = basename(data_file)?;
         data_vec.push(Arc::from(data), Some(data_name))?;
     }
 
-    // check if row names are the same
-    let row_names = data_vec[0].row_names()?;
+    // check if row names are the same unless they are aligned
+    if args.row_alignment == RowAlignment::Strict {
+        let row_names = data_vec[0].row_names()?;
 
-    for j in 1..data_vec.len() {
-        let row_names_j = data_vec[j].row_names()?;
-        if row_names != row_names_j {
-            return Err(anyhow::anyhow!("Row names are not the same"));
+        for j in 1..data_vec.len() {
+            let row_names_j = data_vec[j].row_names()?;
+            if row_names != row_names_j {
+                return Err(anyhow::anyhow!("Row names are not the same"));
+            }
         }
     }
 
     // check batch membership
     let mut batch_membership = Vec::with_capacity(data_vec.len());
 
-    if let Some(batch_files) = &args.batch_files {
+    if let Some(batch_column) = &args.batch_column {
+        info!("Reading batch column: {}", batch_column);
+        batch_membership.extend(data_vec.column_annotation(batch_column)?.to_strings());
+    } else if let Some(batch_files) = &args.batch_files {
         if batch_files.len() != args.data_files.len() {
             return Err(anyhow::anyhow!("# batch files != # of data files"));
         }
@@ -77,5 +69,10 @@ pub fn read_data_vec_membership(args: ReadArgs) -> anyhow::Result<(SparseIoVec,
         ));
     }
 
+    let (data_vec, cells) = args.columns.select_columns(data_vec)?;
+    if let Some(cells) = cells {
+        batch_membership = cells.iter().map(|&j| batch_membership[j].clone()).collect();
+    }
+
     Ok((data_vec, batch_membership))
 }